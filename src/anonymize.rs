@@ -0,0 +1,109 @@
+// Reports are sometimes shared outside the organization, e.g. with a vendor or in a public issue.
+// Anonymization replaces hostnames, IP addresses, cloud account IDs and values matching custom
+// patterns in the report with pseudonyms. A value always gets the same pseudonym, so the report
+// still shows which values are the same, and two reports anonymized with the same salt can be compared
+use crate::utils::fnv1a;
+use regex::Regex;
+use std::error::Error;
+
+// Values found by the rules are replaced, or only the 'v' group when a rule has one
+const RULES: [(&str, &str); 9] = [
+    (
+        "ip",
+        r"\b(?P<v>(?:25[0-5]|2[0-4]\d|1?\d?\d)(?:\.(?:25[0-5]|2[0-4]\d|1?\d?\d)){3})\b",
+    ),
+    // the host of URLs, e.g. 'https://git.example.com/org/repo'
+    (
+        "host",
+        r"(?i)\b[a-z][a-z0-9+.-]*://(?:[^@/\s]+@)?(?P<v>[a-z0-9-]+(?:\.[a-z0-9-]+)*)",
+    ),
+    // SSH remotes, e.g. 'git@git.example.com:org/repo'
+    (
+        "host",
+        r"(?i)\b[a-z0-9._-]+@(?P<v>[a-z0-9-]+(?:\.[a-z0-9-]+)+):",
+    ),
+    // values of host keys, e.g. 'host: app.example.com' or 'hosts: ["*.example.com"]'
+    (
+        "host",
+        r#"(?i)\b(?:host|hostname|hosts|server|fqdn)["']?\s*[:=]\s*\[?\s*["']?(?P<v>[a-z0-9*-]+(?:\.[a-z0-9-]+)+)"#,
+    ),
+    // AWS account IDs in ARNs, ECR registries and account ID keys
+    (
+        "account",
+        r"\barn:aws[a-z-]*:[a-z0-9-]*:[a-z0-9-]*:(?P<v>\d{12}):",
+    ),
+    ("account", r"\b(?P<v>\d{12})\.dkr\.ecr\."),
+    (
+        "account",
+        r#"(?i)\b(?:account|subscription|tenant)[_-]?id["']?\s*[:=]\s*["']?(?P<v>\d{12}|[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\b"#,
+    ),
+    // Azure subscriptions in resource IDs
+    (
+        "account",
+        r"(?i)/subscriptions/(?P<v>[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})",
+    ),
+    // GCP projects in resource names and registries
+    (
+        "account",
+        r"\b(?:projects/|gcr\.io/|docker\.pkg\.dev/)(?P<v>[a-z][a-z0-9-]{4,28}[a-z0-9])\b",
+    ),
+];
+
+#[derive(Clone)]
+pub struct Anonymizer {
+    salt: String,
+    rules: Vec<(&'static str, Regex)>,
+    // values replaced by an earlier rule are kept, e.g. an IP address in a URL
+    pseudonym: Regex,
+}
+
+impl Anonymizer {
+    // 'patterns' are regexes of more values to replace, e.g. internal project names
+    pub fn new(patterns: &[String], salt: &str) -> Result<Anonymizer, Box<dyn Error>> {
+        let mut rules = vec![];
+        for (category, rule) in RULES {
+            rules.push((category, Regex::new(rule)?));
+        }
+        for pattern in patterns {
+            let regex =
+                Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+            rules.push(("value", regex));
+        }
+        Ok(Anonymizer {
+            salt: salt.to_string(),
+            rules,
+            pseudonym: Regex::new(r"^[a-z]+-[0-9a-f]{8}(\.example)?$")?,
+        })
+    }
+
+    fn pseudonym(&self, category: &str, value: &str) -> String {
+        let hash = fnv1a(&format!("{}{}", self.salt, value)) as u32;
+        match category {
+            // a reserved top-level domain, so the pseudonym still reads as a hostname
+            "host" => format!("host-{:08x}.example", hash),
+            category => format!("{}-{:08x}", category, hash),
+        }
+    }
+
+    pub fn anonymize(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (category, regex) in &self.rules {
+            text = regex
+                .replace_all(&text, |caps: &regex::Captures| {
+                    let all = caps.get(0).unwrap();
+                    match caps.name("v") {
+                        Some(v) if self.pseudonym.is_match(v.as_str()) => all.as_str().to_string(),
+                        Some(v) => format!(
+                            "{}{}{}",
+                            &all.as_str()[..v.start() - all.start()],
+                            self.pseudonym(category, v.as_str()),
+                            &all.as_str()[v.end() - all.start()..]
+                        ),
+                        None => self.pseudonym(category, all.as_str()),
+                    }
+                })
+                .to_string();
+        }
+        text
+    }
+}
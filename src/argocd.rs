@@ -1,87 +1,299 @@
+use crate::envtest;
+use crate::manifests::parse_manifest;
 use crate::run_command;
+use crate::utils::{run_command_from_list, set_argocd_opts};
+use crate::workdir;
 use base64::prelude::*;
 use log::{debug, error, info};
+use std::collections::BTreeMap;
 use std::{error::Error, process::Output};
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgoCDProfile {
+    // Only the components needed to render manifests. The CLI talks to the cluster in core mode
+    Minimal,
+    // The stock installation, including the API server, dex and notifications
+    Full,
+}
+
+// Settings of the Argo CD components, tuned for rendering many applications at once
+#[derive(Clone)]
+pub struct ArgoCDTuning {
+    pub repo_server_replicas: u32,
+    pub repo_server_parallelism_limit: u32,
+    pub controller_status_processors: u32,
+}
+
+#[derive(Clone)]
 pub struct ArgoCDOptions<'a> {
     pub version: Option<&'a str>,
     pub debug: bool,
+    // Manifests installed when the Helm Chart can't be. Derived from the chart version when not set
+    pub fallback_manifests: Option<Vec<&'a str>>,
+    pub profile: ArgoCDProfile,
+    pub tuning: ArgoCDTuning,
+    // Set as kustomize.buildOptions in argocd-cm, e.g. '--enable-helm' to inflate Helm charts. Not set when empty
+    pub kustomize_build_options: &'a str,
+    // 'resource.customizations' keys of a production argocd-cm, set in argocd-cm as they are
+    pub resource_customizations: &'a BTreeMap<String, String>,
+    // Image of the Argo CD components as REPOSITORY[:TAG], e.g. a mirror with a variant for the
+    // architecture of the cluster. The image of the chart is used when not set
+    pub image: Option<&'a str>,
 }
 
 const CONFIG_PATH: &str = "argocd-config";
 
-pub async fn install_argo_cd(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn Error>> {
-    info!(
-        "🦑 Installing Argo CD Helm Chart version: '{}'",
-        options.version.unwrap_or("latest")
-    );
+const STABLE_MANIFESTS: &str =
+    "https://raw.githubusercontent.com/argoproj/argo-cd/stable/manifests/install.yaml";
 
-    let (values, values_override) = match std::fs::read_dir(CONFIG_PATH) {
-        Ok(dir) => {
-            debug!("📂 Files in folder 'argocd-config':");
-            for file in dir {
-                debug!("- 📄 {:?}", file.unwrap().file_name());
-            }
-            let values_exist = std::fs::metadata(format!("{}/values.yaml", CONFIG_PATH))
-                .is_ok()
-                .then_some(format!("-f {}/values.yaml", CONFIG_PATH));
-            let values_override_exist =
-                std::fs::metadata(format!("{}/values-override.yaml", CONFIG_PATH))
-                    .is_ok()
-                    .then_some(format!("-f {}/values-override.yaml", CONFIG_PATH));
-            (values_exist, values_override_exist)
+// Helm values of the minimal profile
+const MINIMAL_PROFILE_VALUES: &str = r#"
+dex:
+  enabled: false
+notifications:
+  enabled: false
+server:
+  replicas: 0
+"#;
+
+// Deployments that are not part of the minimal profile. Scaled down when installing from plain manifests
+const MINIMAL_PROFILE_EXCLUDED: [&str; 3] = [
+    "argocd-server",
+    "argocd-dex-server",
+    "argocd-notifications-controller",
+];
+
+const HELM_INSTALL_ATTEMPTS: u64 = 3;
+
+// Settings the Argo CD components expect to exist. They are created by the Helm Chart otherwise
+const LOCAL_SETTINGS: &str = r#"
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: argocd-cm
+  labels:
+    app.kubernetes.io/part-of: argocd
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: argocd-rbac-cm
+  labels:
+    app.kubernetes.io/part-of: argocd
+---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: argocd-secret
+  labels:
+    app.kubernetes.io/part-of: argocd
+type: Opaque
+"#;
+
+// Helm values generated from the options. Applied before the values in 'argocd-config', so they can be overridden
+fn values_from_options(options: &ArgoCDOptions<'_>) -> String {
+    let profile_values = match options.profile {
+        ArgoCDProfile::Minimal => MINIMAL_PROFILE_VALUES,
+        ArgoCDProfile::Full => "",
+    };
+    format!(
+        r#"{}
+repoServer:
+  replicas: {}
+configs:
+  params:
+    reposerver.parallelism.limit: {}
+    controller.status.processors: {}
+"#,
+        profile_values,
+        options.tuning.repo_server_replicas,
+        options.tuning.repo_server_parallelism_limit,
+        options.tuning.controller_status_processors
+    ) + &cm_values(options)
+        + &image_values(options)
+}
+
+fn image_values(options: &ArgoCDOptions<'_>) -> String {
+    let Some(image) = options.image else {
+        return String::new();
+    };
+    // a colon before the last slash is the port of the registry
+    let (repository, tag) = match image.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag)),
+        _ => (image, None),
+    };
+    let mut values = format!("global:\n  image:\n    repository: {}\n", repository);
+    if let Some(tag) = tag {
+        values += &format!("    tag: {}\n", serde_json::Value::from(tag));
+    }
+    values
+}
+
+// Settings of argocd-cm that come from the options
+fn cm_data(options: &ArgoCDOptions<'_>) -> BTreeMap<String, String> {
+    let mut data = options.resource_customizations.clone();
+    let build_options = options.kustomize_build_options.trim();
+    if !build_options.is_empty() {
+        data.insert(
+            "kustomize.buildOptions".to_string(),
+            build_options.to_string(),
+        );
+    }
+    data
+}
+
+fn cm_values(options: &ArgoCDOptions<'_>) -> String {
+    let data = cm_data(options);
+    if data.is_empty() {
+        return String::new();
+    }
+    // the values are quoted as JSON strings, which YAML reads as well
+    let values: Vec<String> = data
+        .iter()
+        .map(|(key, value)| format!("    {}: {}\n", key, serde_json::Value::from(value.as_str())))
+        .collect();
+    format!("  cm:\n{}", values.concat())
+}
+
+// Applies the profile and tuning to an installation from plain manifests, where Helm values can't be used
+async fn adjust_manifest_installation(options: &ArgoCDOptions<'_>) {
+    if options.profile == ArgoCDProfile::Minimal {
+        for deployment in MINIMAL_PROFILE_EXCLUDED {
+            let _ = run_command(
+                &format!(
+                    "kubectl scale deployment {} -n argocd --replicas=0",
+                    deployment
+                ),
+                None,
+            )
+            .await;
         }
-        Err(_e) => {
-            info!("📂 Folder '{}' doesn't exist. Installing Argo CD Helm Chart with default configuration", CONFIG_PATH);
-            (None, None)
+    }
+
+    let data = cm_data(options);
+    if !data.is_empty() {
+        // the settings contain spaces, so the patch is passed as a single argument
+        let patch = serde_json::json!({ "data": data }).to_string();
+        let command = vec![
+            "kubectl",
+            "patch",
+            "configmap",
+            "argocd-cm",
+            "-n",
+            "argocd",
+            "--type",
+            "merge",
+            "-p",
+            &patch,
+        ];
+        if let Err(e) = run_command_from_list(command, None).await {
+            error!(
+                "⚠️ Failed to set the Kustomize build options and resource customizations: {}",
+                String::from_utf8_lossy(&e.stderr)
+            );
         }
-    };
+    }
 
-    // create namespace argocd
-    match run_command("kubectl create ns argocd", None).await {
-        Ok(_) => (),
-        Err(e) => {
-            error!("❌ Failed to create namespace argocd");
-            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+    let tuning = &options.tuning;
+    let patch = format!(
+        r#"{{"data":{{"reposerver.parallelism.limit":"{}","controller.status.processors":"{}"}}}}"#,
+        tuning.repo_server_parallelism_limit, tuning.controller_status_processors
+    );
+    let commands = [
+        format!(
+            "kubectl patch configmap argocd-cmd-params-cm -n argocd --type merge -p {}",
+            patch
+        ),
+        format!(
+            "kubectl scale deployment argocd-repo-server -n argocd --replicas={}",
+            tuning.repo_server_replicas
+        ),
+        // the components read the parameters on startup
+        "kubectl rollout restart deployment/argocd-repo-server statefulset/argocd-application-controller -n argocd".to_string(),
+    ];
+    for command in commands {
+        if let Err(e) = run_command(&command, None).await {
+            error!(
+                "⚠️ Failed to tune Argo CD with '{}': {}",
+                command,
+                String::from_utf8_lossy(&e.stderr)
+            );
         }
     }
+}
 
+async fn install_with_helm(
+    options: &ArgoCDOptions<'_>,
+    generated_values: &str,
+    values: &Option<String>,
+    values_override: &Option<String>,
+) -> Result<(), String> {
     // add argo repo to helm
-    match run_command(
-        "helm repo add argo https://argoproj.github.io/argo-helm",
+    run_command(
+        "helm repo add argo https://argoproj.github.io/argo-helm --force-update",
         None,
     )
     .await
-    {
-        Ok(_) => (),
-        Err(e) => {
-            error!("❌ Failed to add argo repo");
-            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
-        }
-    }
+    .map_err(|e| String::from_utf8_lossy(&e.stderr).to_string())?;
 
     info!("🦑 Installing Argo CD Helm Chart");
 
     let helm_install_command = format!(
-        "helm install argocd argo/argo-cd -n argocd {} {} {}",
-        values.unwrap_or_default(),
-        values_override.unwrap_or_default(),
+        "helm install argocd argo/argo-cd -n argocd -f {} {} {} {}",
+        generated_values,
+        values.clone().unwrap_or_default(),
+        values_override.clone().unwrap_or_default(),
         options
             .version
             .map(|a| format!("--version {}", a))
             .unwrap_or_default(),
     );
 
-    match run_command(&helm_install_command, None).await {
-        Ok(_) => (),
-        Err(e) => {
-            error!("❌ Failed to install Argo CD");
-            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
-        }
-    }
+    run_command(&helm_install_command, None)
+        .await
+        .map_err(|e| String::from_utf8_lossy(&e.stderr).to_string())?;
 
-    info!("🦑 Waiting for Argo CD to start...");
+    Ok(())
+}
+
+// Without argocd-server, the CLI runs the API locally and talks to the cluster directly
+async fn wait_and_use_core_mode() {
+    run_command(
+        "kubectl wait --for=condition=available deployment/argocd-repo-server -n argocd --timeout=300s",
+        None,
+    )
+    .await
+    .expect("failed to wait for argocd-repo-server");
 
+    info!("🦑 Argo CD is now available");
+
+    // core mode reads the Argo CD namespace from the current context. The CLI gets a copy of the
+    // kubeconfig with the namespace set, so the context of the user is left as it is
+    let kubeconfig = workdir::path("argocd-kubeconfig");
+    let config = run_command("kubectl config view --minify --flatten", None)
+        .await
+        .expect("failed to read kubeconfig");
+    std::fs::write(&kubeconfig, &config.stdout).expect("failed to write kubeconfig");
+    let kubeconfig = kubeconfig.to_string_lossy();
+    run_command_from_list(
+        vec![
+            "kubectl",
+            "config",
+            "set-context",
+            "--current",
+            "--namespace=argocd",
+            "--kubeconfig",
+            &kubeconfig,
+        ],
+        None,
+    )
+    .await
+    .expect("failed to set namespace of the kubeconfig of the argocd CLI");
+
+    set_argocd_opts("--core", Some(&kubeconfig));
+}
+
+async fn wait_and_login() {
     // wait for argocd-server to be ready
     run_command(
         "kubectl wait --for=condition=available deployment/argocd-server -n argocd --timeout=300s",
@@ -103,7 +315,7 @@ pub async fn install_argo_cd(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn E
         let mut password_encoded: Option<Output> = None;
         let mut counter = 0;
         while password_encoded.is_none() {
-            password_encoded = match run_command(&command, None).await {
+            password_encoded = match run_command(command, None).await {
                 Ok(a) => Some(a),
                 Err(e) => {
                     if counter == 5 {
@@ -121,10 +333,7 @@ pub async fn install_argo_cd(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn E
         let password_decoded = BASE64_STANDARD
             .decode(password_encoded)
             .expect("failed to decode password");
-        let password =
-            String::from_utf8(password_decoded).expect("failed to convert password to string");
-
-        password
+        String::from_utf8(password_decoded).expect("failed to convert password to string")
     };
 
     // sleep for 5 seconds
@@ -147,10 +356,159 @@ pub async fn install_argo_cd(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn E
     )
     .await
     .expect("failed to login to argocd");
+}
+
+// The install manifests of the Argo CD version of a Helm Chart version, or of the stable release when
+// no version is requested. None when the Argo CD version of the chart can't be looked up
+async fn default_fallback_manifests(chart_version: Option<&str>) -> Option<String> {
+    let Some(chart_version) = chart_version else {
+        return Some(STABLE_MANIFESTS.to_string());
+    };
+    let command = format!("helm show chart argo/argo-cd --version {}", chart_version);
+    let output = run_command(&command, None).await.ok()?;
+    let chart: serde_yaml::Value = serde_yaml::from_slice(&output.stdout).ok()?;
+    let app_version = chart["appVersion"].as_str()?.trim_start_matches('v');
+    debug!(
+        "Helm Chart version {} installs Argo CD {}",
+        chart_version, app_version
+    );
+    Some(format!(
+        "https://raw.githubusercontent.com/argoproj/argo-cd/v{}/manifests/install.yaml",
+        app_version
+    ))
+}
+
+// Waits for Argo CD to start and sets up the CLI to talk to it
+pub async fn connect(profile: &ArgoCDProfile) {
+    info!("🦑 Waiting for Argo CD to start...");
+
+    match profile {
+        ArgoCDProfile::Minimal => wait_and_use_core_mode().await,
+        ArgoCDProfile::Full => wait_and_login().await,
+    }
 
     run_command("argocd app list", None)
         .await
         .expect("Failed to run: argocd app list");
+}
+
+pub async fn install_argo_cd(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn Error>> {
+    info!(
+        "🦑 Installing Argo CD Helm Chart version: '{}' with profile: {:?}",
+        options.version.unwrap_or("latest"),
+        options.profile
+    );
+
+    let generated_values = workdir::path("argocd-values.yaml");
+    std::fs::write(&generated_values, values_from_options(&options))?;
+    let generated_values = generated_values.to_str().unwrap();
+
+    let (values, values_override) = match std::fs::read_dir(CONFIG_PATH) {
+        Ok(dir) => {
+            debug!("📂 Files in folder 'argocd-config':");
+            for file in dir {
+                debug!("- 📄 {:?}", file.unwrap().file_name());
+            }
+            let values_exist = std::fs::metadata(format!("{}/values.yaml", CONFIG_PATH))
+                .is_ok()
+                .then_some(format!("-f {}/values.yaml", CONFIG_PATH));
+            let values_override_exist =
+                std::fs::metadata(format!("{}/values-override.yaml", CONFIG_PATH))
+                    .is_ok()
+                    .then_some(format!("-f {}/values-override.yaml", CONFIG_PATH));
+            (values_exist, values_override_exist)
+        }
+        Err(_e) => {
+            info!("📂 Folder '{}' doesn't exist. Installing Argo CD Helm Chart with default configuration", CONFIG_PATH);
+            (None, None)
+        }
+    };
+
+    // create namespace argocd
+    match run_command("kubectl create ns argocd", None).await {
+        Ok(_) => (),
+        Err(e) => {
+            error!("❌ Failed to create namespace argocd");
+            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+        }
+    }
+
+    let mut installed = false;
+    for attempt in 1..=HELM_INSTALL_ATTEMPTS {
+        match install_with_helm(&options, generated_values, &values, &values_override).await {
+            Ok(_) => {
+                installed = true;
+                break;
+            }
+            Err(e) => {
+                error!(
+                    "⚠️ Failed to install Argo CD Helm Chart (attempt {}/{}): {}",
+                    attempt, HELM_INSTALL_ATTEMPTS, e
+                );
+                let _ = run_command("helm uninstall argocd -n argocd", None).await;
+                if attempt < HELM_INSTALL_ATTEMPTS {
+                    tokio::time::sleep(tokio::time::Duration::from_secs(10 * attempt)).await;
+                }
+            }
+        }
+    }
+
+    // fall back to plain manifests, e.g. when the Helm repository is rate limited
+    let fallback_manifests = match (&options.fallback_manifests, installed) {
+        (_, true) => vec![],
+        (Some(manifests), false) => manifests.iter().map(|m| m.to_string()).collect(),
+        (None, false) => match default_fallback_manifests(options.version).await {
+            Some(manifests) => vec![manifests],
+            None => {
+                error!(
+                    "❌ Failed to look up the Argo CD version of Helm Chart version {}, so it can't be installed from manifests. Set --argocd-fallback-manifests to the manifests of that version",
+                    options.version.unwrap_or_default()
+                );
+                panic!("error: the requested Argo CD version can't be installed")
+            }
+        },
+    };
+    for source in &fallback_manifests {
+        if installed {
+            break;
+        }
+        info!("🦑 Installing Argo CD from manifests: {}", source);
+        if values.is_some() || values_override.is_some() {
+            info!(
+                "⚠️ Values in '{}' are not applied when installing from manifests",
+                CONFIG_PATH
+            );
+        }
+        if let Some(image) = options.image {
+            info!(
+                "⚠️ Image {} is not used when installing from manifests",
+                image
+            );
+        }
+        match run_command(
+            &format!("kubectl apply -n argocd --server-side -f {}", source),
+            None,
+        )
+        .await
+        {
+            Ok(_) => {
+                installed = true;
+                adjust_manifest_installation(&options).await;
+            }
+            Err(e) => error!(
+                "⚠️ Failed to install Argo CD from manifests: {} with error: {}",
+                source,
+                String::from_utf8_lossy(&e.stderr)
+            ),
+        }
+    }
+
+    if !installed {
+        error!("❌ Failed to install Argo CD");
+        panic!("error: all Argo CD installation sources failed")
+    }
+
+    connect(&options.profile).await;
 
     if options.debug {
         let command = "kubectl get configmap -n argocd -o yaml argocd-cmd-params-cm argocd-cm";
@@ -170,3 +528,215 @@ pub async fn install_argo_cd(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn E
     info!("🦑 Argo CD installed successfully");
     Ok(())
 }
+
+// Runs the Argo CD components as local processes of the multi-call 'argocd' binary, which picks the
+// component from ARGOCD_BINARY_NAME. The CRDs match the version of the binary. The components listen
+// on free ports of localhost, and the CLI logs in to the API server with the initial admin password
+pub async fn run_argo_cd_locally(options: ArgoCDOptions<'_>) -> Result<(), Box<dyn Error>> {
+    let version = match run_command("argocd version --client --short", None).await {
+        // e.g. 'argocd: v2.10.4+f5d63a5'
+        Ok(o) => String::from_utf8_lossy(&o.stdout)
+            .rsplit(':')
+            .next()
+            .and_then(|v| v.trim().split('+').next())
+            .unwrap_or_default()
+            .to_string(),
+        Err(e) => {
+            error!("❌ Failed to get the version of the argocd binary");
+            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+        }
+    };
+    info!("🦑 Starting Argo CD {} as local processes", version);
+
+    if options.version.is_some() || std::fs::metadata(CONFIG_PATH).is_ok() {
+        info!(
+            "⚠️ The Helm Chart version and values in '{}' are not used when running Argo CD as local processes",
+            CONFIG_PATH
+        );
+    }
+
+    let settings = workdir::path("argocd-settings.yaml");
+    let mut local_settings = parse_manifest(LOCAL_SETTINGS);
+    for (key, value) in cm_data(&options) {
+        local_settings[0]["data"][key.as_str()] = value.into();
+    }
+    let local_settings = local_settings
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<_>, _>>()?
+        .join("---\n");
+    std::fs::write(&settings, local_settings)?;
+    let commands = [
+        "kubectl create ns argocd".to_string(),
+        format!(
+            "kubectl apply --server-side -k https://github.com/argoproj/argo-cd/manifests/crds?ref={}",
+            version
+        ),
+        format!("kubectl apply -n argocd -f {}", settings.display()),
+    ];
+    for command in commands {
+        if let Err(e) = run_command(&command, None).await {
+            error!("❌ Failed to run: {}", command);
+            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+        }
+    }
+
+    let tuning = &options.tuning;
+    let parallelism_limit = tuning.repo_server_parallelism_limit.to_string();
+    let status_processors = tuning.controller_status_processors.to_string();
+    // every component listens on a free port of localhost, so several runs can share a machine
+    let local = |port: &String| format!("127.0.0.1:{}", port);
+    let [redis_port, repo_server_port, server_port, other_ports @ ..] = envtest::free_ports::<9>()?;
+    let [repo_server_metrics, controller_metrics, server_metrics, appset_ports @ ..] = other_ports;
+    let [appset_metrics, appset_probe, appset_webhook] = appset_ports.map(|p| local(&p));
+    let (redis, repo_server, server) = (
+        local(&redis_port),
+        local(&repo_server_port),
+        local(&server_port),
+    );
+    let components: [(&str, Vec<&str>); 4] = [
+        (
+            "argocd-repo-server",
+            vec![
+                "--address",
+                "127.0.0.1",
+                "--port",
+                &repo_server_port,
+                "--metrics-address",
+                "127.0.0.1",
+                "--metrics-port",
+                &repo_server_metrics,
+                "--disable-tls",
+                "--redis",
+                &redis,
+                "--parallelismlimit",
+                &parallelism_limit,
+            ],
+        ),
+        (
+            "argocd-application-controller",
+            vec![
+                "--namespace",
+                "argocd",
+                "--repo-server",
+                &repo_server,
+                "--repo-server-plaintext",
+                "--redis",
+                &redis,
+                "--metrics-port",
+                &controller_metrics,
+                "--status-processors",
+                &status_processors,
+            ],
+        ),
+        (
+            "argocd-applicationset-controller",
+            vec![
+                "--namespace",
+                "argocd",
+                "--argocd-repo-server",
+                &repo_server,
+                "--repo-server-plaintext",
+                "--metrics-addr",
+                &appset_metrics,
+                "--probe-addr",
+                &appset_probe,
+                "--webhook-addr",
+                &appset_webhook,
+            ],
+        ),
+        (
+            "argocd-server",
+            vec![
+                "--namespace",
+                "argocd",
+                "--insecure",
+                "--address",
+                "127.0.0.1",
+                "--port",
+                &server_port,
+                "--metrics-address",
+                "127.0.0.1",
+                "--metrics-port",
+                &server_metrics,
+                "--repo-server",
+                &repo_server,
+                "--repo-server-plaintext",
+                "--redis",
+                &redis,
+            ],
+        ),
+    ];
+
+    envtest::start_process(
+        "redis",
+        "redis-server",
+        &[
+            "--bind",
+            "127.0.0.1",
+            "--port",
+            &redis_port,
+            "--save",
+            "",
+            "--appendonly",
+            "no",
+        ],
+        &[],
+    )?;
+    for (component, args) in &components {
+        envtest::start_process(
+            component,
+            "argocd",
+            args,
+            &[("ARGOCD_BINARY_NAME", component)],
+        )?;
+    }
+
+    // the CLI gets its own config file, so the config of the user is left as it is
+    let config = workdir::path("argocd-cli-config")
+        .to_string_lossy()
+        .to_string();
+    set_argocd_opts(&format!("--config {}", config), None);
+
+    // the API server creates the initial admin password once it is up
+    info!("🦑 Waiting for Argo CD to start...");
+    for attempt in 1..=60 {
+        let login = async {
+            let secret = run_command(
+                "kubectl -n argocd get secret argocd-initial-admin-secret -o jsonpath={.data.password}",
+                None,
+            )
+            .await?;
+            let password = BASE64_STANDARD.decode(&secret.stdout).unwrap_or_default();
+            run_command_from_list(
+                vec![
+                    "argocd",
+                    "login",
+                    &server,
+                    "--plaintext",
+                    "--username",
+                    "admin",
+                    "--password",
+                    &String::from_utf8_lossy(&password),
+                ],
+                None,
+            )
+            .await
+        };
+        match login.await {
+            Ok(_) => break,
+            Err(e) if attempt == 60 => {
+                error!("❌ Argo CD did not start");
+                panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+            }
+            Err(_) => tokio::time::sleep(tokio::time::Duration::from_secs(2)).await,
+        }
+    }
+    set_argocd_opts(
+        &format!("--server {} --plaintext --config {}", server, config),
+        None,
+    );
+
+    info!("🦑 Argo CD is now available");
+    Ok(())
+}
@@ -0,0 +1,247 @@
+// The resources of a multi-source application come from one of its sources, e.g. a chart from a Helm
+// repository, with its values from another repository. 'argocd app manifests' returns the resources
+// of all sources in one list, so the tool attributes them: a resource comes from the only source
+// that renders resources, or from the chart named in its 'helm.sh/chart' label. Sources that only
+// provide value files ('ref') render nothing, and sources that render the same repository path or
+// chart twice are the same source. The hunks of the diff name the source of their resources
+use crate::manifests::{parse_manifest, resource_id, ResourceId};
+use crate::model::{ApplicationSource, ApplicationSpec};
+use crate::repo_url::RepoUrl;
+use crate::Branch;
+use regex::Regex;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::fs;
+
+// A source that renders resources, with the label it is shown with
+struct RenderingSource {
+    chart: Option<String>,
+    // last segment of the path of a Git source, which is the name of a chart in the repository
+    directory: Option<String>,
+    label: String,
+}
+
+// The name of a source, or else its chart or repository path
+fn describe(source: &ApplicationSource) -> String {
+    if let Some(name) = &source.name {
+        return name.clone();
+    }
+    let repo = RepoUrl::parse(source.repo_url.as_deref().unwrap_or_default());
+    match (&source.chart, &source.path, &source.reference) {
+        (Some(chart), _, _) => format!("chart {}", chart),
+        (None, Some(path), _) => format!("{}/{}", repo.path(), path.trim_matches('/')),
+        (None, None, Some(reference)) => format!("${} ({})", reference, repo.path()),
+        (None, None, None) => repo.path().to_string(),
+    }
+}
+
+// The sources of a multi-source application that render resources, without duplicates. A source
+// with value files of other sources ('$values/...') is labeled with them
+fn rendering_sources(spec: &ApplicationSpec) -> Vec<RenderingSource> {
+    let sources = spec.sources.as_deref().unwrap_or_default();
+    let references: BTreeMap<&str, &ApplicationSource> = sources
+        .iter()
+        .filter_map(|s| Some((s.reference.as_deref()?, s)))
+        .collect();
+
+    let mut seen = vec![];
+    let mut rendering = vec![];
+    for source in sources
+        .iter()
+        .filter(|s| s.chart.is_some() || s.path.is_some())
+    {
+        let repo = RepoUrl::parse(source.repo_url.as_deref().unwrap_or_default());
+        let key = (
+            repo.path().to_string(),
+            source.path.as_deref().unwrap_or_default().trim_matches('/'),
+            source.chart.as_deref(),
+        );
+        if seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+
+        let mut value_sources: Vec<String> = source
+            .value_files()
+            .iter()
+            .filter_map(|f| references.get(f.strip_prefix('$')?.split('/').next()?))
+            .map(|s| describe(s))
+            .collect();
+        value_sources.dedup();
+        let label = match value_sources.is_empty() {
+            true => describe(source),
+            false => format!(
+                "{} with values from {}",
+                describe(source),
+                value_sources.join(", ")
+            ),
+        };
+        rendering.push(RenderingSource {
+            chart: source.chart.clone(),
+            directory: source
+                .path
+                .as_deref()
+                .and_then(|p| p.trim_matches('/').rsplit('/').next())
+                .filter(|d| !d.is_empty())
+                .map(|d| d.to_string()),
+            label,
+        });
+    }
+    rendering
+}
+
+// The source of a resource, when it can be known
+fn source_of<'a>(resource: &Value, sources: &'a [RenderingSource]) -> Option<&'a str> {
+    if let [source] = sources {
+        return Some(&source.label);
+    }
+    // 'helm.sh/chart' is '<chart>-<version>'
+    let chart = resource["metadata"]["labels"]["helm.sh/chart"].as_str()?;
+    let mut matching = sources.iter().filter(|s| {
+        [&s.chart, &s.directory].into_iter().flatten().any(|name| {
+            chart
+                .strip_prefix(name.as_str())
+                .is_some_and(|version| version.starts_with('-'))
+        })
+    });
+    match (matching.next(), matching.next()) {
+        (Some(source), None) => Some(&source.label),
+        _ => None,
+    }
+}
+
+// The source of each resource of a multi-source application. Empty for other applications
+pub fn attribute(application: &Value, manifest: &str) -> BTreeMap<ResourceId, String> {
+    let Ok(spec) = ApplicationSpec::of(application) else {
+        return BTreeMap::new();
+    };
+    if spec.sources.as_ref().is_none_or(|s| s.len() < 2) {
+        return BTreeMap::new();
+    }
+    let sources = rendering_sources(&spec);
+    parse_manifest(manifest)
+        .iter()
+        .filter_map(|r| Some((resource_id(r), source_of(r, &sources)?.to_string())))
+        .collect()
+}
+
+// The resource of each line of a manifest, starting at line 1
+fn line_resources(manifest: &str) -> Vec<Option<ResourceId>> {
+    let mut lines = vec![None];
+    let mut document: Vec<&str> = vec![];
+    let flush = |document: &mut Vec<&str>, lines: &mut Vec<Option<ResourceId>>| {
+        let id = serde_yaml::from_str::<Value>(&document.join("\n"))
+            .ok()
+            .filter(|r| r["kind"].as_str().is_some())
+            .map(|r| resource_id(&r));
+        lines.extend(std::iter::repeat_n(id, document.len()));
+        document.clear();
+    };
+    for line in manifest.lines() {
+        match line {
+            "---" => {
+                flush(&mut document, &mut lines);
+                lines.push(None);
+            }
+            line => document.push(line),
+        }
+    }
+    flush(&mut document, &mut lines);
+    lines
+}
+
+// Appends the sources of the changed lines of each hunk of a unified diff to its header, e.g.
+// '@@ -12,7 +12,7 @@ 📦 chart redis'. 'base' and 'target' are the manifests that were diffed
+pub fn annotate_hunks(
+    diff: &str,
+    base: &str,
+    target: &str,
+    sources: &BTreeMap<ResourceId, String>,
+) -> String {
+    let header = Regex::new(r"^@@ -(\d+)(?:,\d+)? \+(\d+)(?:,\d+)? @@").unwrap();
+    let (before, after) = (line_resources(base), line_resources(target));
+    let source = |lines: &[Option<ResourceId>], line: usize| {
+        lines
+            .get(line)
+            .cloned()
+            .flatten()
+            .and_then(|id| sources.get(&id))
+    };
+
+    let mut out: Vec<String> = vec![];
+    // the index of the header of the current hunk in 'out', and the sources of its changed lines
+    let mut hunk: Option<(usize, Vec<&String>)> = None;
+    let (mut old, mut new) = (0, 0);
+    let finish = |out: &mut Vec<String>, hunk: Option<(usize, Vec<&String>)>| {
+        if let Some((index, labels)) = hunk.filter(|(_, labels)| !labels.is_empty()) {
+            let start = header
+                .find(&out[index])
+                .map(|m| m.end())
+                .unwrap_or_default();
+            let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+            out[index] = format!("{} 📦 {}", &out[index][..start], labels.join(", "));
+        }
+    };
+    for line in diff.lines() {
+        if let Some(captures) = header.captures(line) {
+            finish(&mut out, hunk.take());
+            old = captures[1].parse().unwrap_or_default();
+            new = captures[2].parse().unwrap_or_default();
+            hunk = Some((out.len(), vec![]));
+        } else if let Some((_, labels)) = hunk.as_mut() {
+            let label = match line.chars().next() {
+                Some('-') => {
+                    old += 1;
+                    source(&before, old - 1)
+                }
+                Some('+') => {
+                    new += 1;
+                    source(&after, new - 1)
+                }
+                Some('\\') => None,
+                _ => {
+                    old += 1;
+                    new += 1;
+                    None
+                }
+            };
+            if let Some(label) = label.filter(|l| !labels.contains(l)) {
+                labels.push(label);
+            }
+        }
+        out.push(line.to_string());
+    }
+    finish(&mut out, hunk);
+    match diff.ends_with('\n') {
+        true => out.join("\n") + "\n",
+        false => out.join("\n"),
+    }
+}
+
+// The source of each resource of a multi-source application, from its manifests of both branches.
+// 'applications' are the rendered Applications of the base and the target branch, and 'names' the
+// names of the application in them
+pub fn application_sources(
+    output_folder: &str,
+    applications: &[BTreeMap<String, Value>; 2],
+    names: [&str; 2],
+) -> BTreeMap<ResourceId, String> {
+    let mut sources = BTreeMap::new();
+    for ((branch, applications), name) in [Branch::Base, Branch::Target]
+        .iter()
+        .zip(applications)
+        .zip(names)
+    {
+        // only the manifests of multi-source applications are read
+        let multi_source = |application: &&Value| {
+            ApplicationSpec::of(application)
+                .is_ok_and(|spec| spec.sources.is_some_and(|s| s.len() >= 2))
+        };
+        if let Some(application) = applications.get(name).filter(multi_source) {
+            let manifest = fs::read_to_string(format!("{}/{}/{}", output_folder, branch, name));
+            // the target branch wins for resources in both branches
+            sources.extend(attribute(application, &manifest.unwrap_or_default()));
+        }
+    }
+    sources
+}
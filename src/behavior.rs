@@ -0,0 +1,111 @@
+use crate::diff::ReportSection;
+use crate::manifests::{flatten, resource_id, ResourceId};
+use crate::parsing::get_resources_of_kinds;
+use log::info;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+
+const BEHAVIOR_KINDS: [&str; 3] = ["Application", "ApplicationSet", "AppProject"];
+
+// Settings that change how Argo CD syncs and compares resources. They are removed or have no effect
+// when rendering, so they never show up in the diff of the rendered manifests
+fn behavior_fields(resource: &serde_yaml::Value) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    let spec = &resource["spec"];
+    match resource["kind"].as_str() {
+        Some("Application") => {
+            flatten(&spec["ignoreDifferences"], "ignoreDifferences", &mut fields);
+            flatten(&spec["syncPolicy"], "syncPolicy", &mut fields);
+        }
+        Some("ApplicationSet") => {
+            flatten(
+                &spec["template"]["spec"]["ignoreDifferences"],
+                "template.ignoreDifferences",
+                &mut fields,
+            );
+            flatten(
+                &spec["template"]["spec"]["syncPolicy"],
+                "template.syncPolicy",
+                &mut fields,
+            );
+            flatten(&spec["syncPolicy"], "syncPolicy", &mut fields);
+            flatten(
+                &spec["ignoreApplicationDifferences"],
+                "ignoreApplicationDifferences",
+                &mut fields,
+            );
+        }
+        Some("AppProject") => {
+            flatten(&spec["orphanedResources"], "orphanedResources", &mut fields);
+        }
+        _ => (),
+    }
+    fields.retain(|_, v| v != "null");
+    fields
+}
+
+async fn read_resources(
+    folders: &[String],
+    regex: &Option<Regex>,
+) -> BTreeMap<ResourceId, serde_yaml::Value> {
+    let mut resources = BTreeMap::new();
+    for folder in folders {
+        for r in get_resources_of_kinds(folder, regex, &BEHAVIOR_KINDS).await {
+            resources.insert(resource_id(&r), r);
+        }
+    }
+    resources
+}
+
+fn escape_cell(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+// Compares the sync and comparison settings of the Applications, ApplicationSets and AppProjects in both branches
+pub async fn generate_behavior_section(
+    base_folders: &[String],
+    target_folders: &[String],
+    regex: &Option<Regex>,
+) -> Option<ReportSection> {
+    info!("⚙️ Looking for changes to sync options, ignored differences and orphaned resources");
+
+    let base = read_resources(base_folders, regex).await;
+    let target = read_resources(target_folders, regex).await;
+    let ids: BTreeSet<&ResourceId> = base.keys().chain(target.keys()).collect();
+
+    let mut rows = vec![];
+    for id in ids {
+        let base_fields = base.get(id).map(behavior_fields).unwrap_or_default();
+        let target_fields = target.get(id).map(behavior_fields).unwrap_or_default();
+        let paths: BTreeSet<&String> = base_fields.keys().chain(target_fields.keys()).collect();
+        for path in paths {
+            let (before, after) = (base_fields.get(path), target_fields.get(path));
+            if before != after {
+                rows.push(format!(
+                    "| {} | {} | {} | {} |",
+                    id,
+                    path,
+                    escape_cell(before.map(|v| v.as_str()).unwrap_or("-")),
+                    escape_cell(after.map(|v| v.as_str()).unwrap_or("-")),
+                ));
+            }
+        }
+    }
+
+    if rows.is_empty() {
+        info!("⚙️ No behavioral changes found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!("⚙️ Behavioral changes ({}):", rows.len()),
+        content: [
+            "| Resource | Field | Base | Target |".to_string(),
+            "|---|---|---|---|".to_string(),
+        ]
+        .into_iter()
+        .chain(rows)
+        .collect::<Vec<String>>()
+        .join("\n"),
+    })
+}
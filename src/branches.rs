@@ -0,0 +1,90 @@
+// Besides the base and the target branch, more branches can be rendered in the same run (e.g. a
+// release branch next to main and a hotfix branch), and compared pairwise. This shows whether
+// branches converge to the same rendered state
+use crate::diff::ReportSection;
+use crate::manifests::read_rendered_apps;
+use crate::matrix::compare_rendered_apps;
+use crate::Branch;
+use log::info;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+pub struct ExtraBranch {
+    // The branch sources are pointed at
+    pub name: String,
+    // Where the branch is checked out
+    pub folder: String,
+}
+
+// Parses 'BRANCH=FOLDER'
+pub fn parse(s: &str) -> Result<ExtraBranch, Box<dyn Error>> {
+    match s.split_once('=').map(|(b, f)| (b.trim(), f.trim())) {
+        Some((name, folder)) if !name.is_empty() && !folder.is_empty() => Ok(ExtraBranch {
+            name: name.to_string(),
+            folder: folder.trim_end_matches('/').to_string(),
+        }),
+        _ => Err(format!("invalid branch '{}'. Use BRANCH=FOLDER", s).into()),
+    }
+}
+
+// Folder with the manifests of an extra branch. They are rendered like the target branch, so they
+// end up in a 'target' folder in it
+pub fn rendered_folder(output_folder: &str, branch: &str) -> String {
+    format!("{}/branches/{}", output_folder, branch.replace('/', "-"))
+}
+
+// Compares every pair of rendered branches, except base and target, which are compared by the diff
+pub fn generate_branch_sections(
+    output_folder: &str,
+    base_name: &str,
+    target_name: &str,
+    extra_branches: &[ExtraBranch],
+) -> Vec<ReportSection> {
+    if extra_branches.is_empty() {
+        return vec![];
+    }
+    let mut branches: Vec<(&str, BTreeMap<String, String>)> = vec![
+        (base_name, read_rendered_apps(output_folder, &Branch::Base)),
+        (
+            target_name,
+            read_rendered_apps(output_folder, &Branch::Target),
+        ),
+    ];
+    for branch in extra_branches {
+        let folder = rendered_folder(output_folder, &branch.name);
+        branches.push((&branch.name, read_rendered_apps(&folder, &Branch::Target)));
+    }
+
+    let mut sections = vec![];
+    for (i, (name, apps)) in branches.iter().enumerate() {
+        for (other_name, other_apps) in branches.iter().skip((i + 1).max(2)) {
+            let lines = compare_rendered_apps(
+                apps,
+                other_apps,
+                (
+                    &format!("only rendered in {}", other_name),
+                    &format!("only rendered in {}", name),
+                ),
+            );
+            info!(
+                "🔀 {} differences between {} and {}",
+                lines.len(),
+                name,
+                other_name
+            );
+            sections.push(ReportSection {
+                title: format!(
+                    "🔀 {} ↔ {} ({} differences):",
+                    name,
+                    other_name,
+                    lines.len()
+                ),
+                content: match lines.is_empty() {
+                    true => "The branches render the same manifests.".to_string(),
+                    false => lines.join("\n"),
+                },
+            });
+        }
+    }
+    sections
+}
@@ -0,0 +1,214 @@
+// Every platform the report is published to has its own size limit, e.g. 65536 characters for a
+// GitHub comment. Instead of cutting the diff off at the limit, the report degrades step by step
+// until it fits: the full diff, the diff of as many applications as fit, the summary only, and
+// finally just a link to the full report
+use crate::diff::{print_diff, read_diff};
+use log::info;
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+
+// Size limits of the platforms with presets, in characters
+const PRESETS: [(&str, usize); 3] = [("github", 65536), ("gitlab", 1_000_000), ("slack", 40000)];
+
+#[derive(Clone)]
+pub struct Publisher {
+    pub name: String,
+    pub max_length: usize,
+}
+
+impl Publisher {
+    // Parses a preset ('github', 'gitlab' or 'slack') or 'NAME=LENGTH'
+    pub fn parse(s: &str) -> Result<Publisher, Box<dyn Error>> {
+        let (name, max_length) = match s.split_once('=') {
+            Some((name, length)) => (name.trim(), length.trim().parse()?),
+            None => match PRESETS.iter().find(|(name, _)| *name == s.trim()) {
+                Some((name, length)) => (*name, *length),
+                None => {
+                    return Err(format!(
+                        "unknown publisher '{}'. Use github, gitlab, slack or NAME=LENGTH",
+                        s
+                    )
+                    .into())
+                }
+            },
+        };
+        if name.is_empty() || name.contains(['/', '.']) {
+            return Err(format!("invalid publisher name '{}'", name).into());
+        }
+        Ok(Publisher {
+            name: name.to_string(),
+            max_length,
+        })
+    }
+}
+
+// Where the diff of one application is in 'diff.patch'
+pub struct DiffPart {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+// The parts of the report that are kept for as long as they fit
+pub struct ReportParts<'a> {
+    pub warnings: &'a str,
+    pub summary: &'a str,
+    pub sections: &'a str,
+    pub diff_path: &'a str,
+    pub diff_parts: &'a [DiffPart],
+    pub artifact_url: Option<&'a str>,
+}
+
+const DEGRADED_TEMPLATE: &str = r#"
+## Argo CD Diff Preview
+%warnings%
+Summary:
+```bash
+%summary%
+```
+%sections%
+%note%
+%applications%
+"#;
+
+fn print_degraded(warnings: &str, summary: &str, sections: &str, note: &str, apps: &str) -> String {
+    DEGRADED_TEMPLATE
+        .replace("%warnings%", warnings)
+        .replace("%summary%", summary)
+        .replace("%sections%", sections)
+        .replace("%note%", note)
+        .replace("%applications%", apps)
+        .trim_start()
+        .to_string()
+}
+
+fn read_part(diff_path: &str, part: &DiffPart) -> Result<String, Box<dyn Error>> {
+    let mut file = fs::File::open(diff_path)?;
+    file.seek(SeekFrom::Start(part.offset))?;
+    let mut bytes = vec![];
+    file.take(part.length).read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).trim_end().to_string())
+}
+
+// The diff of each application in a collapsed block, for as many applications as fit in
+// 'max_length'. The other applications are only listed
+fn application_blocks(
+    parts: &ReportParts,
+    max_length: usize,
+) -> Result<(String, usize), Box<dyn Error>> {
+    // the list of omitted applications needs room as well
+    let names_length: usize = parts.diff_parts.iter().map(|p| p.name.len() + 4).sum();
+    let mut blocks = String::new();
+    let mut omitted = vec![];
+    for part in parts.diff_parts {
+        // the applications are shown in order, so later ones are omitted once one doesn't fit
+        if omitted.is_empty() && (part.length as usize) < max_length.saturating_sub(blocks.len()) {
+            let block = format!(
+                "<details>\n<summary>{}</summary>\n<br>\n\n```diff\n{}\n```\n\n</details>\n",
+                part.name,
+                read_part(parts.diff_path, part)?
+            );
+            if blocks.len() + block.len() + names_length < max_length {
+                blocks.push_str(&block);
+                continue;
+            }
+        }
+        omitted.push(format!("`{}`", part.name));
+    }
+    let shown = parts.diff_parts.len() - omitted.len();
+    if !omitted.is_empty() {
+        blocks.push_str(&format!("\nNot shown: {}\n", omitted.join(", ")));
+    }
+    Ok((blocks, shown))
+}
+
+// Writes 'diff-<publisher>.md' with the most detailed report that fits the size limit of the
+// publisher
+pub fn write_report(
+    output_folder: &str,
+    publisher: &Publisher,
+    parts: &ReportParts,
+) -> Result<(), Box<dyn Error>> {
+    let max_length = publisher.max_length;
+    let full_report = match parts.artifact_url {
+        Some(url) => format!("the [full report]({})", url),
+        None => "the `diff.md` artifact of this run".to_string(),
+    };
+    let diff_length = fs::metadata(parts.diff_path)?.len() as usize;
+
+    // the diff is only read when it fits
+    let without_diff = print_diff(parts.warnings, parts.summary, parts.sections, "");
+    let full = match diff_length {
+        0 => Some(print_diff(
+            parts.warnings,
+            parts.summary,
+            parts.sections,
+            "No changes found",
+        )),
+        length if without_diff.len() + length <= max_length => {
+            let diff = read_diff(parts.diff_path, length)?;
+            Some(print_diff(
+                parts.warnings,
+                parts.summary,
+                parts.sections,
+                &diff,
+            ))
+        }
+        _ => None,
+    };
+
+    let per_application = || -> Result<Option<String>, Box<dyn Error>> {
+        let note = format!(
+            "⚠️ The diff is too long for {} ({} characters), so it is shown per application. The whole diff is in {}.\n",
+            publisher.name, max_length, full_report
+        );
+        let without_applications =
+            print_degraded(parts.warnings, parts.summary, parts.sections, &note, "");
+        let room = max_length.saturating_sub(without_applications.len());
+        let (blocks, shown) = application_blocks(parts, room)?;
+        Ok((shown > 0).then(|| {
+            print_degraded(
+                parts.warnings,
+                parts.summary,
+                parts.sections,
+                &note,
+                &blocks,
+            )
+        }))
+    };
+
+    let summary_only = print_degraded(
+        parts.warnings,
+        parts.summary,
+        "",
+        &format!(
+            "⚠️ The report is too long for {} ({} characters), so only the summary is shown. The diff is in {}.\n",
+            publisher.name, max_length, full_report
+        ),
+        "",
+    );
+
+    let (level, markdown) = match full.filter(|m| m.len() <= max_length) {
+        Some(full) => ("full diff", full),
+        None => match per_application()? {
+            Some(markdown) => ("diff per application", markdown),
+            None if summary_only.len() <= max_length => ("summary only", summary_only),
+            None => (
+                "link only",
+                format!(
+                    "## Argo CD Diff Preview\n\n⚠️ The report is too long for {} ({} characters). See {}.\n",
+                    publisher.name, max_length, full_report
+                ),
+            ),
+        },
+    };
+
+    let path = format!("{}/diff-{}.md", output_folder, publisher.name);
+    fs::write(&path, markdown)?;
+    info!(
+        "📏 Wrote the report for {} with the {} to {}",
+        publisher.name, level, path
+    );
+    Ok(())
+}
@@ -0,0 +1,113 @@
+// Helm charts can branch on the capabilities of the cluster (.Capabilities.KubeVersion and
+// .Capabilities.APIVersions), which are those of the preview cluster when rendering. Setting
+// 'helm.kubeVersion' and 'helm.apiVersions' on the sources makes them render as they would against
+// the production cluster
+use crate::config::read_config;
+use crate::model::{ApplicationSpec, HelmSource};
+use log::debug;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[derive(Clone, Default)]
+struct Capabilities {
+    kube_version: Option<String>,
+    api_versions: Option<Vec<String>>,
+}
+
+pub struct HelmCapabilities {
+    // Used for every application
+    default: Capabilities,
+    // Overrides of single applications, keyed by application name
+    applications: BTreeMap<String, Capabilities>,
+}
+
+fn read_capabilities(value: &serde_yaml::Value, key: &str) -> Result<Capabilities, Box<dyn Error>> {
+    let kube_version = match &value["kubeVersion"] {
+        serde_yaml::Value::Null => None,
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        // an unquoted version like 1.30 is parsed as the number 1.3
+        _ => {
+            return Err(format!(
+                "'{}kubeVersion' must be a string. Quote versions like \"1.30\"",
+                key
+            )
+            .into())
+        }
+    };
+    let api_versions = match &value["apiVersions"] {
+        serde_yaml::Value::Null => None,
+        serde_yaml::Value::Sequence(versions) => Some(
+            versions
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(|v| v.to_string())
+                        .ok_or_else(|| format!("'{}apiVersions' must be a list of strings", key))
+                })
+                .collect::<Result<Vec<String>, String>>()?,
+        ),
+        _ => return Err(format!("'{}apiVersions' must be a list of strings", key).into()),
+    };
+    Ok(Capabilities {
+        kube_version,
+        api_versions,
+    })
+}
+
+pub fn read_helm_capabilities(path: &str) -> Result<HelmCapabilities, Box<dyn Error>> {
+    let config: serde_yaml::Value = read_config(path)?;
+    debug!("Helm capabilities: {:?}", config);
+    let mut applications = BTreeMap::new();
+    for (name, application) in config["applications"].as_mapping().into_iter().flatten() {
+        let name = name.as_str().ok_or("application names must be strings")?;
+        let key = format!("applications.{}.", name);
+        applications.insert(name.to_string(), read_capabilities(application, &key)?);
+    }
+    Ok(HelmCapabilities {
+        default: read_capabilities(&config, "")?,
+        applications,
+    })
+}
+
+impl HelmCapabilities {
+    // Sets 'kubeVersion' and 'apiVersions' on the Helm sources of an application. Only sources with
+    // a chart or Helm parameters are Helm sources for sure: adding 'helm' to a directory source
+    // would turn a Kustomize or plain directory into a Helm source. Values the application already
+    // sets are kept, and the ones of the application win over the default ones
+    pub fn apply(&self, spec: &mut ApplicationSpec, application: &str) {
+        let overrides = self
+            .applications
+            .get(application)
+            .cloned()
+            .unwrap_or_default();
+        let kube_version = overrides.kube_version.or(self.default.kube_version.clone());
+        let api_versions = overrides.api_versions.or(self.default.api_versions.clone());
+        if kube_version.is_none() && api_versions.is_none() {
+            return;
+        }
+
+        for source in spec.sources_mut() {
+            if source.chart.is_none() && source.helm.is_none() {
+                continue;
+            }
+            let helm = source.helm.get_or_insert_with(HelmSource::default);
+            if let Some(version) = kube_version
+                .as_ref()
+                .filter(|_| helm.kube_version.is_none())
+            {
+                debug!(
+                    "Setting helm.kubeVersion of application {} to {}",
+                    application, version
+                );
+                helm.kube_version = Some(version.clone());
+            }
+            if let Some(versions) = api_versions
+                .as_ref()
+                .filter(|_| helm.api_versions.is_none())
+            {
+                debug!("Setting helm.apiVersions of application {}", application);
+                helm.api_versions = Some(versions.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::diff::ReportSection;
+use crate::manifests::{read_applications, read_rendered_apps};
+use crate::model::ApplicationSpec;
+use crate::repo_url::RepoUrl;
+use crate::Branch;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fs;
+
+// Locations GitHub looks for the CODEOWNERS file in, in order
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+pub struct CodeOwners {
+    rules: Vec<(Regex, Vec<String>)>,
+}
+
+// Converts a CODEOWNERS pattern (gitignore syntax) to a regex. A pattern matches a path and
+// everything below it. Patterns without a slash (other than a trailing one) match at any depth
+fn pattern_to_regex(pattern: &str) -> Option<Regex> {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // '**/' also matches no directory at all
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push_str("(?:/.*)?$");
+    Regex::new(&regex).ok()
+}
+
+impl CodeOwners {
+    // Reads the CODEOWNERS file of a branch folder
+    pub fn find(branch_folder: &str) -> Option<CodeOwners> {
+        let (location, content) = LOCATIONS.iter().find_map(|location| {
+            let content = fs::read_to_string(format!("{}/{}", branch_folder, location)).ok()?;
+            Some((location, content))
+        })?;
+        info!("👥 Reading code owners from {}", location);
+        Some(CodeOwners::parse(&content))
+    }
+
+    fn parse(content: &str) -> CodeOwners {
+        let rules = content
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?;
+                let regex = pattern_to_regex(pattern).or_else(|| {
+                    info!("⚠️ Ignoring invalid CODEOWNERS pattern: {}", pattern);
+                    None
+                })?;
+                Some((regex, parts.map(|o| o.to_string()).collect()))
+            })
+            .collect();
+        CodeOwners { rules }
+    }
+
+    // The last matching rule wins, like on GitHub. A rule without owners removes the owners
+    pub fn owners(&self, path: &str) -> &[String] {
+        let path = path.trim_start_matches("./").trim_matches('/');
+        self.rules
+            .iter()
+            .rev()
+            .find(|(regex, _)| regex.is_match(path))
+            .map(|(_, owners)| owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+// Paths in the repository an application renders from
+fn source_paths(application: &serde_yaml::Value, repo: &RepoUrl) -> Vec<String> {
+    let Ok(spec) = ApplicationSpec::of(application) else {
+        return vec![];
+    };
+    spec.sources()
+        .filter(|source| source.is_from(repo))
+        .filter_map(|source| source.path.clone())
+        .collect()
+}
+
+// Lists the owners of the source paths of every application with changes. With 'mention', the
+// owners are also mentioned, so only the owners of changed applications are notified
+pub fn generate_owners_section(
+    output_folder: &str,
+    code_owners: &CodeOwners,
+    repo: &str,
+    mention: bool,
+) -> Option<ReportSection> {
+    info!("👥 Looking up the owners of changed applications");
+    let repo = RepoUrl::parse(repo);
+
+    let base = read_rendered_apps(output_folder, &Branch::Base);
+    let target = read_rendered_apps(output_folder, &Branch::Target);
+    let changed: BTreeSet<&String> = base
+        .keys()
+        .chain(target.keys())
+        .filter(|name| base.get(*name) != target.get(*name))
+        .collect();
+
+    // deleted applications only exist in the base branch
+    let mut applications = read_applications(output_folder, &Branch::Base);
+    applications.extend(read_applications(output_folder, &Branch::Target));
+
+    let mut rows = vec![];
+    let mut all_owners = BTreeSet::new();
+    for name in changed {
+        let owners: BTreeSet<&String> = applications
+            .get(name)
+            .map(|a| source_paths(a, &repo))
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|path| code_owners.owners(path))
+            .collect();
+        debug!("Owners of {}: {:?}", name, owners);
+        let cell = match owners.is_empty() {
+            true => "-".to_string(),
+            false => owners
+                .iter()
+                .map(|o| format!("`{}`", o))
+                .collect::<Vec<String>>()
+                .join(", "),
+        };
+        rows.push(format!("| {} | {} |", name, cell));
+        all_owners.extend(owners);
+    }
+
+    if rows.is_empty() {
+        info!("👥 No changed applications found");
+        return None;
+    }
+
+    let mut content = format!("| Application | Owners |\n|---|---|\n{}", rows.join("\n"));
+    if mention && !all_owners.is_empty() {
+        content.push_str(&format!(
+            "\n\ncc {}",
+            all_owners
+                .iter()
+                .map(|o| o.as_str())
+                .collect::<Vec<&str>>()
+                .join(" ")
+        ));
+    }
+
+    Some(ReportSection {
+        title: format!("👥 Owners of changed applications ({}):", rows.len()),
+        content,
+    })
+}
@@ -0,0 +1,117 @@
+// Argo CD reports why an application can't be rendered as a condition in its status, e.g. a
+// ComparisonError when 'helm template' fails. The conditions of every application are written to
+// 'conditions-<branch>.json'. An application of the target branch that fails with an error the base
+// branch doesn't have is broken by the change, and is listed in the report with the error
+use crate::diff::ReportSection;
+use crate::Branch;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AppCondition {
+    #[serde(rename = "type")]
+    pub condition_type: String,
+    pub message: String,
+}
+
+impl AppCondition {
+    // e.g. ComparisonError and InvalidSpecError. Warnings don't stop the rendering
+    pub fn is_error(&self) -> bool {
+        self.condition_type.to_lowercase().contains("error")
+    }
+}
+
+fn conditions_file(output_folder: &str, branch: &Branch) -> String {
+    format!("{}/conditions-{}.json", output_folder, branch)
+}
+
+// Conditions of an earlier run must not be reported
+pub fn remove_conditions(output_folder: &str) {
+    for branch in [Branch::Base, Branch::Target] {
+        fs::remove_file(conditions_file(output_folder, &branch)).unwrap_or_default();
+    }
+}
+
+// The conditions in the status of an Application
+pub fn conditions_of(application: &serde_yaml::Value) -> Vec<AppCondition> {
+    application["status"]["conditions"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| {
+            Some(AppCondition {
+                condition_type: c["type"].as_str()?.to_string(),
+                message: c["message"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+// Writes the conditions of the applications with at least one condition
+pub fn write_conditions(
+    output_folder: &str,
+    branch: &Branch,
+    conditions: &BTreeMap<String, Vec<AppCondition>>,
+) -> Result<(), Box<dyn Error>> {
+    let conditions: BTreeMap<&String, &Vec<AppCondition>> =
+        conditions.iter().filter(|(_, c)| !c.is_empty()).collect();
+    fs::write(
+        conditions_file(output_folder, branch),
+        serde_json::to_string_pretty(&conditions)?,
+    )?;
+    Ok(())
+}
+
+fn read_conditions(output_folder: &str, branch: &Branch) -> BTreeMap<String, Vec<AppCondition>> {
+    fs::read_to_string(conditions_file(output_folder, branch))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+// The error conditions of the applications of the target branch whose type the application
+// doesn't have in the base branch. Messages aren't compared, since they contain temporary paths
+pub fn broken_applications(output_folder: &str) -> BTreeMap<String, Vec<AppCondition>> {
+    let base = read_conditions(output_folder, &Branch::Base);
+    read_conditions(output_folder, &Branch::Target)
+        .into_iter()
+        .filter_map(|(name, conditions)| {
+            let before = base.get(&name);
+            let errors: Vec<AppCondition> = conditions
+                .into_iter()
+                .filter(|c| c.is_error())
+                .filter(|c| {
+                    !before.is_some_and(|b| b.iter().any(|b| b.condition_type == c.condition_type))
+                })
+                .collect();
+            (!errors.is_empty()).then_some((name, errors))
+        })
+        .collect()
+}
+
+// Lists the applications broken by the change, with the errors Argo CD reports for them
+pub fn generate_broken_section(output_folder: &str) -> Option<ReportSection> {
+    let broken = broken_applications(output_folder);
+    if broken.is_empty() {
+        return None;
+    }
+    let mut content = vec![];
+    for (name, errors) in &broken {
+        error!("❌ Application {} is broken by the change", name);
+        for e in errors {
+            content.push(format!(
+                "**{}** (`{}`)\n```\n{}\n```",
+                name,
+                e.condition_type,
+                e.message.trim()
+            ));
+        }
+    }
+    Some(ReportSection {
+        title: format!("💥 Applications broken by this change ({}):", broken.len()),
+        content: content.join("\n\n"),
+    })
+}
@@ -0,0 +1,168 @@
+// Config files (e.g. --verdict-rules or --project-repositories) can reference environment variables
+// as ${VAR}, so one file serves several pipelines and environments. Variables are replaced in the
+// strings of the parsed YAML, so a value can't change the structure of the file, and comments are
+// never interpolated. An undefined variable is an error instead of an empty string, since an empty
+// selector or repository URL would silently match something else. '$${VAR}' is the literal '${VAR}'
+use regex::{Captures, Regex};
+use serde_yaml::{Mapping, Value};
+use std::error::Error;
+use std::fs;
+
+// Replaces the variables in a string with their values from 'lookup'. Undefined variables are
+// added to 'undefined' with the path of the string
+fn interpolate_str(
+    s: &str,
+    path: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+    undefined: &mut Vec<String>,
+) -> String {
+    let variable = Regex::new(r"\$(\$?)\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    variable
+        .replace_all(s, |c: &Captures| {
+            if !c[1].is_empty() {
+                return format!("${{{}}}", &c[2]);
+            }
+            lookup(&c[2]).unwrap_or_else(|| {
+                undefined.push(format!("{} (at {})", &c[2], path));
+                String::new()
+            })
+        })
+        .to_string()
+}
+
+fn interpolate_value(
+    value: Value,
+    path: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+    undefined: &mut Vec<String>,
+) -> Value {
+    match value {
+        Value::String(s) => Value::String(interpolate_str(&s, path, lookup, undefined)),
+        Value::Sequence(items) => Value::Sequence(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| interpolate_value(v, &format!("{}[{}]", path, i), lookup, undefined))
+                .collect(),
+        ),
+        Value::Mapping(m) => {
+            let mut mapping = Mapping::new();
+            for (k, v) in m {
+                let key = match &k {
+                    Value::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other).unwrap_or_default(),
+                };
+                let path = match path.is_empty() {
+                    true => key.trim().to_string(),
+                    false => format!("{}.{}", path, key.trim()),
+                };
+                let k = interpolate_value(k, &path, lookup, undefined);
+                mapping.insert(k, interpolate_value(v, &path, lookup, undefined));
+            }
+            Value::Mapping(mapping)
+        }
+        Value::Tagged(mut tagged) => {
+            tagged.value = interpolate_value(tagged.value, path, lookup, undefined);
+            Value::Tagged(tagged)
+        }
+        other => other,
+    }
+}
+
+// Replaces the variables in the strings of a config with their values from 'lookup'. Fails with
+// all undefined variables and where they are at once, so they can be fixed in one go
+pub fn interpolate(
+    config: Value,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Value, String> {
+    let mut undefined = vec![];
+    let config = interpolate_value(config, "", &lookup, &mut undefined);
+    match undefined.is_empty() {
+        true => Ok(config),
+        false => Err(format!(
+            "undefined environment variables: {}",
+            undefined.join(", ")
+        )),
+    }
+}
+
+// Reads a YAML config file with the environment variables it references
+pub fn read_config(path: &str) -> Result<Value, Box<dyn Error>> {
+    let config: Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    interpolate(config, |name| std::env::var(name).ok())
+        .map_err(|e| format!("{} in {}", e, path).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interpolate;
+    use serde_yaml::Value;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "GIT_HOST" => Some("git.example.com".to_string()),
+            "TOKEN" => Some("a: b # not a comment".to_string()),
+            _ => None,
+        }
+    }
+
+    fn interpolated(config: &str) -> Result<Value, String> {
+        interpolate(serde_yaml::from_str(config).unwrap(), lookup)
+    }
+
+    #[test]
+    fn replaces_variables_in_strings() {
+        let config = interpolated(
+            "repositories:
+  - url: https://${GIT_HOST}/platform/charts.git
+    password: ${TOKEN}
+",
+        )
+        .unwrap();
+        let repository = &config["repositories"][0];
+        assert_eq!(
+            repository["url"].as_str(),
+            Some("https://git.example.com/platform/charts.git")
+        );
+        // a value can't change the structure of the file
+        assert_eq!(
+            repository["password"].as_str(),
+            Some("a: b # not a comment")
+        );
+    }
+
+    #[test]
+    fn ignores_variables_in_comments() {
+        let config = interpolated(
+            "# set ${UNDEFINED} in the pipeline
+host: ${GIT_HOST} # or ${ALSO_UNDEFINED}
+",
+        )
+        .unwrap();
+        assert_eq!(config["host"].as_str(), Some("git.example.com"));
+    }
+
+    #[test]
+    fn keeps_escaped_variables() {
+        let config = interpolated("template: $${GIT_HOST}\n").unwrap();
+        assert_eq!(config["template"].as_str(), Some("${GIT_HOST}"));
+    }
+
+    #[test]
+    fn lists_all_undefined_variables() {
+        let error = interpolated(
+            "protected:
+  - kind: Secret
+    names:
+      - ${FIRST}
+      - ok
+      - ${SECOND}
+",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "undefined environment variables: FIRST (at protected[0].names[0]), SECOND (at protected[0].names[2])"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+// An Application that moves into an ApplicationSet (or out of one) is the same application to Argo
+// CD, but the generated Application often gets another name, e.g. 'api' becomes 'api-prod'. Without
+// pairing them, the report shows every resource of the application as removed and added again.
+// Applications are paired by name, or else by their destination and sources, when exactly one
+// application of each branch has them
+use crate::diff::ReportSection;
+use crate::manifests::read_applications;
+use crate::repo_url::RepoUrl;
+use crate::Branch;
+use log::{debug, info};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+// Where an Application comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Application,
+    ApplicationSet(String),
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Origin::Application => write!(f, "Application"),
+            Origin::ApplicationSet(name) => write!(f, "ApplicationSet {}", name),
+        }
+    }
+}
+
+impl Origin {
+    fn of(application: &Value) -> Origin {
+        application["metadata"]["ownerReferences"]
+            .as_sequence()
+            .into_iter()
+            .flatten()
+            .find(|o| o["kind"].as_str() == Some("ApplicationSet"))
+            .and_then(|o| o["name"].as_str())
+            .map(|name| Origin::ApplicationSet(name.to_string()))
+            .unwrap_or(Origin::Application)
+    }
+
+    fn is_generated(&self) -> bool {
+        matches!(self, Origin::ApplicationSet(_))
+    }
+}
+
+// An application of the base branch that is generated differently in the target branch
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    pub base: String,
+    pub target: String,
+    pub from: Origin,
+    pub to: Origin,
+}
+
+impl std::fmt::Display for Conversion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.base == self.target {
+            true => write!(
+                f,
+                "{} converted from {} to {}",
+                self.base, self.from, self.to
+            ),
+            false => write!(
+                f,
+                "{} converted from {} to {} as {}",
+                self.base, self.from, self.to, self.target
+            ),
+        }
+    }
+}
+
+// The destination and the sources of an application, without the revisions, which differ between
+// the branches
+fn identity(application: &Value) -> String {
+    let spec = &application["spec"];
+    let destination = &spec["destination"];
+    let mut sources: Vec<String> = std::iter::once(&spec["source"])
+        .chain(spec["sources"].as_sequence().into_iter().flatten())
+        .filter(|s| s.is_mapping())
+        .map(|s| {
+            let url = RepoUrl::parse(s["repoURL"].as_str().unwrap_or_default());
+            format!(
+                "{}/{} {} {}",
+                url.host().unwrap_or_default(),
+                url.path(),
+                s["path"].as_str().unwrap_or_default(),
+                s["chart"].as_str().unwrap_or_default()
+            )
+        })
+        .collect();
+    sources.sort();
+    format!(
+        "{} {} {} {}",
+        destination["server"].as_str().unwrap_or_default(),
+        destination["name"].as_str().unwrap_or_default(),
+        destination["namespace"].as_str().unwrap_or_default(),
+        sources.join(", ")
+    )
+}
+
+// Applications that are converted between an Application and an ApplicationSet by the target branch
+pub fn find_conversions(output_folder: &str) -> Vec<Conversion> {
+    let base = read_applications(output_folder, &Branch::Base);
+    let target = read_applications(output_folder, &Branch::Target);
+    let converted = |from: &Origin, to: &Origin| from.is_generated() != to.is_generated();
+
+    let mut conversions = vec![];
+    for (name, application) in &base {
+        let Some(other) = target.get(name) else {
+            continue;
+        };
+        let (from, to) = (Origin::of(application), Origin::of(other));
+        if converted(&from, &to) {
+            conversions.push(Conversion {
+                base: name.clone(),
+                target: name.clone(),
+                from,
+                to,
+            });
+        }
+    }
+
+    // applications that are only in one of the branches, by their identity
+    let by_identity = |apps: &BTreeMap<String, Value>, others: &BTreeMap<String, Value>| {
+        let mut identities: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, application) in apps.iter().filter(|(name, _)| !others.contains_key(*name)) {
+            identities
+                .entry(identity(application))
+                .or_default()
+                .push(name.clone());
+        }
+        identities
+    };
+    let removed = by_identity(&base, &target);
+    let added = by_identity(&target, &base);
+    for (identity, names) in &removed {
+        let Some(others) = added.get(identity) else {
+            continue;
+        };
+        let ([base_name], [target_name]) = (names.as_slice(), others.as_slice()) else {
+            continue;
+        };
+        let (from, to) = (
+            Origin::of(&base[base_name]),
+            Origin::of(&target[target_name]),
+        );
+        if converted(&from, &to) {
+            debug!("Paired {} with {} by {}", base_name, target_name, identity);
+            conversions.push(Conversion {
+                base: base_name.clone(),
+                target: target_name.clone(),
+                from,
+                to,
+            });
+        }
+    }
+    conversions
+}
+
+// The base application a target application is diffed against, when it was renamed by a conversion
+pub fn renamed(conversions: &[Conversion]) -> BTreeMap<String, String> {
+    conversions
+        .iter()
+        .filter(|c| c.base != c.target)
+        .map(|c| (c.target.clone(), c.base.clone()))
+        .collect()
+}
+
+// Lists the converted applications, which are diffed against their application of the base branch
+pub fn generate_conversions_section(conversions: &[Conversion]) -> Option<ReportSection> {
+    if conversions.is_empty() {
+        return None;
+    }
+    info!("🔀 {} applications are converted", conversions.len());
+    let rows: Vec<String> = conversions
+        .iter()
+        .map(|c| format!("| {} | {} | {} | {} |", c.base, c.from, c.target, c.to))
+        .collect();
+    Some(ReportSection {
+        title: format!("🔀 Converted applications ({}):", conversions.len()),
+        content: format!(
+            "| Base application | From | Target application | To |\n|---|---|---|---|\n{}",
+            rows.join("\n")
+        ),
+    })
+}
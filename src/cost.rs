@@ -0,0 +1,156 @@
+use crate::config::read_config;
+use crate::diff::ReportSection;
+use crate::manifests::{parse_manifest, read_rendered_apps};
+use crate::resources::{format_cpu, format_memory, parse_memory, signed, workload_resources};
+use crate::Branch;
+use log::{debug, info};
+use std::collections::BTreeSet;
+use std::error::Error;
+
+// Hours in an average month, used to turn hourly prices into monthly estimates
+const HOURS_PER_MONTH: f64 = 730.0;
+
+pub struct Pricing {
+    currency: String,
+    cpu_core_hour: f64,
+    memory_gib_hour: f64,
+    node_cpu: Option<f64>,
+    node_memory: Option<f64>,
+}
+
+pub fn read_pricing_config(path: &str) -> Result<Pricing, Box<dyn Error>> {
+    let config: serde_yaml::Value = read_config(path)?;
+    debug!("Pricing config: {:?}", config);
+    Ok(Pricing {
+        currency: config["currency"].as_str().unwrap_or("$").to_string(),
+        cpu_core_hour: config["cpuCoreHour"]
+            .as_f64()
+            .ok_or("pricing config is missing 'cpuCoreHour'")?,
+        memory_gib_hour: config["memoryGiBHour"]
+            .as_f64()
+            .ok_or("pricing config is missing 'memoryGiBHour'")?,
+        node_cpu: config["nodeCpu"].as_f64(),
+        node_memory: config["nodeMemory"].as_str().and_then(parse_memory),
+    })
+}
+
+impl Pricing {
+    fn monthly_cost(&self, cpu: f64, memory: f64) -> f64 {
+        let gib = memory / (1024.0 * 1024.0 * 1024.0);
+        (cpu * self.cpu_core_hour + gib * self.memory_gib_hour) * HOURS_PER_MONTH
+    }
+
+    fn format_cost(&self, cost: f64) -> String {
+        match cost {
+            c if c < 0.0 => format!("-{}{:.2}", self.currency, -c),
+            c => format!("+{}{:.2}", self.currency, c),
+        }
+    }
+}
+
+// Sums the CPU and memory requests of all workloads in a manifest
+fn total_requests(manifest: Option<&String>) -> (f64, f64) {
+    manifest
+        .map(|m| parse_manifest(m))
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|r| workload_resources(r, "requests"))
+        .fold((0.0, 0.0), |(cpu, memory), (c, m)| (cpu + c, memory + m))
+}
+
+pub fn generate_cost_section(
+    output_folder: &str,
+    pricing: Option<&Pricing>,
+) -> Option<ReportSection> {
+    info!("💰 Calculating resource request deltas");
+
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let mut rows = vec![];
+    let (mut total_cpu, mut total_memory) = (0.0, 0.0);
+    for name in names {
+        let (base_cpu, base_memory) = total_requests(base_apps.get(name));
+        let (target_cpu, target_memory) = total_requests(target_apps.get(name));
+        let (delta_cpu, delta_memory) = (target_cpu - base_cpu, target_memory - base_memory);
+        if delta_cpu == 0.0 && delta_memory == 0.0 {
+            continue;
+        }
+        total_cpu += delta_cpu;
+        total_memory += delta_memory;
+
+        let mut row = format!(
+            "| {} | {} → {} | {} → {} | {} | {} |",
+            name,
+            format_cpu(base_cpu),
+            format_cpu(target_cpu),
+            format_memory(base_memory),
+            format_memory(target_memory),
+            signed(delta_cpu, format_cpu),
+            signed(delta_memory, format_memory),
+        );
+        if let Some(p) = pricing {
+            row.push_str(&format!(
+                " {} |",
+                p.format_cost(p.monthly_cost(delta_cpu, delta_memory))
+            ));
+        }
+        rows.push(row);
+    }
+
+    if rows.is_empty() {
+        info!("💰 No changes to resource requests found");
+        return None;
+    }
+
+    let mut header =
+        "| Application | CPU requests | Memory requests | Δ CPU | Δ Memory |".to_string();
+    let mut separator = "|---|---|---|---|---|".to_string();
+    let mut total = format!(
+        "| **Total** | | | **{}** | **{}** |",
+        signed(total_cpu, format_cpu),
+        signed(total_memory, format_memory)
+    );
+    if let Some(p) = pricing {
+        header.push_str(" Δ Monthly cost |");
+        separator.push_str("---|");
+        total.push_str(&format!(
+            " **{}** |",
+            p.format_cost(p.monthly_cost(total_cpu, total_memory))
+        ));
+    }
+
+    let mut content = [header, separator]
+        .into_iter()
+        .chain(rows)
+        .chain([total])
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    if let Some(p) = pricing {
+        let nodes = [
+            p.node_cpu.map(|c| total_cpu / c),
+            p.node_memory.map(|m| total_memory / m),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(None, |acc: Option<f64>, n| match acc {
+            Some(a) if a.abs() >= n.abs() => Some(a),
+            _ => Some(n),
+        });
+        if let Some(n) = nodes {
+            content.push_str(&format!("\n\nThis roughly corresponds to {:+.2} nodes.", n));
+        }
+        content.push_str("\n\n_Costs are rough estimates based on resource requests and the provided pricing config._");
+    }
+
+    Some(ReportSection {
+        title: format!(
+            "Resource request deltas (CPU {}, memory {}):",
+            signed(total_cpu, format_cpu),
+            signed(total_memory, format_memory)
+        ),
+        content,
+    })
+}
@@ -0,0 +1,133 @@
+// Argo CD validates rendered resources against the CRDs in the cluster, so charts and
+// kustomizations using custom resources of other controllers (Argo Rollouts, Istio, cert-manager,
+// ...) only render like in production when those CRDs are installed in the preview cluster too
+use crate::utils::{run_command, run_command_from_list};
+use log::{debug, info};
+use std::error::Error;
+use std::path::Path;
+
+// Controllers whose CRDs can be installed by name, with the default version and where the CRDs are
+// published. '{version}' is replaced by the version, and '{bare_version}' by the version without 'v'
+const CURATED: [(&str, &str, &str); 6] = [
+    (
+        "argo-rollouts",
+        "v1.7.2",
+        "https://github.com/argoproj/argo-rollouts/manifests/crds?ref={version}",
+    ),
+    (
+        "cert-manager",
+        "v1.15.3",
+        "https://github.com/cert-manager/cert-manager/releases/download/{version}/cert-manager.crds.yaml",
+    ),
+    (
+        "external-secrets",
+        "v0.10.3",
+        "https://raw.githubusercontent.com/external-secrets/external-secrets/{version}/deploy/crds/bundle.yaml",
+    ),
+    (
+        "istio",
+        "1.23.0",
+        "https://raw.githubusercontent.com/istio/istio/{version}/manifests/charts/base/crds/crd-all.gen.yaml",
+    ),
+    (
+        "keda",
+        "v2.15.1",
+        "https://github.com/kedacore/keda/releases/download/{version}/keda-{bare_version}-crds.yaml",
+    ),
+    (
+        "prometheus-operator",
+        "v0.76.0",
+        "https://github.com/prometheus-operator/prometheus-operator/releases/download/{version}/stripped-down-crds.yaml",
+    ),
+];
+
+#[derive(Debug)]
+pub enum CrdSource {
+    // Manifests, applied with 'kubectl apply -f'
+    Manifests(String),
+    // A kustomization, applied with 'kubectl apply -k'
+    Kustomization(String),
+}
+
+fn curated(name: &str, version: Option<&str>) -> Option<CrdSource> {
+    let (_, default_version, url) = CURATED.iter().find(|(n, _, _)| *n == name)?;
+    let version = version.unwrap_or(default_version);
+    let url = url
+        .replace("{version}", version)
+        .replace("{bare_version}", version.trim_start_matches('v'));
+    Some(match url.contains("?ref=") {
+        true => CrdSource::Kustomization(url),
+        false => CrdSource::Manifests(url),
+    })
+}
+
+// Parses a comma-separated list of curated controllers (optionally with a version, e.g.
+// 'cert-manager@v1.14.0'), URLs and local files or directories
+pub fn parse(s: &str) -> Result<Vec<CrdSource>, Box<dyn Error>> {
+    s.split(',')
+        .map(|e| e.trim())
+        .filter(|e| !e.is_empty())
+        .map(|entry| {
+            if entry.starts_with("https://") || entry.starts_with("http://") {
+                return Ok(CrdSource::Manifests(entry.to_string()));
+            }
+            let path = Path::new(entry);
+            if path.exists() {
+                return Ok(match path.join("kustomization.yaml").is_file() {
+                    true => CrdSource::Kustomization(entry.to_string()),
+                    false => CrdSource::Manifests(entry.to_string()),
+                });
+            }
+            let (name, version) = match entry.split_once('@') {
+                Some((name, version)) => (name, Some(version)),
+                None => (entry, None),
+            };
+            curated(name, version).ok_or_else(|| {
+                format!(
+                    "'{}' is not a URL, an existing path or one of: {}",
+                    entry,
+                    CURATED
+                        .iter()
+                        .map(|(n, _, _)| *n)
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                )
+                .into()
+            })
+        })
+        .collect()
+}
+
+// Installs the CRDs and waits until the API server serves them. Server-side apply is used, since
+// large CRDs don't fit in the last-applied-configuration annotation
+pub async fn install(sources: &[CrdSource]) -> Result<(), Box<dyn Error>> {
+    if sources.is_empty() {
+        return Ok(());
+    }
+    for source in sources {
+        let (flag, location) = match source {
+            CrdSource::Manifests(s) => ("-f", s),
+            CrdSource::Kustomization(s) => ("-k", s),
+        };
+        debug!("Installing CRDs from {}", location);
+        let command = vec!["kubectl", "apply", "--server-side", flag, location];
+        if let Err(e) = run_command_from_list(command, None).await {
+            return Err(format!(
+                "failed to install CRDs from {}: {}",
+                location,
+                String::from_utf8_lossy(&e.stderr)
+            )
+            .into());
+        }
+    }
+    let command = "kubectl wait --for condition=established --timeout=120s crd --all";
+    if let Err(e) = run_command(command, None).await {
+        return Err(format!(
+            "CRDs were not established: {}",
+            String::from_utf8_lossy(&e.stderr)
+        )
+        .into());
+    }
+    info!("🧩 Installed CRDs from {} sources", sources.len());
+    Ok(())
+}
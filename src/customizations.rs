@@ -0,0 +1,112 @@
+// Production Argo CD instances customize how resources are handled with 'resource.customizations'
+// keys in argocd-cm: health checks and actions in Lua, known type fields, and which differences are
+// ignored. The keys are passed to the Argo CD instance of the preview, and the fields their
+// 'ignoreDifferences' select are removed from the rendered manifests, so the diff shows what
+// 'argocd app diff' would show
+use crate::injected::InjectedFields;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+const PREFIX: &str = "resource.customizations";
+const IGNORE_DIFFERENCES_PREFIX: &str = "resource.customizations.ignoreDifferences.";
+
+// Reads the 'resource.customizations' keys of an argocd-cm ConfigMap, or of a file with just its
+// data. Other keys are left out
+pub fn read_resource_customizations(
+    path: &str,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let config: serde_yaml::Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let data = match config["kind"].as_str() {
+        Some("ConfigMap") => &config["data"],
+        _ => &config,
+    };
+    let mut customizations = BTreeMap::new();
+    for (key, value) in data
+        .as_mapping()
+        .ok_or("resource customizations must be a map")?
+    {
+        let Some(key) = key.as_str().filter(|k| k.starts_with(PREFIX)) else {
+            debug!("Skipping {:?} of the resource customizations", key);
+            continue;
+        };
+        let value = value
+            .as_str()
+            .ok_or_else(|| format!("the value of '{}' must be a string", key))?;
+        customizations.insert(key.to_string(), value.to_string());
+    }
+    if customizations.is_empty() {
+        return Err(format!("{} has no '{}' keys", path, PREFIX).into());
+    }
+    Ok(customizations)
+}
+
+// Splits a group and kind like 'apps_Deployment', 'Service' or 'all' (any kind)
+fn group_kind(key: &str) -> (Option<&str>, &str) {
+    match key {
+        "all" => (None, "*"),
+        key => match key.rsplit_once('_') {
+            Some((group, kind)) => (Some(group), kind),
+            None => (Some(""), key),
+        },
+    }
+}
+
+// Adds the JSON pointers of one 'ignoreDifferences' block. jq path expressions and managed fields
+// managers need the live resources, so they are only applied by Argo CD
+fn add_ignore_differences(
+    fields: &mut InjectedFields,
+    group: Option<&str>,
+    kind: &str,
+    ignore_differences: &str,
+) -> Result<usize, Box<dyn Error>> {
+    let config: serde_yaml::Value = serde_yaml::from_str(ignore_differences)?;
+    for key in ["jqPathExpressions", "managedFieldsManagers"] {
+        if config[key].as_sequence().is_some_and(|s| !s.is_empty()) {
+            info!(
+                "⚠️ The {} of the ignored differences of {} are not applied to the diff",
+                key, kind
+            );
+        }
+    }
+    let mut count = 0;
+    for pointer in config["jsonPointers"].as_sequence().into_iter().flatten() {
+        let pointer = pointer
+            .as_str()
+            .ok_or_else(|| format!("the jsonPointers of {} must be strings", kind))?;
+        fields.add(kind, group, pointer)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Adds the fields of the 'ignoreDifferences' customizations to the fields removed before diffing,
+// and returns how many were added. Both the 'resource.customizations.ignoreDifferences.<group_kind>'
+// keys and the deprecated 'resource.customizations' key are read
+pub fn add_ignored_fields(
+    customizations: &BTreeMap<String, String>,
+    fields: &mut InjectedFields,
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+    for (key, value) in customizations {
+        if let Some(key) = key.strip_prefix(IGNORE_DIFFERENCES_PREFIX) {
+            let (group, kind) = group_kind(key);
+            count += add_ignore_differences(fields, group, kind, value)?;
+        } else if key == PREFIX {
+            // e.g. 'apps/Deployment: {ignoreDifferences: "jsonPointers: ..."}'
+            let legacy: serde_yaml::Value = serde_yaml::from_str(value)?;
+            for (group_kind, customization) in legacy.as_mapping().into_iter().flatten() {
+                let group_kind = group_kind.as_str().unwrap_or_default();
+                let (group, kind) = match group_kind.rsplit_once('/') {
+                    Some((group, kind)) => (group, kind),
+                    None => ("", group_kind),
+                };
+                if let Some(ignore_differences) = customization["ignoreDifferences"].as_str() {
+                    count += add_ignore_differences(fields, Some(group), kind, ignore_differences)?;
+                }
+            }
+        }
+    }
+    Ok(count)
+}
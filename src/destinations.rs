@@ -0,0 +1,90 @@
+use crate::diff::ReportSection;
+use crate::manifests::{resource_id, ResourceId};
+use crate::model::{ApplicationSpec, Destination};
+use crate::parsing::get_resources_of_kinds;
+use log::info;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+const DESTINATION_KINDS: [&str; 2] = ["Application", "ApplicationSet"];
+
+// The destinations are read from the branches, since all applications are pointed to the
+// in-cluster destination when patching, so a move to another cluster never shows up in the diff
+async fn read_destinations(
+    folders: &[String],
+    regex: &Option<Regex>,
+) -> BTreeMap<ResourceId, Destination> {
+    let mut destinations = BTreeMap::new();
+    for folder in folders {
+        for r in get_resources_of_kinds(folder, regex, &DESTINATION_KINDS).await {
+            if let Some(destination) = ApplicationSpec::of(&r).ok().and_then(|s| s.destination) {
+                destinations.insert(resource_id(&r), destination);
+            }
+        }
+    }
+    destinations
+}
+
+// The cluster is given by either its server or its name
+fn cluster(destination: &Destination) -> &str {
+    destination
+        .server
+        .as_deref()
+        .or(destination.name.as_deref())
+        .unwrap_or("-")
+}
+
+fn format_destination(destination: &Destination) -> String {
+    format!(
+        "`{}` / `{}`",
+        cluster(destination),
+        destination.namespace.as_deref().unwrap_or("-")
+    )
+}
+
+// Lists the Applications and ApplicationSets whose destination cluster or namespace differs
+// between the branches. Added and removed applications aren't moves, so they are not listed
+pub async fn generate_destination_section(
+    base_folders: &[String],
+    target_folders: &[String],
+    regex: &Option<Regex>,
+) -> Option<ReportSection> {
+    info!("🚚 Looking for applications that move to another cluster or namespace");
+
+    let base = read_destinations(base_folders, regex).await;
+    let target = read_destinations(target_folders, regex).await;
+
+    let rows: Vec<String> = base
+        .iter()
+        .filter_map(|(id, before)| {
+            let after = target.get(id)?;
+            let moved = cluster(before) != cluster(after) || before.namespace != after.namespace;
+            moved.then(|| {
+                format!(
+                    "| {} | {} | {} |",
+                    id,
+                    format_destination(before),
+                    format_destination(after)
+                )
+            })
+        })
+        .collect();
+
+    if rows.is_empty() {
+        info!("🚚 No destination changes found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!("🚚 Destination changes ({}):", rows.len()),
+        content: [
+            "| Application | Base (cluster / namespace) | Target (cluster / namespace) |"
+                .to_string(),
+            "|---|---|---|".to_string(),
+        ]
+        .into_iter()
+        .chain(rows)
+        .collect::<Vec<String>>()
+        .join("\n"),
+    })
+}
@@ -0,0 +1,102 @@
+// Some deletions can't be undone, like the deletion of a Namespace (with everything in it), a
+// PersistentVolumeClaim (with its data) or a CustomResourceDefinition (with all its custom
+// resources). With --fail-fast-on destructive, rendering stops at the first application that
+// deletes a resource of such a kind, instead of rendering everything else first
+use crate::manifests::{index_resources, ResourceId};
+use std::collections::BTreeMap;
+
+pub const DEFAULT_PROTECTED_KINDS: &str =
+    "Namespace,PersistentVolumeClaim,CustomResourceDefinition";
+
+// Resources of the protected kinds in the base manifest of an application that are missing in its
+// target manifest
+pub fn deleted_resources(base: &str, target: &str, protected_kinds: &[String]) -> Vec<ResourceId> {
+    let target = index_resources(target);
+    index_resources(base)
+        .into_keys()
+        .filter(|id| {
+            protected_kinds
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(&id.kind))
+        })
+        .filter(|id| !target.contains_key(id))
+        .collect()
+}
+
+// Resources of the protected kinds of the applications of the base branch that are missing in the
+// target branch, by application. Removing an application deletes its resources, unless another
+// application of the target branch renders them, e.g. after a rename
+pub fn deleted_by_removed_applications(
+    base: &BTreeMap<String, String>,
+    target: &BTreeMap<String, String>,
+    protected_kinds: &[String],
+) -> BTreeMap<String, Vec<ResourceId>> {
+    let all_targets = target
+        .values()
+        .map(|m| m.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n---\n");
+    base.iter()
+        .filter(|(name, _)| !target.contains_key(*name))
+        .map(|(name, manifest)| {
+            let deleted = deleted_resources(manifest, &all_targets, protected_kinds);
+            (name.clone(), deleted)
+        })
+        .filter(|(_, deleted)| !deleted.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::deleted_by_removed_applications;
+    use std::collections::BTreeMap;
+
+    const NAMESPACE: &str = "apiVersion: v1
+kind: Namespace
+metadata:
+  name: payments
+";
+
+    const CONFIG_MAP: &str = "apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+  namespace: payments
+";
+
+    fn apps(apps: &[(&str, &str)]) -> BTreeMap<String, String> {
+        apps.iter()
+            .map(|(name, manifest)| (name.to_string(), manifest.to_string()))
+            .collect()
+    }
+
+    fn kinds() -> Vec<String> {
+        vec!["Namespace".to_string()]
+    }
+
+    #[test]
+    fn removed_application_deletes_its_protected_resources() {
+        let base = apps(&[("payments", &format!("{}---\n{}", NAMESPACE, CONFIG_MAP))]);
+        let deleted = deleted_by_removed_applications(&base, &apps(&[]), &kinds());
+        let names: Vec<String> = deleted["payments"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(names, ["Namespace/payments"]);
+    }
+
+    #[test]
+    fn renamed_application_deletes_nothing() {
+        let base = apps(&[("payments", NAMESPACE)]);
+        let target = apps(&[("payments-v2", NAMESPACE)]);
+        assert!(deleted_by_removed_applications(&base, &target, &kinds()).is_empty());
+    }
+
+    #[test]
+    fn application_in_both_branches_is_not_removed() {
+        let base = apps(&[("payments", NAMESPACE)]);
+        let target = apps(&[("payments", CONFIG_MAP)]);
+        assert!(deleted_by_removed_applications(&base, &target, &kinds()).is_empty());
+    }
+}
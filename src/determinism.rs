@@ -0,0 +1,72 @@
+// Charts that generate random values (e.g. 'randAlphaNum' passwords), timestamps or use 'lookup'
+// render differently every time, which shows up as a diff in every pull request. The target branch
+// is rendered a second time, and the resources that differ between the renders are reported
+use crate::diff::ReportSection;
+use crate::manifests::{parse_manifest, read_rendered_apps};
+use crate::matrix::compare_rendered_apps;
+use crate::Branch;
+use log::info;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+
+// Folder with the manifests of the second render of the target branch
+pub fn second_render_folder(output_folder: &str) -> String {
+    format!("{}/render-twice", output_folder)
+}
+
+// Adds a hard refresh annotation to the applications in 'apps_file'. Otherwise the repo-server
+// returns the manifests it cached during the first render
+pub fn request_hard_refresh(apps_file: &str) -> Result<(), Box<dyn Error>> {
+    let mut applications = parse_manifest(&fs::read_to_string(apps_file)?);
+    for application in &mut applications {
+        if !application["metadata"]["annotations"].is_mapping() {
+            application["metadata"]["annotations"] = serde_yaml::Mapping::new().into();
+        }
+        application["metadata"]["annotations"]["argocd.argoproj.io/refresh"] = "hard".into();
+    }
+    let documents = applications
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<String>, _>>()?;
+    fs::write(apps_file, documents.join("---\n"))?;
+    Ok(())
+}
+
+// Lists the resources of the target branch that were rendered differently the second time
+pub fn generate_determinism_section(output_folder: &str) -> Option<ReportSection> {
+    let first = read_rendered_apps(output_folder, &Branch::Target);
+    let second = read_rendered_apps(&second_render_folder(output_folder), &Branch::Target);
+    let applications: BTreeSet<&String> = first
+        .keys()
+        .chain(second.keys())
+        .filter(|name| first.get(*name) != second.get(*name))
+        .collect();
+    if applications.is_empty() {
+        info!("🎲 All applications rendered the same twice");
+        return None;
+    }
+    info!(
+        "⚠️ {} applications rendered differently the second time",
+        applications.len()
+    );
+
+    let lines = compare_rendered_apps(
+        &first,
+        &second,
+        (
+            "only rendered the second time",
+            "only rendered the first time",
+        ),
+    );
+    Some(ReportSection {
+        title: format!(
+            "🎲 Non-deterministic applications ({}):",
+            applications.len()
+        ),
+        content: format!(
+            "These resources were rendered differently when the target branch was rendered twice, e.g. because of random values, timestamps or lookups. They show up in the diff of every pull request.\n\n{}",
+            lines.join("\n")
+        ),
+    })
+}
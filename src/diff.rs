@@ -1,8 +1,515 @@
+use crate::anonymize::Anonymizer;
+use crate::attribution;
+use crate::budget;
+use crate::conversions::{self, Conversion};
+use crate::grouping::UNGROUPED;
+use crate::guards::{self, SizeGuards};
+use crate::manifests::{read_applications, ResourceId};
+use crate::renames::{self, Rename};
+use crate::semantic;
 use crate::utils::run_command;
+use crate::vcs::Commit;
+use crate::workdir;
 use crate::Branch;
 use log::{debug, info};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
 use std::{error::Error, process::Output};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// An additional part of the report, shown as a collapsible block between the summary and the diff
+pub struct ReportSection {
+    pub title: String,
+    pub content: String,
+}
+
+impl ReportSection {
+    fn to_markdown(&self) -> String {
+        format!(
+            "\n<details>\n<summary>{}</summary>\n<br>\n\n{}\n\n</details>\n",
+            self.title, self.content
+        )
+    }
+
+    fn to_html(&self) -> String {
+        format!(
+            "<h3>{}</h3>\n<div class=\"markdown\">{}</div>\n",
+            escape_html(&self.title),
+            markdown_to_html(&self.content)
+        )
+    }
+}
+
+// Everything that is added to the diff of the rendered manifests
+pub struct Report {
+    // The pull request and the commits that were compared, shown below the title
+    pub header: Option<String>,
+    pub sections: Vec<ReportSection>,
+    // Maps application names to groups (e.g. environments). The diff is ordered by group when set
+    pub groups: BTreeMap<String, String>,
+    // Applications with the same diff, except for their names, are shown once (--dedupe-diffs)
+    pub dedupe_diffs: bool,
+    // Applications converted to or from an ApplicationSet, which are diffed against their
+    // application of the base branch when they were renamed
+    pub conversions: Vec<Conversion>,
+    pub diff_format: DiffFormat,
+    // Changes every reviewer has to see (e.g. to protected resources), shown above the summary
+    pub warnings: Vec<String>,
+    // Platforms the report is published to. A report that fits the size limit is written for each
+    pub publishers: Vec<budget::Publisher>,
+    // Where the full report can be downloaded, linked from reports that don't show the whole diff
+    pub artifact_url: Option<String>,
+    // The 'spec.info' of applications as one line, shown above their diff
+    pub app_info: BTreeMap<String, String>,
+    // Replaces hostnames, IP addresses and account IDs in the report with pseudonyms when set
+    pub anonymizer: Option<Anonymizer>,
+    // Limits above which resources and application diffs are summarized instead of shown
+    pub size_guards: SizeGuards,
+    // Removed and added resources of an application with at least this share of their fields in
+    // common are shown as renamed. Not tracked when not set
+    pub rename_similarity: Option<f64>,
+}
+
+// How the changes of each application are shown in the diff
+#[derive(Clone)]
+pub enum DiffFormat {
+    // A unified diff created by git
+    Unified,
+    // The changed, added and removed values by path
+    Semantic,
+    // An external command (e.g. 'dyff between --omit-header'), called with the base and the target
+    // manifest of the application
+    Command(Vec<String>),
+}
+
+// Terminal colors of external diff tools would show up as escape codes in the report
+fn strip_ansi_codes(s: &str) -> String {
+    let ansi = regex::Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap();
+    ansi.replace_all(s, "").to_string()
+}
+
+// Diffs one application with the semantic renderer or the external command. Their output doesn't
+// name the application, so it gets a header line. Identical manifests aren't passed to the command,
+// since not every tool prints nothing for them
+async fn diff_application(
+    format: &DiffFormat,
+    output_folder: &str,
+    name: &str,
+    base: &str,
+    target: &str,
+    renames: &[Rename],
+    sources: &BTreeMap<ResourceId, String>,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let read = |path: &str| fs::read(Path::new(output_folder).join(path)).ok();
+    if read(base) == read(target) {
+        return Ok(String::new());
+    }
+    let command = match format {
+        DiffFormat::Command(command) if !command.is_empty() => command,
+        _ => {
+            let text =
+                |path: &str| String::from_utf8_lossy(&read(path).unwrap_or_default()).to_string();
+            let lines = semantic::diff_manifests(&text(base), &text(target), renames, sources);
+            return Ok(match lines.is_empty() {
+                true => String::new(),
+                false => format!("━━━ {} ━━━\n{}\n", name, lines.join("\n")),
+            });
+        }
+    };
+    let output = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .arg(base)
+        .arg(target)
+        .current_dir(output_folder)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run diff command '{}': {}", command[0], e))?;
+    // diff tools commonly exit with 1 when the files differ
+    if !output.status.success() && !output.stderr.is_empty() {
+        return Err(format!(
+            "diff command failed for application {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    let diff = strip_ansi_codes(&String::from_utf8_lossy(&output.stdout));
+    Ok(format!("━━━ {} ━━━\n{}\n", name, diff.trim_end()))
+}
+
+// An application ready to be diffed
+struct Prepared {
+    // the folder to diff the application in
+    folder: String,
+    renames: Vec<Rename>,
+    // the files of the base and the target branch in the folder. git shows a missing file as
+    // /dev/null, the same way as in a diff of folders
+    sides: [String; 2],
+}
+
+// Moves the renamed resources of an application next to each other in a copy of its manifests
+fn prepare_application(
+    folder: String,
+    renamed_folder: &Path,
+    files: [String; 2],
+    rename_similarity: Option<f64>,
+) -> Result<Prepared, Box<dyn Error + Send + Sync>> {
+    let branches = [Branch::Base, Branch::Target].map(|b| b.to_string());
+    let renames = match rename_similarity {
+        Some(min_similarity) => renames::align_renames(
+            &folder,
+            renamed_folder,
+            [(&branches[0], &files[0]), (&branches[1], &files[1])],
+            min_similarity,
+        )
+        .map_err(|e| e.to_string())?,
+        None => vec![],
+    };
+    let folder = match renames.is_empty() {
+        true => folder,
+        false => renamed_folder.to_string_lossy().to_string(),
+    };
+    let sides = [0, 1].map(|i| {
+        let path = format!("{}/{}", branches[i], files[i]);
+        match Path::new(&folder).join(&path).is_file() {
+            true => path,
+            false => "/dev/null".to_string(),
+        }
+    });
+    Ok(Prepared {
+        folder,
+        renames,
+        sides,
+    })
+}
+
+// The diff of an application without its name, line numbers and blob hashes, which differ between
+// applications generated from the same template
+fn normalize_diff(diff: &str, name: &str) -> String {
+    let volatile = regex::Regex::new(r"(?m)^(@@ -\d+(,\d+)? \+\d+(,\d+)? @@|index .*$)").unwrap();
+    volatile
+        .replace_all(&diff.replace(name, "\0"), "")
+        .to_string()
+}
+
+// Diffs every application on its own, on all CPU cores, and writes the diffs to 'path' in the order
+// of the application names. The result is the same as the diff of the whole branch folders. With
+// groups, the diffs are ordered by group first, with a header line for each group.
+// git writes each diff straight to a file, so the diffs are never held in memory. Returns where the
+// diff of each application is in the file
+async fn write_diff(
+    output_folder: &str,
+    line_count: usize,
+    patterns_to_ignore: &str,
+    report: &Report,
+    path: &str,
+) -> Result<Vec<budget::DiffPart>, Box<dyn Error>> {
+    let groups = &report.groups;
+    // target applications by the name of their converted application in the base branch
+    let renamed = conversions::renamed(&report.conversions);
+    let names: BTreeSet<String> = [Branch::Base, Branch::Target]
+        .iter()
+        .filter_map(|branch| fs::read_dir(format!("{}/{}", output_folder, branch)).ok())
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| !renamed.values().any(|base| base == name))
+        .collect();
+    let base_name = |name: &String| renamed.get(name).unwrap_or(name).clone();
+
+    let parts_folder = workdir::path("diffs");
+    fs::remove_dir_all(&parts_folder).unwrap_or_default();
+    fs::create_dir_all(&parts_folder)?;
+    let part = |name: &str| parts_folder.join(name);
+    let guarded_folder = workdir::path("guarded");
+    fs::remove_dir_all(&guarded_folder).unwrap_or_default();
+    let renamed_folder = workdir::path("renamed");
+    fs::remove_dir_all(&renamed_folder).unwrap_or_default();
+    // resources that are renamed within an application, by application
+    let mut app_renames: BTreeMap<String, Vec<Rename>> = BTreeMap::new();
+    // the Applications of both branches, for the sources of the resources of multi-source
+    // applications
+    let applications = [Branch::Base, Branch::Target].map(|b| read_applications(output_folder, &b));
+    // the files that were diffed and the sources of their resources, by multi-source application
+    let mut app_sources = BTreeMap::new();
+
+    let parallelism = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    debug!(
+        "Diffing {} applications with {} parallel diffs",
+        names.len(),
+        parallelism
+    );
+
+    let semaphore = Arc::new(Semaphore::new(parallelism));
+    let mut tasks = JoinSet::new();
+    let mut formatter_tasks = JoinSet::new();
+    // the sources of the resources of multi-source applications diffed with git, until their files
+    // are known
+    let mut pending_sources = BTreeMap::new();
+    for name in &names {
+        // identical manifests have no diff, so they aren't diffed at all
+        let read = |branch: Branch, file: &str| {
+            fs::read(Path::new(output_folder).join(branch.to_string()).join(file)).ok()
+        };
+        if read(Branch::Base, &base_name(name)) == read(Branch::Target, name) {
+            continue;
+        }
+        // applications with large resources are diffed in a copy with the resources replaced by stubs
+        let folder = match report.size_guards.max_resource_lines {
+            Some(max_lines)
+                if guards::guard_resources(
+                    output_folder,
+                    &guarded_folder,
+                    &base_name(name),
+                    name,
+                    max_lines,
+                )? =>
+            {
+                guarded_folder.to_string_lossy().to_string()
+            }
+            _ => output_folder.to_string(),
+        };
+        let files = [base_name(name), name.clone()];
+        let (renamed_folder, rename_similarity) =
+            (renamed_folder.clone(), report.rename_similarity);
+
+        let sources = attribution::application_sources(
+            output_folder,
+            &applications,
+            [&base_name(name), name],
+        );
+
+        let semaphore = semaphore.clone();
+        if !matches!(report.diff_format, DiffFormat::Unified) {
+            let (name, format, part) = (name.clone(), report.diff_format.clone(), part(name));
+            formatter_tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let Prepared {
+                    folder,
+                    renames,
+                    sides: [base, target],
+                } = prepare_application(folder, &renamed_folder, files, rename_similarity)?;
+                let diff =
+                    diff_application(&format, &folder, &name, &base, &target, &renames, &sources)
+                        .await?;
+                fs::write(part, diff)?;
+                Ok::<_, Box<dyn Error + Send + Sync>>((name, renames))
+            });
+            continue;
+        }
+        let mut args = vec![
+            "--no-pager".to_string(),
+            "diff".to_string(),
+            "--no-prefix".to_string(),
+            format!("-U{}", line_count),
+            "--no-index".to_string(),
+        ];
+        args.extend(patterns_to_ignore.split_whitespace().map(|a| a.to_string()));
+        if !sources.is_empty() {
+            pending_sources.insert(name.clone(), sources);
+        }
+
+        let (name, part) = (name.clone(), part(name));
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let Prepared {
+                folder,
+                renames,
+                sides,
+            } = prepare_application(folder, &renamed_folder, files, rename_similarity)?;
+            // the file is only opened once the diff runs, so at most 'parallelism' files are open
+            let stdout = fs::File::create(part)?;
+            // 'output()' would capture stdout, so the child is spawned with its stdout set to the file
+            let output = tokio::process::Command::new("git")
+                .args(&args)
+                .args(&sides)
+                .current_dir(&folder)
+                .stdout(stdout)
+                .stderr(Stdio::piped())
+                .spawn()?
+                .wait_with_output()
+                .await?;
+            let files = sides.map(|f| Path::new(&folder).join(f).to_string_lossy().to_string());
+            Ok::<_, Box<dyn Error + Send + Sync>>((name, renames, files, output))
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        let (name, renames, files, output) = result?.map_err(|e| e.to_string())?;
+        // exit code 1 means that the files differ
+        if !output.status.success() && !output.stderr.is_empty() {
+            panic!(
+                "Error running diff command with error: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+        }
+        if let Some(sources) = pending_sources.remove(&name) {
+            app_sources.insert(name.clone(), (files, sources));
+        }
+        if !renames.is_empty() {
+            app_renames.insert(name, renames);
+        }
+    }
+    while let Some(result) = formatter_tasks.join_next().await {
+        let (name, renames) = result?.map_err(|e| e.to_string())?;
+        if !renames.is_empty() {
+            app_renames.insert(name, renames);
+        }
+    }
+    for (name, ([base, target], sources)) in &app_sources {
+        let diff = fs::read_to_string(part(name))?;
+        if !diff.is_empty() {
+            let read = |path: &str| fs::read_to_string(path).unwrap_or_default();
+            let diff = attribution::annotate_hunks(&diff, &read(base), &read(target), sources);
+            fs::write(part(name), diff)?;
+        }
+    }
+
+    // applications without changes have empty diffs
+    let changed: Vec<&String> = names
+        .iter()
+        .filter(|name| {
+            fs::metadata(part(name))
+                .map(|m| m.len() > 0)
+                .unwrap_or(false)
+        })
+        .collect();
+    if let Some(max_lines) = report.size_guards.max_app_diff_lines {
+        for name in &changed {
+            let diff = fs::read_to_string(part(name))?;
+            if let Some(summary) =
+                guards::summarize_diff(output_folder, &base_name(name), name, &diff, max_lines)
+            {
+                fs::write(part(name), summary)?;
+            }
+        }
+    }
+
+    let group_of = |name: &String| match groups.is_empty() {
+        true => "",
+        false => groups.get(name).map(|g| g.as_str()).unwrap_or(UNGROUPED),
+    };
+
+    // applications with the same change as an earlier application of their group, by the earlier one
+    let mut same_change: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+    if report.dedupe_diffs {
+        let mut first_with_diff: BTreeMap<(&str, String), &String> = BTreeMap::new();
+        for name in &changed {
+            let diff = normalize_diff(&fs::read_to_string(part(name))?, name);
+            match first_with_diff.get(&(group_of(name), diff.clone())) {
+                Some(first) => same_change.entry(*first).or_default().push(name),
+                None => {
+                    first_with_diff.insert((group_of(name), diff), name);
+                }
+            }
+        }
+    }
+    let duplicates: BTreeSet<&String> = same_change.values().flatten().copied().collect();
+    if !duplicates.is_empty() {
+        info!(
+            "🪞 {} applications have the same diff as another application",
+            duplicates.len()
+        );
+    }
+
+    let mut grouped: BTreeMap<(bool, &str), Vec<&String>> = BTreeMap::new();
+    for name in changed.into_iter().filter(|n| !duplicates.contains(n)) {
+        let group = group_of(name);
+        grouped
+            .entry((group == UNGROUPED, group))
+            .or_default()
+            .push(name);
+    }
+
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    let mut diff_parts = vec![];
+    let mut offset = 0;
+    for ((_, group), names) in grouped {
+        if !groups.is_empty() {
+            let count: usize = names
+                .iter()
+                .map(|n| 1 + same_change.get(n).map(|s| s.len()).unwrap_or_default())
+                .sum();
+            let header = format!("# ━━━ {} ({} applications) ━━━\n", group, count);
+            out.write_all(header.as_bytes())?;
+            offset += header.len() as u64;
+        }
+        for name in names {
+            // the info is part of the diff of the application, so it is kept with it
+            let mut header = report
+                .app_info
+                .get(name)
+                .map(|info| format!("# ℹ️ {}: {}\n", name, info))
+                .unwrap_or_default();
+            for rename in app_renames.get(name).into_iter().flatten().rev() {
+                header = format!("# ✏️ {}\n{}", rename, header);
+            }
+            if let Some(c) = report.conversions.iter().find(|c| &c.target == name) {
+                header = format!("# 🔀 {}\n{}", c, header);
+            }
+            if let Some(same) = same_change.get(name) {
+                let others: Vec<&str> = same.iter().map(|n| n.as_str()).collect();
+                header = format!(
+                    "# 🪞 Same change in {} applications: {}, {}\n{}",
+                    same.len() + 1,
+                    name,
+                    others.join(", "),
+                    header
+                );
+            }
+            let length = match &report.anonymizer {
+                Some(anonymizer) => {
+                    let diff = anonymizer.anonymize(&(header + &fs::read_to_string(part(name))?));
+                    out.write_all(diff.as_bytes())?;
+                    diff.len() as u64
+                }
+                None => {
+                    out.write_all(header.as_bytes())?;
+                    header.len() as u64 + std::io::copy(&mut fs::File::open(part(name))?, &mut out)?
+                }
+            };
+            diff_parts.push(budget::DiffPart {
+                name: name.clone(),
+                offset,
+                length,
+            });
+            offset += length;
+        }
+    }
+    out.flush()?;
+
+    fs::remove_dir_all(&parts_folder).unwrap_or_default();
+    fs::remove_dir_all(&guarded_folder).unwrap_or_default();
+    fs::remove_dir_all(&renamed_folder).unwrap_or_default();
+    Ok(diff_parts)
+}
+
+// Reads at most 'max_length' bytes of the diff, cut at the last complete character
+pub fn read_diff(path: &str, max_length: usize) -> Result<String, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)?
+        .take(max_length as u64)
+        .read_to_end(&mut bytes)?;
+    let diff = match String::from_utf8(bytes) {
+        Ok(diff) => diff,
+        Err(e) => {
+            let valid_length = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid_length);
+            String::from_utf8(bytes)?
+        }
+    };
+    Ok(diff.trim_end().to_string())
+}
 
 pub async fn generate_diff(
     output_folder: &str,
@@ -11,6 +518,7 @@ pub async fn generate_diff(
     diff_ignore: Option<String>,
     line_count: Option<usize>,
     max_char_count: Option<usize>,
+    report: &Report,
 ) -> Result<(), Box<dyn Error>> {
     let max_diff_message_char_count = max_char_count.unwrap_or(65536);
 
@@ -54,21 +562,46 @@ pub async fn generate_diff(
 
     let summary_as_string =
         parse_diff_output(run_command(&summary_diff_command, Some(output_folder)).await);
+    let anonymize = |text: String| match &report.anonymizer {
+        Some(anonymizer) => anonymizer.anonymize(&text),
+        None => text,
+    };
+    let summary_as_string = anonymize(summary_as_string);
 
-    let diff_command = &format!(
-        "git --no-pager diff --no-prefix -U{} --no-index {} {} {}",
+    let diff_path = format!("{}/diff.patch", output_folder);
+    let diff_parts = write_diff(
+        output_folder,
         line_count.unwrap_or(10),
-        patterns_to_ignore,
-        Branch::Base,
-        Branch::Target
-    );
+        &patterns_to_ignore,
+        report,
+        &diff_path,
+    )
+    .await?;
+    let diff_length = fs::metadata(&diff_path)?.len() as usize;
 
-    debug!("Getting diff with command: {}", diff_command);
+    let sections_as_markdown = anonymize(
+        report
+            .sections
+            .iter()
+            .map(|s| s.to_markdown())
+            .collect::<String>(),
+    );
 
-    let diff_as_string = parse_diff_output(run_command(diff_command, Some(output_folder)).await);
+    // the header is shown above the warnings, right below the title
+    let header = report.header.clone().unwrap_or_default();
+    let warnings_as_markdown = anonymize(
+        report
+            .warnings
+            .iter()
+            .map(|w| warning_to_markdown(w))
+            .fold(header.clone(), |all, w| all + &w),
+    );
 
-    let remaining_max_chars =
-        max_diff_message_char_count - markdown_template_length() - summary_as_string.len();
+    let remaining_max_chars = max_diff_message_char_count
+        .saturating_sub(markdown_template_length())
+        .saturating_sub(warnings_as_markdown.len())
+        .saturating_sub(summary_as_string.len())
+        .saturating_sub(sections_as_markdown.len());
 
     let warning_message = &format!(
         "\n\n ⚠️⚠️⚠️ Diff is too long. Truncated to {} characters. This can be adjusted with the `--max-diff-length` flag",
@@ -76,36 +609,136 @@ pub async fn generate_diff(
     );
 
     let diff_truncated = match remaining_max_chars {
-        remaining if remaining > diff_as_string.len() => diff_as_string, // No need to truncate
+        _ if diff_length == 0 => "No changes found".to_string(),
+        remaining if remaining > diff_length => read_diff(&diff_path, diff_length)?, // No need to truncate
         remaining if remaining > warning_message.len() => {
             info!(
                 "🚨 Diff is too long. Truncating message to {} characters",
                 max_diff_message_char_count
             );
             let last_diff_char = remaining - warning_message.len();
-            diff_as_string[..last_diff_char].to_string() + warning_message
+            read_diff(&diff_path, last_diff_char)? + warning_message
         }
         _ => return Err("Diff is too long and cannot be truncated. Increase the max length with `--max-diff-length`".into())
     };
 
-    let markdown = print_diff(&summary_as_string, &diff_truncated);
+    let markdown = print_diff(
+        &warnings_as_markdown,
+        &summary_as_string,
+        &sections_as_markdown,
+        &diff_truncated,
+    );
 
     let markdown_path = format!("{}/diff.md", output_folder);
     fs::write(&markdown_path, markdown)?;
 
+    // The HTML report is not size limited, so it always contains the full diff
+    let sections_as_html = anonymize(
+        report
+            .sections
+            .iter()
+            .map(|s| s.to_html())
+            .collect::<String>(),
+    );
+    let header_as_html = match header.is_empty() {
+        true => String::new(),
+        false => format!(
+            "<div class=\"markdown\">{}</div>\n",
+            markdown_to_html(&header)
+        ),
+    };
+    let warnings_as_html = anonymize(
+        report
+            .warnings
+            .iter()
+            .map(|w| {
+                format!(
+                    "<div class=\"warning markdown\">{}</div>\n",
+                    markdown_to_html(w)
+                )
+            })
+            .fold(header_as_html, |all, w| all + &w),
+    );
+    write_html(
+        &format!("{}/diff.html", output_folder),
+        &warnings_as_html,
+        &summary_as_string,
+        &sections_as_html,
+        &diff_path,
+    )?;
+
+    for publisher in &report.publishers {
+        budget::write_report(
+            output_folder,
+            publisher,
+            &budget::ReportParts {
+                warnings: &warnings_as_markdown,
+                summary: &summary_as_string,
+                sections: &sections_as_markdown,
+                diff_path: &diff_path,
+                diff_parts: &diff_parts,
+                artifact_url: report.artifact_url.as_deref(),
+            },
+        )?;
+    }
+
     info!("🙏 Please check the {} file for differences", markdown_path);
 
     Ok(())
 }
 
+// Written instead of the report when a run is cancelled or fails, so a pull request comment
+// explains why there is no diff. The error of a failed run is quoted
+pub fn write_partial_report(
+    output_folder: &str,
+    reason: &str,
+    phase: &str,
+    error: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let rendered = |branch: &Branch| {
+        fs::read_dir(format!("{}/{}", output_folder, branch))
+            .map(|dir| dir.flatten().count())
+            .unwrap_or_default()
+    };
+    let error = error.map(|e| format!("\n> {}\n", e)).unwrap_or_default();
+    let markdown = format!(
+        "## Argo CD Diff Preview\n\n⚠️ The run {} while {}, so no diff was generated.\n{}\nRendered applications before it stopped:\n```bash\n{}: {}\n{}: {}\n```\n",
+        reason,
+        phase,
+        error,
+        Branch::Base,
+        rendered(&Branch::Base),
+        Branch::Target,
+        rendered(&Branch::Target),
+    );
+    fs::create_dir_all(output_folder)?;
+    let markdown_path = format!("{}/diff.md", output_folder);
+    fs::write(&markdown_path, markdown)?;
+    info!("📝 Wrote a partial report to {}", markdown_path);
+    Ok(())
+}
+
+// Written instead of the report when rendering is skipped, since no change can affect the rendered
+// manifests
+pub fn write_no_changes_report(output_folder: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(output_folder)?;
+    let markdown_path = format!("{}/diff.md", output_folder);
+    fs::write(
+        &markdown_path,
+        "## Argo CD Diff Preview\n\nNo changes detected. The applications are identical in both branches, and no files changed in their source paths, so nothing was rendered.\n",
+    )?;
+    info!("🙏 Please check the {} file for differences", markdown_path);
+    Ok(())
+}
+
 const MARKDOWN_TEMPLATE: &str = r#"
 ## Argo CD Diff Preview
-
+%warnings%
 Summary:
 ```bash
 %summary%
 ```
-
+%sections%
 <details>
 <summary>Diff:</summary>
 <br>
@@ -117,17 +750,211 @@ Summary:
 </details>
 "#;
 
+// Pipes would end a cell of a markdown table
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+// The pull request and the commits of both branches, so an archived report says what was compared.
+// Each branch is given with its name and its commit, which is None when it can't be read
+pub fn commit_header(
+    pull_request: Option<String>,
+    branches: &[(Branch, &str, Option<Commit>)],
+) -> Option<String> {
+    if pull_request.is_none() && branches.iter().all(|(_, _, c)| c.is_none()) {
+        return None;
+    }
+    let pull_request = pull_request
+        .map(|p| format!("**Pull request:** {}\n\n", p))
+        .unwrap_or_default();
+    let rows: Vec<String> = branches
+        .iter()
+        .map(|(branch, name, commit)| match commit {
+            Some(c) => format!(
+                "| {} (`{}`) | `{}` | {} | {} |",
+                branch,
+                name,
+                c.sha,
+                escape_cell(&c.author),
+                escape_cell(&c.subject)
+            ),
+            None => format!("| {} (`{}`) | | | |", branch, name),
+        })
+        .collect();
+    Some(format!(
+        "\n{}| Branch | Commit | Author | Subject |\n|---|---|---|---|\n{}\n",
+        pull_request,
+        rows.join("\n")
+    ))
+}
+
+// Shown as a GitHub alert, which stands out more than the collapsed sections
+fn warning_to_markdown(warning: &str) -> String {
+    let quoted: Vec<String> = warning
+        .lines()
+        .map(|l| format!("> {}", l).trim_end().to_string())
+        .collect();
+    format!("\n> [!CAUTION]\n{}\n", quoted.join("\n"))
+}
+
 fn markdown_template_length() -> usize {
     MARKDOWN_TEMPLATE
+        .replace("%warnings%", "")
         .replace("%summary%", "")
+        .replace("%sections%", "")
         .replace("%diff%", "")
         .len()
 }
 
-fn print_diff(summary: &str, diff: &str) -> String {
+pub fn print_diff(warnings: &str, summary: &str, sections: &str, diff: &str) -> String {
     MARKDOWN_TEMPLATE
+        .replace("%warnings%", warnings)
         .replace("%summary%", summary)
+        .replace("%sections%", sections)
         .replace("%diff%", diff)
         .trim_start()
         .to_string()
 }
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Argo CD Diff Preview</title>
+<style>
+  body { font-family: sans-serif; margin: 2em; }
+  pre { background: #f6f8fa; padding: 1em; overflow-x: auto; }
+  .added { color: #116329; background: #dafbe1; }
+  .removed { color: #82071e; background: #ffebe9; }
+  .hunk { color: #0550ae; }
+  .markdown table { border-collapse: collapse; }
+  .markdown th, .markdown td { border: 1px solid #d0d7de; padding: 0.2em 0.6em; }
+  .warning { border-left: 4px solid #cf222e; background: #ffebe9; padding: 0 1em; }
+</style>
+</head>
+<body>
+<h2>Argo CD Diff Preview</h2>
+%warnings%
+<h3>Summary</h3>
+<pre>%summary%</pre>
+%sections%
+<h3>Diff</h3>
+<pre>%diff%</pre>
+</body>
+</html>
+"#;
+
+// Streams the diff into the report line by line, since it is not size limited
+fn write_html(
+    path: &str,
+    warnings: &str,
+    summary: &str,
+    sections: &str,
+    diff_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (head, tail) = HTML_TEMPLATE
+        .split_once("%diff%")
+        .expect("HTML template has no diff");
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    out.write_all(
+        head.replace("%warnings%", warnings)
+            .replace("%summary%", &escape_html(summary))
+            .replace("%sections%", sections)
+            .as_bytes(),
+    )?;
+
+    let mut lines = BufReader::new(fs::File::open(diff_path)?)
+        .lines()
+        .peekable();
+    if lines.peek().is_none() {
+        out.write_all(b"No changes found")?;
+    }
+    while let Some(line) = lines.next() {
+        let line = line?;
+        let class = match line.as_str() {
+            l if l.starts_with("+++") || l.starts_with("---") => None,
+            l if l.starts_with('+') => Some("added"),
+            l if l.starts_with('-') => Some("removed"),
+            l if l.starts_with("@@") => Some("hunk"),
+            _ => None,
+        };
+        match class {
+            Some(c) => write!(out, "<span class=\"{}\">{}</span>", c, escape_html(&line))?,
+            None => out.write_all(escape_html(&line).as_bytes())?,
+        }
+        if lines.peek().is_some() {
+            out.write_all(b"\n")?;
+        }
+    }
+
+    out.write_all(tail.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+// Renders the markdown of the report, so that diff.html needs no scripts and works offline. Names
+// and values in the report come from the repository, so raw HTML is escaped, except for the
+// collapsible blocks of the report itself, and links only go to web pages
+fn markdown_to_html(markdown: &str) -> String {
+    let allowed = regex::Regex::new(r"(?i)</?(details|summary)>|<br ?/?>").unwrap();
+    let sanitize = |html: &str| -> CowStr {
+        let mut out = String::new();
+        let mut last = 0;
+        for tag in allowed.find_iter(html) {
+            out.push_str(&escape_html(&html[last..tag.start()]));
+            out.push_str(tag.as_str());
+            last = tag.end();
+        }
+        out.push_str(&escape_html(&html[last..]));
+        out.into()
+    };
+    let safe_url = |url: CowStr<'static>| -> CowStr<'static> {
+        let scheme = url.split_once(':').map(|(scheme, _)| scheme);
+        match scheme {
+            Some(s) if !s.contains('/') && !["http", "https", "mailto"].contains(&s) => "".into(),
+            _ => url,
+        }
+    };
+
+    let events = Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+    .map(|event| match event.into_static() {
+        Event::Html(html) => Event::Html(sanitize(&html)),
+        Event::InlineHtml(html) => Event::InlineHtml(sanitize(&html)),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,130 @@
+// The drift subcommand is meant to run on a schedule: it renders the default branch and diffs it
+// against the report of the previous run, which shows render changes nobody committed, e.g. from
+// floating chart versions or 'latest' tags. Reports are kept in a local file (e.g. restored from a
+// CI cache) or in object storage
+use crate::history::{read_report, StoredReport};
+use crate::manifests::read_rendered_apps;
+use crate::utils::run_command_from_list;
+use crate::workdir;
+use crate::Branch;
+use log::info;
+use regex::Regex;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub struct DriftOptions {
+    // Where the report of the previous run is read from and the new one is written to
+    pub snapshot: String,
+    pub fail_on_drift: bool,
+}
+
+// The CLI (and its command) used for a snapshot in object storage, or None for a local file
+fn storage_cli(location: &str) -> Option<(&'static str, &'static str)> {
+    match location {
+        l if l.starts_with("s3://") => Some(("aws", "s3")),
+        l if l.starts_with("gs://") => Some(("gcloud", "storage")),
+        _ => None,
+    }
+}
+
+async fn copy(from: &str, to: &str, location: &str) -> Result<(), Box<dyn Error>> {
+    let Some((cli, command)) = storage_cli(location) else {
+        if let Some(parent) = Path::new(to).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+        return Ok(());
+    };
+    if let Err(e) = run_command_from_list(vec![cli, command, "cp", from, to], None).await {
+        return Err(format!(
+            "failed to copy {} to {}: {}",
+            from,
+            to,
+            String::from_utf8_lossy(&e.stderr)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// Whether the snapshot exists. Only a listing that clearly doesn't have it counts as missing, so an
+// error of the CLI (e.g. expired credentials or throttling) doesn't replace the stored snapshot
+async fn snapshot_exists(location: &str) -> Result<bool, Box<dyn Error>> {
+    let Some((cli, command)) = storage_cli(location) else {
+        return Ok(Path::new(location).is_file());
+    };
+    let output = match run_command_from_list(vec![cli, command, "ls", location], None).await {
+        Ok(output) => output,
+        Err(e) => {
+            let stderr = String::from_utf8_lossy(&e.stderr);
+            // 'aws s3 ls' exits with 1 and no output when no key matches, gcloud names the URL
+            let not_found = match cli {
+                "aws" => e.status.code() == Some(1) && stderr.trim().is_empty(),
+                _ => stderr.contains("matched no objects"),
+            };
+            if not_found {
+                return Ok(false);
+            }
+            return Err(format!("failed to list {}: {}", location, stderr).into());
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(match cli {
+        // 'aws s3 ls' lists every key that starts with the location, by the part after the last
+        // '/', e.g. '2024-01-01 12:00:00       1234 report.json'
+        "aws" => {
+            let key = location.rsplit('/').next().unwrap_or_default();
+            let entry = Regex::new(r"^\S+ \S+ +\d+ (.*)$").unwrap();
+            stdout.lines().any(|line| {
+                entry
+                    .captures(line)
+                    .is_some_and(|c| c.get(1).map(|m| m.as_str()) == Some(key))
+            })
+        }
+        _ => stdout.lines().any(|line| line.trim() == location),
+    })
+}
+
+// Reads the report of the previous run. Returns None when there is none yet
+pub async fn fetch_snapshot(location: &str) -> Result<Option<StoredReport>, Box<dyn Error>> {
+    if !snapshot_exists(location).await? {
+        info!("📸 No snapshot found at {}", location);
+        return Ok(None);
+    }
+    let file = workdir::path("snapshot.json").display().to_string();
+    copy(location, &file, location).await?;
+    Ok(Some(read_report(&file)?))
+}
+
+// Stores the report of this run as the new snapshot and returns the exit code of the run, which is
+// 1 when applications drifted and --fail-on-drift is set
+pub async fn finish(
+    output_folder: &str,
+    options: &DriftOptions,
+    had_snapshot: bool,
+) -> Result<i32, Box<dyn Error>> {
+    let report = format!("{}/report.json", output_folder);
+    copy(&report, &options.snapshot, &options.snapshot).await?;
+    info!("📸 Stored snapshot at {}", options.snapshot);
+    if !had_snapshot {
+        return Ok(0);
+    }
+
+    let before = read_rendered_apps(output_folder, &Branch::Base);
+    let after = read_rendered_apps(output_folder, &Branch::Target);
+    let drifted = before
+        .keys()
+        .chain(after.keys().filter(|name| !before.contains_key(*name)))
+        .filter(|name| before.get(*name) != after.get(*name))
+        .count();
+    if drifted == 0 {
+        info!("✅ No drift since the last snapshot");
+        return Ok(0);
+    }
+    info!(
+        "🌊 {} applications drifted since the last snapshot",
+        drifted
+    );
+    Ok(if options.fail_on_drift { 1 } else { 0 })
+}
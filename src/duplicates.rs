@@ -0,0 +1,189 @@
+// Copy-pasted overlays render the same resources in several applications. Resources are compared
+// by their content without their metadata, so a copy with another name or namespace is found too.
+// All duplicates are written to 'duplicates.json', and the ones the target branch introduces are
+// listed in the report
+use crate::diff::ReportSection;
+use crate::manifests::{flatten, index_resources, read_rendered_apps, ResourceId};
+use crate::utils::fnv1a;
+use crate::Branch;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+// Resources with fewer fields (e.g. an empty Namespace) are alike in every application
+const MIN_FIELDS: usize = 5;
+// Share of fields two resources must have in common to be near-duplicates
+const SIMILARITY: f64 = 0.9;
+
+// A resource in an application
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Location {
+    application: String,
+    resource: ResourceId,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({})", self.resource, self.application)
+    }
+}
+
+// The fields of a resource that make up its content
+fn content(resource: &serde_yaml::Value) -> BTreeMap<String, String> {
+    let mut content = resource.clone();
+    if let Some(m) = content.as_mapping_mut() {
+        m.remove("metadata");
+        m.remove("status");
+    }
+    let mut fields = BTreeMap::new();
+    flatten(&content, "", &mut fields);
+    fields
+}
+
+fn read_contents(
+    output_folder: &str,
+    branch: &Branch,
+) -> BTreeMap<Location, BTreeMap<String, String>> {
+    let mut contents = BTreeMap::new();
+    for (application, manifest) in read_rendered_apps(output_folder, branch) {
+        for (resource, yaml) in index_resources(&manifest) {
+            let fields = content(&yaml);
+            if fields.len() >= MIN_FIELDS {
+                let location = Location {
+                    application: application.clone(),
+                    resource,
+                };
+                contents.insert(location, fields);
+            }
+        }
+    }
+    contents
+}
+
+fn hash(fields: &BTreeMap<String, String>) -> u64 {
+    let content: Vec<String> = fields.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    fnv1a(&content.join("\n"))
+}
+
+// Groups of identical resources in more than one application
+fn exact_duplicates(
+    contents: &BTreeMap<Location, BTreeMap<String, String>>,
+) -> Vec<BTreeSet<Location>> {
+    let mut groups: BTreeMap<u64, BTreeSet<Location>> = BTreeMap::new();
+    for (location, fields) in contents {
+        groups
+            .entry(hash(fields))
+            .or_default()
+            .insert(location.clone());
+    }
+    groups
+        .into_values()
+        .filter(|g| {
+            let applications: BTreeSet<&String> = g.iter().map(|l| &l.application).collect();
+            applications.len() > 1
+        })
+        .collect()
+}
+
+fn similarity(a: &BTreeMap<String, String>, b: &BTreeMap<String, String>) -> f64 {
+    let shared = a.iter().filter(|(k, v)| b.get(*k) == Some(v)).count();
+    let all = a.len() + b.len() - shared;
+    shared as f64 / all as f64
+}
+
+// The most similar resource of the same kind in another application, for each changed resource
+fn near_duplicates(
+    contents: &BTreeMap<Location, BTreeMap<String, String>>,
+    changed: &BTreeSet<&Location>,
+) -> Vec<(Location, Location, f64)> {
+    let mut near = vec![];
+    for location in changed {
+        let fields = &contents[*location];
+        let best = contents
+            .iter()
+            .filter(|(other, _)| {
+                other.application != location.application
+                    && other.resource.kind == location.resource.kind
+            })
+            .map(|(other, other_fields)| (other, similarity(fields, other_fields)))
+            .filter(|(_, s)| *s >= SIMILARITY && *s < 1.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        // two changed resources can be each other's best match
+        if let Some((other, s)) =
+            best.filter(|(other, _)| !near.iter().any(|(l, o, _)| l == *other && o == *location))
+        {
+            near.push(((*location).clone(), other.clone(), s));
+        }
+    }
+    near
+}
+
+fn location_json(location: &Location) -> serde_json::Value {
+    serde_json::json!({
+        "application": location.application,
+        "resource": location.resource.to_string(),
+    })
+}
+
+// Finds duplicated resources in the rendered manifests of the target branch, writes them to
+// 'duplicates.json' and lists the ones the target branch introduces
+pub fn generate_duplicates_section(
+    output_folder: &str,
+) -> Result<Option<ReportSection>, Box<dyn Error>> {
+    info!("♊ Looking for duplicated resources");
+    let base = read_contents(output_folder, &Branch::Base);
+    let target = read_contents(output_folder, &Branch::Target);
+    // resources that are added or changed by the target branch
+    let changed: BTreeSet<&Location> = target
+        .iter()
+        .filter(|(location, fields)| base.get(*location) != Some(*fields))
+        .map(|(location, _)| location)
+        .collect();
+
+    let exact = exact_duplicates(&target);
+    let near = near_duplicates(&target, &changed);
+    let introduced = |group: &BTreeSet<Location>| group.iter().any(|l| changed.contains(l));
+
+    let json = serde_json::json!({
+        "exact": exact.iter().map(|group| serde_json::json!({
+            "resources": group.iter().map(location_json).collect::<Vec<_>>(),
+            "introduced": introduced(group),
+        })).collect::<Vec<_>>(),
+        "near": near.iter().map(|(location, other, s)| serde_json::json!({
+            "resource": location_json(location),
+            "similarTo": location_json(other),
+            "similarity": (s * 100.0).round() / 100.0,
+        })).collect::<Vec<_>>(),
+    });
+    fs::write(
+        format!("{}/duplicates.json", output_folder),
+        serde_json::to_string_pretty(&json)?,
+    )?;
+
+    let mut rows: Vec<String> = exact
+        .iter()
+        .filter(|group| introduced(group))
+        .map(|group| {
+            let locations: Vec<String> = group.iter().map(|l| l.to_string()).collect();
+            format!("| identical | {} |", locations.join("<br>"))
+        })
+        .collect();
+    rows.extend(near.iter().map(|(location, other, s)| {
+        format!(
+            "| {}% similar | {}<br>{} |",
+            (s * 100.0).floor(),
+            location,
+            other
+        )
+    }));
+    if rows.is_empty() {
+        info!("♊ No duplicated resources introduced");
+        return Ok(None);
+    }
+
+    Ok(Some(ReportSection {
+        title: format!("♊ Duplicated resources ({}):", rows.len()),
+        content: format!("| Match | Resources |\n|---|---|\n{}", rows.join("\n")),
+    }))
+}
@@ -0,0 +1,235 @@
+// A cluster without Docker: etcd and kube-apiserver run as local processes, like controller-runtime's
+// envtest. The cluster has no nodes, which is fine, since applications are only rendered and never synced.
+// The processes listen on free ports of localhost, so several runs can share a machine
+use crate::run_command;
+use crate::workdir;
+use log::{debug, error, info};
+use std::error::Error;
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::sync::Mutex;
+
+// Processes of the cluster (and of Argo CD), stopped in reverse order when the cluster is deleted
+static PROCESSES: Mutex<Vec<(String, Child)>> = Mutex::new(Vec::new());
+
+// Holds the data of etcd, the certificates of the API server, the kubeconfig and the logs of all processes
+fn work_dir() -> PathBuf {
+    workdir::path("envtest")
+}
+
+// Binaries are looked up in KUBEBUILDER_ASSETS (where setup-envtest downloads them to) and then in PATH
+fn binary(name: &str) -> String {
+    std::env::var("KUBEBUILDER_ASSETS")
+        .ok()
+        .map(|dir| PathBuf::from(dir).join(name))
+        .filter(|path| path.is_file())
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| name.to_string())
+}
+
+// Different free ports on localhost. They are released right away, so another process could take
+// one before it is used, but that is unlikely
+pub fn free_ports<const N: usize>() -> Result<[String; N], Box<dyn Error>> {
+    let listeners = (0..N)
+        .map(|_| TcpListener::bind("127.0.0.1:0"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let ports = listeners
+        .iter()
+        .map(|l| Ok(l.local_addr()?.port().to_string()))
+        .collect::<Result<Vec<String>, std::io::Error>>()?;
+    Ok(ports.try_into().unwrap_or_else(|_| unreachable!()))
+}
+
+pub async fn is_installed() -> bool {
+    for name in ["etcd", "kube-apiserver"] {
+        if run_command(&format!("which {}", binary(name)), None)
+            .await
+            .is_err()
+        {
+            return false;
+        }
+    }
+    true
+}
+
+// Starts a process that runs until the cluster is deleted. Its output is written to '<name>.log' in the work directory
+pub fn start_process(
+    name: &str,
+    program: &str,
+    args: &[&str],
+    envs: &[(&str, &str)],
+) -> Result<(), Box<dyn Error>> {
+    let log_file = work_dir().join(format!("{}.log", name));
+    let log = fs::File::create(&log_file)?;
+    let child = Command::new(program)
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", name, e))?;
+    debug!(
+        "Started {} with pid {}. Logs: {}",
+        name,
+        child.id(),
+        log_file.display()
+    );
+    PROCESSES.lock().unwrap().push((name.to_string(), child));
+    Ok(())
+}
+
+fn stop_processes() {
+    let mut processes = PROCESSES.lock().unwrap();
+    while let Some((name, mut child)) = processes.pop() {
+        debug!("Stopping {}", name);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+pub async fn create_cluster() -> Result<(), Box<dyn Error>> {
+    info!("🚀 Creating cluster with local etcd and kube-apiserver...");
+    stop_processes();
+
+    let dir = work_dir();
+    fs::remove_dir_all(&dir).unwrap_or_default();
+    fs::create_dir_all(dir.join("certs"))?;
+    // the token and the kubeconfig are only readable by the user
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    let dir = dir.display().to_string();
+
+    // service account tokens are signed with this key
+    let service_account_key = format!("{}/sa.key", dir);
+    if let Err(e) = run_command(
+        &format!("openssl genrsa -out {} 2048", service_account_key),
+        None,
+    )
+    .await
+    {
+        error!("❌ Failed to generate service account key");
+        panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+    }
+    let [etcd_port, etcd_peer_port, api_server_port] = free_ports()?;
+    let etcd_url = format!("http://127.0.0.1:{}", etcd_port);
+    let etcd_peer_url = format!("http://127.0.0.1:{}", etcd_peer_port);
+    // the token of the admin user is generated per run, since anyone on the machine can reach the ports
+    let token = match run_command("openssl rand -hex 32", None).await {
+        Ok(o) => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+        Err(e) => {
+            error!("❌ Failed to generate the token of the API server");
+            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+        }
+    };
+    let token_file = format!("{}/tokens.csv", dir);
+    fs::write(
+        &token_file,
+        format!("{},admin,admin,system:masters\n", token),
+    )?;
+
+    start_process(
+        "etcd",
+        &binary("etcd"),
+        &[
+            "--data-dir",
+            &format!("{}/etcd", dir),
+            "--listen-client-urls",
+            &etcd_url,
+            "--advertise-client-urls",
+            &etcd_url,
+            "--listen-peer-urls",
+            &etcd_peer_url,
+            "--initial-advertise-peer-urls",
+            &etcd_peer_url,
+            "--initial-cluster",
+            &format!("default={}", etcd_peer_url),
+        ],
+        &[],
+    )?;
+
+    // the serving certificate is self-signed and generated in the cert dir
+    start_process(
+        "kube-apiserver",
+        &binary("kube-apiserver"),
+        &[
+            "--etcd-servers",
+            &etcd_url,
+            "--bind-address",
+            "127.0.0.1",
+            "--advertise-address",
+            "127.0.0.1",
+            "--secure-port",
+            &api_server_port,
+            "--cert-dir",
+            &format!("{}/certs", dir),
+            "--service-cluster-ip-range",
+            "10.0.0.0/24",
+            "--service-account-issuer",
+            "https://kubernetes.default.svc.cluster.local",
+            "--service-account-key-file",
+            &service_account_key,
+            "--service-account-signing-key-file",
+            &service_account_key,
+            "--token-auth-file",
+            &token_file,
+            "--authorization-mode",
+            "RBAC",
+            "--allow-privileged",
+        ],
+        &[],
+    )?;
+
+    let kubeconfig = format!("{}/kubeconfig", dir);
+    fs::write(
+        &kubeconfig,
+        format!(
+            r#"apiVersion: v1
+kind: Config
+clusters:
+  - name: envtest
+    cluster:
+      server: https://127.0.0.1:{}
+      insecure-skip-tls-verify: true
+users:
+  - name: envtest
+    user:
+      token: {}
+contexts:
+  - name: envtest
+    context:
+      cluster: envtest
+      user: envtest
+current-context: envtest
+"#,
+            api_server_port, token
+        ),
+    )?;
+    // kubectl, helm, argocd and the Argo CD components read the kubeconfig from the environment
+    std::env::set_var("KUBECONFIG", &kubeconfig);
+
+    for attempt in 1..=60 {
+        match run_command("kubectl get --raw /readyz", None).await {
+            Ok(_) => break,
+            Err(e) if attempt == 60 => {
+                error!("❌ kube-apiserver did not become ready. Logs: {}", dir);
+                panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+            }
+            Err(_) => tokio::time::sleep(tokio::time::Duration::from_secs(1)).await,
+        }
+    }
+
+    info!("🚀 Cluster created successfully");
+    Ok(())
+}
+
+pub fn delete_cluster() {
+    info!("💥 Deleting cluster...");
+    stop_processes();
+    // the logs are removed with the work directory, unless the run fails
+    fs::remove_dir_all(work_dir().join("etcd")).unwrap_or_default();
+}
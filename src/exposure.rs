@@ -0,0 +1,167 @@
+use crate::diff::ReportSection;
+use crate::manifests::{index_resources, read_rendered_apps, ResourceId};
+use crate::Branch;
+use log::info;
+use std::collections::BTreeSet;
+
+const EXPOSURE_KINDS: [&str; 3] = ["Ingress", "NetworkPolicy", "Service"];
+
+fn compact(value: &serde_yaml::Value) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+// Omitted peers or ports in a NetworkPolicy rule match everything
+fn compact_or_all(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::Null => "all".to_string(),
+        v => compact(v),
+    }
+}
+
+// Host, path and TLS entries of an Ingress
+fn ingress_entries(ingress: &serde_yaml::Value) -> BTreeSet<String> {
+    let mut entries = BTreeSet::new();
+    for rule in ingress["spec"]["rules"].as_sequence().into_iter().flatten() {
+        let host = rule["host"].as_str().unwrap_or("*");
+        entries.insert(format!("host `{}`", host));
+        for path in rule["http"]["paths"].as_sequence().into_iter().flatten() {
+            let backend = &path["backend"]["service"];
+            let port = match &backend["port"]["number"] {
+                serde_yaml::Value::Null => backend["port"]["name"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                number => compact(number),
+            };
+            entries.insert(format!(
+                "path `{}{}` → `{}:{}`",
+                host,
+                path["path"].as_str().unwrap_or("/"),
+                backend["name"].as_str().unwrap_or_default(),
+                port
+            ));
+        }
+    }
+    for tls in ingress["spec"]["tls"].as_sequence().into_iter().flatten() {
+        entries.insert(format!(
+            "TLS `{}` (secret `{}`)",
+            compact(&tls["hosts"]),
+            tls["secretName"].as_str().unwrap_or_default()
+        ));
+    }
+    if let Some(class) = ingress["spec"]["ingressClassName"].as_str() {
+        entries.insert(format!("class `{}`", class));
+    }
+    entries
+}
+
+// Policy types and ingress/egress rules of a NetworkPolicy
+fn network_policy_entries(policy: &serde_yaml::Value) -> BTreeSet<String> {
+    let spec = &policy["spec"];
+    let mut entries = BTreeSet::new();
+    entries.insert(format!("pods `{}`", compact(&spec["podSelector"])));
+    for policy_type in spec["policyTypes"].as_sequence().into_iter().flatten() {
+        entries.insert(format!(
+            "policy type `{}`",
+            policy_type.as_str().unwrap_or_default()
+        ));
+    }
+    for rule in spec["ingress"].as_sequence().into_iter().flatten() {
+        entries.insert(format!(
+            "ingress from `{}` ports `{}`",
+            compact_or_all(&rule["from"]),
+            compact_or_all(&rule["ports"])
+        ));
+    }
+    for rule in spec["egress"].as_sequence().into_iter().flatten() {
+        entries.insert(format!(
+            "egress to `{}` ports `{}`",
+            compact_or_all(&rule["to"]),
+            compact_or_all(&rule["ports"])
+        ));
+    }
+    entries
+}
+
+// Only Services reachable from outside the cluster are relevant for exposure
+fn service_entries(service: &serde_yaml::Value) -> BTreeSet<String> {
+    let spec = &service["spec"];
+    let service_type = spec["type"].as_str().unwrap_or("ClusterIP");
+    let mut entries = BTreeSet::new();
+    if service_type == "ClusterIP" && spec["externalIPs"].is_null() {
+        return entries;
+    }
+    entries.insert(format!("type `{}`", service_type));
+    for port in spec["ports"].as_sequence().into_iter().flatten() {
+        entries.insert(format!("port `{}`", compact(port)));
+    }
+    if !spec["externalIPs"].is_null() {
+        entries.insert(format!("external IPs `{}`", compact(&spec["externalIPs"])));
+    }
+    if !spec["loadBalancerSourceRanges"].is_null() {
+        entries.insert(format!(
+            "source ranges `{}`",
+            compact(&spec["loadBalancerSourceRanges"])
+        ));
+    }
+    entries
+}
+
+fn entries(resource: &serde_yaml::Value) -> BTreeSet<String> {
+    match resource["kind"].as_str() {
+        Some("Ingress") => ingress_entries(resource),
+        Some("NetworkPolicy") => network_policy_entries(resource),
+        Some("Service") => service_entries(resource),
+        _ => BTreeSet::new(),
+    }
+}
+
+pub fn generate_exposure_section(output_folder: &str) -> Option<ReportSection> {
+    info!("🌐 Looking for Ingress, NetworkPolicy and Service exposure changes");
+
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let mut blocks = vec![];
+    for name in names {
+        let base = index_resources(base_apps.get(name).map(|m| m.as_str()).unwrap_or_default());
+        let target = index_resources(
+            target_apps
+                .get(name)
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+        );
+        let ids: BTreeSet<&ResourceId> = base
+            .keys()
+            .chain(target.keys())
+            .filter(|id| EXPOSURE_KINDS.contains(&id.kind.as_str()))
+            .collect();
+
+        for id in ids {
+            let before = base.get(id).map(entries).unwrap_or_default();
+            let after = target.get(id).map(entries).unwrap_or_default();
+            if before == after {
+                continue;
+            }
+            let lines = after
+                .difference(&before)
+                .map(|e| format!("  - ➕ {}", e))
+                .chain(before.difference(&after).map(|e| format!("  - ➖ {}", e)))
+                .collect::<Vec<String>>();
+            blocks.push(format!("- **{}**: `{}`\n{}", name, id, lines.join("\n")));
+        }
+    }
+
+    if blocks.is_empty() {
+        info!("🌐 No exposure changes found");
+        return None;
+    }
+
+    info!("🌐 Found {} exposure changes", blocks.len());
+
+    Some(ReportSection {
+        title: format!("🌐 Network exposure changes ({}):", blocks.len()),
+        content: blocks.join("\n"),
+    })
+}
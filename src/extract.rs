@@ -1,9 +1,23 @@
-use crate::utils::run_command;
+use crate::conditions::{conditions_of, write_conditions};
+use crate::destructive;
+use crate::injected::{remove_injected_fields, InjectedFields};
+use crate::isolation::{self, Locks, RenderIsolation};
+use crate::manifests::{
+    parse_manifest, read_rendered_apps, strip_generated_fields, write_applications,
+};
+use crate::metrics;
+use crate::namespaces::{create_namespaces, NamespaceLabels};
+use crate::projects::{apply_projects, ProjectConfig};
+use crate::retry;
+use crate::sampling::{self, AppSetSample};
+use crate::utils::{run_command, run_command_from_list};
+use crate::workdir;
 use crate::{apply_manifest, apps_file, Branch};
 use log::{debug, error, info};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
 use std::{collections::BTreeMap, error::Error};
 
 static ERROR_MESSAGES: [&str; 10] = [
@@ -19,7 +33,7 @@ static ERROR_MESSAGES: [&str; 10] = [
     "Unknown desc = repository not found",
 ];
 
-static TIMEOUT_MESSAGES: [&str; 7] = [
+static TIMEOUT_MESSAGES: [&str; 9] = [
     "Client.Timeout",
     "failed to get git client for repo",
     "rpc error: code = Unknown desc = Get \"https",
@@ -27,31 +41,321 @@ static TIMEOUT_MESSAGES: [&str; 7] = [
     "Could not resolve host: github.com",
     ":8081: connect: connection refused",
     "Temporary failure in name resolution", // Attempt at fixing: https://github.com/dag-andersen/argocd-diff-preview/issues/44
+    // renders of the same repository that contend for its git lock
+    "index.lock': File exists",
+    "Another git process seems to be running",
 ];
 
+// Streams change events of Applications, so the status loop can continue as soon as an
+// application changes instead of sleeping for the full interval
+struct ApplicationWatch {
+    child: Option<Child>,
+    events: tokio::sync::mpsc::UnboundedReceiver<()>,
+}
+
+impl ApplicationWatch {
+    fn start() -> ApplicationWatch {
+        let (sender, events) = tokio::sync::mpsc::unbounded_channel();
+        let child = Command::new("kubectl")
+            .args("get applications -n argocd -o json --watch".split_whitespace())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let child = match child {
+            Ok(mut child) => {
+                let stdout = child.stdout.take().unwrap();
+                std::thread::spawn(move || {
+                    let stream = serde_json::Deserializer::from_reader(stdout)
+                        .into_iter::<serde_json::Value>();
+                    for event in stream {
+                        if event.is_err() || sender.send(()).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Some(child)
+            }
+            Err(e) => {
+                debug!(
+                    "Failed to watch applications, falling back to polling: {}",
+                    e
+                );
+                None
+            }
+        };
+        ApplicationWatch { child, events }
+    }
+
+    // Waits until an application changes or the interval has passed
+    async fn wait(&mut self, interval: Duration) {
+        match tokio::time::timeout(interval, self.events.recv()).await {
+            // the watch has stopped, so fall back to polling
+            Ok(None) => tokio::time::sleep(interval).await,
+            // give related events a moment to arrive, so they are handled together
+            Ok(Some(_)) => tokio::time::sleep(Duration::from_millis(500)).await,
+            Err(_) => (),
+        }
+        while self.events.try_recv().is_ok() {}
+    }
+}
+
+impl Drop for ApplicationWatch {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// Splits the applications file into manifests of at most 'batch_size' resources. With
+// --render-isolation, applications that wait for the same lock are split into different batches
+fn split_into_batches(
+    app_file: &str,
+    batch_size: Option<usize>,
+    isolation: Option<(&RenderIsolation, &Locks)>,
+) -> Result<VecDeque<String>, Box<dyn Error>> {
+    let content = fs::read_to_string(app_file)?;
+    let batch_size = batch_size.filter(|b| *b > 0);
+    let batches = match (batch_size, isolation) {
+        (_, Some((isolation, locks))) => {
+            isolation.split(parse_manifest(&content), batch_size, locks)
+        }
+        (Some(batch_size), None) => parse_manifest(&content)
+            .chunks(batch_size)
+            .map(|chunk| chunk.to_vec())
+            .collect(),
+        (None, None) => {
+            return Ok(match content.trim().is_empty() {
+                true => VecDeque::new(),
+                false => VecDeque::from([content]),
+            })
+        }
+    };
+    batches
+        .iter()
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(serde_yaml::to_string)
+                .collect::<Result<Vec<String>, _>>()
+                .map(|docs| docs.join("---\n"))
+                .map_err(|e| e.into())
+        })
+        .collect()
+}
+
+fn apply_batch(branch_type: &Branch, batch: &str) {
+    let file = workdir::path(&format!("{}-batch.yaml", branch_type));
+    fs::write(&file, batch).expect("failed to write batch of applications");
+    if let Err(e) = apply_manifest(file.to_str().unwrap()) {
+        error!(
+            "❌ Failed to apply applications for branch: {}",
+            branch_type
+        );
+        panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+    }
+}
+
+// Number of pending requests in the repo-server, read from its metrics through the API server
+async fn repo_server_pending_requests() -> Option<u64> {
+    let pod = run_command(
+        "kubectl get pods -n argocd -l app.kubernetes.io/name=argocd-repo-server -o jsonpath={.items[0].metadata.name}",
+        None,
+    )
+    .await
+    .ok()?;
+    let pod = String::from_utf8_lossy(&pod.stdout).trim().to_string();
+    let metrics = run_command(
+        &format!(
+            "kubectl get --raw /api/v1/namespaces/argocd/pods/{}:8084/proxy/metrics",
+            pod
+        ),
+        None,
+    )
+    .await
+    .ok()?;
+    String::from_utf8_lossy(&metrics.stdout)
+        .lines()
+        .filter(|l| l.starts_with("argocd_repo_pending_request_total"))
+        .filter_map(|l| l.split_whitespace().last()?.parse::<f64>().ok())
+        .map(|v| v as u64)
+        .reduce(|a, b| a + b)
+}
+
+pub struct RenderOptions {
+    // Seconds to wait for all applications to be rendered
+    pub timeout: u64,
+    // Applications applied to the cluster at once. All at once when not set
+    pub batch_size: Option<usize>,
+    // Remove fields that tools generate into manifests, like timestamps and UIDs
+    pub strip_generated_fields: bool,
+    // Remove status, managed fields, fields that admission webhooks inject and the ignored
+    // differences of the resource customizations
+    pub injected_fields: Option<InjectedFields>,
+    // Stop at the first application of the target branch that deletes a resource of these kinds.
+    // Nothing is stopped when empty
+    pub protected_kinds: Vec<String>,
+    // Keep the applications rendered by a failed run and add the ones rendered now (--retry-failed)
+    pub keep_rendered: bool,
+    // Labels of the destination namespaces, which are created before the applications are applied
+    pub namespace_labels: Option<NamespaceLabels>,
+    // Applications keep their projects, which are applied before the applications (--keep-projects)
+    pub projects: Option<ProjectConfig>,
+    // Only a sample of the applications of each ApplicationSet is rendered (--appset-sample)
+    pub appset_sample: Option<AppSetSample>,
+    // Applications that share a locked path of a repository are rendered one at a time
+    // (--render-isolation)
+    pub isolation: Option<RenderIsolation>,
+}
+
+// Records the applications rendered so far, so a retry only renders the missing ones
+fn record_failure(
+    branch_type: &Branch,
+    output_folder: &str,
+    options: &RenderOptions,
+    processed_applications: &[serde_yaml::Value],
+    failed: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    store_applications(branch_type, output_folder, options, processed_applications)?;
+    retry::write_run_info(output_folder, branch_type, failed)
+}
+
+fn store_applications(
+    branch_type: &Branch,
+    output_folder: &str,
+    options: &RenderOptions,
+    processed_applications: &[serde_yaml::Value],
+) -> Result<(), Box<dyn Error>> {
+    match options.keep_rendered {
+        true => {
+            let merged =
+                retry::merge_applications(output_folder, branch_type, processed_applications);
+            write_applications(output_folder, branch_type, &merged)
+        }
+        false => write_applications(output_folder, branch_type, processed_applications),
+    }
+}
+
+// Written instead of the manifests of an application that the target branch broke, so the diff, the
+// verdict and the labels show it as changed, with the error as comments
+fn broken_marker(message: &str) -> String {
+    let lines: Vec<String> = message.lines().map(|l| format!("# {}", l)).collect();
+    format!(
+        "# argocd-diff-preview: the application failed to render in the target branch\n{}\n",
+        lines.join("\n")
+    )
+}
+
+// Describes the deletion of protected resources by an application of the target branch, compared to
+// its manifests of the base branch
+fn destructive_change(
+    branch_type: &Branch,
+    output_folder: &str,
+    name: &str,
+    manifest: &str,
+    protected_kinds: &[String],
+) -> Option<String> {
+    if protected_kinds.is_empty() || !matches!(branch_type, Branch::Target) {
+        return None;
+    }
+    let base = fs::read_to_string(format!("{}/{}/{}", output_folder, Branch::Base, name)).ok()?;
+    let deleted = destructive::deleted_resources(&base, manifest, protected_kinds);
+    if deleted.is_empty() {
+        return None;
+    }
+    let deleted: Vec<String> = deleted.iter().map(|id| id.to_string()).collect();
+    error!(
+        "❌ Application {} deletes {}. Stopping (--fail-fast-on destructive)",
+        name,
+        deleted.join(", ")
+    );
+    Some(format!(
+        "Stopped at the first destructive change: application {} deletes {}",
+        name,
+        deleted.join(", ")
+    ))
+}
+
+// Describes the deletion of protected resources by applications of the base branch that are missing
+// in the target branch. Only known once all applications of the target branch are rendered
+fn removed_applications_change(
+    branch_type: &Branch,
+    output_folder: &str,
+    options: &RenderOptions,
+) -> Option<String> {
+    if options.protected_kinds.is_empty() || !matches!(branch_type, Branch::Target) {
+        return None;
+    }
+    let deleted = destructive::deleted_by_removed_applications(
+        &read_rendered_apps(output_folder, &Branch::Base),
+        &read_rendered_apps(output_folder, &Branch::Target),
+        &options.protected_kinds,
+    );
+    let (name, deleted) = deleted.into_iter().next()?;
+    let deleted: Vec<String> = deleted.iter().map(|id| id.to_string()).collect();
+    error!(
+        "❌ Application {} is removed and deletes {}. Stopping (--fail-fast-on destructive)",
+        name,
+        deleted.join(", ")
+    );
+    Some(format!(
+        "Stopped at the first destructive change: application {} is removed and deletes {}",
+        name,
+        deleted.join(", ")
+    ))
+}
+
+// Renders the applications of the branch into the output folder. 'checkout' is the folder the
+// branch is checked out in
 pub async fn get_resources(
     branch_type: &Branch,
-    timeout: u64,
     output_folder: &str,
+    checkout: &str,
+    options: &RenderOptions,
 ) -> Result<(), Box<dyn Error>> {
     info!("🌚 Getting resources from {}", branch_type);
+    let (timeout, batch_size) = (options.timeout, options.batch_size);
 
     let app_file = apps_file(branch_type);
 
-    if fs::metadata(app_file).unwrap().len() != 0 {
-        if let Err(e) = apply_manifest(app_file) {
-            error!(
-                "❌ Failed to apply applications for branch: {}",
-                branch_type
-            );
-            panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+    if let Some(config) = &options.projects {
+        apply_projects(branch_type, config).await?;
+    }
+    if let Some(labels) = &options.namespace_labels {
+        create_namespaces(&app_file, labels).await?;
+    }
+
+    let locks = match &options.isolation {
+        Some(isolation) => {
+            isolation.locks(&parse_manifest(&fs::read_to_string(&app_file)?), checkout)
+        }
+        None => BTreeMap::new(),
+    };
+    let mut batches = split_into_batches(
+        &app_file,
+        batch_size,
+        options.isolation.as_ref().map(|i| (i, &locks)),
+    )?;
+    let batch_count = batches.len();
+    if let Some(batch) = batches.pop_front() {
+        if batch_count > 1 {
+            info!("📦 Applying batch 1/{} of applications", batch_count);
         }
+        apply_batch(branch_type, &batch);
     }
 
     let mut set_of_processed_apps = HashSet::new();
+    let mut processed_applications = vec![];
     let mut set_of_failed_apps = BTreeMap::new();
+    // conditions of all rendered applications, as last seen
+    let mut conditions = BTreeMap::new();
+    // generated applications that were sampled or left out (--appset-sample)
+    let mut sampled = HashSet::new();
+    let mut left_out = HashSet::new();
 
     let start_time = std::time::Instant::now();
+    let mut watch = ApplicationWatch::start();
 
     loop {
         let output = run_command("kubectl get applications -n argocd -oyaml", None)
@@ -61,14 +365,39 @@ pub async fn get_resources(
             serde_yaml::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
 
         let items = applications["items"].as_sequence().unwrap();
-        if items.is_empty() {
+        for item in items {
+            // applications that aren't sampled are never rendered, so their conditions don't count
+            let held = options.appset_sample.is_some() && sampling::is_held(item);
+            match item["metadata"]["name"].as_str() {
+                Some(name) if !held && !left_out.contains(name) => {
+                    conditions.insert(name.to_string(), conditions_of(item));
+                }
+                _ => (),
+            }
+        }
+        if items.is_empty() && batches.is_empty() {
             break;
         }
 
-        if items.len() == set_of_processed_apps.len() {
+        if items.len() == set_of_processed_apps.len() && batches.is_empty() {
             break;
         }
 
+        if let Some(sample) = &options.appset_sample {
+            for (item, selected) in sample.select(branch_type, items, &sampled).await? {
+                let name = item["metadata"]["name"].as_str().unwrap_or_default();
+                match selected {
+                    true => sampling::release(item).await?,
+                    false => {
+                        set_of_processed_apps.insert(name.to_string());
+                        left_out.insert(name.to_string());
+                        conditions.remove(name);
+                    }
+                }
+                sampled.insert(name.to_string());
+            }
+        }
+
         let mut list_of_timed_out_apps = vec![];
         let mut other_errors = vec![];
 
@@ -79,23 +408,52 @@ pub async fn get_resources(
             if set_of_processed_apps.contains(name) {
                 continue;
             }
+            // the application is rendered once it is sampled
+            if options.appset_sample.is_some() && sampling::is_held(item) {
+                apps_left += 1;
+                continue;
+            }
             match item["status"]["sync"]["status"].as_str() {
                 Some("OutOfSync") | Some("Synced") => {
                     debug!("Getting manifests for application: {}", name);
                     match run_command(&format!("argocd app manifests {}", name), None).await {
                         Ok(o) => {
+                            let manifest = String::from_utf8_lossy(&o.stdout);
+                            let manifest = match options.strip_generated_fields {
+                                true => strip_generated_fields(&manifest),
+                                false => manifest.to_string(),
+                            };
+                            let manifest = match &options.injected_fields {
+                                Some(fields) => remove_injected_fields(&manifest, fields),
+                                None => manifest,
+                            };
+                            if let Some(deleted) = destructive_change(
+                                branch_type,
+                                output_folder,
+                                name,
+                                &manifest,
+                                &options.protected_kinds,
+                            ) {
+                                return Err(deleted.into());
+                            }
                             fs::write(
                                 format!("{}/{}/{}", output_folder, branch_type, name),
-                                &o.stdout,
+                                manifest,
                             )?;
+                            metrics::application_rendered(branch_type);
                             debug!("Got manifests for application: {}", name)
                         }
-                        Err(e) => error!("error: {}", String::from_utf8_lossy(&e.stderr)),
+                        Err(e) => {
+                            metrics::applications_failed(branch_type, 1);
+                            error!("error: {}", String::from_utf8_lossy(&e.stderr))
+                        }
                     }
                     set_of_processed_apps.insert(name.to_string().clone());
+                    processed_applications.push(item.clone());
                     continue;
                 }
                 Some("Unknown") => {
+                    let mut broken = None;
                     if let Some(conditions) = item["status"]["conditions"].as_sequence() {
                         for condition in conditions {
                             if let Some(t) = condition["type"].as_str() {
@@ -104,8 +462,17 @@ pub async fn get_resources(
                                         Some(msg)
                                             if ERROR_MESSAGES.iter().any(|e| msg.contains(e)) =>
                                         {
-                                            set_of_failed_apps
-                                                .insert(name.to_string().clone(), msg.to_string());
+                                            match branch_type {
+                                                // the base branch was rendered, so the change broke
+                                                // the application. It is reported with its error
+                                                Branch::Target => broken = Some(msg.to_string()),
+                                                Branch::Base => {
+                                                    set_of_failed_apps.insert(
+                                                        name.to_string().clone(),
+                                                        msg.to_string(),
+                                                    );
+                                                }
+                                            }
                                             continue;
                                         }
                                         Some(msg)
@@ -123,6 +490,17 @@ pub async fn get_resources(
                             }
                         }
                     }
+                    if let Some(msg) = broken {
+                        error!("❌ Failed to render application: {}", name);
+                        metrics::applications_failed(branch_type, 1);
+                        // the diff shows the application as broken instead of its manifests
+                        fs::write(
+                            format!("{}/{}/{}", output_folder, branch_type, name),
+                            broken_marker(&msg),
+                        )?;
+                        set_of_processed_apps.insert(name.to_string());
+                        continue;
+                    }
                 }
                 _ => (),
             }
@@ -131,17 +509,58 @@ pub async fn get_resources(
 
         // ERRORS
         if !set_of_failed_apps.is_empty() {
+            metrics::applications_failed(branch_type, set_of_failed_apps.len());
             for (name, msg) in &set_of_failed_apps {
                 error!(
                     "❌ Failed to process application: {} with error: \n{}",
                     name, msg
                 );
             }
+            write_conditions(output_folder, branch_type, &conditions)?;
+            record_failure(
+                branch_type,
+                output_folder,
+                options,
+                &processed_applications,
+                &set_of_failed_apps,
+            )?;
             return Err("Failed to process applications".into());
         }
 
+        // BATCHES
+        // the next batch is applied once the applications in flight and the repo-server queue have
+        // drained, and none of the applications in flight renders a locked path of the batch
+        if let Some(next) = batches.front() {
+            let drained = match batch_size {
+                Some(size) => {
+                    let pending = repo_server_pending_requests().await;
+                    debug!(
+                        "{} applications in flight, {:?} pending repo-server requests",
+                        apps_left, pending
+                    );
+                    apps_left < size && pending.unwrap_or_default() < size as u64
+                }
+                None => true,
+            };
+            let in_flight: Vec<&str> = items
+                .iter()
+                .filter_map(|i| i["metadata"]["name"].as_str())
+                .filter(|name| !set_of_processed_apps.contains(*name))
+                .collect();
+            if drained && !isolation::conflicts(&parse_manifest(next), &in_flight, &locks) {
+                info!(
+                    "📦 Applying batch {}/{} of applications",
+                    batch_count - batches.len() + 1,
+                    batch_count
+                );
+                apply_batch(branch_type, &batches.pop_front().unwrap());
+                watch.wait(Duration::from_secs(5)).await;
+                continue;
+            }
+        }
+
         if items.len() == set_of_processed_apps.len() {
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            watch.wait(Duration::from_secs(5)).await;
             continue;
         }
 
@@ -160,6 +579,22 @@ pub async fn get_resources(
                     error!("❌ {}, {}", name, msg);
                 }
             }
+            let mut timed_out: BTreeMap<String, String> = items
+                .iter()
+                .filter_map(|i| i["metadata"]["name"].as_str())
+                .filter(|name| !set_of_processed_apps.contains(*name))
+                .map(|name| (name.to_string(), "timed out".to_string()))
+                .collect();
+            timed_out.extend(other_errors);
+            metrics::applications_failed(branch_type, timed_out.len());
+            write_conditions(output_folder, branch_type, &conditions)?;
+            record_failure(
+                branch_type,
+                output_folder,
+                options,
+                &processed_applications,
+                &timed_out,
+            )?;
             return Err("Timed out".into());
         }
 
@@ -183,15 +618,21 @@ pub async fn get_resources(
 
         if apps_left > 0 {
             info!(
-                "⏳ Waiting for {} out of {} applications to become 'OutOfSync'. Retrying in 5 seconds. Timeout in {} seconds...",
+                "⏳ Waiting for {} out of {} applications to become 'OutOfSync'. Retrying on change or in 5 seconds. Timeout in {} seconds...",
                 apps_left,
                 items.len(),
                 timeout - time_elapsed
             );
         }
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        watch.wait(Duration::from_secs(5)).await;
+    }
+
+    if let Some(deleted) = removed_applications_change(branch_type, output_folder, options) {
+        return Err(deleted.into());
     }
+    store_applications(branch_type, output_folder, options, &processed_applications)?;
+    write_conditions(output_folder, branch_type, &conditions)?;
 
     info!(
         "🌚 Got all resources from {} applications for {}",
@@ -202,6 +643,77 @@ pub async fn get_resources(
     Ok(())
 }
 
+// Writes the manifests of the applications of an existing Argo CD to the output folder, in the layout
+// of a run (e.g. 'output/base/<application>'), without applying anything. The argocd CLI connects
+// with the options set by the caller
+pub async fn extract_existing_applications(
+    branch_type: &Branch,
+    output_folder: &str,
+    selector: Option<&str>,
+    strip: bool,
+) -> Result<usize, Box<dyn Error>> {
+    info!("🌚 Getting existing applications");
+    let mut command = vec!["argocd", "app", "list", "-o", "yaml"];
+    if let Some(selector) = selector {
+        command.extend(["-l", selector]);
+    }
+    let output = run_command_from_list(command, None).await.map_err(|e| {
+        format!(
+            "failed to list applications: {}",
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })?;
+    let applications: Vec<serde_yaml::Value> =
+        serde_yaml::from_str(&String::from_utf8_lossy(&output.stdout))?;
+
+    let folder = format!("{}/{}", output_folder, branch_type);
+    fs::remove_dir_all(&folder).unwrap_or_default();
+    fs::create_dir_all(&folder)?;
+    // with applications in any namespace, the file of an application is still named after it, so
+    // two applications of the same name in different namespaces can't both be written
+    let mut written: HashMap<&str, String> = HashMap::new();
+    for application in &applications {
+        let name = application["metadata"]["name"]
+            .as_str()
+            .ok_or("application without a name")?;
+        let qualified_name = match application["metadata"]["namespace"].as_str() {
+            Some(namespace) => format!("{}/{}", namespace, name),
+            None => name.to_string(),
+        };
+        if let Some(other) = written.insert(name, qualified_name.clone()) {
+            return Err(format!(
+                "applications {} and {} have the same name. Select one of them with --selector",
+                other, qualified_name
+            )
+            .into());
+        }
+        debug!("Getting manifests for application: {}", qualified_name);
+        let output = run_command(&format!("argocd app manifests {}", qualified_name), None)
+            .await
+            .map_err(|e| {
+                format!(
+                    "failed to get manifests of application {}: {}",
+                    qualified_name,
+                    String::from_utf8_lossy(&e.stderr)
+                )
+            })?;
+        let manifest = String::from_utf8_lossy(&output.stdout);
+        let manifest = match strip {
+            true => strip_generated_fields(&manifest),
+            false => manifest.to_string(),
+        };
+        fs::write(format!("{}/{}", folder, name), manifest)?;
+    }
+    write_applications(output_folder, branch_type, &applications)?;
+
+    info!(
+        "🌚 Got all resources from {} applications for {}",
+        applications.len(),
+        branch_type
+    );
+    Ok(applications.len())
+}
+
 pub async fn delete_applications() {
     info!("🧼 Removing applications");
     loop {
@@ -242,6 +754,7 @@ pub async fn delete_applications() {
             .unwrap_or_default()
         {
             let _ = child.kill();
+            let _ = child.wait();
             break;
         }
 
@@ -253,6 +766,7 @@ pub async fn delete_applications() {
             .unwrap_or_default()
         {
             let _ = child.kill();
+            let _ = child.wait();
             break;
         }
 
@@ -260,6 +774,7 @@ pub async fn delete_applications() {
             Ok(_) => debug!("Timed out. Retrying..."),
             Err(e) => error!("❌ Failed to delete applications: {}", e),
         };
+        let _ = child.wait();
     }
     info!("🧼 Removed applications successfully")
 }
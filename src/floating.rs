@@ -0,0 +1,171 @@
+use crate::diff::ReportSection;
+use crate::manifests::{parse_manifest, read_rendered_apps};
+use crate::parsing::get_resources_of_kinds;
+use crate::repo_url::RepoUrl;
+use crate::Branch;
+use log::info;
+use regex::Regex;
+use std::collections::BTreeSet;
+
+// A reference that can point to other content on the next render, which makes previews (and
+// deployments) non-reproducible
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct FloatingReference {
+    application: String,
+    reference: String,
+    reason: &'static str,
+}
+
+// Git revisions are pinned when they are a full commit SHA or look like a tag (e.g. 'v1.2.3'). An
+// abbreviated SHA can be a branch name as well, so it isn't known to be pinned
+fn floating_git_revision(revision: &str) -> Option<&'static str> {
+    let sha = Regex::new(r"^[0-9a-fA-F]{40}$").unwrap();
+    let tag = Regex::new(r"^v?\d+(\.\d+)*([-+].+)?$").unwrap();
+    match revision {
+        "" | "HEAD" => Some("follows the default branch"),
+        r if sha.is_match(r) || tag.is_match(r) => None,
+        _ => Some("follows a branch"),
+    }
+}
+
+// Chart versions are pinned unless they are a range, e.g. '>=1.0.0', '^1.2', '1.x' or '*'
+fn floating_chart_version(version: &str) -> Option<&'static str> {
+    let range = Regex::new(r"[*^~<>|]|(^|\.)[xX](\.|$)| - ").unwrap();
+    match version {
+        "" | "latest" => Some("uses the latest chart version"),
+        v if range.is_match(v) => Some("uses a chart version range"),
+        _ => None,
+    }
+}
+
+// Images are pinned by a digest or a tag other than 'latest'
+fn floating_image(image: &str) -> Option<&'static str> {
+    if image.contains('@') {
+        return None;
+    }
+    // a ':' before the last '/' belongs to a registry port
+    let name = image.rsplit('/').next().unwrap_or(image);
+    match name.split_once(':') {
+        None => Some("has no tag, so it uses 'latest'"),
+        Some((_, "latest")) => Some("uses the 'latest' tag"),
+        Some(_) => None,
+    }
+}
+
+// Sources of the repositories under preview are expected to follow a branch and are skipped.
+// Templated values of ApplicationSets can't be judged before they are generated
+fn source_references(
+    application: &str,
+    source: &serde_yaml::Value,
+    repos: &[RepoUrl],
+    out: &mut BTreeSet<FloatingReference>,
+) {
+    let repo_url = source["repoURL"].as_str().unwrap_or_default();
+    let revision = source["targetRevision"].as_str().unwrap_or_default();
+    if repo_url.contains("{{") || revision.contains("{{") {
+        return;
+    }
+    let reason = match source["chart"].as_str() {
+        Some(_) => floating_chart_version(revision),
+        None if repos.iter().any(|r| r.matches(&RepoUrl::parse(repo_url))) => None,
+        None => floating_git_revision(revision),
+    };
+    if let Some(reason) = reason {
+        let reference = match source["chart"].as_str() {
+            Some(chart) => format!("{} {}@{}", repo_url, chart, revision),
+            None => format!("{}@{}", repo_url, revision),
+        };
+        out.insert(FloatingReference {
+            application: application.to_string(),
+            reference,
+            reason,
+        });
+    }
+}
+
+fn image_references(
+    application: &str,
+    value: &serde_yaml::Value,
+    out: &mut BTreeSet<FloatingReference>,
+) {
+    match value {
+        serde_yaml::Value::Mapping(m) => {
+            for (key, value) in m {
+                if matches!(
+                    key.as_str(),
+                    Some("containers" | "initContainers" | "ephemeralContainers")
+                ) {
+                    for container in value.as_sequence().into_iter().flatten() {
+                        let image = container["image"].as_str().unwrap_or_default();
+                        if let Some(reason) = floating_image(image).filter(|_| !image.is_empty()) {
+                            out.insert(FloatingReference {
+                                application: application.to_string(),
+                                reference: image.to_string(),
+                                reason,
+                            });
+                        }
+                    }
+                }
+                image_references(application, value, out);
+            }
+        }
+        serde_yaml::Value::Sequence(s) => {
+            for value in s {
+                image_references(application, value, out);
+            }
+        }
+        _ => (),
+    }
+}
+
+// Lists the sources and images of the target branch that aren't pinned: Git revisions following a
+// branch, chart version ranges and images without a tag or with the 'latest' tag
+pub async fn generate_floating_section(
+    output_folder: &str,
+    target_folders: &[String],
+    regex: &Option<Regex>,
+    repos: &[&str],
+) -> Option<ReportSection> {
+    info!("🎈 Looking for floating references");
+    let repos: Vec<RepoUrl> = repos.iter().map(|r| RepoUrl::parse(r)).collect();
+
+    let mut references = BTreeSet::new();
+    for folder in target_folders {
+        for resource in
+            get_resources_of_kinds(folder, regex, &["Application", "ApplicationSet"]).await
+        {
+            let name = resource["metadata"]["name"].as_str().unwrap_or_default();
+            let spec = match resource["kind"].as_str() {
+                Some("ApplicationSet") => &resource["spec"]["template"]["spec"],
+                _ => &resource["spec"],
+            };
+            let sources = spec["sources"].as_sequence().into_iter().flatten();
+            for source in sources.chain(Some(&spec["source"]).filter(|s| s.is_mapping())) {
+                source_references(name, source, &repos, &mut references);
+            }
+        }
+    }
+    for (application, manifest) in read_rendered_apps(output_folder, &Branch::Target) {
+        for resource in parse_manifest(&manifest) {
+            image_references(&application, &resource, &mut references);
+        }
+    }
+
+    if references.is_empty() {
+        info!("🎈 No floating references found");
+        return None;
+    }
+
+    let rows = references
+        .iter()
+        .map(|r| format!("| {} | `{}` | {} |", r.application, r.reference, r.reason))
+        .collect::<Vec<String>>()
+        .join("\n");
+    Some(ReportSection {
+        title: format!("🎈 Floating references ({}):", references.len()),
+        content: format!(
+            "| Application | Reference | Reason |\n|---|---|---|\n{}",
+            rows
+        ),
+    })
+}
@@ -0,0 +1,84 @@
+// Merge bots (e.g. Mergify or Kodiak) gate pull requests on simple conditions. Every run writes
+// 'gate.json' with whether anything changed, whether anything is removed and the policies that
+// failed, so their rules don't have to parse the report. Fields are only ever added, and
+// 'version' changes if a field changes its meaning
+use crate::conditions::broken_applications;
+use crate::protected::ProtectedChange;
+use crate::verdict::{collect_facts, Verdict};
+use log::info;
+use std::error::Error;
+use std::fs;
+
+const VERSION: u32 = 1;
+
+fn gate_file(output_folder: &str) -> String {
+    format!("{}/gate.json", output_folder)
+}
+
+// A bot must not gate on the file of an earlier run when this run fails
+pub fn remove_gate(output_folder: &str) {
+    fs::remove_file(gate_file(output_folder)).unwrap_or_default();
+}
+
+pub fn write_gate(
+    output_folder: &str,
+    verdict: Option<Verdict>,
+    failed_block_conditions: &[String],
+    protected_changes: &[ProtectedChange],
+) -> Result<(), Box<dyn Error>> {
+    let facts = collect_facts(output_folder);
+    let broken = broken_applications(output_folder);
+
+    let failure = |policy: &str, subject: String, message: String| {
+        serde_json::json!({
+            "policy": policy,
+            "subject": subject,
+            "message": message,
+        })
+    };
+    let mut policy_failures = vec![];
+    if verdict == Some(Verdict::Block) {
+        policy_failures.extend(failed_block_conditions.iter().map(|c| {
+            failure(
+                "verdict",
+                c.clone(),
+                format!("block condition '{}' doesn't hold", c),
+            )
+        }));
+    }
+    policy_failures.extend(
+        protected_changes
+            .iter()
+            .map(|c| failure("protected-resources", c.resource.to_string(), c.to_string())),
+    );
+    policy_failures.extend(broken.iter().map(|(name, errors)| {
+        let types: Vec<&str> = errors.iter().map(|e| e.condition_type.as_str()).collect();
+        failure(
+            "broken-application",
+            name.clone(),
+            format!("application fails with {}", types.join(", ")),
+        )
+    }));
+
+    let gate = serde_json::json!({
+        "version": VERSION,
+        "changed": facts.changed_resources > 0,
+        "destructive": facts.deleted_resources > 0,
+        "changed_applications": facts.changed_applications,
+        "changed_resources": facts.changed_resources,
+        "deleted_resources": facts.deleted_resources,
+        "verdict": verdict.map(|v| v.to_string()),
+        "policy_failures": policy_failures,
+    });
+    fs::write(
+        gate_file(output_folder),
+        serde_json::to_string_pretty(&gate)?,
+    )?;
+    info!(
+        "🚧 Gate: changed={}, destructive={}, {} policy failures",
+        facts.changed_resources > 0,
+        facts.deleted_resources > 0,
+        policy_failures.len()
+    );
+    Ok(())
+}
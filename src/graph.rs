@@ -0,0 +1,181 @@
+use crate::diff::ReportSection;
+use crate::manifests::{parse_manifest, read_rendered_apps};
+use crate::{apps_file, Branch};
+use log::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+#[derive(PartialEq)]
+enum NodeKind {
+    Application,
+    ApplicationSet,
+    Source,
+}
+
+struct Node {
+    kind: NodeKind,
+    changed: bool,
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: BTreeMap<String, Node>,
+    // (from, to, is_source)
+    edges: BTreeSet<(String, String, bool)>,
+}
+
+impl Graph {
+    fn add_node(&mut self, name: &str, kind: NodeKind, changed: bool) {
+        let node = self
+            .nodes
+            .entry(name.to_string())
+            .or_insert(Node { kind, changed });
+        node.changed |= changed;
+    }
+
+    // Adds the sources of a multi-source application as dependencies of the application
+    fn add_sources(&mut self, app: &str, spec: &serde_yaml::Value) {
+        let sources = match spec["sources"].as_sequence() {
+            Some(s) if s.len() > 1 => s,
+            _ => return,
+        };
+        for source in sources {
+            let Some(repo) = source["repoURL"].as_str() else {
+                continue;
+            };
+            let label = match (source["chart"].as_str(), source["path"].as_str()) {
+                (Some(chart), _) => format!("{} ({})", repo, chart),
+                (_, Some(path)) => format!("{}/{}", repo.trim_end_matches('/'), path),
+                _ => repo.to_string(),
+            };
+            self.add_node(&label, NodeKind::Source, false);
+            self.edges.insert((app.to_string(), label, true));
+        }
+    }
+
+    fn to_mermaid(&self) -> String {
+        let ids: BTreeMap<&String, String> = self
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(i, name)| (name, format!("n{}", i)))
+            .collect();
+
+        let mut lines = vec!["graph LR".to_string()];
+        for (name, node) in &self.nodes {
+            let label = name.replace('"', "'");
+            let shape = match node.kind {
+                NodeKind::Application => format!("{}[\"{}\"]", ids[name], label),
+                NodeKind::ApplicationSet => format!("{}{{{{\"{}\"}}}}", ids[name], label),
+                NodeKind::Source => format!("{}[(\"{}\")]", ids[name], label),
+            };
+            match node.changed {
+                true => lines.push(format!("  {}:::changed", shape)),
+                false => lines.push(format!("  {}", shape)),
+            }
+        }
+        for (from, to, is_source) in &self.edges {
+            let arrow = if *is_source { "-.->" } else { "-->" };
+            lines.push(format!("  {} {} {}", ids[from], arrow, ids[to]));
+        }
+        lines.push("  classDef changed fill:#f9a825,stroke:#f57f17,color:#000".to_string());
+        lines.join("\n")
+    }
+
+    fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph applications {".to_string(),
+            "  rankdir=LR;".to_string(),
+        ];
+        for (name, node) in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Application => "box",
+                NodeKind::ApplicationSet => "hexagon",
+                NodeKind::Source => "cylinder",
+            };
+            let style = match node.changed {
+                true => ", style=filled, fillcolor=orange",
+                false => "",
+            };
+            lines.push(format!("  {:?} [shape={}{}];", name, shape, style));
+        }
+        for (from, to, is_source) in &self.edges {
+            let style = if *is_source { " [style=dashed]" } else { "" };
+            lines.push(format!("  {:?} -> {:?}{};", from, to, style));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+pub fn generate_dependency_graph(output_folder: &str) -> Result<ReportSection, Box<dyn Error>> {
+    info!("🕸️ Generating application dependency graph");
+
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+
+    let mut graph = Graph::default();
+
+    for (branch, rendered_apps) in [(Branch::Base, &base_apps), (Branch::Target, &target_apps)] {
+        // Applications defined directly in the repository
+        let apps = fs::read_to_string(apps_file(&branch)).unwrap_or_default();
+        for app in parse_manifest(&apps) {
+            if let (Some("Application"), Some(name)) =
+                (app["kind"].as_str(), app["metadata"]["name"].as_str())
+            {
+                let changed = base_apps.get(name) != target_apps.get(name);
+                graph.add_node(name, NodeKind::Application, changed);
+                graph.add_sources(name, &app["spec"]);
+            }
+        }
+
+        // Applications and ApplicationSets rendered by other applications (app-of-apps)
+        for (parent, manifest) in rendered_apps {
+            let changed = base_apps.get(parent) != target_apps.get(parent);
+            graph.add_node(parent, NodeKind::Application, changed);
+            for child in parse_manifest(manifest) {
+                let Some(name) = child["metadata"]["name"].as_str() else {
+                    continue;
+                };
+                let (kind, spec) = match child["kind"].as_str() {
+                    Some("Application") => (NodeKind::Application, &child["spec"]),
+                    Some("ApplicationSet") => {
+                        (NodeKind::ApplicationSet, &child["spec"]["template"]["spec"])
+                    }
+                    _ => continue,
+                };
+                let changed =
+                    kind == NodeKind::Application && base_apps.get(name) != target_apps.get(name);
+                graph.add_node(name, kind, changed);
+                graph.add_sources(name, spec);
+                graph
+                    .edges
+                    .insert((parent.clone(), name.to_string(), false));
+            }
+        }
+    }
+
+    let changed_count = graph.nodes.values().filter(|n| n.changed).count();
+    debug!(
+        "🕸️ Dependency graph has {} nodes, {} edges and {} changed applications",
+        graph.nodes.len(),
+        graph.edges.len(),
+        changed_count
+    );
+
+    let dot_path = format!("{}/dependency-graph.dot", output_folder);
+    fs::write(&dot_path, graph.to_dot())?;
+    info!("🕸️ Wrote dependency graph to {}", dot_path);
+
+    Ok(ReportSection {
+        title: format!(
+            "Dependency graph ({} changed applications):",
+            changed_count
+        ),
+        content: format!(
+            "Changed applications are highlighted. Dashed arrows point to the sources of multi-source applications.\n\n```mermaid\n{}\n```",
+            graph.to_mermaid()
+        ),
+    })
+}
@@ -0,0 +1,79 @@
+use crate::manifests::read_applications;
+use crate::model::ApplicationSpec;
+use crate::Branch;
+use log::debug;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+// Applications without a group are listed last
+pub const UNGROUPED: &str = "ungrouped";
+
+// Decides which group (e.g. environment) an application belongs to
+pub enum GroupBy {
+    // The value of a label on the Application
+    Label(String),
+    // The first capture group of a regex matching a source path, e.g. 'overlays/(prod|staging)'
+    Path(Regex),
+}
+
+impl GroupBy {
+    // Parses 'label:<key>' or 'path:<regex>'
+    pub fn parse(s: &str) -> Result<GroupBy, Box<dyn Error>> {
+        match s.split_once(':') {
+            Some(("label", key)) if !key.trim().is_empty() => {
+                Ok(GroupBy::Label(key.trim().to_string()))
+            }
+            Some(("path", regex)) if !regex.trim().is_empty() => {
+                Ok(GroupBy::Path(Regex::new(regex.trim())?))
+            }
+            _ => Err(format!("invalid grouping: '{}'. Use label:<key> or path:<regex>", s).into()),
+        }
+    }
+
+    fn group(&self, application: &serde_yaml::Value) -> Option<String> {
+        match self {
+            GroupBy::Label(key) => application["metadata"]["labels"][key.as_str()]
+                .as_str()
+                .map(|v| v.to_string()),
+            GroupBy::Path(regex) => {
+                let spec = ApplicationSpec::of(application).ok()?;
+                let group = spec
+                    .sources()
+                    .filter_map(|source| source.path.as_deref())
+                    .find_map(|path| {
+                        let captures = regex.captures(path)?;
+                        captures
+                            .get(1)
+                            .or_else(|| captures.get(0))
+                            .map(|m| m.as_str().to_string())
+                    });
+                group
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GroupBy::Label(key) => write!(f, "label:{}", key),
+            GroupBy::Path(regex) => write!(f, "path:{}", regex.as_str()),
+        }
+    }
+}
+
+// Maps the name of every rendered application to its group. The target branch takes precedence
+pub fn group_applications(output_folder: &str, group_by: &GroupBy) -> BTreeMap<String, String> {
+    let mut groups = BTreeMap::new();
+    for branch in [Branch::Base, Branch::Target] {
+        for (name, application) in read_applications(output_folder, &branch) {
+            let group = group_by
+                .group(&application)
+                .unwrap_or_else(|| UNGROUPED.to_string());
+            debug!("Application {} belongs to group {}", name, group);
+            groups.insert(name, group);
+        }
+    }
+    groups
+}
@@ -0,0 +1,161 @@
+// A single generated resource (e.g. a bundled CRD with 40k lines) or an application that changes
+// everything at once can make the report unreadable and push every other application out of it.
+// Resources and application diffs above the limits are summarized in the diff instead of inlined
+use crate::manifests::{index_resources, ResourceId};
+use crate::utils::fnv1a;
+use crate::Branch;
+use log::info;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Default)]
+pub struct SizeGuards {
+    // Resources with more lines are replaced by a stub in the diff
+    pub max_resource_lines: Option<usize>,
+    // Diffs of applications with more lines are replaced by a list of the changed resources
+    pub max_app_diff_lines: Option<usize>,
+}
+
+// A resource that is too large to show, with its id, length and a checksum, so the stub still
+// changes when the resource does
+fn stub(document: &str, lines: usize, max_lines: usize) -> String {
+    let resource: serde_yaml::Value = serde_yaml::from_str(document).unwrap_or_default();
+    let mut metadata = serde_yaml::Mapping::new();
+    metadata.insert("name".into(), resource["metadata"]["name"].clone());
+    if let Some(namespace) = resource["metadata"]["namespace"].as_str() {
+        metadata.insert("namespace".into(), namespace.into());
+    }
+    let mut stub = serde_yaml::Mapping::new();
+    stub.insert("apiVersion".into(), resource["apiVersion"].clone());
+    stub.insert("kind".into(), resource["kind"].clone());
+    stub.insert("metadata".into(), metadata.into());
+    format!(
+        "{}# 📏 {} lines, more than --max-resource-lines ({}), are not shown. Checksum: {:016x}\n",
+        serde_yaml::to_string(&stub).unwrap_or_default(),
+        lines,
+        max_lines,
+        fnv1a(document)
+    )
+}
+
+// The manifest with every resource of more than 'max_lines' lines replaced by a stub, or None when
+// no resource is that large
+fn stub_large_resources(manifest: &str, max_lines: usize) -> Option<String> {
+    let mut documents = vec![String::new()];
+    for line in manifest.lines() {
+        match line {
+            "---" => documents.push(String::new()),
+            line => {
+                let document = documents.last_mut().unwrap();
+                document.push_str(line);
+                document.push('\n');
+            }
+        }
+    }
+    let mut stubbed = false;
+    let documents: Vec<String> = documents
+        .into_iter()
+        .map(|document| match document.lines().count() {
+            lines if lines > max_lines => {
+                stubbed = true;
+                stub(&document, lines, max_lines)
+            }
+            _ => document,
+        })
+        .collect();
+    stubbed.then(|| documents.join("---\n"))
+}
+
+// Writes the manifests of an application to 'folder', with large resources replaced by stubs, when
+// one of the branches has such a resource. Returns whether it did, in which case the application is
+// diffed in 'folder' instead of the output folder. 'base_name' is the name of the application in
+// the base branch, which differs when it was converted to or from an ApplicationSet
+pub fn guard_resources(
+    output_folder: &str,
+    folder: &Path,
+    base_name: &str,
+    name: &str,
+    max_lines: usize,
+) -> Result<bool, Box<dyn Error>> {
+    let manifests: Vec<(Branch, &str, Option<String>)> =
+        [(Branch::Base, base_name), (Branch::Target, name)]
+            .into_iter()
+            .map(|(branch, file)| {
+                let path = format!("{}/{}/{}", output_folder, branch, file);
+                let manifest = fs::read_to_string(path).ok();
+                (branch, file, manifest)
+            })
+            .collect();
+    let stubbed: Vec<Option<String>> = manifests
+        .iter()
+        .map(|(_, _, m)| stub_large_resources(m.as_deref()?, max_lines))
+        .collect();
+    if stubbed.iter().all(|s| s.is_none()) {
+        return Ok(false);
+    }
+    info!(
+        "📏 Application {} has resources with more than {} lines. They are summarized in the diff",
+        name, max_lines
+    );
+    for ((branch, file, manifest), stubbed) in manifests.iter().zip(stubbed) {
+        let folder = folder.join(branch.to_string());
+        fs::create_dir_all(&folder)?;
+        if let Some(manifest) = stubbed.or(manifest.clone()) {
+            fs::write(folder.join(file), manifest)?;
+        }
+    }
+    Ok(true)
+}
+
+// Replaces the diff of an application with more than 'max_lines' lines by its first line (the
+// header) and a list of the added, removed and changed resources
+pub fn summarize_diff(
+    output_folder: &str,
+    base_name: &str,
+    name: &str,
+    diff: &str,
+    max_lines: usize,
+) -> Option<String> {
+    let lines = diff.lines().count();
+    if lines <= max_lines {
+        return None;
+    }
+    info!(
+        "📏 The diff of application {} has {} lines. It is summarized in the report",
+        name, lines
+    );
+    let read = |branch: Branch, file: &str| {
+        index_resources(
+            &fs::read_to_string(format!("{}/{}/{}", output_folder, branch, file))
+                .unwrap_or_default(),
+        )
+    };
+    let (base, target) = (read(Branch::Base, base_name), read(Branch::Target, name));
+    let length =
+        |r: &serde_yaml::Value| serde_yaml::to_string(r).unwrap_or_default().lines().count();
+    let ids: BTreeSet<&ResourceId> = base.keys().chain(target.keys()).collect();
+    let mut changes = vec![];
+    for id in ids {
+        match (base.get(id), target.get(id)) {
+            (None, Some(t)) => changes.push(format!("#   + {} ({} lines)", id, length(t))),
+            (Some(b), None) => changes.push(format!("#   - {} ({} lines)", id, length(b))),
+            (Some(b), Some(t)) if b != t => changes.push(format!(
+                "#   ~ {} ({} → {} lines)",
+                id,
+                length(b),
+                length(t)
+            )),
+            _ => (),
+        }
+    }
+    Some(format!(
+        "{}\n# 📏 The diff of {} has {} lines, more than --max-app-diff-lines ({}). Changed resources:\n{}\n",
+        diff.lines().next().unwrap_or_default(),
+        name,
+        lines,
+        max_lines,
+        changes.join("\n")
+    ))
+}
@@ -0,0 +1,108 @@
+// Every run stores the rendered manifests of the target branch in 'report.json', so a later run
+// can diff against them instead of the base branch (--compare-with), e.g. to find the commit that
+// introduced a regression in the rendered output
+use crate::manifests::{read_applications, read_rendered_apps, write_applications};
+use crate::Branch;
+use log::info;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+pub struct StoredReport {
+    pub branch: String,
+    pub revision: String,
+    manifests: BTreeMap<String, String>,
+    applications: Vec<serde_yaml::Value>,
+}
+
+impl StoredReport {
+    // A report without manifests, for diffing against nothing
+    pub fn empty(branch: &str) -> StoredReport {
+        StoredReport {
+            branch: branch.to_string(),
+            revision: "none".to_string(),
+            manifests: BTreeMap::new(),
+            applications: vec![],
+        }
+    }
+}
+
+// 'metadata' describes the change of every application, with the labels selected with
+// --report-labels. It isn't read back, but kept for dashboards
+pub fn write_report(
+    output_folder: &str,
+    branch: &str,
+    revision: &str,
+    metadata: serde_json::Value,
+) -> Result<(), Box<dyn Error>> {
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let applications: Vec<serde_yaml::Value> = read_applications(output_folder, &Branch::Target)
+        .into_values()
+        .collect();
+    let report = serde_json::json!({
+        "branch": branch,
+        "revision": revision,
+        "createdAt": created_at,
+        "manifests": read_rendered_apps(output_folder, &Branch::Target),
+        "applications": serde_json::to_value(&applications)?,
+        "applicationMetadata": metadata,
+    });
+    fs::write(
+        format!("{}/report.json", output_folder),
+        serde_json::to_string(&report)?,
+    )?;
+    Ok(())
+}
+
+pub fn read_report(path: &str) -> Result<StoredReport, Box<dyn Error>> {
+    let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let manifests = report["manifests"]
+        .as_object()
+        .ok_or("report is missing 'manifests'")?
+        .iter()
+        .map(|(name, manifest)| {
+            let manifest = manifest
+                .as_str()
+                .ok_or_else(|| format!("manifest of {} is not a string", name))?;
+            Ok((name.clone(), manifest.to_string()))
+        })
+        .collect::<Result<BTreeMap<String, String>, Box<dyn Error>>>()?;
+    let applications = match report.get("applications") {
+        Some(a) => serde_json::from_value(a.clone())?,
+        None => vec![],
+    };
+    Ok(StoredReport {
+        branch: report["branch"].as_str().unwrap_or("unknown").to_string(),
+        revision: report["revision"].as_str().unwrap_or("unknown").to_string(),
+        manifests,
+        applications,
+    })
+}
+
+// Writes the manifests of a stored report to the output folder as if they were rendered from the
+// base branch
+pub fn restore_as_base(report: &StoredReport, output_folder: &str) -> Result<(), Box<dyn Error>> {
+    info!(
+        "🕰️ Using {} applications from the report of {} ({}) as base",
+        report.manifests.len(),
+        report.branch,
+        report.revision
+    );
+    // the names come from a file, so a name must not point out of the folder
+    if let Some(name) = report
+        .manifests
+        .keys()
+        .find(|n| n.is_empty() || n.contains(['/', '\\']) || n.contains("..") || *n == ".")
+    {
+        return Err(format!("invalid application name '{}' in the stored report", name).into());
+    }
+    for (name, manifest) in &report.manifests {
+        fs::write(
+            format!("{}/{}/{}", output_folder, Branch::Base, name),
+            manifest,
+        )?;
+    }
+    write_applications(output_folder, &Branch::Base, &report.applications)
+}
@@ -0,0 +1,62 @@
+use log::{error, info};
+use std::error::Error;
+use std::process::Command;
+
+#[derive(Debug)]
+pub enum Hook {
+    PreCluster,
+    PostRender,
+    PrePublish,
+}
+
+impl std::fmt::Display for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Hook::PreCluster => write!(f, "pre-cluster"),
+            Hook::PostRender => write!(f, "post-render"),
+            Hook::PrePublish => write!(f, "pre-publish"),
+        }
+    }
+}
+
+// Describes the run to the hook commands through environment variables
+pub struct HookContext<'a> {
+    pub base_branch: &'a str,
+    pub target_branch: &'a str,
+    pub repo: &'a str,
+    pub output_folder: &'a str,
+    pub cluster_name: &'a str,
+    pub cluster_tool: String,
+}
+
+pub fn run_hook(
+    hook: &Hook,
+    command: Option<&str>,
+    context: &HookContext,
+) -> Result<(), Box<dyn Error>> {
+    let Some(command) = command.filter(|c| !c.trim().is_empty()) else {
+        return Ok(());
+    };
+
+    info!("🪝 Running {} hook: {}", hook, command);
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("ARGOCD_DIFF_PREVIEW_HOOK", hook.to_string())
+        .env("ARGOCD_DIFF_PREVIEW_BASE_BRANCH", context.base_branch)
+        .env("ARGOCD_DIFF_PREVIEW_TARGET_BRANCH", context.target_branch)
+        .env("ARGOCD_DIFF_PREVIEW_REPO", context.repo)
+        .env("ARGOCD_DIFF_PREVIEW_OUTPUT_FOLDER", context.output_folder)
+        .env("ARGOCD_DIFF_PREVIEW_CLUSTER_NAME", context.cluster_name)
+        .env("ARGOCD_DIFF_PREVIEW_CLUSTER_TOOL", &context.cluster_tool)
+        .status()?;
+
+    if !status.success() {
+        error!("❌ The {} hook failed with {}", hook, status);
+        return Err(format!("{} hook failed", hook).into());
+    }
+
+    info!("🪝 Finished {} hook", hook);
+    Ok(())
+}
@@ -0,0 +1,184 @@
+// Admission webhooks and controllers add fields to resources that aren't part of what the
+// applications render, like the status of a sidecar injected by Istio or a CA bundle injected by
+// cert-manager. When such a field ends up in a rendered manifest (e.g. copied from a live resource),
+// it changes whenever the webhook runs. The fields are selected with JSON pointers per kind, like the
+// 'jsonPointers' of Argo CD's 'ignoreDifferences', and removed before the manifests are diffed
+use crate::config::read_config;
+use crate::protected::glob_to_regex;
+use log::debug;
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+
+// Fields that are commonly injected, in the format of the config file
+const DEFAULT_FIELDS: &str = r#"
+injectedFields:
+  - kind: "*"
+    paths:
+      - /status
+      - /metadata/managedFields
+      - /metadata/annotations/sidecar.istio.io~1status
+      - /spec/template/metadata/annotations/sidecar.istio.io~1status
+  - kind: MutatingWebhookConfiguration
+    paths:
+      - /webhooks/*/clientConfig/caBundle
+  - kind: ValidatingWebhookConfiguration
+    paths:
+      - /webhooks/*/clientConfig/caBundle
+  - kind: CustomResourceDefinition
+    paths:
+      - /spec/conversion/webhook/clientConfig/caBundle
+  - kind: APIService
+    paths:
+      - /spec/caBundle
+"#;
+
+struct Rule {
+    kind: String,
+    // API group of the kind, e.g. 'apps'. Any group matches when not set
+    group: Option<String>,
+    // A glob per segment of the JSON pointer, matching keys and list indexes
+    path: Vec<Regex>,
+}
+
+#[derive(Default)]
+pub struct InjectedFields {
+    rules: Vec<Rule>,
+}
+
+// Splits a JSON pointer like '/metadata/annotations/sidecar.istio.io~1status' into a glob per
+// segment
+fn parse_pointer(pointer: &str) -> Result<Vec<Regex>, Box<dyn Error>> {
+    let segments = pointer
+        .strip_prefix('/')
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| format!("'{}' is not a JSON pointer, e.g. /spec/caBundle", pointer))?;
+    segments
+        .split('/')
+        .map(|s| glob_to_regex(&s.replace("~1", "/").replace("~0", "~")))
+        .collect()
+}
+
+fn parse_rules(config: serde_yaml::Value) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let mut rules = vec![];
+    for rule in config["injectedFields"]
+        .as_sequence()
+        .ok_or("injected fields have no 'injectedFields' list")?
+    {
+        let kind = rule["kind"]
+            .as_str()
+            .ok_or("every injected field needs a 'kind'")?;
+        for pointer in rule["paths"].as_sequence().into_iter().flatten() {
+            let pointer = pointer
+                .as_str()
+                .ok_or("'paths' must be a list of strings")?;
+            rules.push(Rule {
+                kind: kind.to_string(),
+                group: None,
+                path: parse_pointer(pointer)?,
+            });
+        }
+    }
+    Ok(rules)
+}
+
+// The default fields, and the ones of the config file if there is one
+pub fn read_injected_fields(path: Option<&str>) -> Result<InjectedFields, Box<dyn Error>> {
+    let mut rules = parse_rules(serde_yaml::from_str(DEFAULT_FIELDS)?)?;
+    if let Some(path) = path {
+        rules.extend(parse_rules(read_config(path)?)?);
+    }
+    Ok(InjectedFields { rules })
+}
+
+impl InjectedFields {
+    // Adds a field to remove from resources of a kind ('*' for all kinds) and optionally a group
+    pub fn add(
+        &mut self,
+        kind: &str,
+        group: Option<&str>,
+        pointer: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.rules.push(Rule {
+            kind: kind.to_string(),
+            group: group.map(|g| g.to_string()),
+            path: parse_pointer(pointer)?,
+        });
+        Ok(())
+    }
+}
+
+// Removes the fields at 'path' below 'value' and returns how many were removed
+fn remove_path(value: &mut serde_yaml::Value, path: &[Regex]) -> usize {
+    let Some((segment, rest)) = path.split_first() else {
+        return 0;
+    };
+    match value {
+        serde_yaml::Value::Mapping(m) => {
+            if rest.is_empty() {
+                let before = m.len();
+                m.retain(|k, _| !k.as_str().is_some_and(|k| segment.is_match(k)));
+                before - m.len()
+            } else {
+                m.iter_mut()
+                    .filter(|(k, _)| k.as_str().is_some_and(|k| segment.is_match(k)))
+                    .map(|(_, v)| remove_path(v, rest))
+                    .sum()
+            }
+        }
+        serde_yaml::Value::Sequence(s) => {
+            if rest.is_empty() {
+                let before = s.len();
+                let mut index = 0;
+                s.retain(|_| {
+                    index += 1;
+                    !segment.is_match(&(index - 1).to_string())
+                });
+                before - s.len()
+            } else {
+                s.iter_mut()
+                    .enumerate()
+                    .filter(|(i, _)| segment.is_match(&i.to_string()))
+                    .map(|(_, v)| remove_path(v, rest))
+                    .sum()
+            }
+        }
+        _ => 0,
+    }
+}
+
+// Removes the injected fields from all resources of a manifest. Like with
+// 'strip_generated_fields', the resources are serialized again, and manifests that can't be parsed
+// are kept as they are
+pub fn remove_injected_fields(manifest: &str, fields: &InjectedFields) -> String {
+    let mut documents = vec![];
+    let mut removed = 0;
+    for document in serde_yaml::Deserializer::from_str(manifest) {
+        let Ok(mut resource) = serde_yaml::Value::deserialize(document) else {
+            return manifest.to_string();
+        };
+        if resource.is_null() {
+            continue;
+        }
+        let kind = resource["kind"].as_str().unwrap_or_default().to_string();
+        // the core group is empty, e.g. for 'v1'
+        let group = resource["apiVersion"]
+            .as_str()
+            .and_then(|v| v.rsplit_once('/'))
+            .map(|(g, _)| g.to_string())
+            .unwrap_or_default();
+        for rule in &fields.rules {
+            if (rule.kind == "*" || rule.kind == kind)
+                && rule.group.as_ref().is_none_or(|g| *g == group)
+            {
+                removed += remove_path(&mut resource, &rule.path);
+            }
+        }
+        match serde_yaml::to_string(&resource) {
+            Ok(document) => documents.push(document),
+            Err(_) => return manifest.to_string(),
+        }
+    }
+    debug!("Removed {} injected fields", removed);
+    documents.join("---\n")
+}
@@ -0,0 +1,34 @@
+// A diff without an application can mean that it is identical in both branches, or that it wasn't
+// rendered at all. Listing the applications that were rendered without differences shows reviewers
+// which applications were checked
+use crate::conditions::broken_applications;
+use crate::diff::ReportSection;
+use crate::manifests::{parse_manifest, read_rendered_apps};
+use crate::Branch;
+use log::info;
+
+// Lists the applications rendered in both branches with identical manifests, and how many
+// resources each has. Applications broken by the change are never unchanged
+pub fn generate_unchanged_section(output_folder: &str) -> Option<ReportSection> {
+    let base = read_rendered_apps(output_folder, &Branch::Base);
+    let target = read_rendered_apps(output_folder, &Branch::Target);
+    let broken = broken_applications(output_folder);
+    let rows: Vec<String> = target
+        .iter()
+        .filter(|(name, manifest)| base.get(*name) == Some(manifest))
+        .filter(|(name, _)| !broken.contains_key(*name))
+        .map(|(name, manifest)| format!("| {} | {} |", name, parse_manifest(manifest).len()))
+        .collect();
+    if rows.is_empty() {
+        return None;
+    }
+    info!("🟰 {} applications have no differences", rows.len());
+
+    Some(ReportSection {
+        title: format!("🟰 Unchanged applications ({}):", rows.len()),
+        content: format!(
+            "| Application | Resources |\n|---|---|\n{}",
+            rows.join("\n")
+        ),
+    })
+}
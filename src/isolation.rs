@@ -0,0 +1,195 @@
+// The repo-server serializes manifest generation in two cases, like described in the monorepo
+// section of the Argo CD high availability docs. Helm charts and config management plugins are
+// rendered one at a time per path of a repository, unless the path has a '.argocd-allow-concurrency'
+// file. Kustomize sources with parameters (e.g. images or namePrefix) change the kustomization in
+// the checkout, so they lock the whole repository and nothing else renders from it meanwhile.
+// When many applications of a monorepo wait for a lock, they run into the timeout of the
+// repo-server and fail with a ComparisonError. With --render-isolation, applications that would
+// wait for each other are applied in different batches, and a batch is only applied when no
+// application in flight holds one of its locks. Applications generated by ApplicationSets are not
+// known up front and not isolated
+use crate::model::{ApplicationSource, ApplicationSpec};
+use crate::repo_url::RepoUrl;
+use log::debug;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+// File in a path of the repository that allows the repo-server to render it concurrently
+const ALLOW_CONCURRENCY: &str = ".argocd-allow-concurrency";
+
+// Kustomize parameters that Argo CD writes into the kustomization before it builds it
+const KUSTOMIZE_PARAMETERS: [&str; 9] = [
+    "images",
+    "commonLabels",
+    "commonAnnotations",
+    "namePrefix",
+    "nameSuffix",
+    "namespace",
+    "replicas",
+    "patches",
+    "components",
+];
+
+pub struct RenderIsolation {
+    // Repositories whose checkout is the branch folder, so their paths can be checked for the file
+    pub repos: Vec<String>,
+}
+
+// A lock of the repo-server: a path of a repository ('host/repo:path') or a whole repository
+// ('host/repo'). Renders of a repository share its lock, and a Kustomize source with parameters
+// holds it alone
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lock {
+    key: String,
+    exclusive: bool,
+}
+
+pub type Locks = BTreeMap<String, BTreeSet<Lock>>;
+
+// Whether renders holding the locks 'a' and 'b' wait for each other
+fn conflict(a: &BTreeSet<Lock>, b: &BTreeSet<Lock>) -> bool {
+    a.iter().any(|l| {
+        b.iter()
+            .any(|m| l.key == m.key && (l.exclusive || m.exclusive))
+    })
+}
+
+fn name(application: &Value) -> &str {
+    application["metadata"]["name"].as_str().unwrap_or_default()
+}
+
+fn has_kustomize_parameters(source: &ApplicationSource) -> bool {
+    source
+        .other
+        .get("kustomize")
+        .and_then(|k| k.as_mapping())
+        .is_some_and(|k| {
+            KUSTOMIZE_PARAMETERS.iter().any(|p| {
+                k.get(*p).is_some_and(|v| match v {
+                    Value::String(s) => !s.is_empty(),
+                    Value::Sequence(s) => !s.is_empty(),
+                    Value::Mapping(m) => !m.is_empty(),
+                    _ => false,
+                })
+            })
+        })
+}
+
+impl RenderIsolation {
+    // The repo-server locks an application takes. Helm and plugin sources lock their path, unless
+    // it has the file. A path of the checkout is a chart when it has a Chart.yaml. Paths of other
+    // repositories can't be checked, so they are always locked
+    fn locks_of(&self, application: &Value, checkout: &str) -> BTreeSet<Lock> {
+        if application["kind"].as_str() != Some("Application") {
+            return BTreeSet::new();
+        }
+        let Ok(spec) = ApplicationSpec::of(application) else {
+            return BTreeSet::new();
+        };
+        let repos: Vec<RepoUrl> = self.repos.iter().map(|r| RepoUrl::parse(r)).collect();
+        let mut locks = BTreeSet::new();
+        for source in spec.sources() {
+            let Some(url) = source.repo_url.as_deref() else {
+                continue;
+            };
+            let url = RepoUrl::parse(url);
+            let repo = format!("{}/{}", url.host().unwrap_or_default(), url.path());
+            // charts of Helm repositories are extracted to a folder per chart and version
+            let path = match (&source.chart, &source.path) {
+                (Some(chart), _) => chart.clone(),
+                (None, Some(path)) => path.trim_matches('/').trim_start_matches("./").to_string(),
+                (None, None) => continue,
+            };
+            let path = if path == "." { String::new() } else { path };
+            let checked = repos.iter().any(|r| source.is_from(r));
+            let folder = Path::new(checkout).join(&path);
+            let serialized = source.chart.is_some()
+                || source.helm.is_some()
+                || source.plugin.is_some()
+                || !checked
+                || folder.join("Chart.yaml").exists();
+            let allowed = checked && folder.join(ALLOW_CONCURRENCY).exists();
+            if serialized && !allowed {
+                locks.insert(Lock {
+                    key: format!("{}:{}", repo, path),
+                    exclusive: true,
+                });
+            }
+            if source.chart.is_none() {
+                locks.insert(Lock {
+                    key: repo.clone(),
+                    exclusive: has_kustomize_parameters(source),
+                });
+            }
+        }
+        locks
+    }
+
+    // The locks of the applications, by application. Shared repository locks are only kept for
+    // repositories that an application locks alone
+    pub fn locks(&self, manifests: &[Value], checkout: &str) -> Locks {
+        let locks: Locks = manifests
+            .iter()
+            .map(|a| (name(a).to_string(), self.locks_of(a, checkout)))
+            .collect();
+        let exclusive: BTreeSet<String> = locks
+            .values()
+            .flatten()
+            .filter(|l| l.exclusive)
+            .map(|l| l.key.clone())
+            .collect();
+        locks
+            .into_iter()
+            .map(|(name, locks)| {
+                let locks: BTreeSet<Lock> = locks
+                    .into_iter()
+                    .filter(|l| exclusive.contains(&l.key))
+                    .collect();
+                (name, locks)
+            })
+            .filter(|(_, locks)| !locks.is_empty())
+            .collect()
+    }
+
+    // Splits the manifests into batches of at most 'batch_size' in which no two applications share
+    // a locked path. Manifests keep their order within and across batches where possible
+    pub fn split(
+        &self,
+        manifests: Vec<Value>,
+        batch_size: Option<usize>,
+        locks: &Locks,
+    ) -> Vec<Vec<Value>> {
+        let none = BTreeSet::new();
+        let mut batches: Vec<(Vec<Value>, BTreeSet<Lock>)> = vec![];
+        for manifest in manifests {
+            let held = locks.get(name(&manifest)).unwrap_or(&none);
+            let batch = batches.iter_mut().find(|(batch, taken)| {
+                batch_size.is_none_or(|size| batch.len() < size) && !conflict(taken, held)
+            });
+            match batch {
+                Some((batch, taken)) => {
+                    taken.extend(held.iter().cloned());
+                    batch.push(manifest);
+                }
+                None => batches.push((vec![manifest], held.clone())),
+            }
+        }
+        debug!("Isolated the applications in {} batches", batches.len());
+        batches.into_iter().map(|(batch, _)| batch).collect()
+    }
+}
+
+// Whether an application in flight holds a lock that an application of the batch waits for
+pub fn conflicts(batch: &[Value], in_flight: &[&str], locks: &Locks) -> bool {
+    let busy: BTreeSet<Lock> = in_flight
+        .iter()
+        .filter_map(|name| locks.get(*name))
+        .flatten()
+        .cloned()
+        .collect();
+    batch
+        .iter()
+        .filter_map(|a| locks.get(name(a)))
+        .any(|held| conflict(&busy, held))
+}
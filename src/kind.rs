@@ -1,4 +1,7 @@
-use crate::{run_command, utils::spawn_command};
+use crate::{
+    run_command,
+    utils::{spawn_command, wait_for_command},
+};
 use log::{error, info};
 use std::error::Error;
 
@@ -6,7 +9,32 @@ pub async fn is_installed() -> bool {
     run_command("which kind", None).await.is_ok()
 }
 
-pub async fn create_cluster(cluster_name: &str) -> Result<(), Box<dyn Error>> {
+// Whether a cluster with the name is running, e.g. one kept by a failed run
+pub async fn cluster_exists(cluster_name: &str) -> bool {
+    match run_command("kind get clusters", None).await {
+        Ok(o) => String::from_utf8_lossy(&o.stdout)
+            .lines()
+            .any(|c| c.trim() == cluster_name),
+        Err(_) => false,
+    }
+}
+
+// Writes the context of the cluster to the kubeconfig and makes it the current context
+pub async fn export_kubeconfig(cluster_name: &str) -> Result<(), Box<dyn Error>> {
+    run_command(
+        &format!("kind export kubeconfig --name {}", cluster_name),
+        None,
+    )
+    .await
+    .map_err(|e| String::from_utf8_lossy(&e.stderr).to_string())?;
+    Ok(())
+}
+
+pub async fn create_cluster(
+    cluster_name: &str,
+    config: Option<&str>,
+    node_image: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
     // check if docker is running
     match run_command("docker ps", None).await {
         Ok(_) => (),
@@ -29,8 +57,23 @@ pub async fn create_cluster(cluster_name: &str) -> Result<(), Box<dyn Error>> {
         }
     };
 
+    let config_arg = match config {
+        Some(c) => {
+            info!("🚀 Using kind config: {}", c);
+            format!("--config {}", c)
+        }
+        None => String::new(),
+    };
+    let image_arg = match node_image {
+        Some(i) => format!("--image {}", i),
+        None => String::new(),
+    };
+
     match run_command(
-        &format!("kind create cluster --name {}", cluster_name),
+        &format!(
+            "kind create cluster --name {} {} {}",
+            cluster_name, config_arg, image_arg
+        ),
         None,
     )
     .await
@@ -46,10 +89,12 @@ pub async fn create_cluster(cluster_name: &str) -> Result<(), Box<dyn Error>> {
     }
 }
 
-pub fn delete_cluster(cluster_name: &str) {
+// Deleting runs in the background, unless the process is about to exit
+pub fn delete_cluster(cluster_name: &str, wait: bool) {
     info!("💥 Deleting cluster...");
-    spawn_command(
-        &format!("kind delete cluster --name {}", cluster_name),
-        None,
-    );
+    let command = format!("kind delete cluster --name {}", cluster_name);
+    match wait {
+        true => wait_for_command(&command),
+        false => spawn_command(&command, None),
+    }
 }
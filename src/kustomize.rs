@@ -0,0 +1,176 @@
+// Argo CD runs 'kustomize build' with the build options of argocd-cm. Kustomizations that inflate
+// Helm charts, run plugins or load files from outside their directory fail to render without the
+// matching option, with an error that doesn't name it. The kustomizations of the branches are
+// scanned for these features, so the options can be set before rendering
+use log::debug;
+use std::collections::BTreeMap;
+use std::path::Path;
+use walkdir::WalkDir;
+
+const KUSTOMIZATION_FILES: [&str; 3] = ["kustomization.yaml", "kustomization.yml", "Kustomization"];
+
+// Plugin fields whose entries are KRM config objects, inline or in a file
+const PLUGIN_FIELDS: [&str; 3] = ["generators", "transformers", "validators"];
+
+// Fields whose entries are files that are loaded relative to the kustomization
+const FILE_FIELDS: [&str; 5] = [
+    "resources",
+    "patchesStrategicMerge",
+    "crds",
+    "configurations",
+    "openapi",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuildOption {
+    EnableHelm,
+    EnableAlphaPlugins,
+    EnableExec,
+    LoadRestrictorNone,
+}
+
+impl BuildOption {
+    // The option as passed to 'kustomize build'
+    pub fn flag(&self) -> &'static str {
+        match self {
+            BuildOption::EnableHelm => "--enable-helm",
+            BuildOption::EnableAlphaPlugins => "--enable-alpha-plugins",
+            BuildOption::EnableExec => "--enable-exec",
+            BuildOption::LoadRestrictorNone => "--load-restrictor LoadRestrictionsNone",
+        }
+    }
+
+    // Options that are added when a kustomization needs them. Plugins and exec functions run code
+    // from the repository, so these options have to be set by the user
+    pub fn is_added_automatically(&self) -> bool {
+        matches!(
+            self,
+            BuildOption::EnableHelm | BuildOption::LoadRestrictorNone
+        )
+    }
+
+    fn is_set(&self, build_options: &str) -> bool {
+        match self {
+            BuildOption::LoadRestrictorNone => build_options.contains("LoadRestrictionsNone"),
+            option => build_options.split_whitespace().any(|o| o == option.flag()),
+        }
+    }
+}
+
+// Plugins that aren't built into Kustomize need alpha plugins, and exec functions also need exec
+fn plugin_options(plugin: &serde_yaml::Value) -> Vec<BuildOption> {
+    if plugin["apiVersion"].as_str() == Some("builtin") {
+        return vec![];
+    }
+    let function = plugin["metadata"]["annotations"]["config.kubernetes.io/function"]
+        .as_str()
+        .unwrap_or_default();
+    match serde_yaml::from_str::<serde_yaml::Value>(function) {
+        Ok(f) if !f["exec"].is_null() => {
+            vec![BuildOption::EnableAlphaPlugins, BuildOption::EnableExec]
+        }
+        _ => vec![BuildOption::EnableAlphaPlugins],
+    }
+}
+
+// A file outside the directory of the kustomization. Directories are other kustomizations, which
+// may be anywhere
+fn is_file_outside(dir: &Path, entry: &str) -> bool {
+    entry.starts_with("..") && dir.join(entry).is_file()
+}
+
+fn options_of(dir: &Path, kustomization: &serde_yaml::Value) -> Vec<BuildOption> {
+    let mut options = vec![];
+    if kustomization["helmCharts"].is_sequence() {
+        options.push(BuildOption::EnableHelm);
+    }
+    for field in PLUGIN_FIELDS {
+        for entry in kustomization[field].as_sequence().into_iter().flatten() {
+            match entry.as_str() {
+                // a config object inlined as a YAML string
+                Some(inline) if inline.contains('\n') => {
+                    if let Ok(plugin) = serde_yaml::from_str(inline) {
+                        options.extend(plugin_options(&plugin));
+                    }
+                }
+                Some(file) if is_file_outside(dir, file) => {
+                    options.push(BuildOption::LoadRestrictorNone);
+                    options.extend(read_plugin(&dir.join(file)));
+                }
+                Some(file) => options.extend(read_plugin(&dir.join(file))),
+                None => options.extend(plugin_options(entry)),
+            }
+        }
+    }
+    let files = FILE_FIELDS
+        .iter()
+        .flat_map(|field| kustomization[*field].as_sequence().into_iter().flatten())
+        .filter_map(|entry| entry.as_str())
+        .chain(
+            kustomization["patches"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .filter_map(|patch| patch["path"].as_str()),
+        );
+    for file in files {
+        if is_file_outside(dir, file) {
+            options.push(BuildOption::LoadRestrictorNone);
+        }
+    }
+    options
+}
+
+// A plugin config in a file. Directories (kustomizations of plugins) are scanned on their own
+fn read_plugin(path: &Path) -> Vec<BuildOption> {
+    match std::fs::read_to_string(path)
+        .ok()
+        .and_then(|c| serde_yaml::from_str(&c).ok())
+    {
+        Some(plugin) => plugin_options(&plugin),
+        None => vec![],
+    }
+}
+
+// The build options the kustomizations in the folders need, each with the first file needing it
+pub fn required_options(folders: &[&str]) -> BTreeMap<BuildOption, String> {
+    let mut required = BTreeMap::new();
+    for folder in folders {
+        let kustomizations = WalkDir::new(folder)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git")
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_type().is_file()
+                    && KUSTOMIZATION_FILES.contains(&e.file_name().to_string_lossy().as_ref())
+            });
+        for entry in kustomizations {
+            let Some(kustomization) = std::fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|c| serde_yaml::from_str::<serde_yaml::Value>(&c).ok())
+            else {
+                continue;
+            };
+            let dir = entry.path().parent().unwrap_or(Path::new("."));
+            for option in options_of(dir, &kustomization) {
+                debug!("{} needs {}", entry.path().display(), option.flag());
+                required
+                    .entry(option)
+                    .or_insert_with(|| entry.path().display().to_string());
+            }
+        }
+    }
+    required
+}
+
+// The required options that aren't in the build options
+pub fn missing_options(
+    build_options: &str,
+    required: &BTreeMap<BuildOption, String>,
+) -> Vec<(BuildOption, String)> {
+    required
+        .iter()
+        .filter(|(option, _)| !option.is_set(build_options))
+        .map(|(option, file)| (*option, file.clone()))
+        .collect()
+}
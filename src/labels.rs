@@ -0,0 +1,160 @@
+// Labels that classify the changes of a pull request (e.g. 'preview/destructive'), so automation
+// can route the review without parsing the report. They are written to 'labels.json', and added to
+// the GitHub pull request with the REST API. Labels with the prefix that no longer apply, e.g.
+// after a new commit, are removed from the pull request
+use crate::conditions::broken_applications;
+use crate::verdict::{collect_facts, Verdict};
+use log::{debug, info};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+// The labels of the changes in the output folder
+pub fn classify(
+    output_folder: &str,
+    prefix: &str,
+    verdict: Option<Verdict>,
+    protected_changed: bool,
+) -> BTreeSet<String> {
+    let facts = collect_facts(output_folder);
+    let mut labels = BTreeSet::new();
+    if facts.changed_resources == 0 {
+        labels.insert("no-changes");
+    } else if facts.other_than_image_tags == 0 {
+        labels.insert("image-tags-only");
+    }
+    if facts.deleted_resources > 0 {
+        labels.insert("destructive");
+    }
+    if facts.rbac_changes > 0 {
+        labels.insert("rbac-change");
+    }
+    if protected_changed {
+        labels.insert("protected-change");
+    }
+    if !broken_applications(output_folder).is_empty() {
+        labels.insert("broken");
+    }
+    let verdict = verdict.map(|v| v.to_string());
+    labels
+        .into_iter()
+        .map(|l| l.to_string())
+        .chain(verdict)
+        .map(|l| format!("{}{}", prefix, l))
+        .collect()
+}
+
+pub fn write_labels(output_folder: &str, labels: &BTreeSet<String>) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        format!("{}/labels.json", output_folder),
+        serde_json::to_string_pretty(&serde_json::json!({ "labels": labels }))?,
+    )?;
+    Ok(())
+}
+
+// Label names are path segments of the API, e.g. 'preview/destructive' as 'preview%2Fdestructive'
+fn encode(label: &str) -> String {
+    label
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Calls the GitHub API with curl. The headers are passed on stdin, so the token is neither part of
+// the command line nor written to a file
+async fn github(
+    method: &str,
+    url: &str,
+    body: Option<&str>,
+    headers: &str,
+) -> Result<String, Box<dyn Error>> {
+    debug!("{} {}", method, url);
+    let mut args = vec!["-sS", "--fail-with-body", "-X", method, "-H", "@-", url];
+    if let Some(body) = body {
+        args.extend(["-d", body]);
+    }
+    let mut child = Command::new("curl")
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(headers.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+        false => Err(format!(
+            "{} {} failed: {}{}",
+            method,
+            url,
+            String::from_utf8_lossy(&output.stderr),
+            String::from_utf8_lossy(&output.stdout)
+        )
+        .into()),
+    }
+}
+
+async fn update_labels(
+    url: &str,
+    headers: &str,
+    prefix: &str,
+    labels: &BTreeSet<String>,
+) -> Result<(), Box<dyn Error>> {
+    let current: Vec<serde_json::Value> =
+        serde_json::from_str(&github("GET", url, None, headers).await?)?;
+    let stale = current
+        .iter()
+        .filter_map(|l| l["name"].as_str())
+        .filter(|l| l.starts_with(prefix) && !labels.contains(*l));
+    for label in stale {
+        let label_url = format!("{}/{}", url, encode(label));
+        github("DELETE", &label_url, None, headers).await?;
+        info!("🏷️ Removed label {}", label);
+    }
+    if !labels.is_empty() {
+        let body = serde_json::json!({ "labels": labels }).to_string();
+        github("POST", url, Some(&body), headers).await?;
+    }
+    Ok(())
+}
+
+// Adds the labels to the pull request and removes the labels with the prefix that don't apply
+// anymore. The token is read from GITHUB_TOKEN and the API from GITHUB_API_URL (GitHub Enterprise)
+pub async fn apply_labels(
+    repo: &str,
+    pull_request: u64,
+    prefix: &str,
+    labels: &BTreeSet<String>,
+) -> Result<(), Box<dyn Error>> {
+    let token = std::env::var("GITHUB_TOKEN").map_err(|_| "GITHUB_TOKEN is not set")?;
+    let api = std::env::var("GITHUB_API_URL").unwrap_or(DEFAULT_API_URL.to_string());
+    let url = format!(
+        "{}/repos/{}/issues/{}/labels",
+        api.trim_end_matches('/'),
+        repo,
+        pull_request
+    );
+    let headers = format!(
+        "Authorization: Bearer {}\nAccept: application/vnd.github+json\n",
+        token
+    );
+    update_labels(&url, &headers, prefix, labels).await?;
+    info!(
+        "🏷️ Labeled pull request #{} with: {}",
+        pull_request,
+        labels.iter().cloned().collect::<Vec<String>>().join(", ")
+    );
+    Ok(())
+}
@@ -0,0 +1,135 @@
+// The binary is built on this library, which also lets other crates test their selectors and
+// patches: 'parsing::get_applications' and 'parsing::patch_applications' select and patch
+// applications, 'selector' matches labels, and the 'testkit' feature adds builders for the
+// resources of a branch folder. The other modules are internal to the binary and may change in
+// any release
+use crate::utils::run_command;
+use std::process::{Command, Output};
+
+pub mod anonymize;
+pub mod argocd;
+pub mod attribution;
+pub mod behavior;
+pub mod branches;
+pub mod budget;
+pub mod capabilities;
+pub mod codeowners;
+pub mod conditions;
+pub mod config;
+pub mod conversions;
+pub mod cost;
+pub mod crds;
+pub mod customizations;
+pub mod destinations;
+pub mod destructive;
+pub mod determinism;
+pub mod diff;
+pub mod drift;
+pub mod duplicates;
+pub mod envtest;
+pub mod exposure;
+pub mod extract;
+pub mod floating;
+pub mod gate;
+pub mod graph;
+pub mod grouping;
+pub mod guards;
+pub mod history;
+pub mod hooks;
+pub mod injected;
+pub mod inventory;
+pub mod isolation;
+pub mod kind;
+pub mod kustomize;
+pub mod labels;
+pub mod links;
+pub mod lock;
+pub mod manifests;
+pub mod matrix;
+pub mod metadata;
+pub mod metrics;
+pub mod minikube;
+pub mod model;
+pub mod namespaces;
+pub mod oci;
+pub mod overrides;
+pub mod parsing;
+pub mod platform;
+pub mod plugins;
+pub mod preview_values;
+pub mod projects;
+pub mod protected;
+pub mod rbac;
+pub mod renames;
+pub mod repo_url;
+pub mod resources;
+pub mod retry;
+pub mod rolling_sync;
+pub mod sampling;
+pub mod selector;
+pub mod semantic;
+pub mod server;
+pub mod skipped;
+pub mod snapshot;
+pub mod specs;
+pub mod stats;
+pub mod summaries;
+pub mod teardown;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+pub mod tui;
+pub mod unchanged;
+pub mod utils;
+pub mod values;
+pub mod vcs;
+pub mod verdict;
+pub mod warnings;
+pub mod workdir;
+
+#[derive(Debug)]
+pub enum Branch {
+    Base,
+    Target,
+}
+
+impl std::str::FromStr for Branch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Branch, String> {
+        match s {
+            "base" => Ok(Branch::Base),
+            "target" => Ok(Branch::Target),
+            s => Err(format!("Invalid branch: {}. Options: base, target", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Branch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Branch::Base => write!(f, "base"),
+            Branch::Target => write!(f, "target"),
+        }
+    }
+}
+
+pub fn apps_file(branch: &Branch) -> String {
+    let name = match branch {
+        Branch::Base => "apps_base_branch.yaml",
+        Branch::Target => "apps_target_branch.yaml",
+    };
+    workdir::path(name).display().to_string()
+}
+
+pub fn apply_manifest(file_name: &str) -> Result<Output, Output> {
+    let output = Command::new("kubectl")
+        .arg("apply")
+        .arg("-f")
+        .arg(file_name)
+        .output()
+        .unwrap_or_else(|_| panic!("failed to apply manifest: {}", file_name));
+    match output.status.success() {
+        true => Ok(output),
+        false => Err(output),
+    }
+}
@@ -0,0 +1,187 @@
+// Links from the changed applications in the report to the files they are defined in, so reviewers
+// can jump from the diff to the source
+use crate::diff::ReportSection;
+use crate::manifests::{read_applications, read_rendered_apps};
+use crate::parsing::{get_resource_files, AppRoot};
+use crate::repo_url::RepoUrl;
+use crate::Branch;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::Path;
+
+// URL templates of file views on the hosts with presets, and their default host
+const PRESETS: [(&str, &str, &str); 2] = [
+    (
+        "github",
+        "github.com",
+        "https://{host}/{repo}/blob/{revision}/{path}",
+    ),
+    (
+        "gitlab",
+        "gitlab.com",
+        "https://{host}/{repo}/-/blob/{revision}/{path}",
+    ),
+];
+
+pub struct SourceLinks {
+    template: String,
+    host: String,
+    repo: String,
+    base_revision: String,
+    target_revision: String,
+}
+
+impl SourceLinks {
+    // Accepts a preset ('github' or 'gitlab') or a URL template with the placeholders {host},
+    // {repo}, {revision} and {path}. The host and repository are taken from '--repo'
+    pub fn new(
+        spec: &str,
+        repo: &str,
+        base_revision: &str,
+        target_revision: &str,
+    ) -> Result<SourceLinks, Box<dyn Error>> {
+        let repo = RepoUrl::parse(repo);
+        let (template, default_host) = match PRESETS.iter().find(|(name, _, _)| *name == spec) {
+            Some((_, host, template)) => (template.to_string(), *host),
+            None if spec.contains("{path}") => (spec.to_string(), ""),
+            None => {
+                return Err(format!(
+                    "invalid source links '{}'. Use github, gitlab or a URL template with {{path}}",
+                    spec
+                )
+                .into())
+            }
+        };
+        Ok(SourceLinks {
+            template,
+            host: repo.host().unwrap_or(default_host).to_string(),
+            repo: repo.path().to_string(),
+            base_revision: base_revision.to_string(),
+            target_revision: target_revision.to_string(),
+        })
+    }
+
+    fn url(&self, branch: &Branch, path: &str) -> String {
+        let revision = match branch {
+            Branch::Base => &self.base_revision,
+            Branch::Target => &self.target_revision,
+        };
+        self.template
+            .replace("{host}", &self.host)
+            .replace("{repo}", &self.repo)
+            .replace("{revision}", revision)
+            .replace("{path}", path)
+    }
+}
+
+// Maps the Applications and ApplicationSets of a branch to the files they are defined in, relative
+// to the root of the repository
+async fn definitions(
+    app_roots: &[AppRoot],
+    branch_folder: &str,
+    regex: &Option<Regex>,
+) -> BTreeMap<(String, String), String> {
+    let mut files = BTreeMap::new();
+    for root in app_roots {
+        let resources = get_resource_files(
+            &root.folder(branch_folder),
+            regex,
+            &["Application", "ApplicationSet"],
+        )
+        .await;
+        for (file, resource) in resources {
+            let kind = resource["kind"].as_str().unwrap_or_default().to_string();
+            let name = resource["metadata"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let path = Path::new(&file)
+                .strip_prefix(branch_folder)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or(file);
+            files.entry((kind, name)).or_insert(path);
+        }
+    }
+    files
+}
+
+// The file an application is defined in. Applications generated by an ApplicationSet link to the
+// ApplicationSet
+fn definition<'a>(
+    name: &str,
+    applications: &BTreeMap<String, serde_yaml::Value>,
+    files: &'a BTreeMap<(String, String), String>,
+) -> Option<&'a String> {
+    let owner = applications
+        .get(name)
+        .and_then(|a| a["metadata"]["ownerReferences"].as_sequence())
+        .into_iter()
+        .flatten()
+        .find(|o| o["kind"].as_str() == Some("ApplicationSet"))
+        .and_then(|o| o["name"].as_str());
+    match owner {
+        Some(owner) => files.get(&("ApplicationSet".to_string(), owner.to_string())),
+        None => files.get(&("Application".to_string(), name.to_string())),
+    }
+}
+
+// Lists the changed applications with links to their files. Deleted applications link to the base
+// branch, all others to the target branch
+pub async fn generate_links_section(
+    output_folder: &str,
+    app_roots: &[AppRoot],
+    branch_folders: (&str, &str),
+    regex: &Option<Regex>,
+    links: &SourceLinks,
+) -> Option<ReportSection> {
+    info!("🔗 Linking changed applications to their files");
+    let base = read_rendered_apps(output_folder, &Branch::Base);
+    let target = read_rendered_apps(output_folder, &Branch::Target);
+    let changed: BTreeSet<&String> = base
+        .keys()
+        .chain(target.keys())
+        .filter(|name| base.get(*name) != target.get(*name))
+        .collect();
+
+    let (base_folder, target_folder) = branch_folders;
+    let branches = [
+        (
+            Branch::Target,
+            read_applications(output_folder, &Branch::Target),
+            definitions(app_roots, target_folder, regex).await,
+        ),
+        (
+            Branch::Base,
+            read_applications(output_folder, &Branch::Base),
+            definitions(app_roots, base_folder, regex).await,
+        ),
+    ];
+
+    let mut rows = vec![];
+    for name in changed {
+        let (branch, applications, files) = match target.contains_key(name) {
+            true => &branches[0],
+            false => &branches[1],
+        };
+        let cell = match definition(name, applications, files) {
+            Some(path) => format!("[{}]({})", path, links.url(branch, path)),
+            None => {
+                debug!("No file found for application {}", name);
+                "-".to_string()
+            }
+        };
+        rows.push(format!("| {} | {} |", name, cell));
+    }
+
+    if rows.is_empty() {
+        info!("🔗 No changed applications found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!("🔗 Files of changed applications ({}):", rows.len()),
+        content: format!("| Application | File |\n|---|---|\n{}", rows.join("\n")),
+    })
+}
@@ -0,0 +1,85 @@
+// Local runs create their cluster with a fixed name, so two runs against the same Docker host
+// would delete and recreate each other's cluster. A run holds an advisory lock on its cluster
+// name until it exits; runs with their own --run-id use their own cluster and lock
+use crate::utils::fnv1a;
+use log::{debug, info};
+use std::error::Error;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// The lock is released by the OS when the process exits, also when it is killed
+static LOCK: OnceLock<File> = OnceLock::new();
+
+// Cluster names must be valid DNS labels, and kind prefixes the name of its nodes
+const MAX_RUN_ID_LENGTH: usize = 32;
+
+pub fn validate_run_id(run_id: &str) -> Result<(), Box<dyn Error>> {
+    let valid_chars = run_id
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if run_id.is_empty()
+        || run_id.len() > MAX_RUN_ID_LENGTH
+        || !valid_chars
+        || run_id.starts_with('-')
+        || run_id.ends_with('-')
+    {
+        return Err(format!(
+            "invalid run id: '{}'. Use up to {} lowercase letters, digits and '-'",
+            run_id, MAX_RUN_ID_LENGTH
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn cluster_name(default: &str, run_id: Option<&str>) -> String {
+    match run_id {
+        Some(id) => format!("{}-{}", default, id),
+        None => default.to_string(),
+    }
+}
+
+// The Docker daemon the cluster is created on. Runs against different daemons don't conflict
+fn docker_host() -> String {
+    std::env::var("DOCKER_HOST")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unix:///var/run/docker.sock".to_string())
+}
+
+fn lock_path(cluster_name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "{}-{:016x}.lock",
+        cluster_name,
+        fnv1a(&docker_host())
+    ))
+}
+
+// Waits until no other run uses the cluster on this Docker host and locks it for this run
+pub fn acquire(cluster_name: &str) -> Result<(), Box<dyn Error>> {
+    let path = lock_path(cluster_name);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    match file.try_lock() {
+        Ok(()) => (),
+        Err(TryLockError::WouldBlock) => {
+            info!(
+                "⏳ Another run is using the cluster '{}' on this Docker host. Waiting for it to finish. Use --run-id to run in parallel",
+                cluster_name
+            );
+            file.lock()?;
+        }
+        Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    // the pid of the holder helps finding the other run
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    debug!("Locked cluster {} with {}", cluster_name, path.display());
+    let _ = LOCK.set(file);
+    Ok(())
+}
@@ -1,33 +1,39 @@
-use crate::utils::{check_if_folder_exists, create_folder_if_not_exists, run_command};
+use argocd_diff_preview::utils::{
+    check_if_folder_exists, create_folder_if_not_exists, wait_for_changes,
+};
+use argocd_diff_preview::*;
 use log::{debug, error, info};
 use regex::Regex;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use std::{
     error::Error,
-    io::Write,
-    process::{Command, Output},
+    io::{BufWriter, Write},
 };
-use structopt::StructOpt;
-mod argocd;
-mod diff;
-mod extract;
-mod kind;
-mod minikube;
-mod parsing;
-mod utils;
+use structopt::{clap::AppSettings, StructOpt};
 
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "argocd-diff-preview",
-    about = "A tool that generates the diff between two branches"
+    about = "A tool that generates the diff between two branches",
+    setting = AppSettings::SubcommandsNegateReqs
 )]
 struct Opt {
+    // Only used to register the subcommands. They are parsed separately in `main`
+    #[allow(dead_code)]
+    #[structopt(subcommand)]
+    command: Option<SubCommand>,
+
     /// Activate debug mode
     // short and long flags (-d, --debug) will be deduced from the field's name
     #[structopt(short, long)]
     debug: bool,
 
+    /// Log without emojis and colors, with the level as prefix of each line, e.g. for CI log processors and Windows terminals
+    #[structopt(long)]
+    plain_logs: bool,
+
     /// Set timeout
     #[structopt(long, default_value = "180", env)]
     timeout: u64,
@@ -40,6 +46,14 @@ struct Opt {
     #[structopt(short = "i", long, env)]
     diff_ignore: Option<String>,
 
+    /// External command that diffs each application instead of git, e.g. 'dyff between --omit-header'. It is called with the base and the target manifest of the application, and its output is added to the report
+    #[structopt(long, env)]
+    diff_command: Option<String>,
+
+    /// How the changes of each application are shown in the diff. Options: unified (a diff of the lines), semantic (the changed, added and removed values by path)
+    #[structopt(long, default_value = "unified", env)]
+    diff_format: String,
+
     /// Generate diffs with <n> lines above and below the highlighted changes in the diff. Default: 10
     #[structopt(short = "c", long, env)]
     line_count: Option<usize>,
@@ -53,87 +67,468 @@ struct Opt {
     base_branch: String,
 
     /// Target branch name
-    #[structopt(short, long, env)]
-    target_branch: String,
+    #[structopt(short, long, env, required_unless = "target-dir")]
+    target_branch: Option<String>,
+
+    /// Folder with the applications of the base side, used instead of the base-branch folder. Applications are rendered as they are, without pointing their sources to a branch. Requires --target-dir
+    #[structopt(long, env)]
+    base_dir: Option<String>,
+
+    /// Folder with the applications of the target side, used instead of the target-branch folder. --repo and --target-branch aren't needed. Requires --base-dir
+    #[structopt(long, env)]
+    target_dir: Option<String>,
+
+    /// Rewrite source repoURLs before rendering, e.g. to point a mirror at the public repository. Format: FROM=TO. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    rewrite_repo: Vec<String>,
+
+    /// Group the diff by environment. Format: label:<key> to group by an Application label, or path:<regex> to group by the first capture group of a source path (e.g. 'path:overlays/(prod|staging)')
+    #[structopt(long, env)]
+    group_by: Option<String>,
+
+    /// Show the diff of applications whose diffs are identical except for the application name once, listing the applications with the same change
+    #[structopt(long)]
+    dedupe_diffs: bool,
+
+    /// Keep the syncPolicy of applications and only remove automated syncing
+    #[structopt(long)]
+    retain_sync_policy: bool,
+
+    /// Skip applications that manage Argo CD itself (e.g. argocd-cm or repository credentials). By default, they are rendered without ever being synced
+    #[structopt(long)]
+    skip_self_managed: bool,
 
-    /// Git Repository. Format: OWNER/REPO
-    #[structopt(long = "repo", env)]
-    repo: String,
+    /// Keep the project of applications instead of rendering them in the default project, and apply the AppProjects of the branches before rendering them
+    #[structopt(long)]
+    keep_projects: bool,
+
+    /// Project-scoped repositories to create for the projects of the applications. Implies --keep-projects
+    #[structopt(long, env)]
+    project_repositories: Option<String>,
+
+    /// Full commit SHA of the base branch. Sources are pinned to this commit instead of the base branch name
+    #[structopt(long, env)]
+    base_sha: Option<String>,
+
+    /// Full commit SHA of the target branch. Sources are pinned to this commit instead of the target branch name
+    #[structopt(long, env)]
+    target_sha: Option<String>,
+
+    /// report.json of a previous run to diff the target branch against, instead of the base branch
+    #[structopt(long, env)]
+    compare_with: Option<String>,
+
+    /// Git Repository. Format: OWNER/REPO or a full repository URL. Sources are matched regardless of HTTPS/SSH notation, a trailing .git and casing
+    #[structopt(long = "repo", env, required_unless = "target-dir")]
+    repo: Option<String>,
 
     /// Output folder where the diff will be saved
     #[structopt(short, long, default_value = "./output", env)]
     output_folder: String,
 
+    /// Directory for the intermediate files of a run. Every run gets its own directory in it, which is removed when the run succeeds and kept when it fails. The checkouts of the branches and --output-folder are not moved. Default: the system temp directory
+    #[structopt(long, env)]
+    workdir: Option<String>,
+
+    /// Write anonymized usage statistics of the run (number of applications, generator types, durations, outcome) to this file
+    #[structopt(long, env)]
+    stats_file: Option<String>,
+
     /// Secrets folder where the secrets are read from
     #[structopt(short, long, default_value = "./secrets", env)]
     secrets_folder: String,
 
-    /// Local cluster tool. Options: kind, minikube, auto. Default: Auto
+    /// Local cluster tool. Options: kind, minikube, envtest, auto. Default: Auto
     #[structopt(long, env)]
     local_cluster_tool: Option<String>,
 
+    /// Kubernetes version of the cluster (e.g. 1.29.2). A comma-separated list renders the target branch on every version and reports the differences
+    #[structopt(long, env)]
+    k8s_version: Option<String>,
+
+    /// Argo CD Helm Chart version to compare with. The target branch is rendered again with this version, and the differences are added to the report
+    #[structopt(long, env)]
+    compare_argocd_chart_version: Option<String>,
+
+    /// kind cluster config file used when creating the cluster (e.g. node image, extra mounts and feature gates)
+    #[structopt(long, env)]
+    kind_config: Option<String>,
+
+    /// Architecture of the kind nodes. Options: amd64, arm64. Default: the architecture of the Docker host
+    #[structopt(long, env)]
+    cluster_arch: Option<String>,
+
+    /// kind node image of the cluster, e.g. a mirror of kindest/node. Pulled for the architecture of the cluster
+    #[structopt(long, env)]
+    node_image: Option<String>,
+
+    /// Image of the Argo CD components as REPOSITORY[:TAG], e.g. a mirror with a variant for the architecture of the cluster. Default: the image of the Helm Chart
+    #[structopt(long, env)]
+    argocd_image: Option<String>,
+
+    /// Boot the kind cluster from a snapshot image created with --create-cluster-snapshot, instead of creating it and installing Argo CD
+    #[structopt(long, env)]
+    cluster_snapshot: Option<String>,
+
+    /// Save the kind node as a docker image after Argo CD is installed, so later runs can boot from it with --cluster-snapshot. Secrets are not part of the snapshot
+    #[structopt(long, env)]
+    create_cluster_snapshot: Option<String>,
+
     /// Max diff message character count. Default: 65536 (GitHub comment limit)
     #[structopt(long, env)]
     max_diff_length: Option<usize>,
-    /// Label selector to filter on, supports '=', '==', and '!='. (e.g. -l key1=value1,key2=value2).
+
+    /// Platforms the report is published to. A report that fits the size limit of each is written to diff-<name>.md, showing less of the diff the larger it is. Options: github, gitlab, slack or NAME=LENGTH. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    publishers: Vec<String>,
+
+    /// URL of the full report, e.g. a build artifact, linked from reports that don't show the whole diff
+    #[structopt(long, env)]
+    artifact_url: Option<String>,
+
+    /// More branches to render and compare pairwise with each other and with the base and target branch. Format: BRANCH=FOLDER. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    compare_branch: Vec<String>,
+
+    /// Files changed between the branches, relative to the repository root (e.g. from 'git diff --name-only'). Separated by commas or newlines. When the applications are identical in both branches and no file changed in their source paths, rendering is skipped
+    #[structopt(long, env)]
+    changed_files: Option<String>,
+
+    /// Compute the files changed between the branches from the git history of the target branch folder, instead of passing them with --changed-files. Options: git (the git CLI), gitoxide (needs a build with the gitoxide feature)
+    #[structopt(long, env)]
+    changed_files_from: Option<String>,
+
+    /// Only render the applications that the changed files can affect (changed specs, or changed files in their source paths), instead of all applications. Requires --changed-files or --changed-files-from
+    #[structopt(long)]
+    render_affected_only: bool,
+
+    /// Directories in the branch folders to read applications from. Format: PATH[;repo=REPO][;base-branch=BRANCH][;target-branch=BRANCH]. Can be repeated. Default: the whole branch folders
+    #[structopt(long, env, use_delimiter = true)]
+    app_dir: Vec<String>,
+
+    /// OCI artifact to read applications from instead of the branch folders, pulled with ORAS. Format: REF[;base-tag=TAG][;target-tag=TAG]. The tags default to the branch names
+    #[structopt(long, env)]
+    apps_oci: Option<String>,
+
+    /// Annotation that excludes applications from rendering. Values: 'true' (both branches), 'base-only' or 'target-only'
+    #[structopt(long, env, default_value = "argocd-diff-preview/ignore")]
+    ignore_annotation: String,
+
+    /// Label selector to filter on, in the Kubernetes label selector syntax (e.g. -l 'app.kubernetes.io/part-of=payments,tier!=test,env in (prod,staging)')
     #[structopt(long, short = "l", env)]
     selector: Option<String>,
-}
 
-#[derive(Debug)]
-enum ClusterTool {
-    Kind,
-    Minikube,
-}
+    /// Serve the HTML report on a local HTTP server. Reloads automatically when combined with --watch. Metrics of the run are served at /metrics
+    #[structopt(long)]
+    serve: bool,
 
-#[derive(Debug)]
-enum Branch {
-    Base,
-    Target,
-}
+    /// Port used by the local HTTP server
+    #[structopt(long, default_value = "8000", env)]
+    serve_port: u16,
 
-impl std::fmt::Display for Branch {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Branch::Base => write!(f, "base"),
-            Branch::Target => write!(f, "target"),
-        }
-    }
-}
+    /// Stop rendering early when a change is found. Options: destructive (an application of the target branch deletes a resource of a kind in --protected-kinds)
+    #[structopt(long, env)]
+    fail_fast_on: Option<String>,
 
-#[derive(Debug)]
-enum Operator {
-    Eq,
-    Ne,
-}
+    /// Kinds of resources whose deletion stops rendering with --fail-fast-on destructive
+    #[structopt(long, env, use_delimiter = true, default_value = destructive::DEFAULT_PROTECTED_KINDS)]
+    protected_kinds: Vec<String>,
+
+    /// Labels of the destination namespaces of the applications (e.g. istio-injection or Pod Security Standards), by namespace. The selected namespaces are created with the labels before rendering
+    #[structopt(long, env)]
+    namespace_labels: Option<String>,
+
+    /// Rules selecting protected resources by kind, name and namespace. Removing or changing them adds a warning to the top of the report
+    #[structopt(long, env)]
+    protected_resources: Option<String>,
+
+    /// Exit with this code when protected resources are removed or changed. Requires --protected-resources
+    #[structopt(long, env)]
+    protected_exit_code: Option<i32>,
+
+    /// Keep the cluster and the intermediate files when a run fails or is cancelled, for debugging
+    #[structopt(long)]
+    keep_cluster_on_failure: bool,
+
+    /// run-info.json of a run that failed to render some applications. Only the applications without manifests in the output folder are rendered, and the report covers all applications. The cluster kept with --keep-cluster-on-failure is reused
+    #[structopt(long, env)]
+    retry_failed: Option<String>,
+
+    /// Name of this run, to run in parallel with other local runs. The cluster is named argocd-diff-preview-<run-id> and gets its own kubeconfig in the work directory
+    #[structopt(long, env)]
+    run_id: Option<String>,
+
+    /// Config with more fields to remove, as JSON pointers per kind. Implies --ignore-injected-fields
+    #[structopt(long, env)]
+    injected_fields: Option<String>,
+
+    /// Replace hostnames, IP addresses and cloud account IDs in the report with stable pseudonyms, so it can be shared outside the organization
+    #[structopt(long)]
+    anonymize: bool,
+
+    /// Regexes of more values to replace with pseudonyms, e.g. internal project names. Can be repeated. Implies --anonymize
+    #[structopt(long, env)]
+    anonymize_pattern: Vec<String>,
+
+    /// Salt of the pseudonyms. Reports anonymized with the same salt use the same pseudonyms. Without a salt, pseudonyms of short values like IP addresses can be guessed
+    #[structopt(long, env, hide_env_values = true)]
+    anonymize_salt: Option<String>,
+
+    /// Keep the cluster running and generate a new diff whenever files in the branch folders change
+    #[structopt(long)]
+    watch: bool,
+
+    /// Remove metadata.creationTimestamp, metadata.generation, metadata.uid and empty status blocks that some tools generate into manifests, so they don't show up in the diff
+    #[structopt(long)]
+    strip_generated_fields: bool,
+
+    /// Remove status blocks, managed fields and fields that admission webhooks inject (e.g. Istio sidecar annotations and cert-manager CA bundles) from the rendered manifests, so they don't show up in the diff
+    #[structopt(long)]
+    ignore_injected_fields: bool,
+
+    /// Render the target branch twice and report the applications that render differently each time (e.g. random values, timestamps or lookups)
+    #[structopt(long)]
+    render_twice: bool,
+
+    /// Render applications that the repo-server would render one at a time (Helm charts and plugins that share a path without a .argocd-allow-concurrency file, Kustomize sources with parameters) in different batches. Avoids ComparisonErrors from the repo-server locks on large monorepos
+    #[structopt(long)]
+    render_isolation: bool,
+
+    /// List the applications that were rendered without differences in the report, so it is clear they were checked
+    #[structopt(long)]
+    list_unchanged: bool,
+
+    /// Add a section with rendered resources that the changes duplicate in other applications, e.g. a copied overlay. All duplicates are written to duplicates.json
+    #[structopt(long)]
+    duplicates: bool,
+
+    /// Replace resources with more lines (e.g. large bundled CRDs) by a stub with their line count and checksum in the diff
+    #[structopt(long, env)]
+    max_resource_lines: Option<usize>,
+
+    /// Replace the diff of applications with more lines by a list of the changed resources
+    #[structopt(long, env)]
+    max_app_diff_lines: Option<usize>,
+
+    /// Show a removed and an added resource of the same kind in an application as renamed when at least <n> percent of their fields are the same, e.g. 80. Off (0) by default
+    #[structopt(long, default_value = "0", env)]
+    rename_similarity: u8,
+
+    /// Show the spec.info entries of each changed application (e.g. owner and dashboard links) above its diff
+    #[structopt(long)]
+    app_info: bool,
+
+    /// Add a diff of the Application and ApplicationSet specs of both branches to the report
+    #[structopt(long)]
+    diff_app_specs: bool,
+
+    /// Add a diff of the inline Helm values (helm.values and helm.valuesObject) of both branches to the report
+    #[structopt(long)]
+    values_changes: bool,
+
+    /// Add a graph of app-of-apps relationships and multi-source dependencies to the report
+    #[structopt(long)]
+    dependency_graph: bool,
+
+    /// Add a section with unpinned references to the report: sources following a branch, chart version ranges and 'latest' images
+    #[structopt(long)]
+    floating_references: bool,
+
+    /// Application labels (e.g. team,env,tier) added to the metadata of each application in report.json and to a section listing the changed applications. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    report_labels: Vec<String>,
+
+    /// Annotations or labels of the Applications holding the Backstage entity they belong to (e.g. backstage.io/kubernetes-id), checked in order. The entity ref of each application is added to its metadata in report.json. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    backstage_entity_keys: Vec<String>,
+
+    /// Add a section with links to the files of the changed applications, at the revisions of the branches. Options: github, gitlab or a URL template with {host}, {repo}, {revision} and {path}
+    #[structopt(long, env)]
+    source_links: Option<String>,
+
+    /// Add a section with the owners of each changed application to the report, read from the CODEOWNERS file of the target branch
+    #[structopt(long)]
+    codeowners: bool,
+
+    /// Mention the owners of changed applications in the report, so only they are notified. Implies --codeowners
+    #[structopt(long)]
+    mention_owners: bool,
+
+    /// Add a section with the CPU and memory request deltas of each application to the report
+    #[structopt(long)]
+    cost_estimation: bool,
+
+    /// Pricing config used to estimate the monthly cost of the resource request deltas. Implies --cost-estimation
+    #[structopt(long, env)]
+    pricing_config: Option<String>,
+
+    /// Rules deciding whether the changes can be auto-approved, need a review or are blocked. The verdict is written to verdict.json
+    #[structopt(long, env)]
+    verdict_rules: Option<String>,
+
+    /// Exit with the verdict as exit code: 0 (auto-approve), 2 (needs-review) or 3 (block). Requires --verdict-rules
+    #[structopt(long)]
+    verdict_exit_code: bool,
+
+    /// Write labels classifying the changes (e.g. preview/destructive) to labels.json, and add them to the pull request given by --pull-request
+    #[structopt(long)]
+    pr_labels: bool,
+
+    /// Number of the pull request, shown at the top of the report. With --pr-labels, the labels are added to this GitHub pull request. The token is read from GITHUB_TOKEN
+    #[structopt(long, env)]
+    pull_request: Option<u64>,
+
+    /// Title of the pull request, shown at the top of the report. Defaults to CI_MERGE_REQUEST_TITLE on GitLab
+    #[structopt(long, env)]
+    pull_request_title: Option<String>,
+
+    /// Prefix of the labels of --pr-labels. Labels with the prefix that no longer apply are removed from the pull request
+    #[structopt(long, default_value = "preview/", env)]
+    pr_label_prefix: String,
+
+    /// Config with the Kubernetes version and API versions Helm charts are rendered against (helm.kubeVersion and helm.apiVersions), by default and per application
+    #[structopt(long, env)]
+    helm_capabilities: Option<String>,
+
+    /// Helm values layered onto an application of the target branch only, to preview a configuration change before it is committed. Format: APP=FILE. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    preview_values: Vec<String>,
+
+    /// CRDs to install in the cluster before rendering, so resources are validated like in production. Comma-separated list of controllers (argo-rollouts, cert-manager, external-secrets, istio, keda, prometheus-operator, optionally with a version, e.g. cert-manager@v1.14.0), URLs, files or directories
+    #[structopt(long, env)]
+    crds: Option<String>,
+
+    /// Config with static responses or stub servers for the plugin generators of ApplicationSets, and environment variables for Config Management Plugins
+    #[structopt(long, env)]
+    plugin_config: Option<String>,
+
+    /// Add a section with changes to resource requests/limits, replicas, autoscalers, quotas and limit ranges to the report
+    #[structopt(long)]
+    resource_changes: bool,
+
+    /// File with one-line summaries for custom resources, used in addition to the built-in summaries
+    #[structopt(long, env)]
+    resource_summaries: Option<String>,
+
+    /// Command run before the cluster is created
+    #[structopt(long, env)]
+    pre_cluster_hook: Option<String>,
+
+    /// Command run after the manifests of both branches have been rendered
+    #[structopt(long, env)]
+    post_render_hook: Option<String>,
+
+    /// Command run after the report has been written
+    #[structopt(long, env)]
+    pre_publish_hook: Option<String>,
+
+    /// Comma-separated list of Argo CD manifest URLs or files used if the Helm Chart installation fails. Default: the install manifests of the Argo CD version of --argocd-chart-version, or of the stable release when it isn't set
+    #[structopt(long, env)]
+    argocd_fallback_manifests: Option<String>,
+
+    /// Apply applications in batches of <n>. The next batch is applied when fewer than <n> applications are still being rendered
+    #[structopt(long, env)]
+    apply_batch_size: Option<usize>,
+
+    /// Render only <n> of the applications each ApplicationSet generates, plus the ones whose spec differs between the branches. Requires Argo CD 2.9 or later
+    #[structopt(long, env)]
+    appset_sample: Option<usize>,
 
-#[derive(Debug)]
-struct Selector {
-    key: String,
-    value: String,
-    operator: Operator,
+    /// Number of Argo CD repo-server replicas
+    #[structopt(long, default_value = "1", env)]
+    repo_server_replicas: u32,
+
+    /// Max number of manifests the Argo CD repo-server renders concurrently. 0 means unlimited
+    #[structopt(long, default_value = "0", env)]
+    repo_server_parallelism_limit: u32,
+
+    /// Number of Argo CD application controller status processors
+    #[structopt(long, default_value = "50", env)]
+    controller_status_processors: u32,
+
+    /// argocd-cm of the production instance, or a file with just its data. Its 'resource.customizations' keys (health checks, actions, ignored differences) are set in the Argo CD instance, and the JSON pointers of the ignored differences are removed from the rendered manifests
+    #[structopt(long, env)]
+    resource_customizations: Option<String>,
+
+    /// Kustomize build options of Argo CD (kustomize.buildOptions in argocd-cm). '--enable-helm' inflates Helm charts referenced by kustomizations. Set to an empty string to use the Argo CD default
+    #[structopt(long, default_value = "--enable-helm", env, allow_hyphen_values = true)]
+    kustomize_build_options: String,
+
+    /// Argo CD installation profile. Options: minimal, full. 'minimal' only installs the components needed to render manifests
+    #[structopt(long, default_value = "minimal", env)]
+    argocd_profile: String,
 }
 
-impl std::fmt::Display for Selector {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Selector {
-                key,
-                value,
-                operator,
-            } => match operator {
-                Operator::Eq => write!(f, "{}={}", key, value),
-                Operator::Ne => write!(f, "{}!={}", key, value),
-            },
-        }
-    }
+#[derive(Debug, StructOpt)]
+enum SubCommand {
+    /// Review the output of a previous run in an interactive terminal UI
+    Tui {
+        /// Output folder where the rendered manifests were saved
+        #[structopt(short, long, default_value = "./output", env)]
+        output_folder: String,
+
+        /// Show <n> lines above and below the highlighted changes in the diff
+        #[structopt(short = "c", long, default_value = "10", env)]
+        line_count: usize,
+    },
+    /// Render the target branch and diff it against the report of the previous drift run, to find render changes nobody committed. Takes the options of a full run (e.g. --repo and --target-branch) before the subcommand
+    Drift {
+        /// Where the report of the previous run is read from and the new one is stored. A local file, s3://BUCKET/KEY or gs://BUCKET/KEY
+        #[structopt(long, env = "DRIFT_SNAPSHOT")]
+        snapshot: String,
+
+        /// Exit with 1 when applications drifted
+        #[structopt(long)]
+        fail_on_drift: bool,
+    },
+    /// Write the applications of one branch as they would be applied to the cluster, after discovery and patching, without creating a cluster. Takes the options of a full run (e.g. --repo and --target-branch) before the subcommand
+    RenderApps {
+        /// Branch to write the applications of. Options: base, target
+        #[structopt(long, default_value = "target")]
+        branch: String,
+
+        /// File the applications are written to. '-' writes them to stdout
+        #[structopt(short, long, default_value = "-")]
+        output: String,
+    },
+    /// Write the manifests of the applications of an existing Argo CD to the output folder, in the layout of a run, e.g. to seed a base branch or build custom comparisons
+    ExtractManifests {
+        /// kubeconfig used to connect to the cluster of Argo CD. Default: the current kubeconfig
+        #[structopt(long, env = "KUBECONFIG")]
+        kubeconfig: Option<String>,
+
+        /// Context of the kubeconfig. Default: the current context
+        #[structopt(long)]
+        kube_context: Option<String>,
+
+        /// Argo CD API server to connect to instead of the cluster (e.g. argocd.example.com). The token is read from ARGOCD_AUTH_TOKEN
+        #[structopt(long, env = "ARGOCD_SERVER")]
+        server: Option<String>,
+
+        /// Label selector of the applications to extract, e.g. 'team=payments'. Default: all applications
+        #[structopt(short = "l", long)]
+        selector: Option<String>,
+
+        /// Branch folder of the output folder the manifests are written to. Options: base, target
+        #[structopt(long, default_value = "base")]
+        branch: String,
+
+        /// Output folder the manifests are written to
+        #[structopt(short, long, default_value = "./output", env)]
+        output_folder: String,
+
+        /// Remove fields that tools generate into manifests, like timestamps and UIDs
+        #[structopt(long)]
+        strip_generated_fields: bool,
+    },
 }
 
-fn apps_file(branch: &Branch) -> &'static str {
-    match branch {
-        Branch::Base => "apps_base_branch.yaml",
-        Branch::Target => "apps_target_branch.yaml",
-    }
+#[derive(Debug, Clone, Copy)]
+enum ClusterTool {
+    Kind,
+    Minikube,
+    // etcd, kube-apiserver and Argo CD as local processes, for environments without Docker
+    Envtest,
 }
 
 const BASE_BRANCH_FOLDER: &str = "base-branch";
@@ -142,20 +537,113 @@ const CLUSTER_NAME: &str = "argocd-diff-preview";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let opt = Opt::from_args();
+    let result = run().await;
+    if let Err(e) = &result {
+        teardown::fail(&e.to_string());
+    }
+    result
+}
 
-    // Start timer
-    let start = std::time::Instant::now();
+async fn run() -> Result<(), Box<dyn Error>> {
+    let matches = Opt::clap().get_matches();
 
-    if opt.debug {
-        std::env::set_var("RUST_LOG", "debug");
-        env_logger::init();
-    } else {
-        std::env::set_var("RUST_LOG", "info");
-        env_logger::builder()
-            .format(|buf, record| writeln!(buf, "{}", record.args()))
-            .init();
+    // Subcommands don't require the arguments of a full run, so they are handled before parsing
+    // those. 'drift' is a full run with the report of the previous run as base, and 'render-apps'
+    // stops after patching the applications
+    if matches!(matches.subcommand_name(), Some("drift" | "render-apps"))
+        && (!matches.is_present("repo") || !matches.is_present("target-branch"))
+        && !matches.is_present("target-dir")
+    {
+        init_logger(
+            matches.is_present("debug"),
+            matches.is_present("plain-logs"),
+        );
+        error!(
+            "❌ {} requires --repo and --target-branch",
+            matches.subcommand_name().unwrap_or_default()
+        );
+        panic!("missing arguments for subcommand");
     }
+    let mut render_apps = None;
+    let drift = match matches
+        .subcommand_name()
+        .map(|_| SubCommand::from_clap(&matches))
+    {
+        Some(SubCommand::Tui {
+            output_folder,
+            line_count,
+        }) => {
+            init_logger(
+                matches.is_present("debug"),
+                matches.is_present("plain-logs"),
+            );
+            return tui::run(&output_folder, line_count).await;
+        }
+        Some(SubCommand::ExtractManifests {
+            kubeconfig,
+            kube_context,
+            server,
+            selector,
+            branch,
+            output_folder,
+            strip_generated_fields,
+        }) => {
+            init_logger(
+                matches.is_present("debug"),
+                matches.is_present("plain-logs"),
+            );
+            let branch: Branch = match branch.parse() {
+                Ok(b) => b,
+                Err(e) => {
+                    error!("❌ {}", e);
+                    panic!("Invalid branch");
+                }
+            };
+            if let Some(kubeconfig) = &kubeconfig {
+                std::env::set_var("KUBECONFIG", kubeconfig);
+            }
+            // without an API server, the argocd CLI reads the applications from the cluster
+            let argocd_opts = match (&server, &kube_context) {
+                (Some(server), _) => format!("--server {} --grpc-web", server),
+                (None, Some(context)) => format!("--core --kube-context {}", context),
+                (None, None) => "--core".to_string(),
+            };
+            utils::set_argocd_opts(&argocd_opts, None);
+            if let Err(e) = workdir::init(matches.value_of("workdir")) {
+                error!("❌ Failed to create work directory");
+                panic!("error: {}", e)
+            }
+            let selector = selector.as_deref().filter(|s| !s.trim().is_empty());
+            extract::extract_existing_applications(
+                &branch,
+                &output_folder,
+                selector,
+                strip_generated_fields,
+            )
+            .await?;
+            workdir::cleanup();
+            return Ok(());
+        }
+        Some(SubCommand::Drift {
+            snapshot,
+            fail_on_drift,
+        }) => Some(drift::DriftOptions {
+            snapshot,
+            fail_on_drift,
+        }),
+        Some(SubCommand::RenderApps { branch, output }) => {
+            render_apps = Some((branch, output));
+            None
+        }
+        None => None,
+    };
+
+    let opt = Opt::from_clap(&matches);
+
+    // Start timer
+    let mut start = std::time::Instant::now();
+
+    init_logger(opt.debug, opt.plain_logs);
 
     debug!("Arguments provided: {:?}", opt);
 
@@ -164,9 +652,39 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .filter(|f| !f.trim().is_empty())
         .map(|f| Regex::new(&f).unwrap());
 
-    let base_branch_name = opt.base_branch;
-    let target_branch_name = opt.target_branch;
-    let repo = opt.repo;
+    // with --base-dir and --target-dir, the folders are compared as they are, without branches
+    let dirs = match (opt.base_dir, opt.target_dir) {
+        (Some(base), Some(target)) => Some((base, target)),
+        (None, None) => None,
+        _ => {
+            error!("❌ --base-dir and --target-dir must be given together");
+            panic!("missing --base-dir or --target-dir");
+        }
+    };
+    let base_folder = dirs
+        .as_ref()
+        .map_or(BASE_BRANCH_FOLDER, |(b, _)| b.as_str());
+    let target_folder = dirs
+        .as_ref()
+        .map_or(TARGET_BRANCH_FOLDER, |(_, t)| t.as_str());
+    let base_branch_set =
+        matches.occurrences_of("base-branch") > 0 || std::env::var("BASE_BRANCH").is_ok();
+    let base_branch_name = match &dirs {
+        Some((base, _)) if !base_branch_set => base.clone(),
+        _ => opt.base_branch,
+    };
+    let target_branch_name = opt
+        .target_branch
+        .unwrap_or_else(|| target_folder.to_string());
+    // sources only point to a branch when they are in the repository, so there is nothing to
+    // point them to without one
+    let repo = match (&dirs, opt.repo) {
+        (Some(_), Some(_)) => {
+            info!("⚠️ --repo is ignored with --base-dir and --target-dir, since sources aren't pointed to a branch");
+            String::new()
+        }
+        (_, repo) => repo.unwrap_or_default(),
+    };
     let diff_ignore = opt.diff_ignore.filter(|f| !f.trim().is_empty());
     let timeout = opt.timeout;
     let output_folder = opt.output_folder.as_str();
@@ -178,96 +696,873 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .filter(|f| !f.trim().is_empty());
     let max_diff_length = opt.max_diff_length;
 
+    let publishers: Vec<budget::Publisher> = opt
+        .publishers
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| match budget::Publisher::parse(p) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("❌ Invalid --publishers: {}", p);
+                panic!("error: {}", e)
+            }
+        })
+        .collect();
+
+    let diff_command = opt.diff_command.as_deref().filter(|c| !c.trim().is_empty());
+    let diff_format = match (opt.diff_format.as_str(), diff_command) {
+        ("unified", None) => diff::DiffFormat::Unified,
+        ("unified", Some(c)) => {
+            diff::DiffFormat::Command(c.split_whitespace().map(|a| a.to_string()).collect())
+        }
+        ("semantic", None) => diff::DiffFormat::Semantic,
+        ("semantic", Some(_)) => {
+            error!("❌ --diff-command can't be combined with --diff-format semantic");
+            panic!("Invalid diff format");
+        }
+        (f, _) => {
+            error!("❌ Invalid diff format: {}. Use unified or semantic", f);
+            panic!("Invalid diff format");
+        }
+    };
+
     // select local cluster tool
     let tool = match opt.local_cluster_tool {
         Some(t) if t == "kind" => ClusterTool::Kind,
         Some(t) if t == "minikube" => ClusterTool::Minikube,
+        Some(t) if t == "envtest" => ClusterTool::Envtest,
+        // no cluster is created when only the applications are patched
+        _ if render_apps.is_some() => ClusterTool::Kind,
         _ if kind::is_installed().await => ClusterTool::Kind,
         _ if minikube::is_installed().await => ClusterTool::Minikube,
+        _ if envtest::is_installed().await => ClusterTool::Envtest,
         _ => {
-            error!("❌ No local cluster tool found. Please install kind or minikube");
+            error!("❌ No local cluster tool found. Please install kind, minikube or etcd and kube-apiserver (envtest)");
             panic!("No local cluster tool found")
         }
     };
 
-    // let repo_regex = Regex::new(r"^[a-zA-Z0-9-]+/[a-zA-Z0-9-]+$").unwrap();
-    // if !repo_regex.is_match(&repo) {
-    //     error!("❌ Invalid repository format. Please use OWNER/REPO");
-    //     panic!("Invalid repository format");
-    // }
+    // sources are pinned to the commit SHAs when given, since branch names can point to other
+    // commits than the checked out ones, e.g. for merge commits in detached-HEAD CI checkouts. Argo CD
+    // can't resolve abbreviated SHAs, so only full ones are accepted
+    let sha_regex = Regex::new(r"^[0-9a-fA-F]{40}$").unwrap();
+    let revision = |sha: &Option<String>, branch: &'static str| -> Option<String> {
+        let sha = sha.as_deref().map(|s| s.trim()).filter(|s| !s.is_empty())?;
+        if !sha_regex.is_match(sha) {
+            error!(
+                "❌ Invalid commit SHA for {}: {}. Use the full 40-character SHA (e.g. from 'git rev-parse HEAD')",
+                branch, sha
+            );
+            panic!("Invalid commit SHA");
+        }
+        Some(sha.to_string())
+    };
+    let base_sha = revision(&opt.base_sha, "base branch");
+    let target_sha = revision(&opt.target_sha, "target branch");
+    let base_revision = base_sha.as_deref().unwrap_or(&base_branch_name);
+    let target_revision = target_sha.as_deref().unwrap_or(&target_branch_name);
 
-    info!("✨ Running with:");
-    info!("✨ - local-cluster-tool: {:?}", tool);
-    info!("✨ - base-branch: {}", base_branch_name);
-    info!("✨ - target-branch: {}", target_branch_name);
-    info!("✨ - secrets-folder: {}", secrets_folder);
-    info!("✨ - output-folder: {}", output_folder);
-    info!("✨ - repo: {}", repo);
-    info!("✨ - timeout: {} seconds", timeout);
-    if let Some(a) = file_regex.clone() {
-        info!("✨ - file-regex: {}", a.as_str());
+    let source_links = opt.source_links.as_deref().map(|s| {
+        match links::SourceLinks::new(s, &repo, base_revision, target_revision) {
+            Ok(links) => links,
+            Err(e) => {
+                error!("❌ {}", e);
+                panic!("Invalid source links");
+            }
+        }
+    });
+
+    let repo_rewrites: Vec<(String, String)> = opt
+        .rewrite_repo
+        .iter()
+        .filter(|r| !r.trim().is_empty())
+        .map(|r| match r.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => {
+                (from.trim().to_string(), to.trim().to_string())
+            }
+            _ => {
+                error!("❌ Invalid repo rewrite format: {}. Use FROM=TO", r);
+                panic!("Invalid repo rewrite format");
+            }
+        })
+        .collect();
+
+    let app_dirs: Vec<&str> = opt
+        .app_dir
+        .iter()
+        .map(|d| d.as_str())
+        .filter(|d| !d.trim().is_empty())
+        .collect();
+    let apps_oci = opt
+        .apps_oci
+        .as_deref()
+        .filter(|o| !o.trim().is_empty())
+        .map(
+            |o| match oci::OciApps::parse(o, &base_branch_name, &target_branch_name) {
+                Ok(apps) => apps,
+                Err(e) => {
+                    error!("❌ {}", e);
+                    panic!("Invalid OCI apps");
+                }
+            },
+        );
+    // the artifact is pulled into the branch folders, so they are only read as a whole without it
+    let mut app_roots: Vec<parsing::AppRoot> = match (app_dirs.is_empty(), &apps_oci) {
+        (true, Some(_)) => vec![],
+        (true, None) => vec![""],
+        (false, _) => app_dirs,
     }
-    if let Some(a) = diff_ignore.clone() {
-        info!("✨ - diff-ignore: {}", a);
+    .into_iter()
+    .map(
+        |d| match parsing::AppRoot::parse(d, &repo, base_revision, target_revision) {
+            Ok(root) => root,
+            Err(e) => {
+                error!("❌ {}", e);
+                panic!("Invalid app dir");
+            }
+        },
+    )
+    .collect();
+    if apps_oci.is_some() {
+        app_roots.push(parsing::AppRoot {
+            path: oci::APPS_DIR.to_string(),
+            repo: repo.clone(),
+            base_revision: base_revision.to_string(),
+            target_revision: target_revision.to_string(),
+        });
     }
-    if let Some(a) = line_count {
-        info!("✨ - line-count: {}", a);
+
+    let extra_branches: Vec<branches::ExtraBranch> = opt
+        .compare_branch
+        .iter()
+        .filter(|b| !b.trim().is_empty())
+        .map(|b| match branches::parse(b) {
+            Ok(branch) => branch,
+            Err(e) => {
+                error!("❌ {}", e);
+                panic!("Invalid branch");
+            }
+        })
+        .collect();
+
+    if opt.changed_files.is_some() && opt.changed_files_from.is_some() {
+        error!("❌ --changed-files and --changed-files-from can't be combined");
+        panic!("--changed-files used with --changed-files-from")
     }
-    if let Some(a) = argocd_version {
-        info!("✨ - argocd-version: {}", a);
+    if let Some(Err(e)) = opt.changed_files_from.as_deref().map(vcs::check) {
+        error!("❌ Invalid --changed-files-from: {}", e);
+        panic!("Invalid --changed-files-from")
     }
-    if let Some(a) = max_diff_length {
-        info!("✨ - max-diff-length: {}", a);
+    if opt.render_affected_only && opt.changed_files.is_none() && opt.changed_files_from.is_none() {
+        error!("❌ --render-affected-only requires --changed-files or --changed-files-from");
+        panic!("--render-affected-only used without changed files")
     }
-
-    // label selectors can be fined in the following format: key1==value1,key2=value2,key3!=value3
-    let selector = opt.selector.map(|s| {
-        let labels: Vec<Selector> = s
-            .split(",")
-            .filter(|l| !l.trim().is_empty())
-            .map(|l| {
-                let not_equal = l.split("!=").collect::<Vec<&str>>();
-                let equal_double = l.split("==").collect::<Vec<&str>>();
-                let equal_single = l.split("=").collect::<Vec<&str>>();
-                let selector = match (not_equal.len(), equal_double.len(), equal_single.len()) {
-                    (2, _, _) => Selector {
-                        key: not_equal[0].trim().to_string(),
-                        value: not_equal[1].trim().to_string(),
-                        operator: Operator::Ne,
-                    },
-                    (_, 2, _) => Selector {
-                        key: equal_double[0].trim().to_string(),
-                        value: equal_double[1].trim().to_string(),
-                        operator: Operator::Eq,
-                    },
-                    (_, _, 2) => Selector {
-                        key: equal_single[0].trim().to_string(),
-                        value: equal_single[1].trim().to_string(),
-                        operator: Operator::Eq,
-                    },
-                    _ => {
-                        error!("❌ Invalid label selector format: {}", l);
-                        panic!("Invalid label selector format");
-                    }
-                };
-                if selector.key.is_empty()
-                    || selector.key.contains("!")
-                    || selector.key.contains("=")
-                    || selector.value.is_empty()
-                    || selector.value.contains("!")
-                    || selector.value.contains("=")
-                {
-                    error!("❌ Invalid label selector format: {}", l);
-                    panic!("Invalid label selector format");
-                }
-                selector
-            })
-            .collect();
-        labels
+    let mut changed_files: Option<Vec<String>> = opt.changed_files.as_ref().map(|files| {
+        files
+            .split([',', '\n'])
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect()
     });
+    if let Some(name) = &opt.changed_files_from {
+        let base_revisions = match &base_sha {
+            Some(sha) => vec![sha.clone()],
+            None => vec![
+                format!("origin/{}", base_branch_name),
+                base_branch_name.clone(),
+            ],
+        };
+        let head = target_sha.as_deref().unwrap_or("HEAD");
+        // without the changed files, all applications are rendered. Reading the history blocks, so
+        // it doesn't hold up the other tasks of the runtime
+        changed_files = match tokio::task::block_in_place(|| {
+            vcs::open(name, target_folder)
+                .and_then(|v| vcs::changed_files(v.as_ref(), &base_revisions, head))
+        }) {
+            Ok(files) => Some(files),
+            Err(e) => {
+                info!(
+                    "⚠️ Failed to compute the changed files with {}: {}",
+                    name, e
+                );
+                None
+            }
+        };
+    }
 
-    if let Some(list) = &selector {
-        info!(
+    let protected_kinds: Vec<String> = match opt.fail_fast_on.as_deref() {
+        None => vec![],
+        Some("destructive") => opt
+            .protected_kinds
+            .iter()
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect(),
+        Some(f) => {
+            error!("❌ Invalid --fail-fast-on: {}. Options: destructive", f);
+            panic!("Invalid --fail-fast-on");
+        }
+    };
+
+    let report_labels: Vec<String> = opt
+        .report_labels
+        .iter()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    let backstage_entity_keys: Vec<String> = opt
+        .backstage_entity_keys
+        .iter()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+
+    let group_by = opt
+        .group_by
+        .as_deref()
+        .filter(|g| !g.trim().is_empty())
+        .map(|g| match grouping::GroupBy::parse(g) {
+            Ok(group_by) => group_by,
+            Err(e) => {
+                error!("❌ {}", e);
+                panic!("Invalid grouping");
+            }
+        });
+
+    let kubernetes_versions: Vec<&str> = opt
+        .k8s_version
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .collect();
+    if kubernetes_versions.len() > 1 && opt.watch {
+        error!("❌ --watch can't be combined with multiple Kubernetes versions");
+        panic!("--watch used with a Kubernetes version matrix")
+    }
+
+    // the versions are given by the local binaries
+    if !kubernetes_versions.is_empty() && matches!(tool, ClusterTool::Envtest) {
+        error!(
+            "❌ --k8s-version can't be used with envtest. The version of kube-apiserver is used"
+        );
+        panic!("--k8s-version used with envtest")
+    }
+
+    let compare_argocd_version = opt
+        .compare_argocd_chart_version
+        .as_deref()
+        .filter(|f| !f.trim().is_empty());
+    if drift.is_some() && (opt.watch || opt.compare_with.is_some()) {
+        error!("❌ drift can't be combined with --watch or --compare-with");
+        panic!("drift used with --watch or --compare-with")
+    }
+    if let Err(e) = opt.run_id.as_deref().map(lock::validate_run_id).transpose() {
+        error!("❌ Invalid run id");
+        panic!("error: {}", e)
+    }
+    // the sample is chosen by comparing the applications generated in both branches
+    if opt.appset_sample.is_some()
+        && (opt.compare_with.is_some() || opt.retry_failed.is_some() || !extra_branches.is_empty())
+    {
+        error!("❌ --appset-sample can't be combined with --compare-with, --retry-failed or --compare-branch");
+        panic!("--appset-sample used with another base")
+    }
+    if opt.retry_failed.is_some() && opt.watch {
+        error!("❌ --retry-failed can't be combined with --watch");
+        panic!("--retry-failed used with --watch")
+    }
+    if compare_argocd_version.is_some() && opt.watch {
+        error!("❌ --watch can't be combined with --compare-argocd-chart-version");
+        panic!("--watch used with --compare-argocd-chart-version")
+    }
+    // the other renders of the target branch read the applications of the target branch, which the
+    // extra branches overwrite
+    if !extra_branches.is_empty()
+        && (kubernetes_versions.len() > 1 || compare_argocd_version.is_some())
+    {
+        error!("❌ --compare-branch can't be combined with multiple Kubernetes versions or --compare-argocd-chart-version");
+        panic!("--compare-branch used with another render of the target branch")
+    }
+    if compare_argocd_version.is_some() && matches!(tool, ClusterTool::Envtest) {
+        error!("❌ --compare-argocd-chart-version can't be used with envtest. The version of the argocd binary is used");
+        panic!("--compare-argocd-chart-version used with envtest")
+    }
+
+    let kind_config = opt.kind_config.as_deref().filter(|f| !f.trim().is_empty());
+    if let Some(config) = kind_config {
+        if !matches!(tool, ClusterTool::Kind) {
+            error!("❌ --kind-config can only be used with kind");
+            panic!("kind config used with {:?}", tool)
+        }
+        if !PathBuf::from(config).is_file() {
+            error!("❌ kind config not found: {}", config);
+            panic!("kind config not found")
+        }
+    }
+
+    let node_image = opt.node_image.as_deref().filter(|i| !i.trim().is_empty());
+    if (node_image.is_some() || opt.cluster_arch.is_some()) && !matches!(tool, ClusterTool::Kind) {
+        error!("❌ --node-image and --cluster-arch can only be used with kind");
+        panic!("node image or cluster arch used with {:?}", tool)
+    }
+    // the node image determines the Kubernetes version of the cluster
+    if node_image.is_some() && !kubernetes_versions.is_empty() {
+        error!("❌ --node-image can't be combined with --k8s-version");
+        panic!("--node-image used with --k8s-version")
+    }
+    let cluster_arch = match (&opt.cluster_arch, tool) {
+        (Some(a), _) => match platform::Arch::parse(a) {
+            Some(arch) => Some(arch),
+            None => {
+                error!(
+                    "❌ Invalid cluster architecture: {}. Options: amd64, arm64",
+                    a
+                );
+                panic!("Invalid cluster architecture")
+            }
+        },
+        (None, ClusterTool::Kind) => Some(platform::detect().await),
+        (None, _) => None,
+    };
+    let argocd_image = opt.argocd_image.as_deref().filter(|i| !i.trim().is_empty());
+    if argocd_image.is_some() && matches!(tool, ClusterTool::Envtest) {
+        error!("❌ --argocd-image can't be used with envtest. The argocd binary is used");
+        panic!("--argocd-image used with envtest")
+    }
+
+    let cluster_snapshot = opt
+        .cluster_snapshot
+        .as_deref()
+        .filter(|f| !f.trim().is_empty());
+    let create_cluster_snapshot = opt
+        .create_cluster_snapshot
+        .as_deref()
+        .filter(|f| !f.trim().is_empty());
+    if cluster_snapshot.is_some() || create_cluster_snapshot.is_some() {
+        if !matches!(tool, ClusterTool::Kind) {
+            error!("❌ Cluster snapshots can only be used with kind");
+            panic!("cluster snapshot used with {:?}", tool)
+        }
+        if kubernetes_versions.len() > 1 {
+            error!("❌ Cluster snapshots can't be combined with multiple Kubernetes versions");
+            panic!("cluster snapshot used with a Kubernetes version matrix")
+        }
+    }
+    if cluster_snapshot.is_some() {
+        // the snapshot already contains the cluster and Argo CD
+        if create_cluster_snapshot.is_some()
+            || kind_config.is_some()
+            || !kubernetes_versions.is_empty()
+            || node_image.is_some()
+        {
+            error!("❌ --cluster-snapshot can't be combined with --create-cluster-snapshot, --kind-config, --k8s-version or --node-image");
+            panic!("--cluster-snapshot used with cluster options")
+        }
+        if opt.argocd_chart_version.is_some() || argocd_image.is_some() {
+            info!("⚠️ --argocd-chart-version and --argocd-image are ignored, since Argo CD is part of the cluster snapshot");
+        }
+    }
+
+    let argocd_profile = match opt.argocd_profile.as_str() {
+        "minimal" => argocd::ArgoCDProfile::Minimal,
+        "full" => argocd::ArgoCDProfile::Full,
+        p => {
+            error!("❌ Invalid Argo CD profile: {}. Options: minimal, full", p);
+            panic!("Invalid Argo CD profile")
+        }
+    };
+
+    // let repo_regex = Regex::new(r"^[a-zA-Z0-9-]+/[a-zA-Z0-9-]+$").unwrap();
+    // if !repo_regex.is_match(&repo) {
+    //     error!("❌ Invalid repository format. Please use OWNER/REPO");
+    //     panic!("Invalid repository format");
+    // }
+
+    info!("✨ Running with:");
+    info!("✨ - local-cluster-tool: {:?}", tool);
+    if let Some(a) = kind_config {
+        info!("✨ - kind-config: {}", a);
+    }
+    if let Some(a) = cluster_arch {
+        info!("✨ - cluster-arch: {}", a);
+    }
+    if let Some(a) = node_image {
+        info!("✨ - node-image: {}", a);
+    }
+    if let Some(a) = argocd_image {
+        info!("✨ - argocd-image: {}", a);
+    }
+    if let Some(a) = cluster_snapshot {
+        info!("✨ - cluster-snapshot: {}", a);
+    }
+    if let Some(a) = create_cluster_snapshot {
+        info!("✨ - create-cluster-snapshot: {}", a);
+    }
+    if !kubernetes_versions.is_empty() {
+        info!("✨ - k8s-version: {}", kubernetes_versions.join(","));
+    }
+    info!("✨ - base-branch: {}", base_branch_name);
+    info!("✨ - target-branch: {}", target_branch_name);
+    if let Some(a) = &base_sha {
+        info!("✨ - base-sha: {}", a);
+    }
+    if let Some(a) = &opt.compare_with {
+        info!("✨ - compare-with: {}", a);
+    }
+    if let Some(a) = &target_sha {
+        info!("✨ - target-sha: {}", a);
+    }
+    info!("✨ - secrets-folder: {}", secrets_folder);
+    info!("✨ - output-folder: {}", output_folder);
+    if let Some(a) = &opt.workdir {
+        info!("✨ - workdir: {}", a);
+    }
+    if let Some(a) = &opt.stats_file {
+        info!("✨ - stats-file: {}", a);
+    }
+    match &dirs {
+        Some((base, target)) => {
+            info!("✨ - base-dir: {}", base);
+            info!("✨ - target-dir: {}", target);
+        }
+        None => info!("✨ - repo: {}", repo),
+    }
+    for (from, to) in &repo_rewrites {
+        info!("✨ - rewrite-repo: {}={}", from, to);
+    }
+    for branch in &extra_branches {
+        info!("✨ - compare-branch: {}={}", branch.name, branch.folder);
+    }
+    if let Some(a) = &opt.changed_files_from {
+        info!("✨ - changed-files-from: {}", a);
+    }
+    if let Some(files) = &changed_files {
+        info!("✨ - changed-files: {} files", files.len());
+    }
+    if opt.render_affected_only {
+        info!("✨ - render-affected-only: true");
+    }
+    if !opt.app_dir.iter().all(|d| d.trim().is_empty()) {
+        for root in app_roots.iter().filter(|r| r.path != oci::APPS_DIR) {
+            info!(
+                "✨ - app-dir: {} (repo: {}, base: {}, target: {})",
+                root.folder("."),
+                root.repo,
+                root.base_revision,
+                root.target_revision
+            );
+        }
+    }
+    if let Some(apps) = &apps_oci {
+        info!(
+            "✨ - apps-oci: {} (base: {}, target: {})",
+            apps.reference, apps.base_tag, apps.target_tag
+        );
+    }
+    info!("✨ - timeout: {} seconds", timeout);
+    if let Some(a) = file_regex.clone() {
+        info!("✨ - file-regex: {}", a.as_str());
+    }
+    if let Some(a) = diff_ignore.clone() {
+        info!("✨ - diff-ignore: {}", a);
+    }
+    if let Some(a) = line_count {
+        info!("✨ - line-count: {}", a);
+    }
+    if let Some(a) = &opt.diff_command {
+        info!("✨ - diff-command: {}", a);
+    }
+    if opt.diff_format != "unified" {
+        info!("✨ - diff-format: {}", opt.diff_format);
+    }
+    if let Some(a) = argocd_version {
+        info!("✨ - argocd-version: {}", a);
+    }
+    if let Some(a) = compare_argocd_version {
+        info!("✨ - compare-argocd-chart-version: {}", a);
+    }
+    if let Some(a) = max_diff_length {
+        info!("✨ - max-diff-length: {}", a);
+    }
+    if !publishers.is_empty() {
+        let names: Vec<&str> = publishers.iter().map(|p| p.name.as_str()).collect();
+        info!("✨ - publishers: {}", names.join(","));
+    }
+    if let Some(a) = &opt.artifact_url {
+        info!("✨ - artifact-url: {}", a);
+    }
+    info!("✨ - argocd-profile: {:?}", argocd_profile);
+    info!("✨ - repo-server-replicas: {}", opt.repo_server_replicas);
+    info!(
+        "✨ - repo-server-parallelism-limit: {}",
+        opt.repo_server_parallelism_limit
+    );
+    info!(
+        "✨ - controller-status-processors: {}",
+        opt.controller_status_processors
+    );
+    if let Some(a) = &opt.resource_customizations {
+        info!("✨ - resource-customizations: {}", a);
+    }
+    info!(
+        "✨ - kustomize-build-options: {}",
+        opt.kustomize_build_options
+    );
+    if let Some(a) = opt.apply_batch_size {
+        info!("✨ - apply-batch-size: {}", a);
+    }
+    if let Some(a) = opt.appset_sample {
+        info!("✨ - appset-sample: {}", a);
+    }
+    if opt.serve {
+        info!("✨ - serve-port: {}", opt.serve_port);
+    }
+    if opt.watch {
+        info!("✨ - watch: true");
+    }
+    if opt.keep_cluster_on_failure {
+        info!("✨ - keep-cluster-on-failure: true");
+    }
+    if let Some(a) = &opt.retry_failed {
+        info!("✨ - retry-failed: {}", a);
+    }
+    if let Some(a) = &opt.run_id {
+        info!("✨ - run-id: {}", a);
+    }
+    if opt.app_info {
+        info!("✨ - app-info: true");
+    }
+    if opt.list_unchanged {
+        info!("✨ - list-unchanged: true");
+    }
+    if opt.duplicates {
+        info!("✨ - duplicates: true");
+    }
+    if opt.render_twice {
+        info!("✨ - render-twice: true");
+    }
+    if opt.render_isolation {
+        info!("✨ - render-isolation: true");
+    }
+    if let Some(a) = opt.max_resource_lines {
+        info!("✨ - max-resource-lines: {}", a);
+    }
+    if let Some(a) = opt.max_app_diff_lines {
+        info!("✨ - max-app-diff-lines: {}", a);
+    }
+    info!("✨ - rename-similarity: {}", opt.rename_similarity);
+    if opt.anonymize || !opt.anonymize_pattern.is_empty() {
+        info!("✨ - anonymize: {} patterns", opt.anonymize_pattern.len());
+    }
+    if opt.ignore_injected_fields {
+        info!("✨ - ignore-injected-fields: true");
+    }
+    if let Some(a) = &opt.injected_fields {
+        info!("✨ - injected-fields: {}", a);
+    }
+    if opt.strip_generated_fields {
+        info!("✨ - strip-generated-fields: true");
+    }
+    if opt.retain_sync_policy {
+        info!("✨ - retain-sync-policy: true");
+    }
+    if opt.skip_self_managed {
+        info!("✨ - skip-self-managed: true");
+    }
+    if opt.keep_projects || opt.project_repositories.is_some() {
+        info!("✨ - keep-projects: true");
+    }
+    if let Some(a) = &opt.project_repositories {
+        info!("✨ - project-repositories: {}", a);
+    }
+    if let Some(g) = &group_by {
+        info!("✨ - group-by: {}", g);
+    }
+    if opt.dedupe_diffs {
+        info!("✨ - dedupe-diffs: true");
+    }
+    if opt.codeowners || opt.mention_owners {
+        info!("✨ - codeowners: true");
+    }
+    if opt.mention_owners {
+        info!("✨ - mention-owners: true");
+    }
+    if let Some(a) = &opt.source_links {
+        info!("✨ - source-links: {}", a);
+    }
+    if !report_labels.is_empty() {
+        info!("✨ - report-labels: {}", report_labels.join(","));
+    }
+    if !backstage_entity_keys.is_empty() {
+        info!(
+            "✨ - backstage-entity-keys: {}",
+            backstage_entity_keys.join(",")
+        );
+    }
+    if !protected_kinds.is_empty() {
+        info!(
+            "✨ - fail-fast-on: destructive ({})",
+            protected_kinds.join(",")
+        );
+    }
+    if let Some(a) = &opt.protected_resources {
+        info!("✨ - protected-resources: {}", a);
+    }
+    if let Some(a) = &opt.namespace_labels {
+        info!("✨ - namespace-labels: {}", a);
+    }
+    if let Some(a) = opt.protected_exit_code {
+        info!("✨ - protected-exit-code: {}", a);
+    }
+    if opt.diff_app_specs {
+        info!("✨ - diff-app-specs: true");
+    }
+    if opt.values_changes {
+        info!("✨ - values-changes: true");
+    }
+    if opt.dependency_graph {
+        info!("✨ - dependency-graph: true");
+    }
+    if opt.floating_references {
+        info!("✨ - floating-references: true");
+    }
+    if opt.cost_estimation {
+        info!("✨ - cost-estimation: true");
+    }
+    if let Some(a) = &opt.pricing_config {
+        info!("✨ - pricing-config: {}", a);
+    }
+    if opt.resource_changes {
+        info!("✨ - resource-changes: true");
+    }
+    if let Some(a) = &opt.crds {
+        info!("✨ - crds: {}", a);
+    }
+    if let Some(a) = &opt.helm_capabilities {
+        info!("✨ - helm-capabilities: {}", a);
+    }
+    if !opt.preview_values.is_empty() {
+        info!("✨ - preview-values: {}", opt.preview_values.join(", "));
+    }
+    if let Some(a) = &opt.plugin_config {
+        info!("✨ - plugin-config: {}", a);
+    }
+    if let Some(a) = &opt.verdict_rules {
+        info!("✨ - verdict-rules: {}", a);
+    }
+    if opt.verdict_exit_code {
+        info!("✨ - verdict-exit-code: true");
+    }
+    if opt.pr_labels {
+        info!("✨ - pr-labels: true");
+        info!("✨ - pr-label-prefix: {}", opt.pr_label_prefix);
+    }
+    if let Some(a) = opt.pull_request {
+        info!("✨ - pull-request: {}", a);
+    }
+    if let Some(a) = &opt.pull_request_title {
+        info!("✨ - pull-request-title: {}", a);
+    }
+    if let Some(a) = &opt.resource_summaries {
+        info!("✨ - resource-summaries: {}", a);
+    }
+    if let Some(a) = &opt.pre_cluster_hook {
+        info!("✨ - pre-cluster-hook: {}", a);
+    }
+    if let Some(a) = &opt.post_render_hook {
+        info!("✨ - post-render-hook: {}", a);
+    }
+    if let Some(a) = &opt.pre_publish_hook {
+        info!("✨ - pre-publish-hook: {}", a);
+    }
+
+    if let Err(e) = workdir::init(opt.workdir.as_deref()) {
+        error!("❌ Failed to create work directory");
+        panic!("error: {}", e)
+    }
+
+    let pricing = match &opt.pricing_config {
+        Some(path) => match cost::read_pricing_config(path) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                error!("❌ Failed to read pricing config: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+
+    let crds = match opt.crds.as_deref().map(crds::parse) {
+        Some(Ok(c)) => c,
+        Some(Err(e)) => {
+            error!("❌ Invalid --crds");
+            panic!("error: {}", e)
+        }
+        None => vec![],
+    };
+
+    let helm_capabilities = match &opt.helm_capabilities {
+        Some(path) => match capabilities::read_helm_capabilities(path) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                error!("❌ Failed to read Helm capabilities: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+
+    let preview_values = match opt.preview_values.is_empty() {
+        true => None,
+        false => match preview_values::read_preview_values(&opt.preview_values) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("❌ Invalid --preview-values");
+                panic!("error: {}", e)
+            }
+        },
+    };
+
+    let plugins = match &opt.plugin_config {
+        Some(path) => match plugins::read_plugin_config(path) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                error!("❌ Failed to read plugin config: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+
+    if opt.verdict_exit_code && opt.verdict_rules.is_none() {
+        error!("❌ --verdict-exit-code requires --verdict-rules");
+        panic!("missing --verdict-rules");
+    }
+    let verdict_rules = match &opt.verdict_rules {
+        Some(path) => match verdict::read_verdict_rules(path) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("❌ Failed to read verdict rules: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+
+    if opt.protected_exit_code.is_some() && opt.protected_resources.is_none() {
+        error!("❌ --protected-exit-code requires --protected-resources");
+        panic!("missing --protected-resources");
+    }
+    let resource_customizations = match &opt.resource_customizations {
+        Some(path) => match customizations::read_resource_customizations(path) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("❌ Failed to read resource customizations: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => BTreeMap::new(),
+    };
+    let namespace_labels = match &opt.namespace_labels {
+        Some(path) => match namespaces::read_namespace_labels(path) {
+            Ok(l) => Some(l),
+            Err(e) => {
+                error!("❌ Failed to read namespace labels: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+    let projects = match (opt.keep_projects, &opt.project_repositories) {
+        (false, None) => None,
+        (_, None) => Some(projects::ProjectConfig::default()),
+        (_, Some(path)) => match projects::read_project_config(path) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                error!("❌ Failed to read project repositories: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+    };
+    let mut injected_fields = match (opt.ignore_injected_fields, &opt.injected_fields) {
+        (false, None) => None,
+        (_, path) => match injected::read_injected_fields(path.as_deref()) {
+            Ok(fields) => Some(fields),
+            Err(e) => {
+                error!(
+                    "❌ Failed to read injected fields: {}",
+                    path.as_deref().unwrap_or_default()
+                );
+                panic!("error: {}", e)
+            }
+        },
+    };
+
+    // the differences Argo CD ignores don't show up in the diff either
+    if !resource_customizations.is_empty() {
+        let fields = injected_fields.get_or_insert_with(injected::InjectedFields::default);
+        match customizations::add_ignored_fields(&resource_customizations, fields) {
+            Ok(count) => info!(
+                "🔧 Ignoring {} fields of the resource customizations",
+                count
+            ),
+            Err(e) => {
+                error!("❌ Invalid ignored differences in the resource customizations");
+                panic!("error: {}", e)
+            }
+        }
+    }
+
+    let anonymizer = match opt.anonymize || !opt.anonymize_pattern.is_empty() {
+        true => match anonymize::Anonymizer::new(
+            &opt.anonymize_pattern,
+            opt.anonymize_salt.as_deref().unwrap_or_default(),
+        ) {
+            Ok(a) => Some(a),
+            Err(e) => {
+                error!("❌ {}", e);
+                panic!("Invalid anonymize pattern")
+            }
+        },
+        false => None,
+    };
+
+    let protected_rules = match &opt.protected_resources {
+        Some(path) => match protected::read_protected_rules(path) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("❌ Failed to read protected resources: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+
+    let summaries = match summaries::read_summaries(opt.resource_summaries.as_deref()) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("❌ Failed to read resource summaries");
+            panic!("error: {}", e)
+        }
+    };
+
+    let selector = opt
+        .selector
+        .as_deref()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| match selector::parse(s) {
+            Ok(selector) => selector,
+            Err(e) => {
+                error!("❌ Invalid label selector: {}", e);
+                panic!("Invalid label selector format");
+            }
+        });
+
+    if let Some(list) = &selector {
+        info!(
             "✨ - selector: {}",
             list.iter()
                 .map(|s| s.to_string())
@@ -275,98 +1570,1018 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .join(",")
         );
     }
+    info!("✨ - ignore-annotation: {}", opt.ignore_annotation);
 
-    if !check_if_folder_exists(&BASE_BRANCH_FOLDER) {
-        error!(
-            "❌ Base branch folder does not exist: {}",
-            BASE_BRANCH_FOLDER
+    // the repo is set per app root
+    let patch_options = parsing::PatchOptions {
+        repo: &repo,
+        repo_rewrites: &repo_rewrites,
+        retain_sync_policy: opt.retain_sync_policy,
+        plugins: plugins.as_ref(),
+        helm_capabilities: helm_capabilities.as_ref(),
+        ignore_annotation: &opt.ignore_annotation,
+        skip_self_managed: opt.skip_self_managed,
+        keep_projects: projects.is_some(),
+        only: None,
+        hold_generated: opt.appset_sample.is_some(),
+        preview_values: None,
+    };
+
+    if let Some((branch, output)) = &render_apps {
+        let (branch, folder) = match branch.parse() {
+            Ok(Branch::Base) => (Branch::Base, base_folder),
+            Ok(Branch::Target) => (Branch::Target, target_folder),
+            Err(e) => {
+                error!("❌ {}", e);
+                panic!("Invalid branch");
+            }
+        };
+        if !check_if_folder_exists(folder) {
+            error!("❌ Branch folder does not exist: {}", folder);
+            panic!("Branch folder does not exist");
+        }
+        if let Some(apps) = &apps_oci {
+            apps.pull(&branch, folder).await?;
+        }
+        let mut out: Box<dyn Write> = match output.as_str() {
+            "-" => Box::new(std::io::stdout().lock()),
+            path => Box::new(BufWriter::new(fs::File::create(path)?)),
+        };
+        let mut skipped = vec![];
+        for root in &app_roots {
+            let revision = match branch {
+                Branch::Base => &root.base_revision,
+                Branch::Target => &root.target_revision,
+            };
+            let patch_options = parsing::PatchOptions {
+                repo: &root.repo,
+                preview_values: match branch {
+                    Branch::Base => None,
+                    Branch::Target => preview_values.as_ref(),
+                },
+                ..patch_options
+            };
+            skipped.extend(
+                parsing::write_patched_applications(
+                    &branch,
+                    &root.folder(folder),
+                    revision,
+                    &file_regex,
+                    &selector,
+                    &patch_options,
+                    &mut out,
+                )
+                .await?,
+            );
+        }
+        out.flush()?;
+        info!(
+            "🎉 Wrote the applications of the {} branch to {} ({} skipped)",
+            branch,
+            if output == "-" { "stdout" } else { output },
+            skipped.len()
         );
+        workdir::cleanup();
+        return Ok(());
+    }
+
+    let mut had_snapshot = false;
+    let compare_with = match (
+        &drift,
+        opt.compare_with.as_deref().filter(|f| !f.trim().is_empty()),
+    ) {
+        (Some(d), _) => match drift::fetch_snapshot(&d.snapshot).await {
+            Ok(r) => {
+                had_snapshot = r.is_some();
+                Some(r.unwrap_or_else(|| history::StoredReport::empty("no snapshot")))
+            }
+            Err(e) => {
+                error!("❌ Failed to fetch snapshot: {}", d.snapshot);
+                panic!("error: {}", e)
+            }
+        },
+        (None, Some(path)) => match history::read_report(path) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("❌ Failed to read report: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        (None, None) => None,
+    };
+    // the diff header names the stored report instead of the base branch
+    let base_name = match &compare_with {
+        Some(r) => format!("{} ({})", r.branch, r.revision),
+        None => base_branch_name.clone(),
+    };
+
+    // the base branch isn't rendered when diffing against a stored report
+    if compare_with.is_none() && !check_if_folder_exists(base_folder) {
+        error!("❌ Base branch folder does not exist: {}", base_folder);
         panic!("Base branch folder does not exist");
     }
 
-    if !check_if_folder_exists(&TARGET_BRANCH_FOLDER) {
-        error!(
-            "❌ Target branch folder does not exist: {}",
-            TARGET_BRANCH_FOLDER
-        );
+    if !check_if_folder_exists(target_folder) {
+        error!("❌ Target branch folder does not exist: {}", target_folder);
         panic!("Target branch folder does not exist");
     }
 
-    let cluster_name = CLUSTER_NAME;
+    for branch in &extra_branches {
+        if !check_if_folder_exists(&branch.folder) {
+            error!(
+                "❌ Folder of branch {} does not exist: {}",
+                branch.name, branch.folder
+            );
+            panic!("Branch folder does not exist");
+        }
+    }
 
-    match tool {
-        ClusterTool::Kind => kind::create_cluster(&cluster_name).await?,
-        ClusterTool::Minikube => minikube::create_cluster().await?,
-    }
-    
-    argocd::install_argo_cd(argocd::ArgoCDOptions {
-        version: argocd_version,
-        debug: opt.debug,
-    })
-    .await?;
-
-    create_folder_if_not_exists(secrets_folder);
-    match apply_folder(secrets_folder) {
-        Ok(count) if count > 0 => info!("🤫 Applied {} secrets", count),
-        Ok(_) => info!("🤷 No secrets found in {}", secrets_folder),
-        Err(e) => {
-            error!("❌ Failed to apply secrets");
+    if let Some(apps) = &apps_oci {
+        if compare_with.is_none() {
+            apps.pull(&Branch::Base, base_folder).await?;
+        }
+        apps.pull(&Branch::Target, target_folder).await?;
+    }
+
+    // a root can be missing in one of the branches, e.g. when it is added in the target branch
+    for root in &app_roots {
+        if !check_if_folder_exists(&root.folder(base_folder))
+            && !check_if_folder_exists(&root.folder(target_folder))
+        {
+            error!("❌ App dir does not exist in either branch: {}", root.path);
+            panic!("App dir does not exist");
+        }
+    }
+
+    // other renders of the target branch are compared even when nothing changed
+    let single_render = extra_branches.is_empty()
+        && kubernetes_versions.len() <= 1
+        && compare_argocd_version.is_none();
+    let mut affected = None;
+    if let Some(files) = changed_files
+        .as_ref()
+        .filter(|_| compare_with.is_none() && !opt.watch && single_render)
+    {
+        if preview_values.is_none()
+            && unchanged::nothing_to_render(
+                &app_roots,
+                &repo,
+                base_folder,
+                target_folder,
+                &file_regex,
+                files,
+            )
+            .await
+        {
+            info!("🎉 No changes detected. Skipping rendering");
+            clean_output_folder(output_folder);
+            diff::write_no_changes_report(output_folder)?;
+            // the branch folders are empty, so nothing is changed, removed or failing
+            gate::write_gate(output_folder, None, &[], &[])?;
+            warnings::write_warnings(output_folder)?;
+            if opt.pr_labels {
+                let labels = labels::classify(output_folder, &opt.pr_label_prefix, None, false);
+                labels::write_labels(output_folder, &labels)?;
+                if let Some(pull_request) = opt.pull_request {
+                    if let Err(e) =
+                        labels::apply_labels(&repo, pull_request, &opt.pr_label_prefix, &labels)
+                            .await
+                    {
+                        info!("⚠️ Failed to label pull request #{}: {}", pull_request, e);
+                    }
+                }
+            }
+            workdir::cleanup();
+            return Ok(());
+        }
+        if opt.render_affected_only {
+            affected = unchanged::affected_applications(
+                &app_roots,
+                &repo,
+                base_folder,
+                target_folder,
+                &file_regex,
+                files,
+            )
+            .await;
+            // applications with preview values change without a changed file
+            if let (Some(names), Some(values)) = (&mut affected, &preview_values) {
+                names.extend(values.files().map(|(name, _)| name.clone()));
+            }
+            if let Some(names) = &affected {
+                info!(
+                    "🎯 Rendering the {} applications affected by the changes",
+                    names.len()
+                );
+            }
+        }
+    } else if opt.render_affected_only && changed_files.is_some() {
+        info!("⚠️ Rendering all applications, since the run compares more than the two branches");
+    }
+
+    let retry_info = match &opt.retry_failed {
+        Some(path) => match retry::read_run_info(path, output_folder) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("❌ Failed to read run info: {}", path);
+                panic!("error: {}", e)
+            }
+        },
+        None => None,
+    };
+
+    // the default build options are completed with the options the kustomizations need. Options
+    // set explicitly are only checked, so they are never changed behind the user's back. Options
+    // that run code from the repository are never added
+    let mut kustomize_build_options = opt.kustomize_build_options.clone();
+    let required = kustomize::required_options(&[base_folder, target_folder]);
+    let missing = kustomize::missing_options(&kustomize_build_options, &required);
+    let explicit = matches.occurrences_of("kustomize-build-options") > 0
+        || std::env::var("KUSTOMIZE_BUILD_OPTIONS").is_ok();
+    let not_added: Vec<_> = missing
+        .iter()
+        .filter(|(option, _)| explicit || !option.is_added_automatically())
+        .collect();
+    if !not_added.is_empty() {
+        for (option, file) in &not_added {
+            error!(
+                "❌ {} needs the Kustomize build option '{}'. Add it to --kustomize-build-options",
+                file,
+                option.flag()
+            );
+        }
+        panic!("missing Kustomize build options");
+    }
+    for (option, file) in &missing {
+        info!(
+            "🔧 Adding Kustomize build option '{}', since {} needs it",
+            option.flag(),
+            file
+        );
+        kustomize_build_options = format!("{} {}", kustomize_build_options, option.flag())
+            .trim()
+            .to_string();
+    }
+
+    let cluster_name = &lock::cluster_name(CLUSTER_NAME, opt.run_id.as_deref());
+
+    let hook_context = hooks::HookContext {
+        base_branch: &base_branch_name,
+        target_branch: &target_branch_name,
+        repo: &repo,
+        output_folder,
+        cluster_name,
+        cluster_tool: format!("{:?}", tool).to_lowercase(),
+    };
+
+    hooks::run_hook(
+        &hooks::Hook::PreCluster,
+        opt.pre_cluster_hook.as_deref(),
+        &hook_context,
+    )?;
+
+    let cluster = ClusterSetup {
+        tool,
+        name: cluster_name,
+        kind_config,
+        arch: cluster_arch,
+        node_image,
+        snapshot: cluster_snapshot,
+        create_snapshot: create_cluster_snapshot,
+        secrets_folder,
+        crds: &crds,
+        plugins: plugins.as_ref(),
+        argocd: argocd::ArgoCDOptions {
+            version: argocd_version,
+            debug: opt.debug,
+            fallback_manifests: opt.argocd_fallback_manifests.as_deref().map(|m| {
+                m.split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }),
+            profile: argocd_profile,
+            tuning: argocd::ArgoCDTuning {
+                repo_server_replicas: opt.repo_server_replicas,
+                repo_server_parallelism_limit: opt.repo_server_parallelism_limit,
+                controller_status_processors: opt.controller_status_processors,
+            },
+            kustomize_build_options: &kustomize_build_options,
+            resource_customizations: &resource_customizations,
+            image: argocd_image,
+        },
+    };
+
+    if let Some(file) = &opt.stats_file {
+        stats::init(file, &hook_context.cluster_tool);
+    }
+    teardown::init(output_folder, opt.keep_cluster_on_failure);
+    teardown::set_phase(Some("creating the cluster"));
+    if !matches!(tool, ClusterTool::Envtest) {
+        if let Err(e) = lock::acquire(cluster_name) {
+            error!("❌ Failed to lock the cluster {}", cluster_name);
             panic!("error: {}", e)
         }
+        // contexts of clusters of other runs must not replace the current context of this run
+        if opt.run_id.is_some() {
+            std::env::set_var("KUBECONFIG", workdir::path("kubeconfig"));
+        }
+    }
+    let name = cluster_name.clone();
+    teardown::cluster_created(Box::new(move || delete_cluster(tool, &name, true)));
+    // a cluster kept by the failed run already has Argo CD and the secrets
+    if retry_info.is_some()
+        && matches!(tool, ClusterTool::Kind)
+        && kind::cluster_exists(cluster_name).await
+    {
+        info!("♻️ Reusing the cluster of the failed run");
+        // the kubeconfig of a run with --run-id was in the work directory of the failed run
+        kind::export_kubeconfig(cluster_name).await?;
+        argocd::connect(&cluster.argocd.profile).await;
+        extract::delete_applications().await;
+    } else {
+        cluster.create(kubernetes_versions.first().copied()).await?;
     }
 
-    // remove .git from repo
-    //let repo = repo.trim_end_matches(".git");
-    let base_apps = parsing::get_applications_as_string(
-        BASE_BRANCH_FOLDER,
-        &base_branch_name,
-        &file_regex,
-        &selector,
-        &repo,
-    )
-    .await?;
-    let target_apps = parsing::get_applications_as_string(
-        TARGET_BRANCH_FOLDER,
-        &target_branch_name,
-        &file_regex,
-        &selector,
-        &repo,
-    )
-    .await?;
+    if opt.serve {
+        let output_folder = output_folder.to_string();
+        let port = opt.serve_port;
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(output_folder, port).await {
+                error!("❌ Failed to serve report: {}", e);
+            }
+        });
+    }
 
-    fs::write(apps_file(&Branch::Base), base_apps)?;
-    fs::write(apps_file(&Branch::Target), &target_apps)?;
+    let pull_request_title = opt
+        .pull_request_title
+        .clone()
+        .or_else(|| std::env::var("CI_MERGE_REQUEST_TITLE").ok())
+        .filter(|t| !t.trim().is_empty());
+    let pull_request = match (pull_request_title, opt.pull_request) {
+        (Some(title), Some(n)) => Some(format!("{} (#{})", title, n)),
+        (Some(title), None) => Some(title),
+        (None, Some(n)) => Some(format!("#{}", n)),
+        (None, None) => None,
+    };
+    let commit = |folder: &str, sha: &Option<String>| {
+        tokio::task::block_in_place(|| vcs::read_commit(folder, sha.as_deref().unwrap_or("HEAD")))
+            .map_err(|e| debug!("No commit metadata for {}: {}", folder, e))
+            .ok()
+    };
+    let report_header = diff::commit_header(
+        pull_request,
+        &[
+            (
+                Branch::Base,
+                &base_branch_name,
+                commit(base_folder, &base_sha),
+            ),
+            (
+                Branch::Target,
+                &target_branch_name,
+                commit(target_folder, &target_sha),
+            ),
+        ],
+    );
 
-    // Cleanup
-    clean_output_folder(output_folder);
+    // the verdict of the last run, used as exit code with --verdict-exit-code
+    let mut verdict = None;
+    // whether the last run removed or changed protected resources, used with --protected-exit-code
+    let mut protected_changed;
+    let render_options = extract::RenderOptions {
+        timeout,
+        batch_size: opt.apply_batch_size,
+        strip_generated_fields: opt.strip_generated_fields,
+        injected_fields,
+        protected_kinds,
+        keep_rendered: retry_info.is_some(),
+        namespace_labels,
+        projects,
+        appset_sample: opt.appset_sample.map(|size| sampling::AppSetSample {
+            size,
+            revisions: app_roots
+                .iter()
+                .flat_map(|r| [r.base_revision.clone(), r.target_revision.clone()])
+                .collect(),
+        }),
+        isolation: opt.render_isolation.then(|| isolation::RenderIsolation {
+            repos: app_roots.iter().map(|r| r.repo.clone()).collect(),
+        }),
+    };
 
-    extract::get_resources(&Branch::Base, timeout, output_folder).await?;
-    extract::delete_applications().await;
-    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-    extract::get_resources(&Branch::Target, timeout, output_folder).await?;
+    loop {
+        teardown::set_phase(Some("patching the applications"));
+        warnings::clear();
+        let mut base_apps = BufWriter::new(fs::File::create(apps_file(&Branch::Base))?);
+        let mut target_apps = BufWriter::new(fs::File::create(apps_file(&Branch::Target))?);
+        let (mut skipped_base, mut skipped_target) = (vec![], vec![]);
+        for root in &app_roots {
+            let patch_options = parsing::PatchOptions {
+                repo: &root.repo,
+                only: affected.as_ref(),
+                ..patch_options
+            };
+            if compare_with.is_none() {
+                skipped_base.extend(
+                    parsing::write_patched_applications(
+                        &Branch::Base,
+                        &root.folder(base_folder),
+                        &root.base_revision,
+                        &file_regex,
+                        &selector,
+                        &patch_options,
+                        &mut base_apps,
+                    )
+                    .await?,
+                );
+            }
+            skipped_target.extend(
+                parsing::write_patched_applications(
+                    &Branch::Target,
+                    &root.folder(target_folder),
+                    &root.target_revision,
+                    &file_regex,
+                    &selector,
+                    &parsing::PatchOptions {
+                        preview_values: preview_values.as_ref(),
+                        ..patch_options
+                    },
+                    &mut target_apps,
+                )
+                .await?,
+            );
+        }
+        base_apps.flush()?;
+        target_apps.flush()?;
+        if let Some(values) = &preview_values {
+            if let Err(e) = values.check_applications(&apps_file(&Branch::Target)) {
+                error!("❌ {}", e);
+                panic!("Invalid preview values");
+            }
+        }
+        // only the affected applications are refreshed, the others aren't applied at all
+        if affected.is_some() {
+            determinism::request_hard_refresh(&apps_file(&Branch::Base))?;
+            determinism::request_hard_refresh(&apps_file(&Branch::Target))?;
+        }
+        if render_options.projects.is_some() {
+            for (branch, folder) in [(Branch::Base, base_folder), (Branch::Target, target_folder)] {
+                let folders: Vec<String> = app_roots.iter().map(|r| r.folder(folder)).collect();
+                projects::write_projects(&branch, &folders, &file_regex, &repo_rewrites).await?;
+            }
+        }
 
-    match tool {
-        ClusterTool::Kind => kind::delete_cluster(&cluster_name),
-        ClusterTool::Minikube => minikube::delete_cluster(),
+        match &retry_info {
+            Some(info) => {
+                for branch in [Branch::Base, Branch::Target] {
+                    retry::remove_rendered(&apps_file(&branch), info.rendered(&branch))?;
+                    create_folder_if_not_exists(&format!("{}/{}", output_folder, branch));
+                }
+            }
+            // Cleanup
+            None => clean_output_folder(output_folder),
+        }
+
+        match &compare_with {
+            Some(report) => history::restore_as_base(report, output_folder)?,
+            None => {
+                if let Some(sample) = &render_options.appset_sample {
+                    teardown::set_phase(Some("generating the applications of the target branch"));
+                    sampling::generate(&Branch::Target, sample).await?;
+                }
+                teardown::set_phase(Some("rendering the base branch"));
+                extract::get_resources(&Branch::Base, output_folder, base_folder, &render_options)
+                    .await?;
+                extract::delete_applications().await;
+                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            }
+        }
+        teardown::set_phase(Some("rendering the target branch"));
+        extract::get_resources(
+            &Branch::Target,
+            output_folder,
+            target_folder,
+            &render_options,
+        )
+        .await?;
+
+        // render the target branch again, without the cached manifests of the first render
+        if opt.render_twice {
+            info!("🎲 Rendering target branch a second time");
+            teardown::set_phase(Some("rendering the target branch a second time"));
+            extract::delete_applications().await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            determinism::request_hard_refresh(&apps_file(&Branch::Target))?;
+            let folder = determinism::second_render_folder(output_folder);
+            clean_output_folder(&folder);
+            extract::get_resources(&Branch::Target, &folder, target_folder, &render_options)
+                .await?;
+        }
+
+        // render the extra branches like the target branch
+        for branch in &extra_branches {
+            info!("🔀 Rendering branch {}", branch.name);
+            teardown::set_phase(Some("rendering the other branches"));
+            extract::delete_applications().await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            let mut apps = BufWriter::new(fs::File::create(apps_file(&Branch::Target))?);
+            for root in &app_roots {
+                parsing::write_patched_applications(
+                    &Branch::Target,
+                    &root.folder(&branch.folder),
+                    &branch.name,
+                    &file_regex,
+                    &selector,
+                    &parsing::PatchOptions {
+                        repo: &root.repo,
+                        ..patch_options
+                    },
+                    &mut apps,
+                )
+                .await?;
+            }
+            apps.flush()?;
+            if render_options.projects.is_some() {
+                let folders: Vec<String> =
+                    app_roots.iter().map(|r| r.folder(&branch.folder)).collect();
+                projects::write_projects(&Branch::Target, &folders, &file_regex, &repo_rewrites)
+                    .await?;
+            }
+            let folder = branches::rendered_folder(output_folder, &branch.name);
+            clean_output_folder(&folder);
+            extract::get_resources(&Branch::Target, &folder, &branch.folder, &render_options)
+                .await?;
+        }
+
+        // render the target branch again on the other Kubernetes versions of the matrix
+        for version in kubernetes_versions.iter().skip(1) {
+            info!("☸️ Rendering target branch on Kubernetes {}", version);
+            cluster.delete();
+            cluster.create(Some(version)).await?;
+            let folder = matrix::version_folder(output_folder, version);
+            clean_output_folder(&folder);
+            extract::get_resources(&Branch::Target, &folder, target_folder, &render_options)
+                .await?;
+        }
+
+        // render the target branch again with the Argo CD version to compare with
+        if let Some(version) = compare_argocd_version {
+            info!(
+                "🦑 Rendering target branch with Argo CD Helm Chart {}",
+                version
+            );
+            let other = ClusterSetup {
+                argocd: argocd::ArgoCDOptions {
+                    version: Some(version),
+                    ..cluster.argocd.clone()
+                },
+                snapshot: None,
+                create_snapshot: None,
+                ..cluster
+            };
+            other.delete();
+            other.create(kubernetes_versions.first().copied()).await?;
+            let folder = matrix::argocd_folder(output_folder, version);
+            clean_output_folder(&folder);
+            extract::get_resources(&Branch::Target, &folder, target_folder, &render_options)
+                .await?;
+        }
+
+        hooks::run_hook(
+            &hooks::Hook::PostRender,
+            opt.post_render_hook.as_deref(),
+            &hook_context,
+        )?;
+
+        history::write_report(
+            output_folder,
+            &target_branch_name,
+            target_revision,
+            metadata::to_json(output_folder, &report_labels, &backstage_entity_keys),
+        )?;
+
+        if !opt.watch {
+            cluster.delete();
+            teardown::cluster_deleted();
+        }
+
+        teardown::set_phase(Some("generating the report"));
+        let protected_changes = protected_rules
+            .as_ref()
+            .map(|rules| protected::find_changes(output_folder, rules))
+            .unwrap_or_default();
+        let mut warnings: Vec<String> =
+            protected::warning(&protected_changes).into_iter().collect();
+        protected_changed = !warnings.is_empty();
+        if let Some(values) = &preview_values {
+            let files: Vec<String> = values
+                .files()
+                .map(|(name, file)| format!("- `{}` for application {}", file, name))
+                .collect();
+            warnings.push(format!(
+                "**🧪 The target branch is rendered with preview values that are not committed**\n\n{}",
+                files.join("\n")
+            ));
+        }
+        let mut sections = vec![];
+        let mut failed_block_conditions = vec![];
+        if let Some(rules) = &verdict_rules {
+            let (v, failed, section) = verdict::evaluate(output_folder, rules)?;
+            verdict = Some(v);
+            failed_block_conditions = failed;
+            sections.push(section);
+        }
+        sections.extend(conditions::generate_broken_section(output_folder));
+        let conversions = conversions::find_conversions(output_folder);
+        sections.extend(conversions::generate_conversions_section(&conversions));
+        sections.extend(rbac::generate_rbac_section(output_folder));
+        sections.extend(exposure::generate_exposure_section(output_folder));
+        sections.extend(summaries::generate_summaries_section(
+            output_folder,
+            &summaries,
+        ));
+        if let Some(links) = &source_links {
+            sections.extend(
+                links::generate_links_section(
+                    output_folder,
+                    &app_roots,
+                    (base_folder, target_folder),
+                    &file_regex,
+                    links,
+                )
+                .await,
+            );
+        }
+        if !report_labels.is_empty() {
+            sections.extend(metadata::generate_labels_section(
+                output_folder,
+                &report_labels,
+            ));
+        }
+        let base_folders: Vec<String> = app_roots.iter().map(|r| r.folder(base_folder)).collect();
+        let target_folders: Vec<String> =
+            app_roots.iter().map(|r| r.folder(target_folder)).collect();
+        sections.extend(
+            behavior::generate_behavior_section(&base_folders, &target_folders, &file_regex).await,
+        );
+        sections.extend(
+            destinations::generate_destination_section(&base_folders, &target_folders, &file_regex)
+                .await,
+        );
+        sections.extend(
+            rolling_sync::generate_rolling_sync_section(
+                output_folder,
+                &base_folders,
+                &target_folders,
+                &file_regex,
+            )
+            .await,
+        );
+        sections.extend(overrides::generate_overrides_section(
+            output_folder,
+            &repo,
+            (base_folder, target_folder),
+        ));
+        if opt.diff_app_specs {
+            sections.extend(
+                specs::generate_spec_section(
+                    output_folder,
+                    &base_folders,
+                    &target_folders,
+                    &file_regex,
+                )
+                .await?,
+            );
+        }
+        if opt.values_changes {
+            sections.extend(
+                values::generate_values_section(
+                    output_folder,
+                    &base_folders,
+                    &target_folders,
+                    &file_regex,
+                )
+                .await?,
+            );
+        }
+        if opt.floating_references {
+            let repos: Vec<&str> = app_roots.iter().map(|r| r.repo.as_str()).collect();
+            sections.extend(
+                floating::generate_floating_section(
+                    output_folder,
+                    &target_folders,
+                    &file_regex,
+                    &repos,
+                )
+                .await,
+            );
+        }
+        if opt.codeowners || opt.mention_owners {
+            match codeowners::CodeOwners::find(target_folder) {
+                Some(code_owners) => sections.extend(codeowners::generate_owners_section(
+                    output_folder,
+                    &code_owners,
+                    &repo,
+                    opt.mention_owners,
+                )),
+                None => info!("⚠️ No CODEOWNERS file found in {}", target_folder),
+            }
+        }
+        if opt.dependency_graph {
+            sections.push(graph::generate_dependency_graph(output_folder)?);
+        }
+        if opt.cost_estimation || pricing.is_some() {
+            sections.extend(cost::generate_cost_section(output_folder, pricing.as_ref()));
+        }
+        if opt.resource_changes {
+            sections.extend(resources::generate_resource_changes_section(output_folder));
+        }
+        if opt.render_twice {
+            sections.extend(determinism::generate_determinism_section(output_folder));
+        }
+        if kubernetes_versions.len() > 1 {
+            sections.extend(matrix::generate_version_section(
+                output_folder,
+                &kubernetes_versions,
+            ));
+        }
+        if let Some(version) = compare_argocd_version {
+            sections.extend(matrix::generate_argocd_version_section(
+                output_folder,
+                argocd_version,
+                version,
+            ));
+        }
+        sections.extend(branches::generate_branch_sections(
+            output_folder,
+            &base_name,
+            &target_branch_name,
+            &extra_branches,
+        ));
+        sections.extend(skipped::generate_skipped_section(
+            &skipped_base,
+            &skipped_target,
+        ));
+        if opt.list_unchanged {
+            sections.extend(inventory::generate_unchanged_section(output_folder));
+        }
+        if opt.duplicates {
+            sections.extend(duplicates::generate_duplicates_section(output_folder)?);
+        }
+        if opt.appset_sample.is_some() {
+            sections.extend(sampling::generate_sample_section());
+        }
+        sections.extend(warnings::generate_warnings_section());
+        stats::record_applications(
+            output_folder,
+            &target_folders,
+            &file_regex,
+            &skipped_base,
+            &skipped_target,
+        )
+        .await;
+
+        diff::generate_diff(
+            output_folder,
+            &base_name,
+            &target_branch_name,
+            diff_ignore.clone(),
+            line_count,
+            max_diff_length,
+            &diff::Report {
+                header: report_header.clone(),
+                sections,
+                groups: group_by
+                    .as_ref()
+                    .map(|g| grouping::group_applications(output_folder, g))
+                    .unwrap_or_default(),
+                dedupe_diffs: opt.dedupe_diffs,
+                conversions,
+                diff_format: diff_format.clone(),
+                warnings,
+                publishers: publishers.clone(),
+                artifact_url: opt.artifact_url.clone(),
+                app_info: match opt.app_info {
+                    true => metadata::info_headers(output_folder),
+                    false => BTreeMap::new(),
+                },
+                anonymizer: anonymizer.clone(),
+                size_guards: guards::SizeGuards {
+                    max_resource_lines: opt.max_resource_lines,
+                    max_app_diff_lines: opt.max_app_diff_lines,
+                },
+                rename_similarity: (opt.rename_similarity > 0)
+                    .then(|| f64::from(opt.rename_similarity.min(100)) / 100.0),
+            },
+        )
+        .await?;
+        gate::write_gate(
+            output_folder,
+            verdict,
+            &failed_block_conditions,
+            &protected_changes,
+        )?;
+        warnings::write_warnings(output_folder)?;
+
+        hooks::run_hook(
+            &hooks::Hook::PrePublish,
+            opt.pre_publish_hook.as_deref(),
+            &hook_context,
+        )?;
+
+        if opt.pr_labels {
+            let labels = labels::classify(
+                output_folder,
+                &opt.pr_label_prefix,
+                verdict,
+                protected_changed,
+            );
+            labels::write_labels(output_folder, &labels)?;
+            if let Some(pull_request) = opt.pull_request {
+                // the report is written, so failing to label the pull request doesn't fail the run
+                if let Err(e) =
+                    labels::apply_labels(&repo, pull_request, &opt.pr_label_prefix, &labels).await
+                {
+                    info!("⚠️ Failed to label pull request #{}: {}", pull_request, e);
+                }
+            }
+        }
+
+        retry::remove_run_info(output_folder);
+        info!("🎉 Done in {} seconds", start.elapsed().as_secs());
+        metrics::diff_finished(start.elapsed().as_secs_f64());
+        teardown::set_phase(None);
+        stats::write("succeeded", None);
+
+        if !opt.watch {
+            break;
+        }
+
+        info!(
+            "👀 Watching '{}' and '{}' for changes...",
+            base_folder, target_folder
+        );
+        wait_for_changes(&[base_folder, target_folder]).await;
+        info!("👀 Change detected. Generating a new diff");
+        start = std::time::Instant::now();
+        extract::delete_applications().await;
     }
 
-    diff::generate_diff(
-        output_folder,
-        &base_branch_name,
-        &target_branch_name,
-        diff_ignore,
-        line_count,
-        max_diff_length,
-    )
-    .await?;
+    let mut exit_code = verdict
+        .filter(|_| opt.verdict_exit_code)
+        .map(|v| v.exit_code())
+        .unwrap_or_default();
+    if let Some(code) = opt.protected_exit_code.filter(|_| protected_changed) {
+        exit_code = exit_code.max(code);
+    }
+    // the report lists the broken applications, but the run still fails
+    if !conditions::broken_applications(output_folder).is_empty() {
+        exit_code = exit_code.max(1);
+    }
+    if let Some(d) = &drift {
+        exit_code = exit_code.max(drift::finish(output_folder, d, had_snapshot).await?);
+    }
+    teardown::finish(exit_code);
+    workdir::cleanup();
+
+    if opt.serve {
+        info!("🌐 Press Ctrl+C to stop serving the report");
+        // the signal handler stops the process with the exit code of the run
+        std::future::pending::<()>().await;
+    }
 
-    info!("🎉 Done in {} seconds", start.elapsed().as_secs());
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
 
     Ok(())
 }
 
+fn init_logger(debug: bool, plain: bool) {
+    if plain {
+        std::env::set_var("RUST_LOG", if debug { "debug" } else { "info" });
+        env_logger::builder()
+            .write_style(env_logger::WriteStyle::Never)
+            .format(|buf, record| {
+                let line = utils::plain_log_line(record.level(), &record.args().to_string());
+                writeln!(buf, "{}", line)
+            })
+            .init();
+    } else if debug {
+        std::env::set_var("RUST_LOG", "debug");
+        env_logger::init();
+    } else {
+        std::env::set_var("RUST_LOG", "info");
+        env_logger::builder()
+            .format(|buf, record| writeln!(buf, "{}", record.args()))
+            .init();
+    }
+}
+
+// Everything needed to create a local cluster with Argo CD
+struct ClusterSetup<'a> {
+    tool: ClusterTool,
+    name: &'a str,
+    kind_config: Option<&'a str>,
+    // architecture of the kind nodes
+    arch: Option<platform::Arch>,
+    // kind node image instead of the one of kind or the Kubernetes version
+    node_image: Option<&'a str>,
+    // kind node image with Argo CD installed to boot the cluster from
+    snapshot: Option<&'a str>,
+    // kind node image to save the cluster to after Argo CD is installed
+    create_snapshot: Option<&'a str>,
+    secrets_folder: &'a str,
+    crds: &'a [crds::CrdSource],
+    plugins: Option<&'a plugins::PluginConfig>,
+    argocd: argocd::ArgoCDOptions<'a>,
+}
+
+impl ClusterSetup<'_> {
+    // Creates the cluster, installs Argo CD and applies the secrets
+    async fn create(&self, kubernetes_version: Option<&str>) -> Result<(), Box<dyn Error>> {
+        match (self.tool, self.snapshot) {
+            (ClusterTool::Kind, Some(image)) => {
+                if let Some(arch) = self.arch {
+                    platform::check_image(image, arch).await;
+                }
+                snapshot::create_cluster_from_snapshot(self.name, image).await?;
+                argocd::connect(&self.argocd.profile).await;
+            }
+            (ClusterTool::Kind, None) => {
+                let image = platform::node_image(self.node_image, kubernetes_version);
+                if let (Some(image), Some(arch)) = (&image, self.arch) {
+                    platform::pull_image(image, arch).await;
+                }
+                kind::create_cluster(self.name, self.kind_config, image.as_deref()).await?;
+                argocd::install_argo_cd(self.argocd.clone()).await?;
+            }
+            (ClusterTool::Minikube, _) => {
+                minikube::create_cluster(minikube_profile(self.name), kubernetes_version).await?;
+                argocd::install_argo_cd(self.argocd.clone()).await?;
+            }
+            (ClusterTool::Envtest, _) => {
+                envtest::create_cluster().await?;
+                argocd::run_argo_cd_locally(self.argocd.clone()).await?;
+            }
+        }
+
+        if let Some(image) = self.create_snapshot {
+            snapshot::create_snapshot(self.name, image).await?;
+            // the pods were stopped while the snapshot was created
+            argocd::connect(&self.argocd.profile).await;
+        }
+
+        crds::install(self.crds).await?;
+
+        create_folder_if_not_exists(self.secrets_folder);
+        match apply_folder(self.secrets_folder) {
+            Ok(count) if count > 0 => info!("🤫 Applied {} secrets", count),
+            Ok(_) => info!("🤷 No secrets found in {}", self.secrets_folder),
+            Err(e) => {
+                error!("❌ Failed to apply secrets");
+                panic!("error: {}", e)
+            }
+        }
+
+        if let Some(plugins) = self.plugins {
+            plugins.install_stubs().await?;
+            match self.tool {
+                // the repo-server is a local process without plugin sidecars
+                ClusterTool::Envtest if plugins.has_repo_server_env() => {
+                    info!("⚠️ repoServerEnv of the plugin config is not supported with envtest")
+                }
+                ClusterTool::Envtest => (),
+                _ => plugins.set_repo_server_env().await?,
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&self) {
+        delete_cluster(self.tool, self.name, false);
+    }
+}
+
+// minikube keeps using its default profile, unless --run-id gives the cluster its own name
+fn minikube_profile(name: &str) -> Option<&str> {
+    (name != CLUSTER_NAME).then_some(name)
+}
+
+fn delete_cluster(tool: ClusterTool, name: &str, wait: bool) {
+    match tool {
+        ClusterTool::Kind => kind::delete_cluster(name, wait),
+        ClusterTool::Minikube => minikube::delete_cluster(minikube_profile(name), wait),
+        ClusterTool::Envtest => envtest::delete_cluster(),
+    }
+}
+
 fn clean_output_folder(output_folder: &str) {
     create_folder_if_not_exists(output_folder);
+    conditions::remove_conditions(output_folder);
+    gate::remove_gate(output_folder);
+    warnings::remove_warnings(output_folder);
     fs::remove_dir_all(format!("{}/{}", output_folder, Branch::Base)).unwrap_or_default();
     fs::remove_dir_all(format!("{}/{}", output_folder, Branch::Target)).unwrap_or_default();
     fs::create_dir(format!("{}/{}", output_folder, Branch::Base))
@@ -375,19 +2590,6 @@ fn clean_output_folder(output_folder: &str) {
         .expect("Unable to create directory");
 }
 
-fn apply_manifest(file_name: &str) -> Result<Output, Output> {
-    let output = Command::new("kubectl")
-        .arg("apply")
-        .arg("-f")
-        .arg(file_name)
-        .output()
-        .expect(format!("failed to apply manifest: {}", file_name).as_str());
-    match output.status.success() {
-        true => Ok(output),
-        false => Err(output),
-    }
-}
-
 fn apply_folder(folder_name: &str) -> Result<u64, String> {
     if !PathBuf::from(folder_name).is_dir() {
         return Err(format!("{} is not a directory", folder_name));
@@ -0,0 +1,210 @@
+use crate::Branch;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+// Returns the rendered manifests of all applications for a branch, keyed by application name
+pub fn read_rendered_apps(output_folder: &str, branch: &Branch) -> BTreeMap<String, String> {
+    fs::read_dir(format!("{}/{}", output_folder, branch))
+        .map(|dir| {
+            dir.flatten()
+                .filter(|e| e.path().is_file())
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    let manifest = fs::read_to_string(e.path()).ok()?;
+                    Some((name, manifest))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// File with the Applications that were rendered for a branch, as they were seen in the cluster.
+// Unlike the applications files, it includes Applications generated by ApplicationSets
+fn applications_file(output_folder: &str, branch: &Branch) -> String {
+    format!("{}/applications-{}.yaml", output_folder, branch)
+}
+
+pub fn write_applications(
+    output_folder: &str,
+    branch: &Branch,
+    applications: &[serde_yaml::Value],
+) -> Result<(), Box<dyn Error>> {
+    // the status is only relevant while rendering
+    let applications: Vec<serde_yaml::Value> = applications
+        .iter()
+        .map(|a| {
+            let mut a = a.clone();
+            a.as_mapping_mut().map(|m| m.remove("status"));
+            a
+        })
+        .collect();
+    fs::write(
+        applications_file(output_folder, branch),
+        serde_yaml::to_string(&applications)?,
+    )?;
+    Ok(())
+}
+
+// Returns the rendered Applications of a branch, keyed by application name
+pub fn read_applications(
+    output_folder: &str,
+    branch: &Branch,
+) -> BTreeMap<String, serde_yaml::Value> {
+    fs::read_to_string(applications_file(output_folder, branch))
+        .ok()
+        .and_then(|f| serde_yaml::from_str::<Vec<serde_yaml::Value>>(&f).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|a| Some((a["metadata"]["name"].as_str()?.to_string(), a)))
+        .collect()
+}
+
+// Splits a multi-document YAML manifest into its Kubernetes resources. Invalid documents are
+// skipped. The deserializer can't recover from an invalid document, so the documents are split at
+// their '---' separators and parsed one by one
+pub fn parse_manifest(manifest: &str) -> Vec<serde_yaml::Value> {
+    let mut documents = vec![String::new()];
+    for line in manifest.lines() {
+        match line.trim_end() == "---" || line.starts_with("--- ") {
+            true => documents.push(String::new()),
+            false => {
+                let document = documents.last_mut().unwrap();
+                document.push_str(line);
+                document.push('\n');
+            }
+        }
+    }
+    documents
+        .iter()
+        .filter_map(|d| serde_yaml::from_str::<serde_yaml::Value>(d).ok())
+        .filter(|r| r["kind"].as_str().is_some())
+        .collect()
+}
+
+// Removes the generated fields of every 'metadata' block, including the ones of pod templates
+fn strip_metadata(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Mapping(m) => {
+            for (key, value) in m.iter_mut() {
+                if key.as_str() == Some("metadata") {
+                    if let Some(metadata) = value.as_mapping_mut() {
+                        for field in ["creationTimestamp", "generation", "uid"] {
+                            metadata.remove(field);
+                        }
+                    }
+                }
+                strip_metadata(value);
+            }
+        }
+        serde_yaml::Value::Sequence(s) => s.iter_mut().for_each(strip_metadata),
+        _ => (),
+    }
+}
+
+// Removes fields that tools like 'kubectl create --dry-run' generate into manifests (timestamps,
+// generations, UIDs and empty status blocks), which cause spurious diffs between branches. All
+// resources are serialized again, so both branches are formatted the same way. Manifests that can't
+// be parsed are kept as they are
+pub fn strip_generated_fields(manifest: &str) -> String {
+    let mut documents = vec![];
+    for document in serde_yaml::Deserializer::from_str(manifest) {
+        let Ok(mut resource) = serde_yaml::Value::deserialize(document) else {
+            return manifest.to_string();
+        };
+        if resource.is_null() {
+            continue;
+        }
+        strip_metadata(&mut resource);
+        if let Some(m) = resource.as_mapping_mut() {
+            let empty_status = match m.get("status") {
+                Some(serde_yaml::Value::Mapping(status)) => status.is_empty(),
+                Some(serde_yaml::Value::Null) => true,
+                _ => false,
+            };
+            if empty_status {
+                m.remove("status");
+            }
+        }
+        match serde_yaml::to_string(&resource) {
+            Ok(document) => documents.push(document),
+            Err(_) => return manifest.to_string(),
+        }
+    }
+    documents.join("---\n")
+}
+
+// Identifies a resource within an application by kind, namespace and name
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResourceId {
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+}
+
+impl std::fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.namespace.as_str() {
+            "" => write!(f, "{}/{}", self.kind, self.name),
+            ns => write!(f, "{}/{}/{}", self.kind, ns, self.name),
+        }
+    }
+}
+
+pub fn resource_id(resource: &serde_yaml::Value) -> ResourceId {
+    ResourceId {
+        kind: resource["kind"].as_str().unwrap_or_default().to_string(),
+        namespace: resource["metadata"]["namespace"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        name: resource["metadata"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+// Indexes the resources of a manifest by their id
+pub fn index_resources(manifest: &str) -> BTreeMap<ResourceId, serde_yaml::Value> {
+    parse_manifest(manifest)
+        .into_iter()
+        .map(|r| (resource_id(&r), r))
+        .collect()
+}
+
+// Flattens a YAML value into a map from paths (e.g. "spec.containers[0].image") to scalar values
+pub fn flatten(value: &serde_yaml::Value, path: &str, out: &mut BTreeMap<String, String>) {
+    match value {
+        serde_yaml::Value::Mapping(m) => {
+            for (k, v) in m {
+                let key = match k {
+                    serde_yaml::Value::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                };
+                let child = match path {
+                    "" => key,
+                    p => format!("{}.{}", p, key),
+                };
+                flatten(v, &child, out);
+            }
+        }
+        serde_yaml::Value::Sequence(s) => {
+            for (i, v) in s.iter().enumerate() {
+                flatten(v, &format!("{}[{}]", path, i), out);
+            }
+        }
+        serde_yaml::Value::Tagged(t) => flatten(&t.value, path, out),
+        serde_yaml::Value::Null => {
+            out.insert(path.to_string(), "null".to_string());
+        }
+        scalar => {
+            let s = serde_yaml::to_string(scalar).unwrap_or_default();
+            out.insert(path.to_string(), s.trim().to_string());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Compares the target branch rendered on the main cluster with renders on other Kubernetes or Argo CD versions
+use crate::diff::ReportSection;
+use crate::manifests::{index_resources, read_rendered_apps, ResourceId};
+use crate::Branch;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Folder with the manifests of the target branch rendered on another Kubernetes version
+pub fn version_folder(output_folder: &str, version: &str) -> String {
+    format!("{}/k8s-{}", output_folder, version.trim_start_matches('v'))
+}
+
+// Folder with the manifests of the target branch rendered with another Argo CD Helm Chart version
+pub fn argocd_folder(output_folder: &str, chart_version: &str) -> String {
+    format!("{}/argocd-{}", output_folder, chart_version)
+}
+
+// Lists the resources that are rendered differently in 'other_folder' than in 'output_folder'
+fn compare_renders(output_folder: &str, other_folder: &str) -> Vec<String> {
+    compare_rendered_apps(
+        &read_rendered_apps(output_folder, &Branch::Target),
+        &read_rendered_apps(other_folder, &Branch::Target),
+        (
+            "only rendered on this version",
+            "not rendered on this version",
+        ),
+    )
+}
+
+// Lists the resources that differ between two sets of rendered applications. 'labels' describe
+// resources that are only in 'after' and only in 'before'
+pub fn compare_rendered_apps(
+    before_apps: &BTreeMap<String, String>,
+    after_apps: &BTreeMap<String, String>,
+    labels: (&str, &str),
+) -> Vec<String> {
+    let names: BTreeSet<&String> = before_apps.keys().chain(after_apps.keys()).collect();
+
+    let mut lines = vec![];
+    for name in names {
+        let before = index_resources(
+            before_apps
+                .get(name)
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+        );
+        let after = index_resources(after_apps.get(name).map(|m| m.as_str()).unwrap_or_default());
+        let ids: BTreeSet<&ResourceId> = before.keys().chain(after.keys()).collect();
+        for id in ids {
+            let status = match (before.get(id), after.get(id)) {
+                (Some(b), Some(a)) if b == a => continue,
+                (None, _) => labels.0,
+                (_, None) => labels.1,
+                _ => "rendered differently",
+            };
+            lines.push(format!("- **{}**: `{}` {}", name, id, status));
+        }
+    }
+    lines
+}
+
+// Compares the target branch rendered on the first Kubernetes version with the other versions
+pub fn generate_version_section(output_folder: &str, versions: &[&str]) -> Option<ReportSection> {
+    let (primary, others) = versions.split_first()?;
+    info!(
+        "☸️ Comparing manifests rendered on Kubernetes {} with {}",
+        primary,
+        others.join(", ")
+    );
+
+    let mut blocks = vec![];
+    for version in others {
+        let lines = compare_renders(output_folder, &version_folder(output_folder, version));
+        if !lines.is_empty() {
+            blocks.push(format!(
+                "- Kubernetes **{}** ({} differences)\n{}",
+                version,
+                lines.len(),
+                lines
+                    .iter()
+                    .map(|l| format!("  {}", l))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ));
+        }
+    }
+
+    if blocks.is_empty() {
+        info!("☸️ No differences between Kubernetes versions found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!(
+            "☸️ Kubernetes version differences (compared to {}):",
+            primary
+        ),
+        content: blocks.join("\n"),
+    })
+}
+
+// Compares the target branch rendered with the installed Argo CD version with another Helm Chart version
+pub fn generate_argocd_version_section(
+    output_folder: &str,
+    current_version: Option<&str>,
+    other_version: &str,
+) -> Option<ReportSection> {
+    let current_version = current_version.unwrap_or("latest");
+    info!(
+        "🦑 Comparing manifests rendered with Argo CD Helm Chart {} and {}",
+        current_version, other_version
+    );
+
+    let lines = compare_renders(output_folder, &argocd_folder(output_folder, other_version));
+    if lines.is_empty() {
+        info!("🦑 No differences between Argo CD versions found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!(
+            "🦑 Argo CD version differences ({} → {}, {} differences):",
+            current_version,
+            other_version,
+            lines.len()
+        ),
+        content: lines.join("\n"),
+    })
+}
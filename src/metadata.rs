@@ -0,0 +1,172 @@
+// Labels of the Applications (e.g. team, env or tier) carried into the report, so dashboards can
+// slice preview results by owner and environment. The 'spec.info' entries of the Applications (e.g.
+// owner or dashboard links) give reviewers context next to the diff of each application. With
+// --backstage-entity-keys, each application is mapped to the Backstage entity it belongs to, so
+// developer portals can show preview results on the page of the component
+use crate::diff::ReportSection;
+use crate::manifests::{read_applications, read_rendered_apps};
+use crate::Branch;
+use std::collections::{BTreeMap, BTreeSet};
+
+struct ApplicationMetadata {
+    change: &'static str,
+    labels: BTreeMap<String, String>,
+    // 'spec.info' as name and value pairs, in the order of the Application
+    info: Vec<(String, String)>,
+    // Backstage entity ref, e.g. 'component:default/payments'
+    entity_ref: Option<String>,
+}
+
+// Completes an entity ref the way Backstage does, with the kind 'component' and the namespace
+// 'default' when they are left out, e.g. 'payments' becomes 'component:default/payments'
+fn entity_ref(value: &str) -> Option<String> {
+    let value = value.trim();
+    let (kind, rest) = value.split_once(':').unwrap_or(("component", value));
+    let (namespace, name) = rest.split_once('/').unwrap_or(("default", rest));
+    match kind.is_empty() || namespace.is_empty() || name.is_empty() {
+        true => None,
+        false => Some(format!(
+            "{}:{}/{}",
+            kind.to_lowercase(),
+            namespace.to_lowercase(),
+            name.to_lowercase()
+        )),
+    }
+}
+
+fn collect(
+    output_folder: &str,
+    labels: &[String],
+    entity_keys: &[String],
+) -> BTreeMap<String, ApplicationMetadata> {
+    let base = read_rendered_apps(output_folder, &Branch::Base);
+    let target = read_rendered_apps(output_folder, &Branch::Target);
+    // deleted applications only exist in the base branch
+    let mut applications = read_applications(output_folder, &Branch::Base);
+    applications.extend(read_applications(output_folder, &Branch::Target));
+
+    let names: BTreeSet<&String> = base.keys().chain(target.keys()).collect();
+    names
+        .into_iter()
+        .map(|name| {
+            let change = match (base.get(name), target.get(name)) {
+                (None, _) => "added",
+                (_, None) => "deleted",
+                (a, b) if a != b => "modified",
+                _ => "unchanged",
+            };
+            let values = &applications
+                .get(name)
+                .map(|a| a["metadata"]["labels"].clone())
+                .unwrap_or_default();
+            let labels = labels
+                .iter()
+                .filter_map(|l| Some((l.clone(), values[l.as_str()].as_str()?.to_string())))
+                .collect();
+            let info = applications
+                .get(name)
+                .and_then(|a| a["spec"]["info"].as_sequence())
+                .into_iter()
+                .flatten()
+                .filter_map(|i| {
+                    let value = i["value"].as_str()?.split_whitespace().collect::<Vec<_>>();
+                    Some((i["name"].as_str()?.to_string(), value.join(" ")))
+                })
+                .collect();
+            // the first key the Application has as annotation or label, in the order of the keys
+            let metadata = &applications
+                .get(name)
+                .map(|a| a["metadata"].clone())
+                .unwrap_or_default();
+            let entity_ref = entity_keys.iter().find_map(|key| {
+                let value = metadata["annotations"][key.as_str()]
+                    .as_str()
+                    .or_else(|| metadata["labels"][key.as_str()].as_str())?;
+                entity_ref(value)
+            });
+            (
+                name.clone(),
+                ApplicationMetadata {
+                    change,
+                    labels,
+                    info,
+                    entity_ref,
+                },
+            )
+        })
+        .collect()
+}
+
+// The change and the selected labels of every application, keyed by application name, for
+// 'report.json'
+pub fn to_json(
+    output_folder: &str,
+    labels: &[String],
+    entity_keys: &[String],
+) -> serde_json::Value {
+    let applications: serde_json::Map<String, serde_json::Value> =
+        collect(output_folder, labels, entity_keys)
+            .into_iter()
+            .map(|(name, m)| {
+                let mut value = serde_json::json!({ "change": m.change, "labels": m.labels });
+                if !m.info.is_empty() {
+                    let info: Vec<serde_json::Value> = m
+                        .info
+                        .iter()
+                        .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+                        .collect();
+                    value["info"] = info.into();
+                }
+                if let Some(entity_ref) = m.entity_ref {
+                    value["backstage"] = serde_json::json!({ "entityRef": entity_ref });
+                }
+                (name, value)
+            })
+            .collect();
+    serde_json::Value::Object(applications)
+}
+
+// Lists the changed applications with a column for each selected label
+pub fn generate_labels_section(output_folder: &str, labels: &[String]) -> Option<ReportSection> {
+    let rows: Vec<String> = collect(output_folder, labels, &[])
+        .into_iter()
+        .filter(|(_, m)| m.change != "unchanged")
+        .map(|(name, m)| {
+            let cells: Vec<&str> = labels
+                .iter()
+                .map(|l| m.labels.get(l).map(|v| v.as_str()).unwrap_or("-"))
+                .collect();
+            format!("| {} | {} | {} |", name, m.change, cells.join(" | "))
+        })
+        .collect();
+    if rows.is_empty() {
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!("🏷️ Labels of changed applications ({}):", rows.len()),
+        content: format!(
+            "| Application | Change | {} |\n|---|---|{}\n{}",
+            labels.join(" | "),
+            "---|".repeat(labels.len()),
+            rows.join("\n")
+        ),
+    })
+}
+
+// The 'spec.info' of each changed application with info as one line, e.g. 'Owner: team-a ·
+// Dashboard: https://grafana.example.com/d/payments', shown above the diff of the application
+pub fn info_headers(output_folder: &str) -> BTreeMap<String, String> {
+    collect(output_folder, &[], &[])
+        .into_iter()
+        .filter(|(_, m)| m.change != "unchanged" && !m.info.is_empty())
+        .map(|(name, m)| {
+            let entries: Vec<String> = m
+                .info
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            (name, entries.join(" · "))
+        })
+        .collect()
+}
@@ -0,0 +1,140 @@
+// Shared preview runners (--watch, --serve) run for hours, and platform teams want to monitor them
+// like their other services. The report server exposes the metrics of the run at '/metrics' in the
+// Prometheus text format: the time spent per phase and the rendered and failed applications.
+// Counters are added up over all diffs of the run
+use crate::Branch;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Default)]
+struct Metrics {
+    phase: Option<(&'static str, Instant)>,
+    // Seconds spent per finished phase
+    phase_seconds: BTreeMap<&'static str, f64>,
+    // Counters by branch
+    rendered: BTreeMap<String, u64>,
+    failed: BTreeMap<String, u64>,
+    diffs: u64,
+    last_diff_seconds: Option<f64>,
+}
+
+static METRICS: Mutex<Option<Metrics>> = Mutex::new(None);
+
+fn update(f: impl FnOnce(&mut Metrics)) {
+    // a panic while the metrics are locked must not stop the run
+    let Ok(mut guard) = METRICS.lock() else {
+        return;
+    };
+    f(guard.get_or_insert_with(Default::default));
+}
+
+// Ends the current phase and starts the next one
+pub fn enter_phase(phase: Option<&'static str>) {
+    update(|m| {
+        if let Some((name, start)) = m.phase.take() {
+            *m.phase_seconds.entry(name).or_default() += start.elapsed().as_secs_f64();
+        }
+        m.phase = phase.map(|p| (p, Instant::now()));
+    });
+}
+
+pub fn application_rendered(branch: &Branch) {
+    update(|m| *m.rendered.entry(branch.to_string()).or_default() += 1);
+}
+
+pub fn applications_failed(branch: &Branch, count: usize) {
+    update(|m| *m.failed.entry(branch.to_string()).or_default() += count as u64);
+}
+
+pub fn diff_finished(seconds: f64) {
+    update(|m| {
+        m.diffs += 1;
+        m.last_diff_seconds = Some(seconds);
+    });
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} {}\n",
+        name, help, name, kind
+    ));
+    for (labels, value) in samples {
+        out.push_str(&format!("{}{} {}\n", name, labels, value));
+    }
+}
+
+fn by_label(label: &str, values: &BTreeMap<String, u64>) -> Vec<(String, f64)> {
+    values
+        .iter()
+        .map(|(value, count)| (format!("{{{}=\"{}\"}}", label, value), *count as f64))
+        .collect()
+}
+
+// The metrics in the Prometheus text format
+pub fn render() -> String {
+    let Ok(mut guard) = METRICS.lock() else {
+        return String::new();
+    };
+    let m = guard.get_or_insert_with(Default::default);
+    let mut phase_seconds = m.phase_seconds.clone();
+    if let Some((name, start)) = m.phase {
+        *phase_seconds.entry(name).or_default() += start.elapsed().as_secs_f64();
+    }
+    let phase_seconds: Vec<(String, f64)> = phase_seconds
+        .iter()
+        .map(|(phase, seconds)| (format!("{{phase=\"{}\"}}", phase), *seconds))
+        .collect();
+    let current_phase: Vec<(String, f64)> = m
+        .phase
+        .iter()
+        .map(|(phase, _)| (format!("{{phase=\"{}\"}}", phase), 1.0))
+        .collect();
+
+    let mut out = String::new();
+    family(
+        &mut out,
+        "argocd_diff_preview_phase_duration_seconds_total",
+        "counter",
+        "Seconds spent in each phase of the run",
+        &phase_seconds,
+    );
+    family(
+        &mut out,
+        "argocd_diff_preview_phase",
+        "gauge",
+        "The phase the run is in",
+        &current_phase,
+    );
+    family(
+        &mut out,
+        "argocd_diff_preview_applications_rendered_total",
+        "counter",
+        "Applications rendered, by branch",
+        &by_label("branch", &m.rendered),
+    );
+    family(
+        &mut out,
+        "argocd_diff_preview_applications_failed_total",
+        "counter",
+        "Applications that failed to render or timed out, by branch",
+        &by_label("branch", &m.failed),
+    );
+    family(
+        &mut out,
+        "argocd_diff_preview_diffs_total",
+        "counter",
+        "Diffs generated",
+        &[(String::new(), m.diffs as f64)],
+    );
+    if let Some(seconds) = m.last_diff_seconds {
+        family(
+            &mut out,
+            "argocd_diff_preview_last_diff_duration_seconds",
+            "gauge",
+            "Seconds the last diff took",
+            &[(String::new(), seconds)],
+        );
+    }
+    out
+}
@@ -1,4 +1,7 @@
-use crate::{run_command, utils::spawn_command};
+use crate::{
+    run_command,
+    utils::{spawn_command, wait_for_command},
+};
 use log::{error, info};
 use std::error::Error;
 
@@ -6,7 +9,18 @@ pub async fn is_installed() -> bool {
     run_command("which minikube", None).await.is_ok()
 }
 
-pub async fn create_cluster() -> Result<(), Box<dyn Error>> {
+// The default profile is used, unless a run id gives the cluster its own name
+fn command(profile: Option<&str>, args: &str) -> String {
+    match profile {
+        Some(p) => format!("minikube -p {} {}", p, args),
+        None => format!("minikube {}", args),
+    }
+}
+
+pub async fn create_cluster(
+    profile: Option<&str>,
+    kubernetes_version: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
     // check if docker is running
     match run_command("docker ps", None).await {
         Ok(_) => (),
@@ -17,14 +31,22 @@ pub async fn create_cluster() -> Result<(), Box<dyn Error>> {
     }
 
     info!("🚀 Creating cluster...");
-    match run_command("minikube delete", None).await {
+    match run_command(&command(profile, "delete"), None).await {
         Ok(o) => o,
         Err(e) => {
             panic!("error: {}", String::from_utf8_lossy(&e.stderr))
         }
     };
 
-    match run_command("minikube start", None).await {
+    let start = match kubernetes_version {
+        Some(v) => command(
+            profile,
+            &format!("start --kubernetes-version=v{}", v.trim_start_matches('v')),
+        ),
+        None => command(profile, "start"),
+    };
+
+    match run_command(&start, None).await {
         Ok(_) => {
             info!("🚀 Cluster created successfully");
             Ok(())
@@ -36,7 +58,12 @@ pub async fn create_cluster() -> Result<(), Box<dyn Error>> {
     }
 }
 
-pub fn delete_cluster() {
+// Deleting runs in the background, unless the process is about to exit
+pub fn delete_cluster(profile: Option<&str>, wait: bool) {
     info!("💥 Deleting cluster...");
-    spawn_command("minikube delete", None);
+    let delete = command(profile, "delete");
+    match wait {
+        true => wait_for_command(&delete),
+        false => spawn_command(&delete, None),
+    }
 }
@@ -0,0 +1,156 @@
+// Typed views of the fields of Applications and ApplicationSets that the tool reads and patches.
+// Fields the tool doesn't know are kept in 'other' of each struct, so an application is written
+// back as it was apart from the patched fields. A spec with an unexpected shape, like a source that
+// isn't a map, fails to parse instead of being patched half-way
+use crate::repo_url::RepoUrl;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<ApplicationSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<ApplicationSource>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<Destination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_policy: Option<SyncPolicy>,
+    #[serde(flatten)]
+    pub other: Mapping,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationSource {
+    #[serde(rename = "repoURL", skip_serializing_if = "Option::is_none")]
+    pub repo_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chart: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_revision: Option<String>,
+    // makes the repository available to the value files of other sources as '$<ref>'
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    // names the source of a multi-source application in Argo CD (2.14+)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub helm: Option<HelmSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<PluginSource>,
+    #[serde(flatten)]
+    pub other: Mapping,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HelmSource {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub value_files: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kube_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_versions: Option<Vec<String>>,
+    // inline values, as a YAML string or as an object
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values_object: Option<Value>,
+    #[serde(flatten)]
+    pub other: Mapping,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PluginSource {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<PluginEnv>>,
+    #[serde(flatten)]
+    pub other: Mapping,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PluginEnv {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Destination {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(flatten)]
+    pub other: Mapping,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncPolicy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub automated: Option<Value>,
+    #[serde(flatten)]
+    pub other: Mapping,
+}
+
+impl ApplicationSpec {
+    // The spec of an Application, or the template spec of an ApplicationSet
+    pub fn of(application: &Value) -> Result<ApplicationSpec, serde_yaml::Error> {
+        let spec = match application["kind"].as_str() {
+            Some("ApplicationSet") => &application["spec"]["template"]["spec"],
+            _ => &application["spec"],
+        };
+        serde_yaml::from_value(spec.clone())
+    }
+
+    // The single source and the sources of a multi-source application
+    pub fn sources(&self) -> impl Iterator<Item = &ApplicationSource> {
+        self.source.iter().chain(self.sources.iter().flatten())
+    }
+
+    pub fn sources_mut(&mut self) -> impl Iterator<Item = &mut ApplicationSource> {
+        self.source
+            .iter_mut()
+            .chain(self.sources.iter_mut().flatten())
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_yaml::to_value(self).unwrap_or_default()
+    }
+}
+
+impl ApplicationSource {
+    // Whether the source reads from 'repo', in any notation of its URL
+    pub fn is_from(&self, repo: &RepoUrl) -> bool {
+        self.repo_url
+            .as_deref()
+            .is_some_and(|url| RepoUrl::parse(url).matches(repo))
+    }
+
+    // The inline values passed to Helm. Like in Argo CD, valuesObject takes precedence over values
+    pub fn inline_values(&self) -> Option<Value> {
+        let helm = self.helm.as_ref()?;
+        match (&helm.values_object, &helm.values) {
+            (Some(object), _) => Some(object.clone()),
+            (None, Some(values)) => {
+                Some(serde_yaml::from_str(values).unwrap_or_else(|_| Value::String(values.clone())))
+            }
+            (None, None) => None,
+        }
+    }
+
+    pub fn value_files(&self) -> &[String] {
+        self.helm
+            .as_ref()
+            .map(|h| h.value_files.as_slice())
+            .unwrap_or_default()
+    }
+}
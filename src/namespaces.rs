@@ -0,0 +1,131 @@
+// Argo CD doesn't create the destination namespaces of applications it only renders, so they don't
+// exist in the preview cluster, while in production they are labeled for admission controllers,
+// e.g. 'istio-injection: enabled' or the Pod Security Standards. Namespaces selected by the
+// namespace labels config are created with their labels before the applications are applied, so
+// server-side diffs and admission behave like in production
+use crate::config::read_config;
+use crate::manifests::parse_manifest;
+use crate::protected::glob_to_regex;
+use crate::utils::run_command_from_list;
+use crate::workdir;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+struct Rule {
+    // Globs of namespaces. Any namespace matches when empty
+    namespaces: Vec<Regex>,
+    labels: BTreeMap<String, String>,
+}
+
+pub struct NamespaceLabels {
+    rules: Vec<Rule>,
+}
+
+pub fn read_namespace_labels(path: &str) -> Result<NamespaceLabels, Box<dyn Error>> {
+    let config: serde_yaml::Value = read_config(path)?;
+    debug!("Namespace labels: {:?}", config);
+    let rules = config["namespaceLabels"]
+        .as_sequence()
+        .ok_or("namespace labels have no 'namespaceLabels' list")?
+        .iter()
+        .map(|rule| {
+            let namespaces = rule["namespaces"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .map(|g| glob_to_regex(g.as_str().ok_or("'namespaces' must be a list of strings")?))
+                .collect::<Result<Vec<Regex>, Box<dyn Error>>>()?;
+            let labels = rule["labels"]
+                .as_mapping()
+                .ok_or("every namespace labels rule needs 'labels'")?
+                .iter()
+                .map(|(k, v)| match (k.as_str(), v.as_str()) {
+                    (Some(k), Some(v)) => Ok((k.to_string(), v.to_string())),
+                    _ => Err(format!("the label {:?} must be a string", k).into()),
+                })
+                .collect::<Result<BTreeMap<String, String>, Box<dyn Error>>>()?;
+            Ok(Rule { namespaces, labels })
+        })
+        .collect::<Result<Vec<Rule>, Box<dyn Error>>>()?;
+    Ok(NamespaceLabels { rules })
+}
+
+impl NamespaceLabels {
+    // The labels of all rules matching the namespace. Later rules win. None when no rule matches
+    fn labels(&self, namespace: &str) -> Option<BTreeMap<String, String>> {
+        let mut rules = self
+            .rules
+            .iter()
+            .filter(|r| {
+                r.namespaces.is_empty() || r.namespaces.iter().any(|g| g.is_match(namespace))
+            })
+            .peekable();
+        rules.peek()?;
+        Some(rules.flat_map(|r| r.labels.clone()).collect())
+    }
+}
+
+// Destination namespaces of the applications, and of ApplicationSet templates without placeholders
+fn destination_namespaces(applications: &[serde_yaml::Value]) -> Vec<String> {
+    let mut namespaces: Vec<String> = applications
+        .iter()
+        .filter_map(|a| match a["kind"].as_str() {
+            Some("ApplicationSet") => {
+                a["spec"]["template"]["spec"]["destination"]["namespace"].as_str()
+            }
+            _ => a["spec"]["destination"]["namespace"].as_str(),
+        })
+        .filter(|n| !n.is_empty() && !n.contains("{{"))
+        .map(|n| n.to_string())
+        .collect();
+    namespaces.sort();
+    namespaces.dedup();
+    namespaces
+}
+
+// Creates the destination namespaces of the applications in 'apps_file' that the config selects,
+// with their labels. Existing namespaces get the labels added
+pub async fn create_namespaces(
+    apps_file: &str,
+    config: &NamespaceLabels,
+) -> Result<(), Box<dyn Error>> {
+    let applications = parse_manifest(&fs::read_to_string(apps_file)?);
+    let namespaces: Vec<serde_json::Value> = destination_namespaces(&applications)
+        .into_iter()
+        .filter_map(|name| {
+            let labels = config.labels(&name)?;
+            debug!("Namespace {} gets labels {:?}", name, labels);
+            Some(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Namespace",
+                "metadata": { "name": name, "labels": labels },
+            }))
+        })
+        .collect();
+    if namespaces.is_empty() {
+        return Ok(());
+    }
+
+    let documents = namespaces
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<String>, _>>()?;
+    let file = workdir::path("namespaces.yaml");
+    fs::write(&file, documents.join("---\n"))?;
+    run_command_from_list(
+        vec!["kubectl", "apply", "-f", &file.to_string_lossy()],
+        None,
+    )
+    .await
+    .map_err(|e| {
+        format!(
+            "failed to create namespaces: {}",
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })?;
+    info!("🏷️ Created {} labeled namespaces", namespaces.len());
+    Ok(())
+}
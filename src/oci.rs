@@ -0,0 +1,123 @@
+// Some platforms package their applications (e.g. an app-of-apps) as an OCI artifact instead of
+// keeping them in the repository. The artifact of each branch is pulled with ORAS into the branch
+// folder, and read like an app dir, so the applications are patched and rendered like any other
+use crate::utils::{run_command, run_command_from_list};
+use crate::Branch;
+use log::{debug, info};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Where the artifact is extracted to, relative to the branch folder
+pub const APPS_DIR: &str = ".apps-oci";
+
+pub struct OciApps {
+    pub reference: String,
+    pub base_tag: String,
+    pub target_tag: String,
+}
+
+impl OciApps {
+    // Parses 'REF[;base-tag=TAG][;target-tag=TAG]'. The tags default to the branch names
+    pub fn parse(
+        s: &str,
+        base_branch: &str,
+        target_branch: &str,
+    ) -> Result<OciApps, Box<dyn Error>> {
+        let mut parts = s.split(';').map(|p| p.trim());
+        let reference = parts.next().unwrap_or_default();
+        if reference.is_empty() || reference.contains('@') {
+            return Err(format!(
+                "invalid OCI reference '{}'. Use REGISTRY/REPOSITORY without a tag or digest",
+                s
+            )
+            .into());
+        }
+        let mut apps = OciApps {
+            reference: reference.to_string(),
+            base_tag: base_branch.to_string(),
+            target_tag: target_branch.to_string(),
+        };
+        for part in parts.filter(|p| !p.is_empty()) {
+            match part.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("base-tag", v)) if !v.is_empty() => apps.base_tag = v.to_string(),
+                Some(("target-tag", v)) if !v.is_empty() => apps.target_tag = v.to_string(),
+                _ => return Err(format!("invalid setting '{}' in OCI apps '{}'", part, s).into()),
+            }
+        }
+        Ok(apps)
+    }
+
+    fn tag(&self, branch: &Branch) -> &str {
+        match branch {
+            Branch::Base => &self.base_tag,
+            Branch::Target => &self.target_tag,
+        }
+    }
+
+    // Pulls the artifact of a branch into its branch folder, replacing the one of a previous run.
+    // Archives in the artifact are extracted, since ORAS only unpacks directories it pushed itself
+    pub async fn pull(&self, branch: &Branch, branch_folder: &str) -> Result<(), Box<dyn Error>> {
+        if run_command("which oras", None).await.is_err() {
+            return Err("--apps-oci requires ORAS (https://oras.land)".into());
+        }
+        let artifact = format!("{}:{}", self.reference, self.tag(branch));
+        let folder = format!("{}/{}", branch_folder, APPS_DIR);
+        fs::remove_dir_all(&folder).unwrap_or_default();
+        fs::create_dir_all(&folder)?;
+
+        info!(
+            "📦 Pulling applications of the {} branch from {}",
+            branch, artifact
+        );
+        run_command_from_list(vec!["oras", "pull", &artifact, "-o", &folder], None)
+            .await
+            .map_err(|e| {
+                format!(
+                    "failed to pull {}: {}",
+                    artifact,
+                    String::from_utf8_lossy(&e.stderr)
+                )
+            })?;
+
+        for entry in fs::read_dir(&folder)?.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !(name.ends_with(".tar.gz") || name.ends_with(".tgz") || name.ends_with(".tar")) {
+                continue;
+            }
+            debug!("Extracting {}", path.display());
+            run_command_from_list(vec!["tar", "-xf", &name], Some(&folder))
+                .await
+                .map_err(|e| {
+                    format!(
+                        "failed to extract {} of {}: {}",
+                        name,
+                        artifact,
+                        String::from_utf8_lossy(&e.stderr)
+                    )
+                })?;
+            fs::remove_file(&path)?;
+        }
+
+        let files = count_yaml_files(Path::new(&folder));
+        if files == 0 {
+            return Err(format!("{} contains no YAML files", artifact).into());
+        }
+        info!("📦 Pulled {} YAML files from {}", files, artifact);
+        Ok(())
+    }
+}
+
+fn count_yaml_files(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .map(|p| match p.is_dir() {
+            true => count_yaml_files(&p),
+            false => p.extension().is_some_and(|e| e == "yaml" || e == "yml") as usize,
+        })
+        .sum()
+}
@@ -0,0 +1,89 @@
+// Argo CD reads parameter overrides from '.argocd-source.yaml' and '.argocd-source-<app>.yaml' in
+// the source path of an application, e.g. image tags written back by the Image Updater. The repo
+// server applies them when rendering, but a change to them is easy to miss, since the Application
+// itself doesn't change
+use crate::diff::ReportSection;
+use crate::manifests::read_applications;
+use crate::model::ApplicationSpec;
+use crate::repo_url::RepoUrl;
+use crate::Branch;
+use log::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+// The override files that apply to the applications of a branch, relative to the root of the
+// repository, with the applications they apply to
+fn override_files(
+    output_folder: &str,
+    branch: &Branch,
+    repo: &RepoUrl,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut files: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (name, application) in read_applications(output_folder, branch) {
+        let Ok(spec) = ApplicationSpec::of(&application) else {
+            continue;
+        };
+        for source in spec.sources() {
+            let Some(path) = source.path.as_deref().filter(|_| source.is_from(repo)) else {
+                continue;
+            };
+            let path = path.trim_start_matches("./").trim_matches('/');
+            let dir = match path {
+                "" | "." => String::new(),
+                path => format!("{}/", path),
+            };
+            for file in [
+                ".argocd-source.yaml".to_string(),
+                format!(".argocd-source-{}.yaml", name),
+            ] {
+                files
+                    .entry(format!("{}{}", dir, file))
+                    .or_default()
+                    .insert(name.clone());
+            }
+        }
+    }
+    files
+}
+
+// Lists the override files that differ between the branches, with the applications they apply to
+pub fn generate_overrides_section(
+    output_folder: &str,
+    repo: &str,
+    branch_folders: (&str, &str),
+) -> Option<ReportSection> {
+    let repo = RepoUrl::parse(repo);
+    let mut files = override_files(output_folder, &Branch::Base, &repo);
+    for (file, names) in override_files(output_folder, &Branch::Target, &repo) {
+        files.entry(file).or_default().extend(names);
+    }
+
+    let (base_folder, target_folder) = branch_folders;
+    let read = |folder: &str, file: &str| fs::read_to_string(format!("{}/{}", folder, file)).ok();
+    let mut rows = vec![];
+    for (file, names) in files {
+        let change = match (read(base_folder, &file), read(target_folder, &file)) {
+            (None, None) => continue,
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(a), Some(b)) if a != b => "modified",
+            _ => continue,
+        };
+        debug!("Override file {} was {}", file, change);
+        let names: Vec<String> = names.into_iter().collect();
+        rows.push(format!("| {} | {} | {} |", file, change, names.join(", ")));
+    }
+
+    if rows.is_empty() {
+        return None;
+    }
+    info!("🎛️ {} parameter override files changed", rows.len());
+
+    Some(ReportSection {
+        title: format!("🎛️ Changed parameter overrides ({}):", rows.len()),
+        content: format!(
+            "| File | Change | Applications |\n|---|---|---|\n{}",
+            rows.join("\n")
+        ),
+    })
+}
@@ -1,37 +1,146 @@
-use crate::{Operator, Selector};
-use log::{debug, info};
+use crate::capabilities::HelmCapabilities;
+use crate::model::{ApplicationSource, ApplicationSpec};
+use crate::plugins::PluginConfig;
+use crate::preview_values::PreviewValues;
+use crate::repo_url::RepoUrl;
+use crate::sampling::hold_generated_applications;
+use crate::selector::Selector;
+use crate::skipped::SkippedApplication;
+use crate::warnings::{warn, WarningKind};
+use crate::Branch;
+use log::{debug, error, info};
 use regex::Regex;
-use serde_yaml::Mapping;
-use std::{error::Error, io::BufRead};
+use std::{collections::BTreeSet, error::Error, io::BufRead, io::Write};
 use std::fmt::{Debug};
 
+// A resource read from a YAML file of a branch folder
 #[derive(Debug)]
-struct K8sResource {
-    file_name: String,
-    yaml: serde_yaml::Value,
+pub struct K8sResource {
+    pub file_name: String,
+    pub yaml: serde_yaml::Value,
 }
 
+// An Application or ApplicationSet selected for rendering
 #[derive(Debug)]
-struct Application {
-    file_name: String,
-    yaml: serde_yaml::Value,
-    kind: ApplicationKind,
+pub struct Application {
+    pub file_name: String,
+    pub yaml: serde_yaml::Value,
+    pub kind: ApplicationKind,
 }
 
-#[derive(Debug)]
-enum ApplicationKind {
+#[derive(Debug, PartialEq)]
+pub enum ApplicationKind {
     Application,
     ApplicationSet,
 }
 
-pub async fn get_applications_as_string(
+// A directory in the branch folders with applications, and the repository and revisions their
+// sources are redirected to
+#[derive(Debug)]
+pub struct AppRoot {
+    pub path: String,
+    pub repo: String,
+    pub base_revision: String,
+    pub target_revision: String,
+}
+
+impl AppRoot {
+    // Parses 'PATH[;repo=REPO][;base-branch=BRANCH][;target-branch=BRANCH]'. Settings that are
+    // left out fall back to the given defaults
+    pub fn parse(
+        s: &str,
+        repo: &str,
+        base_revision: &str,
+        target_revision: &str,
+    ) -> Result<AppRoot, Box<dyn Error>> {
+        let mut parts = s.split(';').map(|p| p.trim());
+        let path = parts.next().unwrap_or_default().trim_matches('/');
+        let mut root = AppRoot {
+            path: if path == "." { "" } else { path }.to_string(),
+            repo: repo.to_string(),
+            base_revision: base_revision.to_string(),
+            target_revision: target_revision.to_string(),
+        };
+        for part in parts.filter(|p| !p.is_empty()) {
+            match part.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("repo", v)) if !v.is_empty() => root.repo = v.to_string(),
+                Some(("base-branch", v)) if !v.is_empty() => root.base_revision = v.to_string(),
+                Some(("target-branch", v)) if !v.is_empty() => root.target_revision = v.to_string(),
+                _ => return Err(format!("invalid setting '{}' in app dir '{}'", part, s).into()),
+            }
+        }
+        Ok(root)
+    }
+
+    // The directory of the root in a branch folder
+    pub fn folder(&self, branch_folder: &str) -> String {
+        match self.path.as_str() {
+            "" => branch_folder.to_string(),
+            path => format!("{}/{}", branch_folder, path),
+        }
+    }
+}
+
+// How applications are selected and patched before they are applied to the cluster
+pub struct PatchOptions<'a> {
+    pub repo: &'a str,
+    pub repo_rewrites: &'a [(String, String)],
+    pub retain_sync_policy: bool,
+    pub plugins: Option<&'a PluginConfig>,
+    pub helm_capabilities: Option<&'a HelmCapabilities>,
+    // Annotation that excludes an application from both branches ('true') or from one of them
+    // ('base-only' or 'target-only')
+    pub ignore_annotation: &'a str,
+    // Skip applications that manage Argo CD itself instead of rendering them without syncing
+    pub skip_self_managed: bool,
+    // Keep the project of applications instead of setting it to 'default' (--keep-projects)
+    pub keep_projects: bool,
+    // Only the applications with these names are rendered (--render-affected-only)
+    pub only: Option<&'a BTreeSet<String>>,
+    // ApplicationSets generate their applications without rendering them, until they are sampled
+    // (--appset-sample)
+    pub hold_generated: bool,
+    // Helm values layered onto applications of the target branch (--preview-values)
+    pub preview_values: Option<&'a PreviewValues>,
+}
+
+// Helm charts of Argo CD, which an application installs or configures Argo CD with
+const ARGO_CD_CHARTS: [&str; 2] = ["argo-cd", "argocd-apps"];
+
+// Whether an application manages Argo CD itself, e.g. argocd-cm or repository credentials. Its
+// resources would replace the settings of the preview instance if it was synced. With label
+// tracking, an application named 'argocd' also claims the resources of the preview instance, which
+// are labeled 'app.kubernetes.io/instance: argocd' by the Helm Chart
+fn manages_argo_cd(application: &serde_yaml::Value, kind: &ApplicationKind) -> bool {
+    let metadata = match kind {
+        ApplicationKind::Application => &application["metadata"],
+        ApplicationKind::ApplicationSet => &application["spec"]["template"]["metadata"],
+    };
+    let spec = ApplicationSpec::of(application).unwrap_or_default();
+    let namespace = spec
+        .destination
+        .as_ref()
+        .and_then(|d| d.namespace.as_deref());
+    metadata["name"].as_str() == Some("argocd")
+        || namespace == Some("argocd")
+        || spec
+            .sources()
+            .filter_map(|s| s.chart.as_deref())
+            .any(|c| ARGO_CD_CHARTS.contains(&c))
+}
+
+// Writes the patched applications to 'out' one by one, so the whole output is never held in memory.
+// Returns the applications that were skipped
+pub async fn write_patched_applications(
+    branch: &Branch,
     directory: &str,
-    branch: &str,
+    revision: &str,
     regex: &Option<Regex>,
     selector: &Option<Vec<Selector>>,
-    repo: &str,
-) -> Result<String, Box<dyn Error>> {
-    debug!("Starting to fetch applications as string with directory: '{}', branch: '{}', regex: '{:?}', selector: '{:?}', repo: '{}'", directory, branch, regex, selector, repo);
+    options: &PatchOptions<'_>,
+    out: &mut impl Write,
+) -> Result<Vec<SkippedApplication>, Box<dyn Error>> {
+    debug!("Starting to write applications with directory: '{}', revision: '{}', regex: '{:?}', selector: '{:?}', repo: '{}'", directory, revision, regex, selector, options.repo);
     
     let yaml_files = get_yaml_files(directory, regex).await;
     debug!("Collected YAML files: {:?}", yaml_files);
@@ -39,13 +148,57 @@ pub async fn get_applications_as_string(
     let k8s_resources = parse_yaml(yaml_files).await;
     debug!("Parsed K8s resources: {:?}", k8s_resources);
     
-    let applications = get_applications(k8s_resources, selector);
+    let (applications, skipped) = get_applications(k8s_resources, selector, options, branch);
     debug!("Filtered applications: {:?}", applications);
     
-    let output = patch_applications(applications, branch, repo).await?;
-    debug!("Final output: {}", output);
-    
-    Ok(output)
+    let applications = patch_applications(applications, revision, options);
+
+    // convert back to yaml, one application at a time
+    for a in &applications {
+        debug!("Application after patching: {:?}", a);
+        serde_yaml::to_writer(&mut *out, &a.yaml)?;
+        out.write_all(b"---\n")?;
+    }
+
+    info!(
+        "🤖 Patched {} Argo CD Application[Sets] for revision: {}",
+        applications.len(),
+        revision
+    );
+    Ok(skipped)
+}
+
+// Returns the unpatched resources of the given kinds, e.g. to compare settings that are removed when patching
+pub async fn get_resources_of_kinds(
+    directory: &str,
+    regex: &Option<Regex>,
+    kinds: &[&str],
+) -> Vec<serde_yaml::Value> {
+    get_resource_files(directory, regex, kinds)
+        .await
+        .into_iter()
+        .map(|(_, yaml)| yaml)
+        .collect()
+}
+
+// Returns the unpatched resources of the given kinds together with the files they are defined in
+pub async fn get_resource_files(
+    directory: &str,
+    regex: &Option<Regex>,
+    kinds: &[&str],
+) -> Vec<(String, serde_yaml::Value)> {
+    let yaml_files = get_yaml_files(directory, regex).await;
+    parse_yaml(yaml_files)
+        .await
+        .into_iter()
+        .filter(|r| {
+            r.yaml["kind"]
+                .as_str()
+                .map(|k| kinds.contains(&k))
+                .unwrap_or(false)
+        })
+        .map(|r| (r.file_name, r.yaml))
+        .collect()
 }
 
 async fn get_yaml_files(directory: &str, regex: &Option<Regex>) -> Vec<String> {
@@ -80,6 +233,13 @@ async fn get_yaml_files(directory: &str, regex: &Option<Regex>) -> Vec<String> {
     yaml_files
 }
 
+// Whether a document that failed to parse declares an Application or ApplicationSet. Other documents,
+// like Helm templates, are expected to fail
+fn looks_like_application(document: &str) -> bool {
+    let kind = Regex::new(r"(?m)^kind:\s*['\x22]?Application(Set)?['\x22]?\s*$").unwrap();
+    kind.is_match(document)
+}
+
 async fn parse_yaml(files: Vec<String>) -> Vec<K8sResource> {
     debug!("Starting to parse YAML files: {:?}", files);
 
@@ -105,6 +265,10 @@ async fn parse_yaml(files: Vec<String>) -> Vec<K8sResource> {
             let yaml_vec: Vec<K8sResource> = raw_yaml_chunks.iter_mut().enumerate().map(|(i,r)| {
                 let yaml = match serde_yaml::from_str(r) {
                     Ok(r) => r,
+                    Err(e) if looks_like_application(r) => {
+                        warn(WarningKind::SkippedDocument, f, format!("Skipping element number {} in file '{}', which looks like an application but isn't valid YAML: '{}'", i+1, f, e));
+                        serde_yaml::Value::Null
+                    }
                     Err(e) => {
                         debug!("⚠️ Failed to parse element number {}, in file '{}', with error: '{}'", i+1, f, e);
                         serde_yaml::Value::Null
@@ -122,72 +286,116 @@ async fn parse_yaml(files: Vec<String>) -> Vec<K8sResource> {
         .collect()
 }
 
-async fn patch_applications(
+// Makes sure an application that manages Argo CD is only rendered and never changes the preview
+// instance: it can't be synced, not even by an operation in its manifest, and deleting it doesn't
+// delete any resources
+fn render_only(a: &mut Application) {
+    info!(
+        "🛡️ Application {:?} in file '{}' manages Argo CD, so it is only rendered",
+        a.yaml["metadata"]["name"].as_str().unwrap_or("unknown"),
+        a.file_name
+    );
+    let template = match a.kind {
+        ApplicationKind::Application => &mut a.yaml,
+        ApplicationKind::ApplicationSet => &mut a.yaml["spec"]["template"],
+    };
+    if let Some(spec) = template["spec"].as_mapping_mut() {
+        spec.remove("syncPolicy");
+    }
+    if let Some(metadata) = template["metadata"].as_mapping_mut() {
+        metadata.remove("finalizers");
+    }
+    if let Some(application) = a.yaml.as_mapping_mut() {
+        application.remove("operation");
+    }
+}
+
+// Points the sources of the applications in 'repo' to 'revision', which is a branch name or commit
+// SHA, and makes them render in the preview cluster without syncing
+pub fn patch_applications(
     applications: Vec<Application>,
-    branch: &str,
-    repo: &str,
-) -> Result<String, Box<dyn Error>> {
-    info!("🤖 Patching applications for branch: {}", branch);
+    revision: &str,
+    options: &PatchOptions<'_>,
+) -> Vec<Application> {
+    info!("🤖 Patching applications for revision: {}", revision);
+    let repo = RepoUrl::parse(options.repo);
+    let repo_rewrites: Vec<(RepoUrl, &String)> = options
+        .repo_rewrites
+        .iter()
+        .map(|(from, to)| (RepoUrl::parse(from), to))
+        .collect();
     debug!("Applications before patching: {:?}", applications);
 
-    let point_destination_to_in_cluster = |spec: &mut Mapping| {
+    let point_destination_to_in_cluster = |spec: &mut ApplicationSpec| {
         debug!("Patching destination to in-cluster...");
-        if spec.contains_key("destination") {
-            spec["destination"]["name"] = serde_yaml::Value::String("in-cluster".to_string());
-            spec["destination"]
-                .as_mapping_mut()
-                .map(|a| a.remove("server"));
-            debug!("Updated destination to in-cluster: {:?}", spec["destination"]);
+        if let Some(destination) = &mut spec.destination {
+            destination.name = Some("in-cluster".to_string());
+            destination.server = None;
+            debug!("Updated destination to in-cluster: {:?}", destination);
         }
     };
 
-    let set_project_to_default =
-        |spec: &mut Mapping| {
-            debug!("Setting project to default...");
-            spec["project"] = serde_yaml::Value::String("default".to_string());
-            debug!("Updated project: {:?}", spec["project"]);
-        };
+    let set_project_to_default = |spec: &mut ApplicationSpec| {
+        debug!("Setting project to default...");
+        spec.project = Some("default".to_string());
+    };
 
-    let remove_sync_policy = |spec: &mut Mapping| {
+    let remove_sync_policy = |spec: &mut ApplicationSpec| {
+        if options.retain_sync_policy {
+            // disable automation, but keep the rest so it is part of the applied application
+            debug!("Removing syncPolicy.automated...");
+            if let Some(sync_policy) = &mut spec.sync_policy {
+                sync_policy.automated = None;
+            }
+            return;
+        }
         debug!("Removing syncPolicy...");
-        spec.remove("syncPolicy");
-        debug!("SyncPolicy removed.");
+        spec.sync_policy = None;
     };
 
-    let redirect_sources = |spec: &mut Mapping, file: &str| {
+    let rewrite_repo = |source: &mut ApplicationSource| {
+        let Some(url) = source.repo_url.as_deref().map(RepoUrl::parse) else {
+            return;
+        };
+        if let Some((_, to)) = repo_rewrites.iter().find(|(from, _)| from.matches(&url)) {
+            debug!("Rewriting repoURL {:?} to '{}'", source.repo_url, to);
+            source.repo_url = Some(to.to_string());
+        }
+    };
+
+    let redirect_sources = |spec: &mut ApplicationSpec, file: &str| {
         debug!("Redirecting sources in file: {}", file);
-        if spec.contains_key("source") {
-            if spec["source"]["chart"].as_str().is_some() {
+        for source in spec.sources_mut() {
+            rewrite_repo(source);
+            if source.chart.is_some() {
                 debug!("Source is a Helm chart, skipping repo URL update.");
-                return;
+                continue;
             }
-            match spec["source"]["repoURL"].as_str() {
-                Some(url) if url.contains(repo) => {
-                    spec["source"]["targetRevision"] = serde_yaml::Value::String(branch.to_string());
-                    debug!("Updated targetRevision to branch '{}'", branch);
-                }
-                _ => debug!("Found no 'repoURL' under spec.source in file: {}", file),
-            }
-        } else if spec.contains_key("sources") {
-            if let Some(sources) = spec["sources"].as_sequence_mut() {
-                for source in sources {
-                    if source["chart"].as_str().is_some() {
-                        debug!("Source is a Helm chart, skipping repo URL update.");
-                        continue;
-                    }
-                    match source["repoURL"].as_str() {
-                        Some(url) if url.contains(repo) => {
-                            source["targetRevision"] = serde_yaml::Value::String(branch.to_string());
-                            debug!("Updated targetRevision to branch '{}'", branch);
-                        }
-                        _ => debug!("Found no 'repoURL' under spec.sources[] in file: {}", file),
-                    }
+            match source.repo_url.as_deref() {
+                Some(url) if RepoUrl::parse(url).matches(&repo) => {
+                    source.target_revision = Some(revision.to_string());
+                    debug!("Updated targetRevision to '{}'", revision);
                 }
+                // nothing is pointed to a branch when directories are compared
+                _ if options.repo.is_empty() => (),
+                url => warn(
+                    WarningKind::UnmatchedRepoUrl,
+                    file,
+                    format!(
+                        "Source '{}' in file '{}' is not in {}, so changes to it are not previewed",
+                        url.unwrap_or_default(),
+                        file,
+                        options.repo
+                    ),
+                ),
             }
         }
+        if let Some(sources) = &spec.sources {
+            validate_value_file_refs(sources, &repo, file);
+        }
     };
 
-    let applications: Vec<Application> = applications
+    applications
         .into_iter()
         .map(|mut a| {
             // Update namespace
@@ -195,18 +403,76 @@ async fn patch_applications(
             debug!("Updated namespace for application in file '{}'", a.file_name);
             a
         })
+        .map(|mut a| {
+            if let (ApplicationKind::ApplicationSet, Some(plugins)) = (&a.kind, options.plugins) {
+                plugins.patch_generators(&mut a.yaml["spec"]["generators"], &a.file_name);
+            }
+            a
+        })
+        .map(|mut a| {
+            if manages_argo_cd(&a.yaml, &a.kind) {
+                render_only(&mut a);
+            }
+            a
+        })
         .filter_map(|mut a| {
+            let name = a.yaml["metadata"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let mut spec = match ApplicationSpec::of(&a.yaml) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    warn(
+                        WarningKind::SkippedDocument,
+                        &a.file_name,
+                        format!(
+                            "Skipping application {:?} in file '{}' with an invalid spec: {}",
+                            name, a.file_name, e
+                        ),
+                    );
+                    return None;
+                }
+            };
+            validate_fields(&spec, &name, &a.file_name);
+            if spec.destination.is_none() {
+                warn(
+                    WarningKind::MissingDestination,
+                    &a.file_name,
+                    format!(
+                        "Application {:?} in file '{}' has no destination",
+                        name, a.file_name
+                    ),
+                );
+            }
             // Clean up the spec
-            let spec = match a.kind {
-                ApplicationKind::Application => a.yaml["spec"].as_mapping_mut()?,
+            remove_sync_policy(&mut spec);
+            if !options.keep_projects {
+                set_project_to_default(&mut spec);
+            }
+            point_destination_to_in_cluster(&mut spec);
+            redirect_sources(&mut spec, &a.file_name);
+            if let Some(plugins) = options.plugins {
+                plugins.inject_env(&mut spec, &name);
+            }
+            if let Some(capabilities) = options.helm_capabilities {
+                capabilities.apply(&mut spec, &name);
+            }
+            if let Some(values) = options.preview_values {
+                if let Err(e) = values.apply(&mut spec, &name) {
+                    error!("❌ {}", e);
+                    panic!("Invalid preview values");
+                }
+            }
+            match a.kind {
+                ApplicationKind::Application => a.yaml["spec"] = spec.to_value(),
                 ApplicationKind::ApplicationSet => {
-                    a.yaml["spec"]["template"]["spec"].as_mapping_mut()?
+                    a.yaml["spec"]["template"]["spec"] = spec.to_value();
+                    if options.hold_generated {
+                        hold_generated_applications(&mut a.yaml);
+                    }
                 }
-            };
-            remove_sync_policy(spec);
-            set_project_to_default(spec);
-            point_destination_to_in_cluster(spec);
-            redirect_sources(spec, &a.file_name);
+            }
             debug!(
                 "Processed application {:?} in file: {}",
                 a.yaml["metadata"]["name"].as_str().unwrap_or("unknown"),
@@ -214,33 +480,147 @@ async fn patch_applications(
             );
             Some(a)
         })
+        .collect()
+}
+
+// Helm value files like '$values/path/values.yaml' are read from the source with 'ref: values'.
+// Only refs to redirected sources resolve against the branch, so the others are reported
+fn validate_value_file_refs(sources: &[ApplicationSource], repo: &RepoUrl, file: &str) {
+    let refs: Vec<(&str, bool)> = sources
+        .iter()
+        .filter_map(|source| {
+            let name = source.reference.as_deref()?;
+            let redirected = source.chart.is_none()
+                && source
+                    .repo_url
+                    .as_deref()
+                    .map(|url| RepoUrl::parse(url).matches(repo))
+                    .unwrap_or(false);
+            Some((name, redirected))
+        })
         .collect();
 
-    info!(
-        "🤖 Patching {} Argo CD Application[Sets] for branch: {}",
-        applications.len(),
-        branch
-    );
-    debug!("Applications after patching: {:?}", applications);
+    let value_files = sources
+        .iter()
+        .flat_map(|source| source.helm.iter().flat_map(|h| &h.value_files));
+
+    for value_file in value_files {
+        let Some(name) = value_file
+            .strip_prefix('$')
+            .and_then(|f| f.split('/').next())
+        else {
+            continue;
+        };
+        match refs.iter().find(|(r, _)| *r == name) {
+            Some((_, true)) => debug!("Value file '{}' is read from the branch", value_file),
+            Some((_, false)) => warn(
+                WarningKind::ValueFileRef,
+                file,
+                format!(
+                    "Value file '{}' in file '{}' refers to a source that is not redirected to the branch, so changes to it are not previewed",
+                    value_file, file
+                ),
+            ),
+            None => warn(
+                WarningKind::ValueFileRef,
+                file,
+                format!(
+                    "Value file '{}' in file '{}' refers to '${}', but no source has 'ref: {}'",
+                    value_file, file, name, name
+                ),
+            ),
+        }
+    }
+}
 
-    // convert back to yaml string
-    let mut output = String::new();
-    for r in applications {
-        output.push_str(&serde_yaml::to_string(&r.yaml)?);
-        output.push_str("---\n");
+// Fields of an application spec, a source and its Helm settings that Argo CD knows, besides the
+// ones of the model
+const SPEC_FIELDS: [&str; 4] = [
+    "ignoreDifferences",
+    "info",
+    "revisionHistoryLimit",
+    "sourceHydrator",
+];
+const SOURCE_FIELDS: [&str; 2] = ["directory", "kustomize"];
+const HELM_FIELDS: [&str; 10] = [
+    "fileParameters",
+    "ignoreMissingValueFiles",
+    "namespace",
+    "parameters",
+    "passCredentials",
+    "releaseName",
+    "skipCrds",
+    "skipSchemaValidation",
+    "skipTests",
+    "version",
+];
+
+// Reports the fields of the spec that Argo CD doesn't know, e.g. 'targetRevison'. Argo CD drops
+// them, so the application renders differently than its author expects
+fn validate_fields(spec: &ApplicationSpec, name: &str, file: &str) {
+    let unknown = |fields: &serde_yaml::Mapping, known: &[&str], parent: &str| {
+        for field in fields.keys().filter_map(|k| k.as_str()) {
+            if !known.contains(&field) {
+                warn(
+                    WarningKind::UnknownField,
+                    file,
+                    format!(
+                        "Application {:?} in file '{}' has the unknown field '{}{}'",
+                        name, file, parent, field
+                    ),
+                );
+            }
+        }
+    };
+    unknown(&spec.other, &SPEC_FIELDS, "spec.");
+    for source in spec.sources() {
+        unknown(&source.other, &SOURCE_FIELDS, "source.");
+        if let Some(helm) = &source.helm {
+            unknown(&helm.other, &HELM_FIELDS, "source.helm.");
+        }
     }
-    debug!("Final YAML output: {}", output);
+}
 
-    Ok(output)
+// Whether the value of the ignore annotation excludes an application from the branch
+fn is_ignored(value: &str, branch: &Branch, file: &str) -> bool {
+    match (value, branch) {
+        ("true", _) => true,
+        ("base-only", Branch::Base) | ("target-only", Branch::Target) => true,
+        ("base-only", _) | ("target-only", _) | ("false", _) => false,
+        (other, _) => {
+            warn(
+                WarningKind::IgnoreAnnotation,
+                file,
+                format!("Unknown ignore annotation value '{}' in file '{}'. Use 'true', 'base-only' or 'target-only'", other, file),
+            );
+            false
+        }
+    }
 }
 
-fn get_applications(
+// Selects the applications and application sets of the resources. Returns the ones that are
+// excluded by the ignore annotation, the selector or because they manage Argo CD as skipped
+pub fn get_applications(
     k8s_resources: Vec<K8sResource>,
     selector: &Option<Vec<Selector>>,
-) -> Vec<Application> {
+    options: &PatchOptions<'_>,
+    branch: &Branch,
+) -> (Vec<Application>, Vec<SkippedApplication>) {
     debug!("Getting applications from K8s resources: {:?}", k8s_resources);
 
-    k8s_resources
+    let mut skipped = vec![];
+    let mut skip = |r: &K8sResource, reason: String| {
+        skipped.push(SkippedApplication {
+            name: r.yaml["metadata"]["name"]
+                .as_str()
+                .unwrap_or("unknown")
+                .to_string(),
+            file: r.file_name.clone(),
+            reason,
+        });
+    };
+
+    let applications = k8s_resources
         .into_iter()
         .filter_map(|r| {
             debug!("Processing file: {}", r.file_name);
@@ -254,14 +634,25 @@ fn get_applications(
                         _ => None,
                     })?;
 
-            if r.yaml["metadata"]["annotations"]["argocd-diff-preview/ignore"].as_str()
-                == Some("true")
-            {
+            let ignore_annotation = options.ignore_annotation;
+            let ignore = r.yaml["metadata"]["annotations"][ignore_annotation]
+                .as_str()
+                .unwrap_or_default();
+            if !ignore.is_empty() && is_ignored(ignore, branch, &r.file_name) {
                 debug!(
-                    "Ignoring application {:?} due to 'argocd-diff-preview/ignore=true' in file: {}",
+                    "Ignoring application {:?} due to '{}={}' in file: {}",
                     r.yaml["metadata"]["name"].as_str().unwrap_or("unknown"),
+                    ignore_annotation,
+                    ignore,
                     r.file_name
                 );
+                skip(
+                    &r,
+                    format!(
+                        "ignored with annotation `{}: {}`",
+                        ignore_annotation, ignore
+                    ),
+                );
                 return None;
             }
 
@@ -277,16 +668,21 @@ fn get_applications(
                 };
                 debug!("Application labels: {:?}", labels);
 
-                let selected = selector.iter().all(|l| match l.operator {
-                    Operator::Eq => labels.iter().any(|(k, v)| k == &l.key && v == &l.value),
-                    Operator::Ne => labels.iter().all(|(k, v)| k != &l.key || v != &l.value),
-                });
+                let selected = selector
+                    .iter()
+                    .all(|l| l.matches(labels.iter().find(|(k, _)| k == &l.key).map(|(_, v)| *v)));
                 if !selected {
                     debug!(
                         "Ignoring application {:?} due to selector mismatch in file: {}",
                         r.yaml["metadata"]["name"].as_str().unwrap_or("unknown"),
                         r.file_name
                     );
+                    let selector = selector
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<String>>()
+                        .join(",");
+                    skip(&r, format!("doesn't match selector `{}`", selector));
                     return None;
                 } else {
                     debug!(
@@ -297,11 +693,321 @@ fn get_applications(
                 }
             }
 
+            if let Some(only) = options.only {
+                let name = r.yaml["metadata"]["name"].as_str().unwrap_or_default();
+                if !only.contains(name) {
+                    debug!(
+                        "Ignoring application {:?}, since the changes don't affect it",
+                        name
+                    );
+                    skip(&r, "not affected by the changed files".to_string());
+                    return None;
+                }
+            }
+
+            if options.skip_self_managed && manages_argo_cd(&r.yaml, &kind) {
+                debug!(
+                    "Ignoring application {:?}, since it manages Argo CD, in file: {}",
+                    r.yaml["metadata"]["name"].as_str().unwrap_or("unknown"),
+                    r.file_name
+                );
+                skip(&r, "manages Argo CD itself".to_string());
+                return None;
+            }
+
             Some(Application {
                 kind,
                 file_name: r.file_name,
                 yaml: r.yaml,
             })
         })
-        .collect()
+        .collect();
+    (applications, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApplicationKind;
+    use crate::testkit::{
+        names, parse_selector, patch_options, select_and_patch, ApplicationBuilder,
+    };
+    use crate::Branch;
+
+    const REPO: &str = "https://github.com/org/repo.git";
+
+    #[test]
+    fn selects_applications_by_labels() {
+        let resources = vec![
+            ApplicationBuilder::application("payments")
+                .label("team", "payments")
+                .build(),
+            ApplicationBuilder::application("search")
+                .label("team", "search")
+                .build(),
+            ApplicationBuilder::application("legacy")
+                .file("legacy/app.yaml")
+                .build(),
+        ];
+        let options = patch_options(REPO);
+        let (applications, skipped) = select_and_patch(
+            resources,
+            &parse_selector("team in (payments,checkout)"),
+            &options,
+            &Branch::Target,
+            "feature",
+        );
+        assert_eq!(names(&applications), ["payments"]);
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(
+            skipped[0].reason,
+            "doesn't match selector `team in (payments,checkout)`"
+        );
+        assert_eq!(skipped[1].file, "legacy/app.yaml");
+    }
+
+    #[test]
+    fn skips_ignored_applications_per_branch() {
+        let resources = || {
+            vec![
+                ApplicationBuilder::application("new")
+                    .annotation("argocd-diff-preview/ignore", "base-only")
+                    .build(),
+                ApplicationBuilder::application("old")
+                    .annotation("argocd-diff-preview/ignore", "false")
+                    .build(),
+            ]
+        };
+        let options = patch_options(REPO);
+        let (base, _) = select_and_patch(resources(), &None, &options, &Branch::Base, "main");
+        let (target, _) = select_and_patch(resources(), &None, &options, &Branch::Target, "pr");
+        assert_eq!(names(&base), ["old"]);
+        assert_eq!(names(&target), ["new", "old"]);
+    }
+
+    #[test]
+    fn detects_applications_that_fail_to_parse() {
+        assert!(super::looks_like_application(
+            "apiVersion: argoproj.io/v1alpha1\nkind: Application\nspec:\n  source: [\n"
+        ));
+        assert!(super::looks_like_application("kind: \"ApplicationSet\"\n"));
+        assert!(!super::looks_like_application(
+            "kind: Deployment\nmetadata:\n  name: {{ .Values.name }}\n"
+        ));
+    }
+
+    #[test]
+    fn renders_only_the_affected_applications() {
+        let resources = vec![
+            ApplicationBuilder::application("payments").build(),
+            ApplicationBuilder::application("search").build(),
+        ];
+        let affected = ["search".to_string()].into();
+        let options = super::PatchOptions {
+            only: Some(&affected),
+            ..patch_options(REPO)
+        };
+        let (applications, skipped) =
+            select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        assert_eq!(names(&applications), ["search"]);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "payments");
+        assert_eq!(skipped[0].reason, "not affected by the changed files");
+    }
+
+    #[test]
+    fn redirects_sources_of_the_repository() {
+        let resources = vec![ApplicationBuilder::application("app")
+            .source("git@github.com:org/repo", "apps/app", "HEAD")
+            .source("https://github.com/org/other", "apps/app", "main")
+            .chart("https://github.com/org/repo", "app", "1.0.0")
+            .build()];
+        let options = patch_options(REPO);
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        let sources = &applications[0].yaml["spec"]["sources"];
+        assert_eq!(sources[0]["targetRevision"], "pr");
+        assert_eq!(sources[1]["targetRevision"], "main");
+        assert_eq!(sources[2]["targetRevision"], "1.0.0");
+    }
+
+    #[test]
+    fn renders_in_the_preview_cluster_without_syncing() {
+        let resources = vec![ApplicationBuilder::application("app")
+            .destination("https://prod.example.com", "payments")
+            .automated_sync()
+            .build()];
+        let options = patch_options(REPO);
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Base, "main");
+        let spec = &applications[0].yaml["spec"];
+        assert_eq!(spec["destination"]["name"], "in-cluster");
+        assert!(spec["destination"]["server"].is_null());
+        assert_eq!(spec["destination"]["namespace"], "payments");
+        assert_eq!(spec["project"], "default");
+        assert!(spec["syncPolicy"].is_null());
+    }
+
+    #[test]
+    fn keeps_the_project_with_keep_projects() {
+        let resources = vec![ApplicationBuilder::application("app")
+            .spec_field("project", "payments")
+            .build()];
+        let options = super::PatchOptions {
+            keep_projects: true,
+            ..patch_options(REPO)
+        };
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Base, "main");
+        assert_eq!(applications[0].yaml["spec"]["project"], "payments");
+    }
+
+    #[test]
+    fn retains_the_sync_policy_without_automation() {
+        let resources = vec![ApplicationBuilder::application("app")
+            .automated_sync()
+            .build()];
+        let options = super::PatchOptions {
+            retain_sync_policy: true,
+            ..patch_options(REPO)
+        };
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Base, "main");
+        let sync_policy = &applications[0].yaml["spec"]["syncPolicy"];
+        assert!(sync_policy["automated"].is_null());
+        assert_eq!(sync_policy["syncOptions"][0], "CreateNamespace=true");
+    }
+
+    #[test]
+    fn patches_the_template_of_application_sets() {
+        let resources = vec![ApplicationBuilder::application_set("apps")
+            .source(REPO, "apps/{{name}}", "HEAD")
+            .automated_sync()
+            .build()];
+        let options = patch_options(REPO);
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        assert_eq!(applications[0].kind, ApplicationKind::ApplicationSet);
+        let spec = &applications[0].yaml["spec"]["template"]["spec"];
+        assert_eq!(spec["source"]["targetRevision"], "pr");
+        assert!(spec["syncPolicy"].is_null());
+    }
+
+    #[test]
+    fn holds_generated_applications_with_appset_sample() {
+        let resources = vec![ApplicationBuilder::application_set("apps")
+            .source(REPO, "apps/{{name}}", "HEAD")
+            .spec_field("project", "payments")
+            .build()];
+        let options = super::PatchOptions {
+            keep_projects: true,
+            hold_generated: true,
+            ..patch_options(REPO)
+        };
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        let appset = &applications[0].yaml["spec"];
+        assert_eq!(
+            appset["template"]["spec"]["project"],
+            "argocd-diff-preview-sample"
+        );
+        assert_eq!(
+            appset["template"]["metadata"]["annotations"]["argocd-diff-preview/project"],
+            "payments"
+        );
+        assert_eq!(
+            appset["ignoreApplicationDifferences"][0]["jsonPointers"][0],
+            "/spec/project"
+        );
+    }
+
+    #[test]
+    fn skips_applications_managing_argo_cd() {
+        let resources = vec![
+            ApplicationBuilder::application("argocd").build(),
+            ApplicationBuilder::application("app").build(),
+        ];
+        let options = super::PatchOptions {
+            skip_self_managed: true,
+            ..patch_options(REPO)
+        };
+        let (applications, skipped) =
+            select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        assert_eq!(names(&applications), ["app"]);
+        assert_eq!(skipped[0].reason, "manages Argo CD itself");
+    }
+
+    #[test]
+    fn keeps_fields_it_does_not_patch() {
+        let resources = vec![ApplicationBuilder::application("app")
+            .spec_field(
+                "source",
+                "{repoURL: 'org/repo', path: app, kustomize: {namePrefix: prod-}}",
+            )
+            .spec_field(
+                "ignoreDifferences",
+                "[{kind: Secret, jsonPointers: [/data]}]",
+            )
+            .build()];
+        let options = patch_options(REPO);
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        let spec = &applications[0].yaml["spec"];
+        assert_eq!(spec["ignoreDifferences"][0]["jsonPointers"][0], "/data");
+        assert_eq!(spec["source"]["kustomize"]["namePrefix"], "prod-");
+        assert_eq!(spec["source"]["targetRevision"], "pr");
+    }
+
+    #[test]
+    fn keeps_inline_helm_values() {
+        let resources = vec![ApplicationBuilder::application("app")
+            .spec_field(
+                "sources",
+                "[{repoURL: 'org/repo', path: chart, helm: {values: 'replicas: 2', valuesObject: {image: {tag: v2}}}}]",
+            )
+            .build()];
+        let options = patch_options(REPO);
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        let helm = &applications[0].yaml["spec"]["sources"][0]["helm"];
+        assert_eq!(helm["values"], "replicas: 2");
+        assert_eq!(helm["valuesObject"]["image"]["tag"], "v2");
+    }
+
+    #[test]
+    fn skips_applications_with_invalid_specs() {
+        let resources = vec![
+            ApplicationBuilder::application("broken")
+                .spec_field("source", "not-a-source")
+                .build(),
+            ApplicationBuilder::application("app").build(),
+        ];
+        let options = patch_options(REPO);
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        assert_eq!(names(&applications), ["app"]);
+    }
+
+    #[test]
+    fn layers_preview_values_onto_helm_sources() {
+        // unique per test run, so parallel runs don't read each other's file
+        let file = std::env::temp_dir().join(format!(
+            "argocd-diff-preview-preview-values-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(&file, "replicas: 3\nimage:\n  tag: v2\n").unwrap();
+        let values =
+            crate::preview_values::read_preview_values(&[format!("payments={}", file.display())])
+                .unwrap();
+        std::fs::remove_file(&file).unwrap();
+        let resources = vec![ApplicationBuilder::application("payments")
+            .spec_field(
+                "source",
+                "{repoURL: 'https://charts.example.com', chart: payments, targetRevision: 1.0.0, helm: {values: 'image: {repository: payments, tag: v1}'}}",
+            )
+            .build()];
+        let options = super::PatchOptions {
+            preview_values: Some(&values),
+            ..patch_options(REPO)
+        };
+        let (applications, _) = select_and_patch(resources, &None, &options, &Branch::Target, "pr");
+        let helm = &applications[0].yaml["spec"]["source"]["helm"];
+        assert!(helm["values"].is_null());
+        assert_eq!(
+            helm["valuesObject"]["image"]["repository"].as_str(),
+            Some("payments")
+        );
+        assert_eq!(helm["valuesObject"]["image"]["tag"].as_str(), Some("v2"));
+        assert_eq!(helm["valuesObject"]["replicas"].as_u64(), Some(3));
+    }
 }
@@ -0,0 +1,114 @@
+// kind nodes run as containers of the Docker host, so they should match its architecture. An image
+// of another architecture runs emulated, which is many times slower, or not at all. Docker only
+// picks the native variant of a multi-arch image when pulling, so a node image that is already
+// present for another architecture (e.g. pulled under Rosetta) is pulled again for the host
+use crate::utils::run_command_from_list;
+use log::{debug, info};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    Amd64,
+    Arm64,
+}
+
+impl Arch {
+    // Accepts the names used by Docker, Go and Rust, with or without the 'linux/' prefix
+    pub fn parse(name: &str) -> Option<Arch> {
+        match name.trim().trim_start_matches("linux/") {
+            "amd64" | "x86_64" | "x86-64" => Some(Arch::Amd64),
+            "arm64" | "aarch64" | "arm64/v8" => Some(Arch::Arm64),
+            _ => None,
+        }
+    }
+
+    pub fn platform(&self) -> &'static str {
+        match self {
+            Arch::Amd64 => "linux/amd64",
+            Arch::Arm64 => "linux/arm64",
+        }
+    }
+}
+
+impl std::fmt::Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Arch::Amd64 => write!(f, "amd64"),
+            Arch::Arm64 => write!(f, "arm64"),
+        }
+    }
+}
+
+async fn docker(args: &[&str]) -> Option<String> {
+    let command = std::iter::once("docker")
+        .chain(args.iter().copied())
+        .collect();
+    match run_command_from_list(command, None).await {
+        Ok(o) => Some(String::from_utf8_lossy(&o.stdout).trim().to_string()),
+        Err(e) => {
+            debug!(
+                "docker {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&e.stderr)
+            );
+            None
+        }
+    }
+}
+
+// The architecture of the Docker host, which differs from the one of this binary when it runs
+// emulated or talks to a remote daemon. Falls back to the architecture of this binary
+pub async fn detect() -> Arch {
+    match docker(&["info", "--format", "{{.Architecture}}"])
+        .await
+        .and_then(|a| Arch::parse(&a))
+    {
+        Some(arch) => arch,
+        None => {
+            debug!(
+                "Using the architecture of the binary: {}",
+                std::env::consts::ARCH
+            );
+            Arch::parse(std::env::consts::ARCH).unwrap_or(Arch::Amd64)
+        }
+    }
+}
+
+// The kind node image of the cluster. None leaves the choice to kind
+pub fn node_image(node_image: Option<&str>, kubernetes_version: Option<&str>) -> Option<String> {
+    match (node_image, kubernetes_version) {
+        (Some(image), _) => Some(image.to_string()),
+        // the node image determines the Kubernetes version of the cluster
+        (None, Some(v)) => Some(format!("kindest/node:v{}", v.trim_start_matches('v'))),
+        (None, None) => None,
+    }
+}
+
+// Pulls the variant of the image for the architecture. kind pulls the image itself if this fails,
+// e.g. for an image that only exists locally
+pub async fn pull_image(image: &str, arch: Arch) {
+    info!("📦 Pulling {} for {}", image, arch.platform());
+    if docker(&["pull", "--platform", arch.platform(), image])
+        .await
+        .is_none()
+    {
+        info!(
+            "⚠️ Failed to pull {} for {}. The image may run emulated",
+            image,
+            arch.platform()
+        );
+    }
+}
+
+// Snapshots are saved from a node of the machine that created them, so they can have another
+// architecture than the Docker host
+pub async fn check_image(image: &str, arch: Arch) {
+    let image_arch = docker(&["image", "inspect", "-f", "{{.Architecture}}", image]).await;
+    if let Some(a) = image_arch.as_deref().and_then(Arch::parse) {
+        if a != arch {
+            info!(
+                "⚠️ Image {} is built for {}, but the Docker host runs {}. The cluster runs emulated",
+                image, a, arch
+            );
+        }
+    }
+}
@@ -0,0 +1,313 @@
+// Plugin generators of ApplicationSets ask an HTTP service for their parameters, which doesn't exist
+// in the preview cluster. A plugin can either be replaced by a static response, which turns the
+// generator into a list generator, or be pointed at a stub server.
+// Config Management Plugins often depend on environment variables that are set in production, which
+// are injected into the plugin sources of applications or the repo-server
+use crate::config::read_config;
+use crate::model::{ApplicationSpec, PluginEnv};
+use crate::utils::{run_command, run_command_from_list};
+use crate::workdir;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+// Secret the stub tokens are read from. Argo CD only reads secrets with the part-of label
+const STUB_SECRET: &str = "argocd-diff-preview-plugin-stubs";
+
+enum PluginGenerator {
+    // The parameters the plugin would return
+    Response(Vec<serde_yaml::Value>),
+    // A server implementing the plugin API (POST /api/v1/getparams.execute)
+    Stub { base_url: String, token: String },
+}
+
+pub struct PluginConfig {
+    // Keyed by the name of the plugin's ConfigMap (generators[].plugin.configMapRef.name)
+    generators: BTreeMap<String, PluginGenerator>,
+    // Added to the plugin sources of every application
+    env: BTreeMap<String, String>,
+    // Added to the plugin sources of single applications, keyed by application name
+    application_env: BTreeMap<String, BTreeMap<String, String>>,
+    // Set on the repo-server and its plugin sidecars
+    repo_server_env: BTreeMap<String, String>,
+}
+
+fn read_env(
+    value: &serde_yaml::Value,
+    key: &str,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    value
+        .as_mapping()
+        .into_iter()
+        .flatten()
+        .map(|(name, value)| {
+            let name = name
+                .as_str()
+                .ok_or_else(|| format!("'{}' has a variable name that is not a string", key))?;
+            let value = match value {
+                serde_yaml::Value::String(s) => s.clone(),
+                serde_yaml::Value::Number(n) => n.to_string(),
+                serde_yaml::Value::Bool(b) => b.to_string(),
+                _ => return Err(format!("'{}.{}' must be a string", key, name).into()),
+            };
+            Ok((name.to_string(), value))
+        })
+        .collect()
+}
+
+// A response file contains either the response of the plugin ({"output": {"parameters": [...]}})
+// or just the list of parameters
+fn read_response(path: &str) -> Result<Vec<serde_yaml::Value>, Box<dyn Error>> {
+    let response: serde_yaml::Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let parameters = match response.as_sequence() {
+        Some(parameters) => parameters,
+        None => response["output"]["parameters"]
+            .as_sequence()
+            .ok_or_else(|| format!("{} is missing 'output.parameters'", path))?,
+    };
+    Ok(parameters.clone())
+}
+
+pub fn read_plugin_config(path: &str) -> Result<PluginConfig, Box<dyn Error>> {
+    let config: serde_yaml::Value = read_config(path)?;
+    debug!("Plugin config: {:?}", config);
+    let mut generators = BTreeMap::new();
+    for (name, generator) in config["generators"].as_mapping().into_iter().flatten() {
+        let name = name
+            .as_str()
+            .ok_or("plugin generator names must be strings")?;
+        let generator = match (
+            generator["response"].as_str(),
+            generator["baseUrl"].as_str(),
+        ) {
+            (Some(response), None) => PluginGenerator::Response(read_response(response)?),
+            (None, Some(base_url)) => PluginGenerator::Stub {
+                base_url: base_url.to_string(),
+                token: generator["token"]
+                    .as_str()
+                    .unwrap_or("argocd-diff-preview")
+                    .to_string(),
+            },
+            _ => {
+                return Err(format!(
+                    "plugin generator '{}' needs either 'response' or 'baseUrl'",
+                    name
+                )
+                .into())
+            }
+        };
+        generators.insert(name.to_string(), generator);
+    }
+
+    let mut application_env = BTreeMap::new();
+    for (name, application) in config["applications"].as_mapping().into_iter().flatten() {
+        let name = name.as_str().ok_or("application names must be strings")?;
+        let env = read_env(&application["env"], &format!("applications.{}.env", name))?;
+        application_env.insert(name.to_string(), env);
+    }
+
+    Ok(PluginConfig {
+        generators,
+        env: read_env(&config["env"], "env")?,
+        application_env,
+        repo_server_env: read_env(&config["repoServerEnv"], "repoServerEnv")?,
+    })
+}
+
+impl PluginConfig {
+    // Replaces plugin generators with a static response by list generators, including the ones
+    // nested in matrix and merge generators
+    pub fn patch_generators(&self, generators: &mut serde_yaml::Value, file: &str) {
+        for generator in generators.as_sequence_mut().into_iter().flatten() {
+            for nested in ["matrix", "merge"] {
+                if generator[nested]["generators"].is_sequence() {
+                    self.patch_generators(&mut generator[nested]["generators"], file);
+                }
+            }
+            let Some(plugin) = generator.get("plugin").cloned() else {
+                continue;
+            };
+            let name = plugin["configMapRef"]["name"].as_str().unwrap_or_default();
+            match self.generators.get(name) {
+                Some(PluginGenerator::Response(parameters)) => {
+                    debug!("Replacing plugin generator '{}' in {} with its response", name, file);
+                    // the plugin adds its 'values' to every set of parameters
+                    let elements = parameters
+                        .iter()
+                        .cloned()
+                        .map(|mut p| {
+                            if plugin["values"].is_mapping() {
+                                p["values"] = plugin["values"].clone();
+                            }
+                            p
+                        })
+                        .collect();
+                    let mut list = serde_yaml::Mapping::new();
+                    list.insert("elements".into(), serde_yaml::Value::Sequence(elements));
+                    if let Some(g) = generator.as_mapping_mut() {
+                        g.remove("plugin");
+                        g.insert("list".into(), serde_yaml::Value::Mapping(list));
+                    }
+                }
+                Some(PluginGenerator::Stub { .. }) => (),
+                None => info!(
+                    "⚠️ ApplicationSet in {} uses plugin generator '{}', which has no response or stub configured",
+                    file, name
+                ),
+            }
+        }
+    }
+
+    // Adds the environment variables to 'plugin.env' of the sources that use a plugin. Variables
+    // the application already sets are kept, and the ones of the application win over the global ones
+    pub fn inject_env(&self, spec: &mut ApplicationSpec, application: &str) {
+        let mut env = self.env.clone();
+        env.extend(
+            self.application_env
+                .get(application)
+                .cloned()
+                .unwrap_or_default(),
+        );
+        if env.is_empty() {
+            return;
+        }
+
+        for plugin in spec.sources_mut().filter_map(|s| s.plugin.as_mut()) {
+            let entries = plugin.env.get_or_insert_with(Vec::new);
+            for (name, value) in &env {
+                if entries.iter().any(|e| &e.name == name) {
+                    continue;
+                }
+                debug!("Setting plugin env {} of application {}", name, application);
+                entries.push(PluginEnv {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+    }
+
+    // Sets the environment variables on all containers of the repo-server, which includes the
+    // sidecars of Config Management Plugins, and waits for the new pods
+    pub async fn set_repo_server_env(&self) -> Result<(), Box<dyn Error>> {
+        if self.repo_server_env.is_empty() {
+            return Ok(());
+        }
+        let variables: Vec<String> = self
+            .repo_server_env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let command = [
+            "kubectl",
+            "set",
+            "env",
+            "deployment/argocd-repo-server",
+            "-n",
+            "argocd",
+            "--containers=*",
+        ]
+        .into_iter()
+        .chain(variables.iter().map(|v| v.as_str()))
+        .collect();
+        if let Err(e) = run_command_from_list(command, None).await {
+            return Err(format!(
+                "failed to set repo-server env: {}",
+                String::from_utf8_lossy(&e.stderr)
+            )
+            .into());
+        }
+        if let Err(e) = run_command(
+            "kubectl rollout status deployment/argocd-repo-server -n argocd --timeout=300s",
+            None,
+        )
+        .await
+        {
+            return Err(format!(
+                "repo-server did not restart: {}",
+                String::from_utf8_lossy(&e.stderr)
+            )
+            .into());
+        }
+        info!(
+            "🔌 Set {} environment variables on the repo-server",
+            variables.len()
+        );
+        Ok(())
+    }
+
+    pub fn has_repo_server_env(&self) -> bool {
+        !self.repo_server_env.is_empty()
+    }
+
+    // Creates the ConfigMaps of the plugins that are pointed at a stub server
+    pub async fn install_stubs(&self) -> Result<(), Box<dyn Error>> {
+        let stubs: Vec<(&String, &String, &String)> = self
+            .generators
+            .iter()
+            .filter_map(|(name, generator)| match generator {
+                PluginGenerator::Stub { base_url, token } => Some((name, base_url, token)),
+                PluginGenerator::Response(_) => None,
+            })
+            .collect();
+        if stubs.is_empty() {
+            return Ok(());
+        }
+
+        let mut tokens = serde_yaml::Mapping::new();
+        let mut manifests = vec![];
+        for (name, base_url, token) in &stubs {
+            tokens.insert(format!("{}.token", name).into(), token.as_str().into());
+            manifests.push(serde_yaml::from_str::<serde_yaml::Value>(&format!(
+                r#"
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: {}
+  namespace: argocd
+data:
+  baseUrl: {}
+  token: "${}:{}.token"
+"#,
+                name, base_url, STUB_SECRET, name
+            ))?);
+        }
+        let mut secret: serde_yaml::Value = serde_yaml::from_str(&format!(
+            r#"
+apiVersion: v1
+kind: Secret
+metadata:
+  name: {}
+  namespace: argocd
+  labels:
+    app.kubernetes.io/part-of: argocd
+"#,
+            STUB_SECRET
+        ))?;
+        secret["stringData"] = serde_yaml::Value::Mapping(tokens);
+        manifests.push(secret);
+
+        let file = workdir::path("plugin-stubs.yaml");
+        fs::write(
+            &file,
+            manifests
+                .iter()
+                .map(serde_yaml::to_string)
+                .collect::<Result<Vec<String>, _>>()?
+                .join("---\n"),
+        )?;
+        if let Err(e) = run_command(&format!("kubectl apply -f {}", file.display()), None).await {
+            return Err(format!(
+                "failed to create plugin stubs: {}",
+                String::from_utf8_lossy(&e.stderr)
+            )
+            .into());
+        }
+        info!(
+            "🔌 Pointed {} plugin generators at stub servers",
+            stubs.len()
+        );
+        Ok(())
+    }
+}
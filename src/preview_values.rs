@@ -0,0 +1,124 @@
+// Engineers want to see what a configuration change does before they commit it. With
+// --preview-values APP=FILE, the Helm values of the file are layered onto the inline values of the
+// application, only in the target branch, so the diff shows their effect. Like inline values, they
+// win over the value files of the application
+use crate::manifests::parse_manifest;
+use crate::model::ApplicationSpec;
+use log::debug;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+pub struct PreviewValues {
+    // Values by the name of the Application or ApplicationSet they are layered onto
+    applications: BTreeMap<String, (String, Value)>,
+}
+
+// Merges 'overlay' into 'base'. Maps are merged key by key, and other values are replaced
+fn merge(base: &mut Value, overlay: &Value) {
+    match (base.as_mapping_mut(), overlay.as_mapping()) {
+        (Some(base), Some(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        _ => *base = overlay.clone(),
+    }
+}
+
+// Parses the APP=FILE pairs and reads the files
+pub fn read_preview_values(pairs: &[String]) -> Result<PreviewValues, Box<dyn Error>> {
+    let mut applications = BTreeMap::new();
+    for pair in pairs {
+        let (name, file) = pair
+            .split_once('=')
+            .filter(|(name, file)| !name.is_empty() && !file.is_empty())
+            .ok_or_else(|| format!("invalid preview values '{}'. Use APP=FILE", pair))?;
+        let content = fs::read_to_string(file)
+            .map_err(|e| format!("failed to read preview values {}: {}", file, e))?;
+        let values: Value = serde_yaml::from_str(&content)
+            .map_err(|e| format!("invalid preview values {}: {}", file, e))?;
+        if !values.is_mapping() {
+            return Err(format!("preview values {} must be a map of values", file).into());
+        }
+        if applications
+            .insert(name.to_string(), (file.to_string(), values))
+            .is_some()
+        {
+            return Err(format!("preview values are given twice for {}", name).into());
+        }
+    }
+    Ok(PreviewValues { applications })
+}
+
+impl PreviewValues {
+    // The applications with preview values and their files
+    pub fn files(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.applications
+            .iter()
+            .map(|(name, (file, _))| (name, file))
+    }
+
+    // Fails when an application with preview values is not among the patched applications of the
+    // target branch, e.g. because of a typo in its name
+    pub fn check_applications(&self, apps_file: &str) -> Result<(), Box<dyn Error>> {
+        let applications = parse_manifest(&fs::read_to_string(apps_file)?);
+        let names: BTreeSet<&str> = applications
+            .iter()
+            .filter_map(|a| a["metadata"]["name"].as_str())
+            .collect();
+        match self
+            .files()
+            .find(|(name, _)| !names.contains(name.as_str()))
+        {
+            Some((name, file)) => Err(format!(
+                "no application {} in the target branch for the preview values of {}",
+                name, file
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    // Layers the values onto the inline values of the Helm sources of the application. Like with
+    // --helm-capabilities, only sources with a chart or Helm parameters are Helm sources for sure
+    pub fn apply(&self, spec: &mut ApplicationSpec, application: &str) -> Result<(), String> {
+        let Some((file, overlay)) = self.applications.get(application) else {
+            return Ok(());
+        };
+        let helm_sources = spec
+            .sources_mut()
+            .filter(|s| s.chart.is_some() || s.helm.is_some());
+        let mut layered = 0;
+        for source in helm_sources {
+            let mut values = source
+                .inline_values()
+                .filter(|v| v.is_mapping())
+                .unwrap_or(Value::Mapping(Default::default()));
+            merge(&mut values, overlay);
+            let helm = source.helm.get_or_insert_with(Default::default);
+            helm.values = None;
+            helm.values_object = Some(values);
+            layered += 1;
+        }
+        match layered {
+            0 => Err(format!(
+                "application {} has no Helm source to layer the preview values of {} onto",
+                application, file
+            )),
+            _ => {
+                debug!(
+                    "Layered the preview values of {} onto {} sources of application {}",
+                    file, layered, application
+                );
+                Ok(())
+            }
+        }
+    }
+}
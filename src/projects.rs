@@ -0,0 +1,342 @@
+// By default, every application is rendered in the 'default' project, which permits every source
+// and destination. With --keep-projects, applications keep their project, so a source that isn't
+// permitted in it fails to render like in production. The AppProjects of the branch are applied
+// before its applications, together with the project-scoped repositories of the config, which the
+// projects need to read private sources. A project that an application refers to, but that is
+// neither in the branch nor in the cluster (e.g. from the secrets folder), is created as a project
+// that permits everything, with a warning
+use crate::config::read_config;
+use crate::manifests::parse_manifest;
+use crate::model::ApplicationSpec;
+use crate::parsing::get_resource_files;
+use crate::repo_url::RepoUrl;
+use crate::sampling;
+use crate::utils::run_command_from_list;
+use crate::warnings::{warn, WarningKind};
+use crate::workdir;
+use crate::{apps_file, Branch};
+use log::{debug, info};
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A repository only the applications of one project may read from
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectRepository {
+    pub project: String,
+    pub url: String,
+    // 'git' or 'helm'
+    #[serde(rename = "type", default = "default_type")]
+    pub repo_type: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn default_type() -> String {
+    "git".to_string()
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub repositories: Vec<ProjectRepository>,
+}
+
+pub fn read_project_config(path: &str) -> Result<ProjectConfig, Box<dyn Error>> {
+    let config: ProjectConfig = serde_yaml::from_value(read_config(path)?)?;
+    debug!("Project-scoped repositories: {}", config.repositories.len());
+    Ok(config)
+}
+
+// The patched AppProjects of the branch, written with its applications
+pub fn projects_file(branch: &Branch) -> PathBuf {
+    workdir::path(&format!("projects_{}_branch.yaml", branch))
+}
+
+// Label of the projects the tool creates, so they aren't mistaken for existing projects in the
+// next diff (--watch)
+const GENERATED_LABEL: &str = "argocd-diff-preview/generated";
+
+// Whether a pattern of 'sourceRepos' (without its '!') matches a repository URL. Like in Argo CD,
+// '*' matches any characters, including '/'. URLs without wildcards match in any notation
+fn repo_pattern_matches(pattern: &str, url: &str) -> bool {
+    if !pattern.contains(['*', '?']) {
+        return RepoUrl::parse(pattern).matches(&RepoUrl::parse(url));
+    }
+    let glob = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    Regex::new(&format!("^{}$", glob)).is_ok_and(|r| r.is_match(url))
+}
+
+// The applications are rendered in the preview cluster, so every destination of a project is also
+// permitted in the in-cluster destination, and clusters don't have to be scoped to the project.
+// Sources are rewritten with --rewrite-repo, so the repositories a project permits (or denies) are
+// permitted (or denied) after the rewrite too
+fn patch_project(project: &Value, repo_rewrites: &[(String, String)]) -> Value {
+    let mut patched = project.clone();
+    let mut metadata = Mapping::new();
+    metadata.insert("name".into(), project["metadata"]["name"].clone());
+    metadata.insert("namespace".into(), "argocd".into());
+    patched["metadata"] = metadata.into();
+    if let Some(spec) = patched["spec"].as_mapping_mut() {
+        spec.remove("permitOnlyProjectScopedClusters");
+        if let Some(Value::Sequence(destinations)) = spec.get_mut("destinations") {
+            let in_cluster: Vec<Value> = destinations
+                .iter()
+                .filter_map(|d| {
+                    let mut destination = Mapping::new();
+                    destination.insert("name".into(), "in-cluster".into());
+                    destination.insert("namespace".into(), d.get("namespace")?.clone());
+                    Some(destination.into())
+                })
+                .collect();
+            for destination in in_cluster {
+                if !destinations.contains(&destination) {
+                    destinations.push(destination);
+                }
+            }
+        }
+    }
+    if let Some(Value::Sequence(repos)) = patched["spec"].get_mut("sourceRepos") {
+        let mut rewritten = vec![];
+        for pattern in repos.iter().filter_map(|r| r.as_str()) {
+            let (deny, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => ("!", pattern),
+                None => ("", pattern),
+            };
+            for (from, to) in repo_rewrites {
+                let from_url = RepoUrl::parse(from);
+                let matches = [
+                    from.to_string(),
+                    format!(
+                        "https://{}/{}",
+                        from_url.host().unwrap_or_default(),
+                        from_url.path()
+                    ),
+                ]
+                .iter()
+                .any(|url| repo_pattern_matches(pattern, url));
+                if matches {
+                    rewritten.push(Value::from(format!("{}{}", deny, to)));
+                }
+            }
+        }
+        for repo in rewritten {
+            if !repos.contains(&repo) {
+                repos.push(repo);
+            }
+        }
+    }
+    if let Some(m) = patched.as_mapping_mut() {
+        m.remove("status");
+    }
+    patched
+}
+
+// A project that permits every source, destination and resource, like the 'default' project
+fn permissive_project(name: &str) -> Value {
+    let project = serde_json::json!({
+        "apiVersion": "argoproj.io/v1alpha1",
+        "kind": "AppProject",
+        "metadata": {
+            "name": name,
+            "namespace": "argocd",
+            "labels": { GENERATED_LABEL: "true" },
+        },
+        "spec": {
+            "sourceRepos": ["*"],
+            "destinations": [
+                { "name": "*", "namespace": "*" },
+                { "server": "*", "namespace": "*" },
+            ],
+            "clusterResourceWhitelist": [{ "group": "*", "kind": "*" }],
+        },
+    });
+    serde_yaml::to_value(project).unwrap_or_default()
+}
+
+// The projects in the cluster that the tool didn't create, e.g. 'default' and the projects of the
+// secrets folder. None when they can't be listed
+async fn existing_projects() -> Option<BTreeSet<String>> {
+    let selector = format!("!{}", GENERATED_LABEL);
+    let output = run_command_from_list(
+        vec![
+            "kubectl",
+            "get",
+            "appprojects",
+            "-n",
+            "argocd",
+            "-l",
+            &selector,
+            "-o",
+            "jsonpath={.items[*].metadata.name}",
+        ],
+        None,
+    )
+    .await
+    .map_err(|e| {
+        debug!(
+            "Failed to list projects: {}",
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })
+    .ok()?;
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .map(|p| p.to_string())
+            .collect(),
+    )
+}
+
+// The projects the applications of the branch refer to, with the name of an application that
+// refers to each. Templated projects of ApplicationSets are only known once they are generated
+fn referenced_projects(branch: &Branch) -> BTreeMap<String, String> {
+    let applications = parse_manifest(&fs::read_to_string(apps_file(branch)).unwrap_or_default());
+    let mut projects = BTreeMap::new();
+    for application in &applications {
+        let Some(project) = ApplicationSpec::of(application)
+            .ok()
+            .and_then(|s| s.project)
+            .filter(|p| !p.is_empty() && !p.contains('{'))
+            // applications that aren't sampled are held in a project that must not exist
+            .filter(|p| p != sampling::SAMPLE_PROJECT)
+        else {
+            continue;
+        };
+        let name = application["metadata"]["name"].as_str().unwrap_or_default();
+        projects.entry(project).or_insert_with(|| name.to_string());
+    }
+    projects
+}
+
+// Writes the AppProjects in the folders of the branch to the projects file of the branch, together
+// with a permissive project for every project the applications of the branch refer to that
+// doesn't exist
+pub async fn write_projects(
+    branch: &Branch,
+    folders: &[String],
+    regex: &Option<Regex>,
+    repo_rewrites: &[(String, String)],
+) -> Result<(), Box<dyn Error>> {
+    let kinds = ["AppProject", "Application", "ApplicationSet"];
+    let mut documents = vec![];
+    let mut found = BTreeSet::new();
+    // the files of the applications, for the warnings
+    let mut files = BTreeMap::new();
+    for folder in folders {
+        for (file, resource) in get_resource_files(folder, regex, &kinds).await {
+            let name = resource["metadata"]["name"].as_str().unwrap_or_default();
+            match resource["kind"].as_str() {
+                Some("AppProject") => {
+                    found.insert(name.to_string());
+                    let project = patch_project(&resource, repo_rewrites);
+                    documents.push(serde_yaml::to_string(&project)?);
+                }
+                _ => {
+                    files.insert(name.to_string(), file);
+                }
+            }
+        }
+    }
+    info!(
+        "🗂️ Found {} AppProjects in the {} branch",
+        documents.len(),
+        branch
+    );
+
+    if let Some(existing) = existing_projects().await {
+        for (project, application) in referenced_projects(branch) {
+            if found.contains(&project) || existing.contains(&project) {
+                continue;
+            }
+            let file = files
+                .get(&application)
+                .map(|f| f.as_str())
+                .unwrap_or_default();
+            warn(
+                WarningKind::MissingProject,
+                file,
+                format!(
+                    "Project '{}' of application '{}' is not in the {} branch or the cluster. It is created without restrictions",
+                    project, application, branch
+                ),
+            );
+            found.insert(project.clone());
+            documents.push(serde_yaml::to_string(&permissive_project(&project))?);
+        }
+    }
+    fs::write(projects_file(branch), documents.join("---\n"))?;
+    Ok(())
+}
+
+fn repository_secret(index: usize, repository: &ProjectRepository) -> Value {
+    let mut data = serde_json::json!({
+        "project": repository.project,
+        "url": repository.url,
+        "type": repository.repo_type,
+    });
+    if let Some(username) = &repository.username {
+        data["username"] = username.clone().into();
+    }
+    if let Some(password) = &repository.password {
+        data["password"] = password.clone().into();
+    }
+    let secret = serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": {
+            "name": format!("project-repository-{}", index),
+            "namespace": "argocd",
+            "labels": { "argocd.argoproj.io/secret-type": "repository" },
+        },
+        "stringData": data,
+    });
+    serde_yaml::to_value(secret).unwrap_or_default()
+}
+
+async fn apply_file(file: &Path) -> Result<(), Box<dyn Error>> {
+    run_command_from_list(
+        vec!["kubectl", "apply", "-f", &file.to_string_lossy()],
+        None,
+    )
+    .await
+    .map_err(|e| {
+        format!(
+            "failed to apply {}: {}",
+            file.display(),
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })?;
+    Ok(())
+}
+
+// Applies the AppProjects of the branch and the project-scoped repositories. Projects of an
+// earlier branch stay in the cluster, so applications of both branches can be rendered
+pub async fn apply_projects(branch: &Branch, config: &ProjectConfig) -> Result<(), Box<dyn Error>> {
+    let projects = projects_file(branch);
+    if fs::read_to_string(&projects).is_ok_and(|p| !p.trim().is_empty()) {
+        apply_file(&projects).await?;
+    }
+    if !config.repositories.is_empty() {
+        let documents = config
+            .repositories
+            .iter()
+            .enumerate()
+            .map(|(i, r)| serde_yaml::to_string(&repository_secret(i, r)))
+            .collect::<Result<Vec<String>, _>>()?;
+        let file = workdir::path("project-repositories.yaml");
+        fs::write(&file, documents.join("---\n"))?;
+        apply_file(&file).await?;
+        info!(
+            "🗂️ Applied {} project-scoped repositories",
+            config.repositories.len()
+        );
+    }
+    Ok(())
+}
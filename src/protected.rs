@@ -0,0 +1,148 @@
+// Resources that must not be removed or changed by accident, like production namespaces or the
+// secrets of a payment provider. A lightweight alternative to policy engines: rules only select
+// resources by kind, name and namespace, and every change to them is called out in the report
+use crate::config::read_config;
+use crate::manifests::{index_resources, read_rendered_apps, ResourceId};
+use crate::Branch;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::error::Error;
+
+struct Rule {
+    kind: String,
+    // Globs of names and namespaces. Any name or namespace matches when empty
+    names: Vec<Regex>,
+    namespaces: Vec<Regex>,
+}
+
+pub struct ProtectedRules {
+    rules: Vec<Rule>,
+}
+
+// Converts a glob with '*' and '?' to a regex matching the whole string
+pub fn glob_to_regex(glob: &str) -> Result<Regex, Box<dyn Error>> {
+    let pattern = regex::escape(glob).replace("\\*", ".*").replace("\\?", ".");
+    Ok(Regex::new(&format!("^{}$", pattern))?)
+}
+
+fn read_globs(rule: &serde_yaml::Value, key: &str) -> Result<Vec<Regex>, Box<dyn Error>> {
+    rule[key]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .map(|g| {
+            let glob = g
+                .as_str()
+                .ok_or_else(|| format!("'{}' must be a list of strings", key))?;
+            glob_to_regex(glob)
+        })
+        .collect()
+}
+
+pub fn read_protected_rules(path: &str) -> Result<ProtectedRules, Box<dyn Error>> {
+    let config: serde_yaml::Value = read_config(path)?;
+    debug!("Protected resources: {:?}", config);
+    let rules = config["protected"]
+        .as_sequence()
+        .ok_or("protected resources have no 'protected' list")?
+        .iter()
+        .map(|rule| {
+            Ok(Rule {
+                kind: rule["kind"]
+                    .as_str()
+                    .ok_or("every protected resource needs a 'kind'")?
+                    .to_string(),
+                names: read_globs(rule, "names")?,
+                namespaces: read_globs(rule, "namespaces")?,
+            })
+        })
+        .collect::<Result<Vec<Rule>, Box<dyn Error>>>()?;
+    Ok(ProtectedRules { rules })
+}
+
+impl ProtectedRules {
+    fn protects(&self, id: &ResourceId) -> bool {
+        let matches = |globs: &[Regex], value: &str| {
+            globs.is_empty() || globs.iter().any(|g| g.is_match(value))
+        };
+        self.rules.iter().any(|rule| {
+            (rule.kind == "*" || rule.kind == id.kind)
+                && matches(&rule.names, &id.name)
+                && matches(&rule.namespaces, &id.namespace)
+        })
+    }
+}
+
+// A protected resource that is removed or changed by an application
+pub struct ProtectedChange {
+    pub resource: ResourceId,
+    pub change: &'static str,
+    pub application: String,
+}
+
+impl std::fmt::Display for ProtectedChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is {} by application {}",
+            self.resource, self.change, self.application
+        )
+    }
+}
+
+// Finds the protected resources that are removed or changed
+pub fn find_changes(output_folder: &str, rules: &ProtectedRules) -> Vec<ProtectedChange> {
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let mut changes = vec![];
+    for name in names {
+        let (before, after) = (base_apps.get(name), target_apps.get(name));
+        if before == after {
+            continue;
+        }
+        let before = index_resources(before.map(|m| m.as_str()).unwrap_or_default());
+        let after = index_resources(after.map(|m| m.as_str()).unwrap_or_default());
+        for (id, resource) in before.iter().filter(|(id, _)| rules.protects(id)) {
+            let change = match after.get(id) {
+                None => "removed",
+                Some(r) if r != resource => "changed",
+                Some(_) => continue,
+            };
+            changes.push(ProtectedChange {
+                resource: id.clone(),
+                change,
+                application: name.clone(),
+            });
+        }
+    }
+    changes
+}
+
+// A warning listing the protected resources that are removed or changed
+pub fn warning(changes: &[ProtectedChange]) -> Option<String> {
+    if changes.is_empty() {
+        info!("🛡️ No protected resources are removed or changed");
+        return None;
+    }
+    info!(
+        "🛡️ {} protected resources are removed or changed",
+        changes.len()
+    );
+    let lines: Vec<String> = changes
+        .iter()
+        .map(|c| {
+            format!(
+                "- `{}` is {} by application {}",
+                c.resource, c.change, c.application
+            )
+        })
+        .collect();
+    Some(format!(
+        "**🛡️ {} protected resources are removed or changed**\n\n{}",
+        changes.len(),
+        lines.join("\n")
+    ))
+}
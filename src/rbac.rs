@@ -0,0 +1,207 @@
+use crate::diff::ReportSection;
+use crate::manifests::{index_resources, read_rendered_apps, ResourceId};
+use crate::Branch;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+
+const ROLE_KINDS: [&str; 2] = ["Role", "ClusterRole"];
+const BINDING_KINDS: [&str; 2] = ["RoleBinding", "ClusterRoleBinding"];
+
+// Verbs that allow privilege escalation, highlighted in the report
+const DANGEROUS_VERBS: [&str; 4] = ["*", "escalate", "bind", "impersonate"];
+
+fn strings(value: &serde_yaml::Value) -> Vec<String> {
+    value
+        .as_sequence()
+        .map(|s| {
+            s.iter()
+                .filter_map(|v| v.as_str().map(|v| v.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Maps each resource (or non-resource URL) a role grants access to, to the verbs allowed on it
+fn permissions(role: &serde_yaml::Value) -> BTreeMap<String, BTreeSet<String>> {
+    let mut permissions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for rule in role["rules"].as_sequence().into_iter().flatten() {
+        let verbs = strings(&rule["verbs"]);
+        let names = match strings(&rule["resourceNames"]) {
+            n if n.is_empty() => "".to_string(),
+            n => format!(" ({})", n.join(", ")),
+        };
+        let mut targets = vec![];
+        for group in strings(&rule["apiGroups"]) {
+            for resource in strings(&rule["resources"]) {
+                targets.push(match group.as_str() {
+                    "" => format!("{}{}", resource, names),
+                    g => format!("{}/{}{}", g, resource, names),
+                });
+            }
+        }
+        for url in strings(&rule["nonResourceURLs"]) {
+            targets.push(format!("url: {}", url));
+        }
+        for target in targets {
+            permissions
+                .entry(target)
+                .or_default()
+                .extend(verbs.iter().cloned());
+        }
+    }
+    permissions
+}
+
+fn subjects(binding: &serde_yaml::Value) -> BTreeSet<String> {
+    binding["subjects"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .map(|s| {
+            let kind = s["kind"].as_str().unwrap_or_default();
+            let name = s["name"].as_str().unwrap_or_default();
+            match s["namespace"].as_str() {
+                Some(ns) => format!("{} {}/{}", kind, ns, name),
+                None => format!("{} {}", kind, name),
+            }
+        })
+        .collect()
+}
+
+fn role_ref(binding: &serde_yaml::Value) -> String {
+    format!(
+        "{}/{}",
+        binding["roleRef"]["kind"].as_str().unwrap_or_default(),
+        binding["roleRef"]["name"].as_str().unwrap_or_default()
+    )
+}
+
+fn format_verbs(verbs: &BTreeSet<String>) -> String {
+    let verbs = verbs.iter().cloned().collect::<Vec<String>>().join(", ");
+    match DANGEROUS_VERBS
+        .iter()
+        .any(|d| verbs.split(", ").any(|v| v == *d))
+    {
+        true => format!("`{}` ⚠️", verbs),
+        false => format!("`{}`", verbs),
+    }
+}
+
+fn role_changes(
+    base: Option<&serde_yaml::Value>,
+    target: Option<&serde_yaml::Value>,
+) -> Vec<String> {
+    let base = base.map(permissions).unwrap_or_default();
+    let target = target.map(permissions).unwrap_or_default();
+    let resources: BTreeSet<&String> = base.keys().chain(target.keys()).collect();
+
+    let mut lines = vec![];
+    for resource in resources {
+        let empty = BTreeSet::new();
+        let before = base.get(resource).unwrap_or(&empty);
+        let after = target.get(resource).unwrap_or(&empty);
+        let added: BTreeSet<String> = after.difference(before).cloned().collect();
+        let removed: BTreeSet<String> = before.difference(after).cloned().collect();
+        if !added.is_empty() {
+            lines.push(format!("  - ➕ {} on `{}`", format_verbs(&added), resource));
+        }
+        if !removed.is_empty() {
+            lines.push(format!(
+                "  - ➖ {} on `{}`",
+                format_verbs(&removed),
+                resource
+            ));
+        }
+    }
+    lines
+}
+
+fn binding_changes(
+    base: Option<&serde_yaml::Value>,
+    target: Option<&serde_yaml::Value>,
+) -> Vec<String> {
+    let mut lines = vec![];
+    let (base_ref, target_ref) = (base.map(role_ref), target.map(role_ref));
+    match (&base_ref, &target_ref) {
+        (Some(b), Some(t)) if b != t => lines.push(format!("  - 🔄 role `{}` → `{}`", b, t)),
+        (None, Some(t)) => lines.push(format!("  - ➕ role `{}`", t)),
+        (Some(b), None) => lines.push(format!("  - ➖ role `{}`", b)),
+        _ => (),
+    }
+
+    let before = base.map(subjects).unwrap_or_default();
+    let after = target.map(subjects).unwrap_or_default();
+    for subject in after.difference(&before) {
+        lines.push(format!("  - ➕ subject `{}`", subject));
+    }
+    for subject in before.difference(&after) {
+        lines.push(format!("  - ➖ subject `{}`", subject));
+    }
+    lines
+}
+
+pub fn generate_rbac_section(output_folder: &str) -> Option<ReportSection> {
+    info!("🔐 Looking for RBAC changes");
+
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let is_rbac = |id: &ResourceId| {
+        ROLE_KINDS.contains(&id.kind.as_str()) || BINDING_KINDS.contains(&id.kind.as_str())
+    };
+
+    let mut blocks = vec![];
+    for name in names {
+        let base = index_resources(base_apps.get(name).map(|m| m.as_str()).unwrap_or_default());
+        let target = index_resources(
+            target_apps
+                .get(name)
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+        );
+        let ids: BTreeSet<&ResourceId> = base
+            .keys()
+            .chain(target.keys())
+            .filter(|id| is_rbac(id))
+            .collect();
+
+        for id in ids {
+            let (before, after) = (base.get(id), target.get(id));
+            if before == after {
+                continue;
+            }
+            let lines = match ROLE_KINDS.contains(&id.kind.as_str()) {
+                true => role_changes(before, after),
+                false => binding_changes(before, after),
+            };
+            let status = match (before, after) {
+                (None, _) => "added",
+                (_, None) => "removed",
+                _ => "modified",
+            };
+            if lines.is_empty() && status == "modified" {
+                continue;
+            }
+            blocks.push(format!(
+                "- **{}**: `{}` ({})\n{}",
+                name,
+                id,
+                status,
+                lines.join("\n")
+            ));
+        }
+    }
+
+    if blocks.is_empty() {
+        info!("🔐 No RBAC changes found");
+        return None;
+    }
+
+    info!("🔐 Found {} RBAC changes", blocks.len());
+
+    Some(ReportSection {
+        title: format!("🔐 RBAC changes ({}):", blocks.len()),
+        content: blocks.join("\n"),
+    })
+}
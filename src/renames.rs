@@ -0,0 +1,179 @@
+// A resource that is renamed within an application (e.g. Deployment 'api' becomes 'api-v2') shows up
+// as a removed and an added resource, so the whole resource is in the diff twice. A removed and an
+// added resource of the same kind are paired when most of their fields are the same, and the
+// application is diffed with the pairs next to each other, so only the name and the fields that
+// changed with it show up
+use crate::manifests::{flatten, resource_id, ResourceId};
+use log::debug;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub from: ResourceId,
+    pub to: ResourceId,
+    // Share of the fields of both resources that are the same, from 0 to 1
+    pub similarity: f64,
+}
+
+impl std::fmt::Display for Rename {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} renamed to {} ({:.0}% similar)",
+            self.from,
+            self.to,
+            self.similarity * 100.0
+        )
+    }
+}
+
+// The documents of a manifest as they are written, so the diff keeps their formatting
+fn documents(manifest: &str) -> Vec<String> {
+    let mut documents = vec![String::new()];
+    for line in manifest.lines() {
+        match line {
+            "---" => documents.push(String::new()),
+            line => {
+                let document = documents.last_mut().unwrap();
+                document.push_str(line);
+                document.push('\n');
+            }
+        }
+    }
+    documents
+        .into_iter()
+        .filter(|d| !d.trim().is_empty())
+        .collect()
+}
+
+// The fields of a resource without its name. Values that contain the name, like the labels of a
+// selector, are compared without it
+fn fields(resource: &Value) -> BTreeSet<(String, String)> {
+    let name = resource_id(resource).name;
+    let mut flat = BTreeMap::new();
+    flatten(resource, "", &mut flat);
+    flat.remove("metadata.name");
+    flat.into_iter()
+        .map(|(path, value)| match name.is_empty() {
+            true => (path, value),
+            false => (path, value.replace(&name, "\0")),
+        })
+        .collect()
+}
+
+fn similarity(a: &BTreeSet<(String, String)>, b: &BTreeSet<(String, String)>) -> f64 {
+    match a.len() + b.len() {
+        0 => 1.0,
+        total => 2.0 * a.intersection(b).count() as f64 / total as f64,
+    }
+}
+
+// Pairs the removed and added resources of the same kind with at least 'min_similarity' of their
+// fields in common, the most similar pairs first
+pub fn find_renames(base: &[Value], target: &[Value], min_similarity: f64) -> Vec<Rename> {
+    let index = |resources: &[Value]| -> BTreeMap<ResourceId, BTreeSet<(String, String)>> {
+        resources
+            .iter()
+            .map(|r| (resource_id(r), fields(r)))
+            .collect()
+    };
+    let (before, after) = (index(base), index(target));
+    let removed: Vec<_> = before
+        .iter()
+        .filter(|(id, _)| !after.contains_key(id))
+        .collect();
+    let added: Vec<_> = after
+        .iter()
+        .filter(|(id, _)| !before.contains_key(id))
+        .collect();
+
+    let mut candidates = vec![];
+    for (from, a) in &removed {
+        for (to, b) in added.iter().filter(|(to, _)| to.kind == from.kind) {
+            let similarity = similarity(a, b);
+            if similarity >= min_similarity {
+                candidates.push((similarity, *from, *to));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let (mut paired_from, mut paired_to) = (BTreeSet::new(), BTreeSet::new());
+    let mut renames = vec![];
+    for (similarity, from, to) in candidates {
+        if paired_from.contains(from) || paired_to.contains(to) {
+            continue;
+        }
+        paired_from.insert(from);
+        paired_to.insert(to);
+        renames.push(Rename {
+            from: from.clone(),
+            to: to.clone(),
+            similarity,
+        });
+    }
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+    renames
+}
+
+// Writes the manifests of an application to 'folder' with the renamed resources moved to the end,
+// in the same order in both branches, so the diff shows each pair as one changed resource. Returns
+// the renamed resources, and writes nothing when there are none
+pub fn align_renames(
+    source_folder: &str,
+    folder: &Path,
+    files: [(&str, &str); 2],
+    min_similarity: f64,
+) -> Result<Vec<Rename>, Box<dyn Error>> {
+    let [base, target] = files.map(|(branch, file)| {
+        let manifest = fs::read_to_string(Path::new(source_folder).join(branch).join(file));
+        let documents: Vec<(String, Value)> = documents(&manifest.unwrap_or_default())
+            .into_iter()
+            .map(|d| {
+                let resource = serde_yaml::from_str(&d).unwrap_or_default();
+                (d, resource)
+            })
+            .collect();
+        documents
+    });
+    let resources = |documents: &[(String, Value)]| -> Vec<Value> {
+        documents
+            .iter()
+            .map(|(_, r)| r.clone())
+            .filter(|r: &Value| r["kind"].as_str().is_some())
+            .collect()
+    };
+    let renames = find_renames(&resources(&base), &resources(&target), min_similarity);
+    if renames.is_empty() {
+        return Ok(renames);
+    }
+    debug!("Renamed resources: {:?}", renames);
+
+    let align = |documents: &[(String, Value)], ids: Vec<&ResourceId>| -> String {
+        let (mut kept, mut moved) = (vec![], vec![None; ids.len()]);
+        for (document, resource) in documents {
+            match ids.iter().position(|id| **id == resource_id(resource)) {
+                Some(i) => moved[i] = Some(document.clone()),
+                None => kept.push(document.clone()),
+            }
+        }
+        kept.into_iter()
+            .chain(moved.into_iter().flatten())
+            .collect::<Vec<String>>()
+            .join("---\n")
+    };
+    let manifests = [
+        align(&base, renames.iter().map(|r| &r.from).collect()),
+        align(&target, renames.iter().map(|r| &r.to).collect()),
+    ];
+    for ((branch, file), manifest) in files.iter().zip(manifests) {
+        let folder = folder.join(branch);
+        fs::create_dir_all(&folder)?;
+        fs::write(folder.join(file), manifest)?;
+    }
+    Ok(renames)
+}
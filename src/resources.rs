@@ -0,0 +1,229 @@
+// Helpers and report sections for the compute resources of rendered workloads
+use crate::diff::ReportSection;
+use crate::manifests::{flatten, index_resources, read_rendered_apps};
+use crate::Branch;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Parses a Kubernetes CPU quantity (e.g. "500m", "2") into cores
+pub fn parse_cpu(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    match quantity.strip_suffix('m') {
+        Some(millis) => millis.parse::<f64>().ok().map(|m| m / 1000.0),
+        None => quantity.parse::<f64>().ok(),
+    }
+}
+
+// Parses a Kubernetes memory quantity (e.g. "128Mi", "1G") into bytes
+pub fn parse_memory(quantity: &str) -> Option<f64> {
+    const SUFFIXES: [(&str, f64); 12] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Pi", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Ei", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+    let quantity = quantity.trim();
+    for (suffix, factor) in SUFFIXES {
+        if let Some(value) = quantity.strip_suffix(suffix) {
+            return value.parse::<f64>().ok().map(|v| v * factor);
+        }
+    }
+    quantity.parse::<f64>().ok()
+}
+
+// Reads a quantity that may be written as a YAML string or number
+fn quantity(value: &serde_yaml::Value, parse: fn(&str) -> Option<f64>) -> Option<f64> {
+    match value {
+        serde_yaml::Value::String(s) => parse(s),
+        serde_yaml::Value::Number(n) => n.as_f64(),
+        _ => None,
+    }
+}
+
+pub fn format_cpu(cores: f64) -> String {
+    if cores != 0.0 && cores.abs() < 1.0 {
+        format!("{}m", (cores * 1000.0).round())
+    } else {
+        format!("{}", (cores * 1000.0).round() / 1000.0)
+    }
+}
+
+pub fn format_memory(bytes: f64) -> String {
+    const UNITS: [(&str, f64); 4] = [
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Ki", 1024.0),
+    ];
+    for (unit, factor) in UNITS {
+        if bytes.abs() >= factor {
+            return format!("{}{}", (bytes / factor * 100.0).round() / 100.0, unit);
+        }
+    }
+    format!("{}", bytes)
+}
+
+// Formats a delta with an explicit sign
+pub fn signed(value: f64, format: fn(f64) -> String) -> String {
+    match value {
+        v if v > 0.0 => format!("+{}", format(v)),
+        v if v < 0.0 => format!("-{}", format(-v)),
+        _ => "0".to_string(),
+    }
+}
+
+// Returns the pod spec and number of pods of a workload resource
+pub fn pod_spec(resource: &serde_yaml::Value) -> Option<(&serde_yaml::Value, f64)> {
+    let replicas = |field: &serde_yaml::Value| field.as_f64().unwrap_or(1.0);
+    match resource["kind"].as_str()? {
+        "Deployment" | "StatefulSet" | "ReplicaSet" | "ReplicationController" | "Rollout" => {
+            Some((
+                &resource["spec"]["template"]["spec"],
+                replicas(&resource["spec"]["replicas"]),
+            ))
+        }
+        "DaemonSet" => Some((&resource["spec"]["template"]["spec"], 1.0)),
+        "Job" => Some((
+            &resource["spec"]["template"]["spec"],
+            replicas(&resource["spec"]["parallelism"]),
+        )),
+        "CronJob" => Some((
+            &resource["spec"]["jobTemplate"]["spec"]["template"]["spec"],
+            replicas(&resource["spec"]["jobTemplate"]["spec"]["parallelism"]),
+        )),
+        "Pod" => Some((&resource["spec"], 1.0)),
+        _ => None,
+    }
+}
+
+// Total CPU (cores) and memory (bytes) of a field (e.g. "requests" or "limits") across all pods of a workload
+pub fn workload_resources(resource: &serde_yaml::Value, field: &str) -> Option<(f64, f64)> {
+    let (spec, pods) = pod_spec(resource)?;
+    let containers = spec["containers"].as_sequence()?;
+    let (cpu, memory) = containers.iter().fold((0.0, 0.0), |(cpu, memory), c| {
+        let resources = &c["resources"][field];
+        (
+            cpu + quantity(&resources["cpu"], parse_cpu).unwrap_or_default(),
+            memory + quantity(&resources["memory"], parse_memory).unwrap_or_default(),
+        )
+    });
+    Some((cpu * pods, memory * pods))
+}
+
+// Returns the fields of a resource that affect scheduling and scaling, keyed by a readable path
+fn scaling_fields(resource: &serde_yaml::Value) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    match resource["kind"].as_str() {
+        Some("HorizontalPodAutoscaler") => {
+            flatten(&resource["spec"]["minReplicas"], "minReplicas", &mut fields);
+            flatten(&resource["spec"]["maxReplicas"], "maxReplicas", &mut fields);
+        }
+        Some("PodDisruptionBudget") => {
+            flatten(
+                &resource["spec"]["minAvailable"],
+                "minAvailable",
+                &mut fields,
+            );
+            flatten(
+                &resource["spec"]["maxUnavailable"],
+                "maxUnavailable",
+                &mut fields,
+            );
+        }
+        Some("ResourceQuota") => flatten(&resource["spec"]["hard"], "hard", &mut fields),
+        Some("LimitRange") => {
+            for limit in resource["spec"]["limits"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+            {
+                let path = format!("limits[{}]", limit["type"].as_str().unwrap_or_default());
+                let mut limit = limit.clone();
+                limit.as_mapping_mut().map(|m| m.remove("type"));
+                flatten(&limit, &path, &mut fields);
+            }
+        }
+        _ => {
+            if let Some((spec, _)) = pod_spec(resource) {
+                if !resource["spec"]["replicas"].is_null() {
+                    flatten(&resource["spec"]["replicas"], "replicas", &mut fields);
+                }
+                for container in spec["containers"].as_sequence().into_iter().flatten() {
+                    let path = format!(
+                        "containers[{}].resources",
+                        container["name"].as_str().unwrap_or_default()
+                    );
+                    flatten(&container["resources"], &path, &mut fields);
+                }
+            }
+        }
+    }
+    fields.retain(|_, v| v != "null");
+    fields
+}
+
+pub fn generate_resource_changes_section(output_folder: &str) -> Option<ReportSection> {
+    info!("📏 Looking for changes to resources, replicas and autoscaling");
+
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let mut rows = vec![];
+    for name in names {
+        let base = index_resources(base_apps.get(name).map(|m| m.as_str()).unwrap_or_default());
+        let target = index_resources(
+            target_apps
+                .get(name)
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+        );
+        let ids: BTreeSet<_> = base.keys().chain(target.keys()).collect();
+        for id in ids {
+            let base_fields = base.get(id).map(scaling_fields).unwrap_or_default();
+            let target_fields = target.get(id).map(scaling_fields).unwrap_or_default();
+            let paths: BTreeSet<&String> = base_fields.keys().chain(target_fields.keys()).collect();
+            for path in paths {
+                let (before, after) = (base_fields.get(path), target_fields.get(path));
+                if before != after {
+                    rows.push(format!(
+                        "| {} | {} | {} | {} | {} |",
+                        name,
+                        id,
+                        path,
+                        before.map(|v| v.as_str()).unwrap_or("-"),
+                        after.map(|v| v.as_str()).unwrap_or("-"),
+                    ));
+                }
+            }
+        }
+    }
+
+    if rows.is_empty() {
+        info!("📏 No changes to resources, replicas or autoscaling found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!(
+            "Resource, replica and autoscaling changes ({}):",
+            rows.len()
+        ),
+        content: [
+            "| Application | Resource | Field | Base | Target |".to_string(),
+            "|---|---|---|---|---|".to_string(),
+        ]
+        .into_iter()
+        .chain(rows)
+        .collect::<Vec<String>>()
+        .join("\n"),
+    })
+}
@@ -0,0 +1,133 @@
+// A run that fails to render some applications (e.g. a flaky chart repository) writes 'run-info.json'
+// to the output folder. '--retry-failed' reads it and only renders the applications that have no
+// manifests yet, keeping the manifests of the failed run, so the report covers all applications
+use crate::manifests::{parse_manifest, read_applications};
+use crate::Branch;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+const RUN_INFO_FILE: &str = "run-info.json";
+
+pub struct RunInfo {
+    base: BTreeSet<String>,
+    target: BTreeSet<String>,
+}
+
+impl RunInfo {
+    // The applications of a branch that were rendered by the failed run
+    pub fn rendered(&self, branch: &Branch) -> &BTreeSet<String> {
+        match branch {
+            Branch::Base => &self.base,
+            Branch::Target => &self.target,
+        }
+    }
+}
+
+fn rendered_apps(output_folder: &str, branch: &Branch) -> BTreeSet<String> {
+    fs::read_dir(format!("{}/{}", output_folder, branch))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect()
+}
+
+// Records which applications were rendered, and the errors of the ones that failed in 'branch'
+pub fn write_run_info(
+    output_folder: &str,
+    branch: &Branch,
+    failed: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let info = serde_json::json!({
+        "failedBranch": branch.to_string(),
+        "failedApplications": failed,
+        "renderedApplications": {
+            "base": rendered_apps(output_folder, &Branch::Base),
+            "target": rendered_apps(output_folder, &Branch::Target),
+        },
+    });
+    let path = format!("{}/{}", output_folder, RUN_INFO_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&info)?)?;
+    info!(
+        "📝 Wrote {}. Rerun with --retry-failed {} to only render the missing applications",
+        path, path
+    );
+    Ok(())
+}
+
+// A finished run has nothing to retry
+pub fn remove_run_info(output_folder: &str) {
+    fs::remove_file(format!("{}/{}", output_folder, RUN_INFO_FILE)).unwrap_or_default();
+}
+
+// Reads the run info of a failed run. Applications whose manifests were removed from the output
+// folder since are rendered again
+pub fn read_run_info(path: &str, output_folder: &str) -> Result<RunInfo, Box<dyn Error>> {
+    let info: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let rendered = |branch: &Branch| -> Result<BTreeSet<String>, Box<dyn Error>> {
+        let names = info["renderedApplications"][branch.to_string()]
+            .as_array()
+            .ok_or_else(|| format!("{} has no rendered applications of {}", path, branch))?;
+        let existing = rendered_apps(output_folder, branch);
+        Ok(names
+            .iter()
+            .filter_map(|n| n.as_str())
+            .filter(|n| existing.contains(*n))
+            .map(|n| n.to_string())
+            .collect())
+    };
+    let failed: Vec<&String> = info["failedApplications"]
+        .as_object()
+        .into_iter()
+        .flat_map(|f| f.keys())
+        .collect();
+    info!(
+        "🔁 Retrying the applications that weren't rendered. Failed before: {}",
+        failed
+            .iter()
+            .map(|f| f.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+    Ok(RunInfo {
+        base: rendered(&Branch::Base)?,
+        target: rendered(&Branch::Target)?,
+    })
+}
+
+// Removes the Applications that are already rendered from an applications file. ApplicationSets are
+// kept, since the Applications they generate are only known once they are applied
+pub fn remove_rendered(apps_file: &str, rendered: &BTreeSet<String>) -> Result<(), Box<dyn Error>> {
+    let applications: Vec<serde_yaml::Value> = parse_manifest(&fs::read_to_string(apps_file)?)
+        .into_iter()
+        .filter(|a| {
+            a["kind"].as_str() != Some("Application")
+                || !rendered.contains(a["metadata"]["name"].as_str().unwrap_or_default())
+        })
+        .collect();
+    let mut out = String::new();
+    for a in &applications {
+        out.push_str(&serde_yaml::to_string(a)?);
+        out.push_str("---\n");
+    }
+    fs::write(apps_file, out)?;
+    Ok(())
+}
+
+// The Applications of a previous attempt, merged with the ones rendered now
+pub fn merge_applications(
+    output_folder: &str,
+    branch: &Branch,
+    applications: &[serde_yaml::Value],
+) -> Vec<serde_yaml::Value> {
+    let mut merged = read_applications(output_folder, branch);
+    for a in applications {
+        if let Some(name) = a["metadata"]["name"].as_str() {
+            merged.insert(name.to_string(), a.clone());
+        }
+    }
+    merged.into_values().collect()
+}
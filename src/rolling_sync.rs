@@ -0,0 +1,158 @@
+// ApplicationSets with the RollingSync strategy update their Applications in steps, selected by the
+// labels of the Applications. A pull request that moves an Application to another step changes the
+// order in which production is rolled out, which the diff of the manifests doesn't show
+use crate::diff::ReportSection;
+use crate::manifests::{read_applications, read_rendered_apps};
+use crate::parsing::get_resources_of_kinds;
+use crate::selector::{Operator, Selector};
+use crate::Branch;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+
+// The steps of an ApplicationSet, each with the label requirements an Application must match
+type Steps = Vec<Vec<Selector>>;
+
+fn read_expression(expression: &serde_yaml::Value) -> Option<Selector> {
+    let operator = match expression["operator"].as_str()? {
+        "In" => Operator::In,
+        "NotIn" => Operator::NotIn,
+        "Exists" => Operator::Exists,
+        "DoesNotExist" => Operator::DoesNotExist,
+        _ => return None,
+    };
+    Some(Selector {
+        key: expression["key"].as_str()?.to_string(),
+        operator,
+        values: expression["values"]
+            .as_sequence()
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str().map(|v| v.to_string()))
+            .collect(),
+    })
+}
+
+async fn read_steps(folders: &[String], regex: &Option<Regex>) -> BTreeMap<String, Steps> {
+    let mut steps = BTreeMap::new();
+    for folder in folders {
+        for set in get_resources_of_kinds(folder, regex, &["ApplicationSet"]).await {
+            let strategy = &set["spec"]["strategy"];
+            if strategy["type"].as_str() != Some("RollingSync") {
+                continue;
+            }
+            let Some(name) = set["metadata"]["name"].as_str() else {
+                continue;
+            };
+            let set_steps: Steps = strategy["rollingSync"]["steps"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .map(|step| {
+                    step["matchExpressions"]
+                        .as_sequence()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(read_expression)
+                        .collect()
+                })
+                .collect();
+            debug!("ApplicationSet {} has {} steps", name, set_steps.len());
+            steps.insert(name.to_string(), set_steps);
+        }
+    }
+    steps
+}
+
+// The ApplicationSet and the step (counted from 1) of every Application generated by a RollingSync
+// ApplicationSet. Like in Argo CD, an Application belongs to the first step it matches, and to no
+// step when it matches none
+fn assign_steps(
+    output_folder: &str,
+    branch: &Branch,
+    steps: &BTreeMap<String, Steps>,
+) -> BTreeMap<String, (String, Option<usize>)> {
+    read_applications(output_folder, branch)
+        .into_iter()
+        .filter_map(|(name, application)| {
+            let owner = application["metadata"]["ownerReferences"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .find(|o| o["kind"].as_str() == Some("ApplicationSet"))?["name"]
+                .as_str()?
+                .to_string();
+            let labels = &application["metadata"]["labels"];
+            let step = steps.get(&owner)?.iter().position(|requirements| {
+                requirements
+                    .iter()
+                    .all(|r| r.matches(labels[r.key.as_str()].as_str()))
+            });
+            Some((name, (owner, step.map(|s| s + 1))))
+        })
+        .collect()
+}
+
+// Lists the step of every changed Application generated by a RollingSync ApplicationSet, and of every
+// Application that moved to another step
+pub async fn generate_rolling_sync_section(
+    output_folder: &str,
+    base_folders: &[String],
+    target_folders: &[String],
+    regex: &Option<Regex>,
+) -> Option<ReportSection> {
+    let base_steps = read_steps(base_folders, regex).await;
+    let target_steps = read_steps(target_folders, regex).await;
+    if base_steps.is_empty() && target_steps.is_empty() {
+        return None;
+    }
+    info!("🪜 Looking up the rollout steps of applications");
+
+    let base = assign_steps(output_folder, &Branch::Base, &base_steps);
+    let target = assign_steps(output_folder, &Branch::Target, &target_steps);
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+
+    let step_text = |assignment: Option<&(String, Option<usize>)>| match assignment {
+        None => "-".to_string(),
+        Some((_, None)) => "none".to_string(),
+        Some((_, Some(step))) => step.to_string(),
+    };
+
+    let names: BTreeSet<&String> = base.keys().chain(target.keys()).collect();
+    let mut rows = vec![];
+    let mut moved = 0;
+    for name in names {
+        let (before, after) = (base.get(name), target.get(name));
+        let step_changed = step_text(before) != step_text(after);
+        if !step_changed && base_apps.get(name) == target_apps.get(name) {
+            continue;
+        }
+        let (set, _) = after.or(before)?;
+        let mut after_text = step_text(after);
+        if step_changed {
+            moved += 1;
+            after_text = format!("⚠️ {}", after_text);
+        }
+        rows.push(format!(
+            "| {} | {} | {} | {} |",
+            name,
+            set,
+            step_text(before),
+            after_text
+        ));
+    }
+
+    if rows.is_empty() {
+        info!("🪜 No changed applications in RollingSync ApplicationSets");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!("🪜 Rollout steps ({} step changes):", moved),
+        content: format!(
+            "| Application | ApplicationSet | Base step | Target step |\n|---|---|---|---|\n{}",
+            rows.join("\n")
+        ),
+    })
+}
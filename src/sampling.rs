@@ -0,0 +1,360 @@
+// ApplicationSets of a fleet generate hundreds of near-identical Applications. With --appset-sample,
+// the Applications are generated in a project that doesn't exist, so Argo CD doesn't render them.
+// Once an ApplicationSet has generated its Applications, the ones whose spec differs between the
+// branches and N of the others are moved back to their project and rendered. The same Applications
+// are sampled in both branches, so the ones left out are missing from both and not in the diff.
+// The Applications of the target branch are generated before the base branch is rendered, so both
+// branches know the Applications of the other one
+use crate::diff::ReportSection;
+use crate::manifests::parse_manifest;
+use crate::utils::{run_command, run_command_from_list};
+use crate::{apps_file, workdir, Branch};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Project the generated Applications are held in until they are sampled
+pub const SAMPLE_PROJECT: &str = "argocd-diff-preview-sample";
+// Annotation with the project of a generated Application, which the ApplicationSet templates too
+const PROJECT_ANNOTATION: &str = "argocd-diff-preview/project";
+// Seconds to wait for the ApplicationSets of the target branch to generate their Applications
+const GENERATE_TIMEOUT: u64 = 120;
+
+pub struct AppSetSample {
+    // Applications rendered per ApplicationSet, besides the ones that changed
+    pub size: usize,
+    // Revisions the sources are pointed to, which differ between the branches
+    pub revisions: Vec<String>,
+}
+
+// A generated Application, as recorded for the other branch
+#[derive(Debug, Serialize, Deserialize)]
+struct Generated {
+    appset: String,
+    spec: Value,
+    selected: bool,
+}
+
+// Makes the ApplicationSet generate its Applications in the sample project. The ApplicationSet
+// controller must not move them back when they are sampled, so the project is an ignored difference
+pub fn hold_generated_applications(appset: &mut Value) {
+    let project = appset["spec"]["template"]["spec"]["project"]
+        .as_str()
+        .unwrap_or("default")
+        .to_string();
+    appset["spec"]["template"]["metadata"]["annotations"][PROJECT_ANNOTATION] = project.into();
+    appset["spec"]["template"]["spec"]["project"] = SAMPLE_PROJECT.into();
+    let ignored: Value = serde_yaml::from_str("jsonPointers: [/spec/project]").unwrap_or_default();
+    match appset["spec"]["ignoreApplicationDifferences"].as_sequence_mut() {
+        Some(differences) => differences.push(ignored),
+        None => appset["spec"]["ignoreApplicationDifferences"] = vec![ignored].into(),
+    }
+}
+
+// Whether the Application is waiting to be sampled
+pub fn is_held(application: &Value) -> bool {
+    application["spec"]["project"].as_str() == Some(SAMPLE_PROJECT)
+}
+
+fn name(application: &Value) -> &str {
+    application["metadata"]["name"].as_str().unwrap_or_default()
+}
+
+fn owner(application: &Value) -> Option<&str> {
+    application["metadata"]["ownerReferences"]
+        .as_sequence()?
+        .iter()
+        .find(|o| o["kind"].as_str() == Some("ApplicationSet"))?["name"]
+        .as_str()
+}
+
+fn record_file(branch: &Branch) -> PathBuf {
+    workdir::path(&format!("generated_{}_branch.json", branch))
+}
+
+fn read_record(branch: &Branch) -> BTreeMap<String, Generated> {
+    fs::read_to_string(record_file(branch))
+        .ok()
+        .and_then(|r| serde_json::from_str(&r).ok())
+        .unwrap_or_default()
+}
+
+fn write_record(
+    branch: &Branch,
+    record: &BTreeMap<String, Generated>,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(record_file(branch), serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+impl AppSetSample {
+    // The spec without the project and the revisions of the branch, which differ between the
+    // branches for every Application
+    fn normalized_spec(&self, application: &Value) -> Value {
+        let mut spec = application["spec"].clone();
+        if let Some(m) = spec.as_mapping_mut() {
+            m.remove("project");
+        }
+        let mut sources: Vec<&mut Value> = vec![];
+        if let Some(m) = spec.as_mapping_mut() {
+            for (key, value) in m.iter_mut() {
+                match key.as_str() {
+                    Some("source") => sources.push(value),
+                    Some("sources") => {
+                        sources.extend(value.as_sequence_mut().into_iter().flatten())
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for source in sources {
+            let revision = source["targetRevision"].as_str().unwrap_or_default();
+            if self.revisions.iter().any(|r| r == revision) {
+                source.as_mapping_mut().map(|m| m.remove("targetRevision"));
+            }
+        }
+        spec
+    }
+
+    // The Applications of an ApplicationSet that are rendered: the ones whose spec differs from
+    // the other branch, and the first ones by name of the others
+    fn sample(
+        &self,
+        generated: &BTreeMap<String, Value>,
+        other: &BTreeMap<String, Generated>,
+    ) -> BTreeSet<String> {
+        let (unchanged, changed): (Vec<_>, Vec<_>) = generated
+            .iter()
+            .partition(|(name, spec)| other.get(*name).is_some_and(|o| &o.spec == *spec));
+        changed
+            .into_iter()
+            .chain(unchanged.into_iter().take(self.size))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    // Decides which of the held Applications are rendered, once their ApplicationSet has generated
+    // all of its Applications. Applications that were decided before are left out
+    pub async fn select<'a>(
+        &self,
+        branch: &Branch,
+        applications: &'a [Value],
+        decided: &HashSet<String>,
+    ) -> Result<Vec<(&'a Value, bool)>, Box<dyn Error>> {
+        let held: Vec<&Value> = applications
+            .iter()
+            .filter(|a| is_held(a) && !decided.contains(name(a)))
+            .collect();
+        if held.is_empty() {
+            return Ok(vec![]);
+        }
+        let other = read_record(&match branch {
+            Branch::Base => Branch::Target,
+            Branch::Target => Branch::Base,
+        });
+        let mut record = read_record(branch);
+        let mut decisions = vec![];
+        for appset in generated_appsets().await? {
+            let waiting: Vec<&Value> = held
+                .iter()
+                .copied()
+                .filter(|a| owner(a) == Some(appset.as_str()))
+                .collect();
+            if waiting.is_empty() {
+                continue;
+            }
+            let generated: BTreeMap<String, Value> = applications
+                .iter()
+                .filter(|a| owner(a) == Some(appset.as_str()))
+                .map(|a| (name(a).to_string(), self.normalized_spec(a)))
+                .collect();
+            let sample = self.sample(&generated, &other);
+            info!(
+                "🎲 Rendering {} of {} applications of ApplicationSet {}",
+                sample.len(),
+                generated.len(),
+                appset
+            );
+            for (name, spec) in generated {
+                let selected = sample.contains(&name);
+                let appset = appset.clone();
+                record.insert(
+                    name,
+                    Generated {
+                        appset,
+                        spec,
+                        selected,
+                    },
+                );
+            }
+            for a in waiting {
+                decisions.push((a, sample.contains(name(a))));
+            }
+        }
+        write_record(branch, &record)?;
+        Ok(decisions)
+    }
+}
+
+// Moves a sampled Application back to its project, so it is rendered
+pub async fn release(application: &Value) -> Result<(), Box<dyn Error>> {
+    let project = application["metadata"]["annotations"][PROJECT_ANNOTATION]
+        .as_str()
+        .unwrap_or("default");
+    let patch = serde_json::json!({ "spec": { "project": project } }).to_string();
+    let command = vec![
+        "kubectl",
+        "patch",
+        "applications.argoproj.io",
+        name(application),
+        "-n",
+        "argocd",
+        "--type",
+        "merge",
+        "-p",
+        &patch,
+    ];
+    run_command_from_list(command, None).await.map_err(|e| {
+        format!(
+            "failed to release application {}: {}",
+            name(application),
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })?;
+    debug!("Released application {}", name(application));
+    Ok(())
+}
+
+// ApplicationSets that have generated all of their Applications, or failed to
+async fn generated_appsets() -> Result<BTreeSet<String>, Box<dyn Error>> {
+    let output = run_command(
+        "kubectl get applicationsets.argoproj.io -n argocd -oyaml",
+        None,
+    )
+    .await
+    .map_err(|e| {
+        format!(
+            "failed to get ApplicationSets: {}",
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })?;
+    let appsets: Value = serde_yaml::from_str(&String::from_utf8_lossy(&output.stdout))?;
+    Ok(appsets["items"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .filter(|a| {
+            a["status"]["conditions"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .any(|c| {
+                    matches!(
+                        c["type"].as_str(),
+                        Some("ResourcesUpToDate") | Some("ErrorOccurred")
+                    ) && c["status"].as_str() == Some("True")
+                })
+        })
+        .map(|a| name(a).to_string())
+        .collect())
+}
+
+// Applies the ApplicationSets of the branch and records the Applications they generate, without
+// rendering them. The ApplicationSets and Applications are deleted again
+pub async fn generate(branch: &Branch, sample: &AppSetSample) -> Result<(), Box<dyn Error>> {
+    let appsets: Vec<Value> = parse_manifest(&fs::read_to_string(apps_file(branch))?)
+        .into_iter()
+        .filter(|r| r["kind"].as_str() == Some("ApplicationSet"))
+        .collect();
+    if appsets.is_empty() {
+        return write_record(branch, &BTreeMap::new());
+    }
+    info!(
+        "🎲 Generating the applications of {} ApplicationSets of the {} branch",
+        appsets.len(),
+        branch
+    );
+    let file = workdir::path(&format!("appsets_{}_branch.yaml", branch));
+    let documents = appsets
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<String>, _>>()?;
+    fs::write(&file, documents.join("---\n"))?;
+    run_command_from_list(
+        vec!["kubectl", "apply", "-f", &file.to_string_lossy()],
+        None,
+    )
+    .await
+    .map_err(|e| {
+        format!(
+            "failed to apply the ApplicationSets: {}",
+            String::from_utf8_lossy(&e.stderr)
+        )
+    })?;
+
+    let start = std::time::Instant::now();
+    while generated_appsets().await?.len() < appsets.len() {
+        if start.elapsed().as_secs() > GENERATE_TIMEOUT {
+            info!("⚠️ Timed out waiting for the ApplicationSets to generate their applications");
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+
+    let output = run_command("kubectl get applications -n argocd -oyaml", None)
+        .await
+        .map_err(|e| {
+            format!(
+                "failed to get applications: {}",
+                String::from_utf8_lossy(&e.stderr)
+            )
+        })?;
+    let applications: Value = serde_yaml::from_str(&String::from_utf8_lossy(&output.stdout))?;
+    let record: BTreeMap<String, Generated> = applications["items"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| {
+            let generated = Generated {
+                appset: owner(a)?.to_string(),
+                spec: sample.normalized_spec(a),
+                selected: false,
+            };
+            Some((name(a).to_string(), generated))
+        })
+        .collect();
+    debug!("Generated {} applications", record.len());
+    write_record(branch, &record)?;
+    crate::extract::delete_applications().await;
+    Ok(())
+}
+
+// Lists the ApplicationSets of which only a sample of the applications was rendered
+pub fn generate_sample_section() -> Option<ReportSection> {
+    let mut appsets: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for generated in read_record(&Branch::Target).into_values() {
+        let (rendered, all) = appsets.entry(generated.appset).or_default();
+        *rendered += generated.selected as usize;
+        *all += 1;
+    }
+    let rows: Vec<String> = appsets
+        .iter()
+        .filter(|(_, (rendered, all))| rendered < all)
+        .map(|(appset, (rendered, all))| format!("| {} | {} | {} |", appset, rendered, all))
+        .collect();
+    if rows.is_empty() {
+        return None;
+    }
+    Some(ReportSection {
+        title: format!("🎲 Sampled ApplicationSets ({}):", rows.len()),
+        content: format!(
+            "| ApplicationSet | Rendered | Generated |\n|---|---|---|\n{}",
+            rows.join("\n")
+        ),
+    })
+}
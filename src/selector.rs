@@ -0,0 +1,184 @@
+// Kubernetes label selectors, e.g. 'app.kubernetes.io/part-of=payments,tier!=test,env in (prod,staging),!legacy'
+use regex::Regex;
+use std::error::Error;
+
+#[derive(Debug)]
+pub enum Operator {
+    Eq,
+    Ne,
+    In,
+    NotIn,
+    Exists,
+    DoesNotExist,
+}
+
+#[derive(Debug)]
+pub struct Selector {
+    pub key: String,
+    pub operator: Operator,
+    pub values: Vec<String>,
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = &self.key;
+        let values = self.values.join(",");
+        match self.operator {
+            Operator::Eq => write!(f, "{}={}", key, values),
+            Operator::Ne => write!(f, "{}!={}", key, values),
+            Operator::In => write!(f, "{} in ({})", key, values),
+            Operator::NotIn => write!(f, "{} notin ({})", key, values),
+            Operator::Exists => write!(f, "{}", key),
+            Operator::DoesNotExist => write!(f, "!{}", key),
+        }
+    }
+}
+
+impl Selector {
+    // Like in Kubernetes, '!=' and 'notin' also match when the label is missing
+    pub fn matches(&self, value: Option<&str>) -> bool {
+        let contains = |v: &str| self.values.iter().any(|value| value == v);
+        match (&self.operator, value) {
+            (Operator::Eq | Operator::In, Some(v)) => contains(v),
+            (Operator::Ne | Operator::NotIn, Some(v)) => !contains(v),
+            (Operator::Exists, Some(_)) => true,
+            (Operator::DoesNotExist, None) => true,
+            (Operator::Ne | Operator::NotIn, None) => true,
+            _ => false,
+        }
+    }
+}
+
+// Splits a selector on the commas between requirements, skipping the ones in value sets
+fn split_requirements(s: &str) -> Result<Vec<&str>, Box<dyn Error>> {
+    let mut requirements = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' if depth == 0 => depth += 1,
+            '(' => return Err(format!("nested '(' in '{}'", &s[start..]).into()),
+            ')' if depth == 0 => return Err(format!("unexpected ')' in '{}'", &s[start..]).into()),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                requirements.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if depth > 0 {
+        return Err(format!("missing ')' in '{}'", &s[start..]).into());
+    }
+    requirements.push(&s[start..]);
+    Ok(requirements)
+}
+
+fn validate_key(key: &str) -> Result<(), Box<dyn Error>> {
+    let name_regex = Regex::new(r"^[A-Za-z0-9]([-A-Za-z0-9_.]*[A-Za-z0-9])?$").unwrap();
+    let prefix_regex =
+        Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$").unwrap();
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    if let Some(prefix) = prefix {
+        if prefix.len() > 253 || !prefix_regex.is_match(prefix) {
+            return Err(format!(
+                "invalid label key '{}': the prefix must be a DNS subdomain, e.g. 'app.kubernetes.io'",
+                key
+            )
+            .into());
+        }
+    }
+    if name.len() > 63 || !name_regex.is_match(name) {
+        return Err(format!(
+            "invalid label key '{}': the name must be at most 63 alphanumeric characters, '-', '_' or '.', starting and ending with an alphanumeric character",
+            key
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn validate_value(value: &str) -> Result<(), Box<dyn Error>> {
+    let value_regex = Regex::new(r"^([A-Za-z0-9]([-A-Za-z0-9_.]*[A-Za-z0-9])?)?$").unwrap();
+    if value.len() > 63 || !value_regex.is_match(value) {
+        return Err(format!(
+            "invalid label value '{}': it must be at most 63 alphanumeric characters, '-', '_' or '.', starting and ending with an alphanumeric character",
+            value
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn parse_requirement(requirement: &str) -> Result<Selector, Box<dyn Error>> {
+    let regex = Regex::new(
+        r"^(?:!\s*(?P<absent>[^\s!=(),]+)|(?P<key>[^\s!=(),]+)\s*(?:(?P<op>!=|==|=)\s*(?P<value>[^\s!=(),]*)|\s+(?P<set>in|notin)\s*\((?P<values>[^()]*)\))?)$",
+    )
+    .unwrap();
+    let captures = regex.captures(requirement).ok_or_else(|| {
+        format!(
+            "invalid requirement '{}'. Expected 'key', '!key', 'key=value', 'key!=value', 'key in (v1,v2)' or 'key notin (v1,v2)'",
+            requirement
+        )
+    })?;
+
+    if let Some(key) = captures.name("absent") {
+        validate_key(key.as_str())?;
+        return Ok(Selector {
+            key: key.as_str().to_string(),
+            operator: Operator::DoesNotExist,
+            values: vec![],
+        });
+    }
+
+    let key = captures.name("key").map(|k| k.as_str()).unwrap_or_default();
+    validate_key(key)?;
+    let (operator, values) = match (captures.name("op"), captures.name("set")) {
+        (Some(op), _) => {
+            let operator = match op.as_str() {
+                "!=" => Operator::Ne,
+                _ => Operator::Eq,
+            };
+            let value = captures
+                .name("value")
+                .map(|v| v.as_str())
+                .unwrap_or_default();
+            (operator, vec![value.to_string()])
+        }
+        (None, Some(set)) => {
+            let operator = match set.as_str() {
+                "in" => Operator::In,
+                _ => Operator::NotIn,
+            };
+            let values: Vec<String> = captures["values"]
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .collect();
+            if values.iter().all(|v| v.is_empty()) {
+                return Err(format!("empty value set in '{}'", requirement).into());
+            }
+            (operator, values)
+        }
+        (None, None) => (Operator::Exists, vec![]),
+    };
+    for value in &values {
+        validate_value(value)?;
+    }
+    Ok(Selector {
+        key: key.to_string(),
+        operator,
+        values,
+    })
+}
+
+// Parses a label selector. All requirements must match
+pub fn parse(s: &str) -> Result<Vec<Selector>, Box<dyn Error>> {
+    split_requirements(s)?
+        .into_iter()
+        .map(|r| r.trim())
+        .filter(|r| !r.is_empty())
+        .map(parse_requirement)
+        .collect()
+}
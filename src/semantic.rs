@@ -0,0 +1,95 @@
+// A dyff-inspired rendering of the changes of an application: instead of lines of text, it lists
+// the values that changed, were added or were removed, by resource and path. Reordered or
+// reformatted YAML doesn't show up at all. Lines start with '+' and '-', so the report highlights
+// them like a diff
+use crate::manifests::{flatten, index_resources, ResourceId};
+use crate::renames::Rename;
+use std::collections::{BTreeMap, BTreeSet};
+
+fn push_value(lines: &mut Vec<String>, sign: char, value: &str) {
+    for line in value.lines() {
+        lines.push(format!("{}   {}", sign, line));
+    }
+}
+
+fn diff_fields(base: &serde_yaml::Value, target: &serde_yaml::Value) -> Vec<String> {
+    let (mut before, mut after) = (BTreeMap::new(), BTreeMap::new());
+    flatten(base, "", &mut before);
+    flatten(target, "", &mut after);
+    let paths: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    let mut lines = vec![];
+    for path in paths {
+        match (before.get(path), after.get(path)) {
+            (Some(old), Some(new)) if old != new => {
+                lines.push(format!("  changed value at {}", path));
+                push_value(&mut lines, '-', old);
+                push_value(&mut lines, '+', new);
+            }
+            (None, Some(new)) => {
+                lines.push(format!("+ added {}", path));
+                push_value(&mut lines, '+', new);
+            }
+            (Some(old), None) => {
+                lines.push(format!("- removed {}", path));
+                push_value(&mut lines, '-', old);
+            }
+            _ => (),
+        }
+    }
+    lines
+}
+
+// Returns the changes between two manifests of an application, grouped by resource. Empty when
+// they describe the same resources. Renamed resources are diffed against their old resource, and
+// resources are labeled with the source of the application they come from, when it is known
+pub fn diff_manifests(
+    base: &str,
+    target: &str,
+    renames: &[Rename],
+    sources: &BTreeMap<ResourceId, String>,
+) -> Vec<String> {
+    let before = index_resources(base);
+    let after = index_resources(target);
+    let renamed: BTreeSet<_> = renames.iter().flat_map(|r| [&r.from, &r.to]).collect();
+    let ids: BTreeSet<_> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|id| !renamed.contains(id))
+        .collect();
+
+    let source = |id: &ResourceId| {
+        sources
+            .get(id)
+            .map(|s| format!(" 📦 {}", s))
+            .unwrap_or_default()
+    };
+
+    let mut lines = vec![];
+    for rename in renames {
+        if let (Some(old), Some(new)) = (before.get(&rename.from), after.get(&rename.to)) {
+            lines.push(format!(
+                "@@ {} → {} @@{}",
+                rename.from,
+                rename.to,
+                source(&rename.to)
+            ));
+            lines.extend(diff_fields(old, new));
+        }
+    }
+    for id in ids {
+        match (before.get(id), after.get(id)) {
+            (Some(old), Some(new)) => {
+                let changes = diff_fields(old, new);
+                if !changes.is_empty() {
+                    lines.push(format!("@@ {} @@{}", id, source(id)));
+                    lines.extend(changes);
+                }
+            }
+            (None, Some(_)) => lines.push(format!("+ added resource {}{}", id, source(id))),
+            (Some(_), None) => lines.push(format!("- removed resource {}{}", id, source(id))),
+            (None, None) => (),
+        }
+    }
+    lines
+}
@@ -0,0 +1,92 @@
+use crate::metrics;
+use log::{debug, error, info};
+use std::error::Error;
+use std::fs;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Polls the report version and reloads the page when a new report has been written
+const LIVE_RELOAD_SCRIPT: &str = r#"
+<script>
+  let version = null;
+  setInterval(async () => {
+    const response = await fetch("/version");
+    const latest = await response.text();
+    if (version !== null && latest !== version) {
+      location.reload();
+    }
+    version = latest;
+  }, 2000);
+</script>
+"#;
+
+const WAITING_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Argo CD Diff Preview</title></head>
+<body><h2>⏳ Waiting for the diff to be generated...</h2></body>
+</html>
+"#;
+
+pub async fn serve(output_folder: String, port: u16) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    info!("🌐 Serving report at http://localhost:{}", port);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        if let Err(e) = handle_connection(stream, &output_folder).await {
+            error!("❌ Failed to handle request: {}", e);
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    output_folder: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut buffer = [0; 4096];
+    let n = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..n]);
+    let path = request
+        .lines()
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .unwrap_or("/");
+
+    debug!("🌐 Request: {}", path);
+
+    let report_path = format!("{}/diff.html", output_folder);
+
+    let (status, content_type, body) = match path {
+        "/" | "/index.html" => {
+            let page = fs::read_to_string(&report_path).unwrap_or(WAITING_PAGE.to_string());
+            let page = page.replace("</body>", &format!("{}</body>", LIVE_RELOAD_SCRIPT));
+            ("200 OK", "text/html; charset=utf-8", page)
+        }
+        "/version" => ("200 OK", "text/plain", report_version(&report_path)),
+        "/metrics" => ("200 OK", "text/plain; version=0.0.4", metrics::render()),
+        _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+fn report_version(report_path: &str) -> String {
+    fs::metadata(report_path)
+        .and_then(|m| m.modified())
+        .map(|t| {
+            t.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+                .to_string()
+        })
+        .unwrap_or_default()
+}
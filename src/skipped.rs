@@ -0,0 +1,54 @@
+use crate::diff::ReportSection;
+use log::info;
+use std::collections::BTreeMap;
+
+// An application (or ApplicationSet) that was found in a branch but not rendered
+#[derive(Debug)]
+pub struct SkippedApplication {
+    pub name: String,
+    pub file: String,
+    pub reason: String,
+}
+
+// Lists the skipped applications of both branches with the reason they were skipped, so it is
+// clear why an application is missing from the diff
+pub fn generate_skipped_section(
+    base: &[SkippedApplication],
+    target: &[SkippedApplication],
+) -> Option<ReportSection> {
+    let mut skipped: BTreeMap<(&str, &str), (&str, Vec<&str>)> = BTreeMap::new();
+    for (branch, applications) in [("base", base), ("target", target)] {
+        for a in applications {
+            skipped
+                .entry((&a.name, &a.reason))
+                .or_insert((&a.file, vec![]))
+                .1
+                .push(branch);
+        }
+    }
+
+    if skipped.is_empty() {
+        return None;
+    }
+    info!("🙈 Skipped {} applications", skipped.len());
+
+    let rows = skipped
+        .iter()
+        .map(|((name, reason), (file, branches))| {
+            let branches = match branches.len() {
+                1 => branches[0],
+                _ => "both",
+            };
+            format!("| {} | `{}` | {} | {} |", name, file, branches, reason)
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    Some(ReportSection {
+        title: format!("🙈 Skipped applications ({}):", skipped.len()),
+        content: format!(
+            "| Application | File | Branch | Reason |\n|---|---|---|---|\n{}",
+            rows
+        ),
+    })
+}
@@ -0,0 +1,198 @@
+// Snapshots of a kind node with Argo CD already installed. The node keeps the cluster state
+// (etcd, images and pods) in an anonymous volume at /var, which 'docker commit' leaves out, so the
+// volume is archived into the image and restored by the entrypoint before the node boots
+use crate::utils::{run_command, run_command_from_list};
+use log::{debug, error, info};
+use std::error::Error;
+
+const ARCHIVE_PATH: &str = "/var-snapshot.tar";
+const RESTORE_SCRIPT_PATH: &str = "/usr/local/bin/restore-snapshot";
+const NODE_IP_LABEL: &str = "argocd-diff-preview.node-ip";
+const NODE_SUBNET_LABEL: &str = "argocd-diff-preview.node-subnet";
+
+// kind's node entrypoint, started after the volume is restored
+const RESTORE_SCRIPT: &str = r#"#!/bin/sh
+if [ ! -f /var/.snapshot-restored ]; then
+  tar -C /var -xf /var-snapshot.tar && touch /var/.snapshot-restored
+fi
+exec /usr/local/bin/entrypoint /sbin/init
+"#;
+
+fn node_name(cluster_name: &str) -> String {
+    format!("{}-control-plane", cluster_name)
+}
+
+async fn docker(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    debug!("Running: docker {}", args.join(" "));
+    let command = std::iter::once("docker")
+        .chain(args.iter().copied())
+        .collect();
+    match run_command_from_list(command, None).await {
+        Ok(o) => Ok(String::from_utf8_lossy(&o.stdout).trim().to_string()),
+        Err(e) => Err(format!(
+            "docker {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&e.stderr)
+        )
+        .into()),
+    }
+}
+
+// Saves the node of the running cluster as an image. Kubernetes is stopped while the volume is
+// archived, so etcd is consistent, and started again afterwards
+pub async fn create_snapshot(cluster_name: &str, image: &str) -> Result<(), Box<dyn Error>> {
+    info!("📸 Creating cluster snapshot: {}", image);
+    let node = node_name(cluster_name);
+
+    let ip = docker(&[
+        "inspect",
+        "-f",
+        "{{ .NetworkSettings.Networks.kind.IPAddress }}",
+        &node,
+    ])
+    .await?;
+    let subnet = docker(&[
+        "network",
+        "inspect",
+        "-f",
+        "{{ (index .IPAM.Config 0).Subnet }}",
+        "kind",
+    ])
+    .await?;
+
+    let archive = format!(
+        "systemctl stop kubelet && crictl stop $(crictl ps -q) >/dev/null; \
+         tar -C /var --exclude=./log -cf {archive} . ; status=$?; \
+         systemctl start kubelet; exit $status",
+        archive = ARCHIVE_PATH
+    );
+    docker(&["exec", &node, "sh", "-c", &archive]).await?;
+
+    let script = format!(
+        "cat > {path} <<'EOF'\n{script}EOF\nchmod +x {path}",
+        path = RESTORE_SCRIPT_PATH,
+        script = RESTORE_SCRIPT
+    );
+    docker(&["exec", &node, "sh", "-c", &script]).await?;
+
+    docker(&[
+        "commit",
+        "--change",
+        &format!("ENTRYPOINT [\"{}\"]", RESTORE_SCRIPT_PATH),
+        "--change",
+        "CMD []",
+        "--change",
+        &format!("LABEL {}={}", NODE_IP_LABEL, ip),
+        "--change",
+        &format!("LABEL {}={}", NODE_SUBNET_LABEL, subnet),
+        &node,
+        image,
+    ])
+    .await?;
+
+    // the archive is only needed in the image
+    docker(&["exec", &node, "rm", "-f", ARCHIVE_PATH]).await?;
+
+    info!("📸 Created cluster snapshot: {}", image);
+    Ok(())
+}
+
+// Starts a kind node from a snapshot the same way 'kind create cluster' does, so kind can export
+// the kubeconfig and delete the cluster. The node gets its old IP address, since it is part of
+// the API server configuration
+pub async fn create_cluster_from_snapshot(
+    cluster_name: &str,
+    image: &str,
+) -> Result<(), Box<dyn Error>> {
+    info!("🚀 Creating cluster from snapshot: {}", image);
+    let node = node_name(cluster_name);
+
+    let label = |key: &str| format!("{{{{ index .Config.Labels \"{}\" }}}}", key);
+    let ip = docker(&["image", "inspect", "-f", &label(NODE_IP_LABEL), image]).await?;
+    let subnet = docker(&["image", "inspect", "-f", &label(NODE_SUBNET_LABEL), image]).await?;
+    if ip.is_empty() || ip == "<no value>" {
+        error!("❌ Image {} is not a cluster snapshot", image);
+        return Err(format!("missing label {} on image {}", NODE_IP_LABEL, image).into());
+    }
+
+    if let Err(e) = run_command(
+        &format!("kind delete cluster --name {}", cluster_name),
+        None,
+    )
+    .await
+    {
+        panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+    }
+
+    if docker(&["network", "inspect", "kind"]).await.is_err() {
+        info!("🚀 Creating docker network 'kind' with subnet {}", subnet);
+        docker(&["network", "create", "--subnet", &subnet, "kind"]).await?;
+    }
+
+    let cluster_label = format!("io.x-k8s.kind.cluster={}", cluster_name);
+    docker(&[
+        "run",
+        "--detach",
+        "--name",
+        &node,
+        "--hostname",
+        &node,
+        "--label",
+        &cluster_label,
+        "--label",
+        "io.x-k8s.kind.role=control-plane",
+        "--privileged",
+        "--security-opt",
+        "seccomp=unconfined",
+        "--security-opt",
+        "apparmor=unconfined",
+        "--cgroupns",
+        "private",
+        "--tmpfs",
+        "/tmp",
+        "--tmpfs",
+        "/run",
+        "--volume",
+        "/var",
+        "--volume",
+        "/lib/modules:/lib/modules:ro",
+        "--network",
+        "kind",
+        "--ip",
+        &ip,
+        "--publish",
+        "127.0.0.1::6443",
+        image,
+    ])
+    .await?;
+
+    if let Err(e) = run_command(
+        &format!("kind export kubeconfig --name {}", cluster_name),
+        None,
+    )
+    .await
+    {
+        error!("❌ Failed to export kubeconfig of cluster {}", cluster_name);
+        panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+    }
+
+    // the API server needs a moment to start before nodes can be queried
+    for attempt in 1..=30 {
+        match run_command(
+            "kubectl wait --for=condition=Ready nodes --all --timeout=10s",
+            None,
+        )
+        .await
+        {
+            Ok(_) => break,
+            Err(e) if attempt == 30 => {
+                error!("❌ Cluster from snapshot did not become ready");
+                panic!("error: {}", String::from_utf8_lossy(&e.stderr))
+            }
+            Err(_) => tokio::time::sleep(tokio::time::Duration::from_secs(2)).await,
+        }
+    }
+
+    info!("🚀 Cluster created from snapshot");
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::diff::ReportSection;
+use crate::manifests::{resource_id, ResourceId};
+use crate::parsing::get_resources_of_kinds;
+use crate::utils::run_command;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+const SPEC_KINDS: [&str; 2] = ["Application", "ApplicationSet"];
+const SPECS_FOLDER: &str = "app-specs";
+
+// The specs are compared as they are in the branches, since patching rewrites the revisions and
+// removes the sync policy, which would hide exactly the changes this section is about
+async fn read_specs(
+    folders: &[String],
+    regex: &Option<Regex>,
+) -> BTreeMap<ResourceId, serde_yaml::Value> {
+    let mut specs = BTreeMap::new();
+    for folder in folders {
+        for mut r in get_resources_of_kinds(folder, regex, &SPEC_KINDS).await {
+            r.as_mapping_mut().map(|m| m.remove("status"));
+            specs.insert(resource_id(&r), r);
+        }
+    }
+    specs
+}
+
+fn file_name(id: &ResourceId) -> String {
+    id.to_string().replace('/', "_") + ".yaml"
+}
+
+// Diffs the Application and ApplicationSet manifests of both branches, so changes to labels,
+// annotations or sync options are visible even when the rendered manifests are identical
+pub async fn generate_spec_section(
+    output_folder: &str,
+    base_folders: &[String],
+    target_folders: &[String],
+    regex: &Option<Regex>,
+) -> Result<Option<ReportSection>, Box<dyn Error>> {
+    info!("📝 Comparing the Application and ApplicationSet specs of both branches");
+
+    let base = read_specs(base_folders, regex).await;
+    let target = read_specs(target_folders, regex).await;
+    let changed: BTreeSet<&ResourceId> = base
+        .keys()
+        .chain(target.keys())
+        .filter(|id| base.get(*id) != target.get(*id))
+        .collect();
+    let folder = format!("{}/{}", output_folder, SPECS_FOLDER);
+    fs::remove_dir_all(&folder).unwrap_or_default();
+    if changed.is_empty() {
+        info!("📝 No spec changes found");
+        return Ok(None);
+    }
+
+    // only the changed specs are written, so the diff has no unchanged files
+    for (branch, specs) in [("base", &base), ("target", &target)] {
+        fs::create_dir_all(format!("{}/{}", folder, branch))?;
+        for id in &changed {
+            if let Some(spec) = specs.get(*id) {
+                fs::write(
+                    format!("{}/{}/{}", folder, branch, file_name(id)),
+                    serde_yaml::to_string(spec)?,
+                )?;
+            }
+        }
+    }
+
+    let diff = diff_branch_folders(&folder).await?;
+
+    Ok(Some(ReportSection {
+        title: format!("📝 Application spec changes ({}):", changed.len()),
+        content: format!("```diff\n{}\n```", diff),
+    }))
+}
+
+// Diffs the 'base' and 'target' folders in 'folder', with the paths relative to it
+pub async fn diff_branch_folders(folder: &str) -> Result<String, Box<dyn Error>> {
+    let command = "git --no-pager diff --no-index base target";
+    debug!("Diffing {} with command: {}", folder, command);
+    // git exits with 1 when there are differences
+    let diff = match run_command(command, Some(folder)).await {
+        Ok(o) => o.stdout,
+        Err(e) if e.stderr.is_empty() => e.stdout,
+        Err(e) => {
+            return Err(format!(
+                "failed to diff {}: {}",
+                folder,
+                String::from_utf8_lossy(&e.stderr)
+            )
+            .into())
+        }
+    };
+    Ok(String::from_utf8_lossy(&diff).trim_end().to_string())
+}
@@ -0,0 +1,150 @@
+// Usage statistics of a run (number of applications, generator types, durations, outcome), written
+// to a local file so platform teams can aggregate them to plan their preview infrastructure. Nothing
+// is sent anywhere, and the file contains no names, branches or repositories
+use crate::manifests::read_rendered_apps;
+use crate::parsing::get_resources_of_kinds;
+use crate::skipped::SkippedApplication;
+use crate::Branch;
+use log::{debug, error};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Instant;
+
+struct Stats {
+    file: String,
+    cluster_tool: String,
+    start: Instant,
+    phase: Option<(&'static str, Instant)>,
+    // Seconds spent per phase. With --watch, the phases of all diffs are added up
+    durations: BTreeMap<&'static str, f64>,
+    applications: BTreeMap<&'static str, usize>,
+    generators: BTreeMap<String, usize>,
+}
+
+static STATS: Mutex<Option<Stats>> = Mutex::new(None);
+
+pub fn init(file: &str, cluster_tool: &str) {
+    *STATS.lock().unwrap() = Some(Stats {
+        file: file.to_string(),
+        cluster_tool: cluster_tool.to_string(),
+        start: Instant::now(),
+        phase: None,
+        durations: BTreeMap::new(),
+        applications: BTreeMap::new(),
+        generators: BTreeMap::new(),
+    });
+}
+
+// Ends the current phase and starts the next one
+pub fn enter_phase(phase: Option<&'static str>) {
+    let Ok(mut guard) = STATS.try_lock() else {
+        return;
+    };
+    let Some(stats) = guard.as_mut() else {
+        return;
+    };
+    if let Some((name, start)) = stats.phase.take() {
+        *stats.durations.entry(name).or_default() += start.elapsed().as_secs_f64();
+    }
+    stats.phase = phase.map(|p| (p, Instant::now()));
+}
+
+// Counts the generator types of ApplicationSets, including the ones nested in matrix and merge
+// generators
+fn count_generators(generators: &serde_yaml::Value, counts: &mut BTreeMap<String, usize>) {
+    for generator in generators.as_sequence().into_iter().flatten() {
+        for (kind, value) in generator.as_mapping().into_iter().flatten() {
+            let Some(kind) = kind.as_str() else {
+                continue;
+            };
+            // selector and template are options of a generator, not generators
+            if kind == "selector" || kind == "template" {
+                continue;
+            }
+            *counts.entry(kind.to_string()).or_default() += 1;
+            count_generators(&value["generators"], counts);
+        }
+    }
+}
+
+// Records the applications of the run: the rendered, changed and skipped ones, and the
+// ApplicationSets of the target branch with their generators
+pub async fn record_applications(
+    output_folder: &str,
+    target_folders: &[String],
+    regex: &Option<Regex>,
+    skipped_base: &[SkippedApplication],
+    skipped_target: &[SkippedApplication],
+) {
+    if STATS.lock().unwrap().is_none() {
+        return;
+    }
+    let base = read_rendered_apps(output_folder, &Branch::Base);
+    let target = read_rendered_apps(output_folder, &Branch::Target);
+    let changed = base
+        .keys()
+        .chain(target.keys().filter(|name| !base.contains_key(*name)))
+        .filter(|name| base.get(*name) != target.get(*name))
+        .count();
+    let mut application_sets = 0;
+    let mut generators = BTreeMap::new();
+    for folder in target_folders {
+        for set in get_resources_of_kinds(folder, regex, &["ApplicationSet"]).await {
+            application_sets += 1;
+            count_generators(&set["spec"]["generators"], &mut generators);
+        }
+    }
+
+    if let Some(stats) = STATS.lock().unwrap().as_mut() {
+        stats.applications = BTreeMap::from([
+            ("base", base.len()),
+            ("target", target.len()),
+            ("changed", changed),
+            ("skippedBase", skipped_base.len()),
+            ("skippedTarget", skipped_target.len()),
+            ("applicationSets", application_sets),
+        ]);
+        stats.generators = generators;
+    }
+}
+
+// Writes the statistics. 'outcome' is 'succeeded', 'failed' or 'cancelled', and 'failed_phase' is
+// what the run was doing when it stopped
+pub fn write(outcome: &str, failed_phase: Option<&str>) {
+    let Ok(guard) = STATS.try_lock() else {
+        return;
+    };
+    let Some(stats) = guard.as_ref() else {
+        return;
+    };
+    let mut durations = stats.durations.clone();
+    if let Some((name, start)) = stats.phase {
+        *durations.entry(name).or_default() += start.elapsed().as_secs_f64();
+    }
+    let round = |seconds: f64| (seconds * 10.0).round() / 10.0;
+    let json = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "outcome": outcome,
+        "failedPhase": failed_phase,
+        "clusterTool": stats.cluster_tool,
+        "durationSeconds": round(stats.start.elapsed().as_secs_f64()),
+        "phaseDurationSeconds": durations
+            .iter()
+            .map(|(name, seconds)| (name.to_string(), round(*seconds)))
+            .collect::<BTreeMap<String, f64>>(),
+        "applications": stats.applications,
+        "generators": stats.generators,
+    });
+    match serde_json::to_string_pretty(&json)
+        .map_err(|e| e.to_string())
+        .and_then(|s| fs::write(&stats.file, s).map_err(|e| e.to_string()))
+    {
+        Ok(_) => debug!("Wrote usage statistics to {}", stats.file),
+        Err(e) => error!(
+            "❌ Failed to write usage statistics to {}: {}",
+            stats.file, e
+        ),
+    }
+}
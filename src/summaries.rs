@@ -0,0 +1,152 @@
+use crate::config::read_config;
+use crate::diff::ReportSection;
+use crate::manifests::{flatten, index_resources, read_rendered_apps, ResourceId};
+use crate::Branch;
+use log::{debug, info};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+// Turns a custom resource into a human-readable one-liner, e.g. "hosts: a.example.com"
+pub struct Summary {
+    kind: String,
+    group: Option<String>,
+    template: String,
+}
+
+// Summaries for well-known custom resources. User supplied summaries take precedence
+const DEFAULT_SUMMARIES: &str = r#"
+- kind: VirtualService
+  group: networking.istio.io
+  summary: "hosts: {spec.hosts}, gateways: {spec.gateways}, destinations: {spec.http[*].route[*].destination.host}"
+- kind: DestinationRule
+  group: networking.istio.io
+  summary: "host: {spec.host}, subsets: {spec.subsets[*].name}"
+- kind: Certificate
+  group: cert-manager.io
+  summary: "dns names: {spec.dnsNames}, issuer: {spec.issuerRef.kind}/{spec.issuerRef.name}"
+- kind: Rollout
+  group: argoproj.io
+  summary: "replicas: {spec.replicas}, images: {spec.template.spec.containers[*].image}"
+"#;
+
+fn parse_summaries(config: serde_yaml::Value) -> Result<Vec<Summary>, Box<dyn Error>> {
+    config
+        .as_sequence()
+        .ok_or("summaries config must be a list")?
+        .iter()
+        .map(|s| {
+            Ok(Summary {
+                kind: s["kind"]
+                    .as_str()
+                    .ok_or("summary is missing 'kind'")?
+                    .to_string(),
+                group: s["group"].as_str().map(|g| g.to_string()),
+                template: s["summary"]
+                    .as_str()
+                    .ok_or("summary is missing 'summary'")?
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn read_summaries(path: Option<&str>) -> Result<Vec<Summary>, Box<dyn Error>> {
+    let mut summaries = match path {
+        Some(p) => parse_summaries(read_config(p)?)?,
+        None => vec![],
+    };
+    summaries.extend(parse_summaries(serde_yaml::from_str(DEFAULT_SUMMARIES)?)?);
+    Ok(summaries)
+}
+
+impl Summary {
+    fn matches(&self, resource: &serde_yaml::Value) -> bool {
+        let group = resource["apiVersion"]
+            .as_str()
+            .and_then(|v| v.rsplit_once('/'))
+            .map(|(g, _)| g)
+            .unwrap_or_default();
+        resource["kind"].as_str() == Some(&self.kind)
+            && self.group.as_ref().map(|g| g == group).unwrap_or(true)
+    }
+
+    // Replaces every {path} in the template with the value(s) found at that path.
+    // '[*]' matches any index, and paths pointing to lists or maps show all values below them
+    fn render(&self, resource: &serde_yaml::Value) -> String {
+        let mut fields = BTreeMap::new();
+        flatten(resource, "", &mut fields);
+
+        let placeholder = Regex::new(r"\{([^}]+)\}").unwrap();
+        placeholder
+            .replace_all(&self.template, |caps: &regex::Captures| {
+                let path = regex::escape(&caps[1]).replace(r"\[\*\]", r"\[\d+\]");
+                let pattern = Regex::new(&format!(r"^{}($|\.|\[)", path)).unwrap();
+                let values: Vec<&str> = fields
+                    .iter()
+                    .filter(|(k, _)| pattern.is_match(k))
+                    .map(|(_, v)| v.as_str())
+                    .collect();
+                match values.is_empty() {
+                    true => "-".to_string(),
+                    false => values.join(", "),
+                }
+            })
+            .to_string()
+    }
+}
+
+pub fn generate_summaries_section(
+    output_folder: &str,
+    summaries: &[Summary],
+) -> Option<ReportSection> {
+    info!("📝 Summarizing changes to custom resources");
+
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let summarize = |resource: &serde_yaml::Value| {
+        summaries
+            .iter()
+            .find(|s| s.matches(resource))
+            .map(|s| s.render(resource))
+    };
+
+    let mut lines = vec![];
+    for name in names {
+        let base = index_resources(base_apps.get(name).map(|m| m.as_str()).unwrap_or_default());
+        let target = index_resources(
+            target_apps
+                .get(name)
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+        );
+        let ids: BTreeSet<&ResourceId> = base.keys().chain(target.keys()).collect();
+        for id in ids {
+            let (before, after) = (base.get(id), target.get(id));
+            if before == after {
+                continue;
+            }
+            let line = match (before.and_then(summarize), after.and_then(summarize)) {
+                (None, None) => continue,
+                (Some(b), Some(a)) if b == a => continue,
+                (None, Some(a)) => format!("➕ `{}`", a),
+                (Some(b), None) => format!("➖ `{}`", b),
+                (Some(b), Some(a)) => format!("`{}` → `{}`", b, a),
+            };
+            debug!("Summarized {} in {}: {}", id, name, line);
+            lines.push(format!("- **{}**: `{}`: {}", name, id, line));
+        }
+    }
+
+    if lines.is_empty() {
+        info!("📝 No summarized custom resource changes found");
+        return None;
+    }
+
+    Some(ReportSection {
+        title: format!("📝 Custom resource changes ({}):", lines.len()),
+        content: lines.join("\n"),
+    })
+}
@@ -0,0 +1,129 @@
+// When CI cancels a job, the process gets a SIGINT or SIGTERM and is killed shortly after. Without
+// handling them, the cluster (and its docker containers) and the work directory are left behind.
+// The same happens when a run fails, so failures go through the same teardown
+use crate::diff;
+use crate::metrics;
+use crate::stats;
+use crate::workdir;
+use log::{error, info};
+use std::sync::Mutex;
+use tokio::signal::unix::{signal, SignalKind};
+
+struct State {
+    output_folder: String,
+    keep_cluster_on_failure: bool,
+    // What the run is doing. None when it hasn't started or has finished
+    phase: Option<&'static str>,
+    delete_cluster: Option<Box<dyn Fn() + Send>>,
+    // Exit code of a finished run, used when it is stopped while serving the report
+    exit_code: Option<i32>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+// Installs the signal handlers and a panic hook, which tear down the run
+pub fn init(output_folder: &str, keep_cluster_on_failure: bool) {
+    *STATE.lock().unwrap() = Some(State {
+        output_folder: output_folder.to_string(),
+        keep_cluster_on_failure,
+        phase: None,
+        delete_cluster: None,
+        exit_code: None,
+    });
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        // panics of background tasks, like the report server, don't end the run
+        if std::thread::current().name() == Some("main") {
+            stop("failed", "failed", None);
+        }
+    }));
+
+    tokio::spawn(async {
+        let (mut interrupt, mut terminate) = match (
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::terminate()),
+        ) {
+            (Ok(i), Ok(t)) => (i, t),
+            _ => {
+                info!("⚠️ Failed to install signal handlers");
+                return;
+            }
+        };
+        let (name, code) = tokio::select! {
+            _ = interrupt.recv() => ("SIGINT", 130),
+            _ = terminate.recv() => ("SIGTERM", 143),
+        };
+        let (exit_code, keep) = match STATE.lock().unwrap().as_ref() {
+            Some(s) => (s.exit_code.unwrap_or(code), s.keep_cluster_on_failure),
+            None => (code, false),
+        };
+        // the work directory is only kept for debugging a cancelled run when the cluster is kept too
+        if !(stop(&format!("was cancelled ({})", name), "cancelled", None) && keep) {
+            workdir::cleanup();
+        }
+        std::process::exit(exit_code);
+    });
+}
+
+pub fn set_phase(phase: Option<&'static str>) {
+    stats::enter_phase(phase);
+    metrics::enter_phase(phase);
+    if let Some(state) = STATE.lock().unwrap().as_mut() {
+        state.phase = phase;
+    }
+}
+
+// Registers how to delete the cluster, which is needed until it is deleted
+pub fn cluster_created(delete_cluster: Box<dyn Fn() + Send>) {
+    if let Some(state) = STATE.lock().unwrap().as_mut() {
+        state.delete_cluster = Some(delete_cluster);
+    }
+}
+
+pub fn cluster_deleted() {
+    if let Some(state) = STATE.lock().unwrap().as_mut() {
+        state.delete_cluster = None;
+    }
+}
+
+pub fn finish(exit_code: i32) {
+    if let Some(state) = STATE.lock().unwrap().as_mut() {
+        state.phase = None;
+        state.exit_code = Some(exit_code);
+    }
+}
+
+// Tears down a run that returned an error. The error is added to the partial report
+pub fn fail(reason: &str) {
+    error!("❌ {}", reason);
+    stop("failed", "failed", Some(reason));
+}
+
+// Writes a partial report and deletes the cluster, unless it is kept for debugging. Returns whether
+// the run was still in progress
+fn stop(reason: &str, outcome: &str, error: Option<&str>) -> bool {
+    // a panic while the state is locked must not deadlock
+    let Ok(mut guard) = STATE.try_lock() else {
+        return false;
+    };
+    let Some(state) = guard.as_mut() else {
+        return false;
+    };
+    let phase = state.phase.take();
+    if let Some(phase) = phase {
+        error!("❌ Run {} while {}", reason, phase);
+        if let Err(e) = diff::write_partial_report(&state.output_folder, reason, phase, error) {
+            error!("❌ Failed to write partial report: {}", e);
+        }
+        stats::write(outcome, Some(phase));
+    }
+    if let Some(delete_cluster) = state.delete_cluster.take() {
+        match phase.is_some() && state.keep_cluster_on_failure {
+            true => info!("🔍 Keeping the cluster for debugging (--keep-cluster-on-failure)"),
+            false => delete_cluster(),
+        }
+    }
+    phase.is_some()
+}
@@ -0,0 +1,203 @@
+// Builders for the resources of a branch folder, so the selection and patching of applications can
+// be tested without files or a cluster, e.g. to check that a selector picks the intended
+// applications. Other crates get the kit with the 'testkit' feature
+use crate::parsing::{
+    get_applications, patch_applications, Application, K8sResource, PatchOptions,
+};
+use crate::selector::{self, Selector};
+use crate::skipped::SkippedApplication;
+use crate::Branch;
+use serde_yaml::Value;
+
+pub struct ApplicationBuilder {
+    yaml: Value,
+    file_name: String,
+}
+
+impl ApplicationBuilder {
+    pub fn application(name: &str) -> ApplicationBuilder {
+        let yaml = serde_yaml::from_str(&format!(
+            "apiVersion: argoproj.io/v1alpha1
+kind: Application
+metadata:
+  name: {}
+  namespace: argocd
+spec:
+  project: default
+  destination:
+    server: https://kubernetes.default.svc
+    namespace: default
+",
+            name
+        ))
+        .unwrap();
+        ApplicationBuilder {
+            yaml,
+            file_name: format!("apps/{}.yaml", name),
+        }
+    }
+
+    // An ApplicationSet with a list generator and the spec of the builder as template
+    pub fn application_set(name: &str) -> ApplicationBuilder {
+        let application = ApplicationBuilder::application(name);
+        let mut template = application.yaml.clone();
+        template["metadata"] = serde_yaml::from_str("name: '{{name}}'").unwrap();
+        let mut yaml: Value = serde_yaml::from_str(&format!(
+            "apiVersion: argoproj.io/v1alpha1
+kind: ApplicationSet
+metadata:
+  name: {}
+  namespace: argocd
+spec:
+  generators:
+    - list:
+        elements:
+          - name: {}
+",
+            name, name
+        ))
+        .unwrap();
+        yaml["spec"]["template"] = template;
+        ApplicationBuilder {
+            yaml,
+            file_name: application.file_name,
+        }
+    }
+
+    // The spec of an Application, or the template spec of an ApplicationSet
+    fn spec(&mut self) -> &mut Value {
+        match self.yaml["kind"].as_str() {
+            Some("ApplicationSet") => &mut self.yaml["spec"]["template"]["spec"],
+            _ => &mut self.yaml["spec"],
+        }
+    }
+
+    fn metadata(&mut self, field: &str, key: &str, value: &str) -> &mut ApplicationBuilder {
+        if !self.yaml["metadata"][field].is_mapping() {
+            self.yaml["metadata"][field] = serde_yaml::Mapping::new().into();
+        }
+        self.yaml["metadata"][field][key] = value.into();
+        self
+    }
+
+    pub fn file(mut self, file_name: &str) -> ApplicationBuilder {
+        self.file_name = file_name.to_string();
+        self
+    }
+
+    pub fn label(mut self, key: &str, value: &str) -> ApplicationBuilder {
+        self.metadata("labels", key, value);
+        self
+    }
+
+    pub fn annotation(mut self, key: &str, value: &str) -> ApplicationBuilder {
+        self.metadata("annotations", key, value);
+        self
+    }
+
+    pub fn destination(mut self, server: &str, namespace: &str) -> ApplicationBuilder {
+        self.spec()["destination"] = serde_yaml::from_str(&format!(
+            "{{server: '{}', namespace: '{}'}}",
+            server, namespace
+        ))
+        .unwrap();
+        self
+    }
+
+    // A source of a path in a git repository. A second source turns 'source' into 'sources'
+    pub fn source(mut self, repo: &str, path: &str, revision: &str) -> ApplicationBuilder {
+        let source: Value = serde_yaml::from_str(&format!(
+            "{{repoURL: '{}', path: '{}', targetRevision: '{}'}}",
+            repo, path, revision
+        ))
+        .unwrap();
+        self.add_source(source);
+        self
+    }
+
+    // A source of a chart in a Helm repository
+    pub fn chart(mut self, repo: &str, chart: &str, version: &str) -> ApplicationBuilder {
+        let source: Value = serde_yaml::from_str(&format!(
+            "{{repoURL: '{}', chart: '{}', targetRevision: '{}'}}",
+            repo, chart, version
+        ))
+        .unwrap();
+        self.add_source(source);
+        self
+    }
+
+    fn add_source(&mut self, source: Value) {
+        let spec = self.spec().as_mapping_mut().unwrap();
+        match (spec.remove("source"), spec.get_mut("sources")) {
+            (None, Some(Value::Sequence(sources))) => sources.push(source),
+            (None, _) => {
+                spec.insert("source".into(), source);
+            }
+            (Some(first), _) => {
+                spec.insert("sources".into(), Value::Sequence(vec![first, source]));
+            }
+        }
+    }
+
+    // Sets a field of the spec to a YAML value, e.g. one the builder has no method for
+    pub fn spec_field(mut self, key: &str, yaml: &str) -> ApplicationBuilder {
+        self.spec()[key] = serde_yaml::from_str(yaml).unwrap();
+        self
+    }
+
+    pub fn automated_sync(mut self) -> ApplicationBuilder {
+        self.spec()["syncPolicy"] = serde_yaml::from_str(
+            "{automated: {prune: true, selfHeal: true}, syncOptions: [CreateNamespace=true]}",
+        )
+        .unwrap();
+        self
+    }
+
+    pub fn build(self) -> K8sResource {
+        K8sResource {
+            file_name: self.file_name,
+            yaml: self.yaml,
+        }
+    }
+}
+
+// Patch options for applications of 'repo' with the defaults of the CLI
+pub fn patch_options(repo: &str) -> PatchOptions<'_> {
+    PatchOptions {
+        repo,
+        repo_rewrites: &[],
+        retain_sync_policy: false,
+        plugins: None,
+        helm_capabilities: None,
+        ignore_annotation: "argocd-diff-preview/ignore",
+        skip_self_managed: false,
+        keep_projects: false,
+        only: None,
+        hold_generated: false,
+        preview_values: None,
+    }
+}
+
+pub fn parse_selector(s: &str) -> Option<Vec<Selector>> {
+    Some(selector::parse(s).unwrap())
+}
+
+// Selects and patches the applications like a run does for a branch
+pub fn select_and_patch(
+    resources: Vec<K8sResource>,
+    selector: &Option<Vec<Selector>>,
+    options: &PatchOptions<'_>,
+    branch: &Branch,
+    revision: &str,
+) -> (Vec<Application>, Vec<SkippedApplication>) {
+    let (applications, skipped) = get_applications(resources, selector, options, branch);
+    (patch_applications(applications, revision, options), skipped)
+}
+
+// The names of the applications, e.g. to compare the selection with the expected one
+pub fn names(applications: &[Application]) -> Vec<&str> {
+    applications
+        .iter()
+        .map(|a| a.yaml["metadata"]["name"].as_str().unwrap_or_default())
+        .collect()
+}
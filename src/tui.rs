@@ -0,0 +1,277 @@
+use crate::manifests::{index_resources, read_rendered_apps};
+use crate::utils::run_command_from_list;
+use crate::Branch;
+use log::info;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use std::collections::BTreeSet;
+use std::error::Error;
+
+struct ChangedApp {
+    name: String,
+    diff: String,
+    kinds: BTreeSet<String>,
+}
+
+enum Mode {
+    Browse,
+    Search,
+}
+
+struct App {
+    apps: Vec<ChangedApp>,
+    kinds: Vec<String>,
+    kind_filter: Option<usize>,
+    search: String,
+    mode: Mode,
+    list_state: ListState,
+    scroll: u16,
+}
+
+pub async fn run(output_folder: &str, line_count: usize) -> Result<(), Box<dyn Error>> {
+    let apps = get_changed_apps(output_folder, line_count).await?;
+    if apps.is_empty() {
+        info!("🤷 No changed applications found in {}", output_folder);
+        return Ok(());
+    }
+
+    let kinds = apps
+        .iter()
+        .flat_map(|a| a.kinds.iter().cloned())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect();
+
+    let mut app = App {
+        apps,
+        kinds,
+        kind_filter: None,
+        search: String::new(),
+        mode: Mode::Browse,
+        list_state: ListState::default().with_selected(Some(0)),
+        scroll: 0,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+async fn get_changed_apps(
+    output_folder: &str,
+    line_count: usize,
+) -> Result<Vec<ChangedApp>, Box<dyn Error>> {
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+
+    let names: BTreeSet<&String> = base_apps.keys().chain(target_apps.keys()).collect();
+
+    let mut apps = vec![];
+    for name in names {
+        let base = base_apps.get(name);
+        let target = target_apps.get(name);
+        if base == target {
+            continue;
+        }
+
+        let base_path = match base {
+            Some(_) => format!("{}/{}", Branch::Base, name),
+            None => "/dev/null".to_string(),
+        };
+        let target_path = match target {
+            Some(_) => format!("{}/{}", Branch::Target, name),
+            None => "/dev/null".to_string(),
+        };
+        let unified = format!("-U{}", line_count);
+        let diff = match run_command_from_list(
+            vec![
+                "git",
+                "--no-pager",
+                "diff",
+                "--no-prefix",
+                &unified,
+                "--no-index",
+                &base_path,
+                &target_path,
+            ],
+            Some(output_folder),
+        )
+        .await
+        {
+            Ok(o) => String::from_utf8_lossy(&o.stdout).to_string(),
+            Err(o) => String::from_utf8_lossy(&o.stdout).to_string(),
+        };
+
+        let kinds = changed_kinds(
+            base.map(|m| m.as_str()).unwrap_or_default(),
+            target.map(|m| m.as_str()).unwrap_or_default(),
+        );
+
+        apps.push(ChangedApp {
+            name: name.clone(),
+            diff,
+            kinds,
+        });
+    }
+
+    Ok(apps)
+}
+
+// Returns the kinds of all resources that were added, removed or modified between the two manifests
+fn changed_kinds(base: &str, target: &str) -> BTreeSet<String> {
+    let base = index_resources(base);
+    let target = index_resources(target);
+
+    base.keys()
+        .chain(target.keys())
+        .filter(|k| base.get(*k) != target.get(*k))
+        .map(|id| id.kind.clone())
+        .collect()
+}
+
+impl App {
+    fn visible_apps(&self) -> Vec<&ChangedApp> {
+        let search = self.search.to_lowercase();
+        self.apps
+            .iter()
+            .filter(|a| search.is_empty() || a.name.to_lowercase().contains(&search))
+            .filter(|a| match self.kind_filter {
+                Some(i) => a.kinds.contains(&self.kinds[i]),
+                None => true,
+            })
+            .collect()
+    }
+
+    fn select(&mut self, offset: i64) {
+        let count = self.visible_apps().len() as i64;
+        if count == 0 {
+            self.list_state.select(None);
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as i64;
+        let next = (current + offset).clamp(0, count - 1);
+        self.list_state.select(Some(next as usize));
+        self.scroll = 0;
+    }
+
+    fn cycle_kind_filter(&mut self) {
+        self.kind_filter = match self.kind_filter {
+            None if !self.kinds.is_empty() => Some(0),
+            Some(i) if i + 1 < self.kinds.len() => Some(i + 1),
+            _ => None,
+        };
+        self.list_state.select(Some(0));
+        self.select(0);
+    }
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<(), Box<dyn Error>> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match app.mode {
+            Mode::Search => match key.code {
+                KeyCode::Enter | KeyCode::Esc => app.mode = Mode::Browse,
+                KeyCode::Backspace => {
+                    app.search.pop();
+                    app.select(0);
+                }
+                KeyCode::Char(c) => {
+                    app.search.push(c);
+                    app.list_state.select(Some(0));
+                    app.select(0);
+                }
+                _ => (),
+            },
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => app.select(1),
+                KeyCode::Up | KeyCode::Char('k') => app.select(-1),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    app.scroll = app.scroll.saturating_add(20)
+                }
+                KeyCode::PageUp => app.scroll = app.scroll.saturating_sub(20),
+                KeyCode::Char('/') => app.mode = Mode::Search,
+                KeyCode::Char('f') => app.cycle_kind_filter(),
+                _ => (),
+            },
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .split(rows[0]);
+
+    let visible = app.visible_apps();
+    let selected = app.list_state.selected().and_then(|i| visible.get(i));
+
+    let diff_lines: Vec<Line> = selected
+        .map(|a| a.diff.lines().map(diff_line).collect())
+        .unwrap_or_default();
+    let diff_title = selected
+        .map(|a| format!(" {} ", a.name))
+        .unwrap_or(" No application selected ".to_string());
+
+    let items: Vec<ListItem> = visible
+        .iter()
+        .map(|a| ListItem::new(a.name.clone()))
+        .collect();
+    let list_title = format!(" Applications ({}/{}) ", visible.len(), app.apps.len());
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(list_title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let diff = Paragraph::new(diff_lines)
+        .block(Block::default().borders(Borders::ALL).title(diff_title))
+        .scroll((app.scroll, 0));
+
+    let kind = app
+        .kind_filter
+        .map(|i| app.kinds[i].as_str())
+        .unwrap_or("all");
+    let status = match app.mode {
+        Mode::Search => format!("Search: {}█", app.search),
+        Mode::Browse => format!(
+            "q: quit | ↑/↓: select | PgUp/PgDn: scroll | /: search ({}) | f: kind ({})",
+            app.search, kind
+        ),
+    };
+
+    let mut list_state = app.list_state.clone();
+    frame.render_stateful_widget(list, columns[0], &mut list_state);
+    frame.render_widget(diff, columns[1]);
+    frame.render_widget(Paragraph::new(status), rows[1]);
+    app.list_state = list_state;
+}
+
+fn diff_line(line: &str) -> Line<'_> {
+    let color = match line {
+        l if l.starts_with("+++") || l.starts_with("---") => Color::White,
+        l if l.starts_with('+') => Color::Green,
+        l if l.starts_with('-') => Color::Red,
+        l if l.starts_with("@@") => Color::Cyan,
+        _ => Color::Reset,
+    };
+    Line::from(Span::styled(line, Style::default().fg(color)))
+}
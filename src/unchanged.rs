@@ -0,0 +1,301 @@
+// Rendering takes minutes, while many pull requests (e.g. documentation changes) can't change the
+// rendered manifests at all: the applications are identical in both branches, and none of the
+// changed files is in a directory the applications render from, or in a directory their
+// kustomizations, charts and value files load from
+use crate::model::{ApplicationSource, ApplicationSpec};
+use crate::parsing::{get_resources_of_kinds, AppRoot};
+use crate::repo_url::RepoUrl;
+use log::{debug, info};
+use regex::Regex;
+use serde_yaml::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path};
+
+const APPLICATION_KINDS: [&str; 2] = ["Application", "ApplicationSet"];
+
+const KUSTOMIZATION_FILES: [&str; 3] = ["kustomization.yaml", "kustomization.yml", "Kustomization"];
+
+// Kustomization fields whose entries are files or directories, relative to the kustomization
+const KUSTOMIZATION_FIELDS: [&str; 9] = [
+    "resources",
+    "components",
+    "bases",
+    "crds",
+    "configurations",
+    "patchesStrategicMerge",
+    "generators",
+    "transformers",
+    "validators",
+];
+
+async fn read_applications(folders: &[String], regex: &Option<Regex>) -> Vec<serde_yaml::Value> {
+    let mut applications = vec![];
+    for folder in folders {
+        applications.extend(get_resources_of_kinds(folder, regex, &APPLICATION_KINDS).await);
+    }
+    applications.sort_by_key(|a| serde_yaml::to_string(a).unwrap_or_default());
+    applications
+}
+
+// Resolves '.' and '..' in a path relative to the root of the repository. None when it leaves it
+fn clean(path: &Path) -> Option<String> {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+// Remote bases and charts are not in the repository
+fn is_remote(entry: &str) -> bool {
+    entry.contains("://") || entry.starts_with("github.com/") || entry.starts_with("git@")
+}
+
+fn strings(value: &Value) -> impl Iterator<Item = &str> {
+    value
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str())
+}
+
+// The files and directories the kustomization or the Helm chart in a directory of the repository
+// loads, e.g. 'resources: [../base]' or a 'file://' dependency of a chart
+fn references(checkout: &Path, dir: &str) -> Vec<String> {
+    let read = |file: &str| {
+        fs::read_to_string(checkout.join(dir).join(file))
+            .ok()
+            .and_then(|c| serde_yaml::from_str::<Value>(&c).ok())
+    };
+    let mut entries: Vec<String> = vec![];
+    for kustomization in KUSTOMIZATION_FILES.iter().filter_map(|f| read(f)) {
+        for field in KUSTOMIZATION_FIELDS {
+            entries.extend(strings(&kustomization[field]).map(String::from));
+        }
+        for patch in kustomization["patches"].as_sequence().into_iter().flatten() {
+            entries.extend(patch["path"].as_str().map(String::from));
+        }
+        for generator in ["configMapGenerator", "secretGenerator"] {
+            for g in kustomization[generator].as_sequence().into_iter().flatten() {
+                // files are 'path' or 'key=path'
+                let files = strings(&g["files"]).filter_map(|f| f.rsplit('=').next());
+                entries.extend(files.chain(strings(&g["envs"])).map(String::from));
+                entries.extend(g["env"].as_str().map(String::from));
+            }
+        }
+        for chart in kustomization["helmCharts"]
+            .as_sequence()
+            .into_iter()
+            .flatten()
+        {
+            entries.extend(chart["valuesFile"].as_str().map(String::from));
+            entries.extend(strings(&chart["additionalValuesFiles"]).map(String::from));
+        }
+        entries.extend(
+            kustomization["helmGlobals"]["chartHome"]
+                .as_str()
+                .map(String::from),
+        );
+        entries.extend(kustomization["openapi"]["path"].as_str().map(String::from));
+    }
+    if let Some(chart) = read("Chart.yaml") {
+        for dependency in chart["dependencies"].as_sequence().into_iter().flatten() {
+            let repository = dependency["repository"].as_str().unwrap_or_default();
+            entries.extend(repository.strip_prefix("file://").map(String::from));
+        }
+    }
+    entries
+        .iter()
+        .filter(|e| !is_remote(e))
+        .filter_map(|e| clean(&Path::new(dir).join(e)))
+        .collect()
+}
+
+// The paths and everything they load from other directories, in the checkouts of both branches
+fn with_references(paths: BTreeSet<String>, checkouts: &[&Path]) -> BTreeSet<String> {
+    let mut all = BTreeSet::new();
+    let mut pending: Vec<String> = paths.into_iter().collect();
+    while let Some(path) = pending.pop() {
+        if all.insert(path.clone()) {
+            for checkout in checkouts {
+                pending.extend(references(checkout, &path));
+            }
+        }
+    }
+    all
+}
+
+// The directories of the repository the applications render from: the paths of their sources, the
+// value files they reference from it and everything their kustomizations and charts load, in the
+// checkouts of the branches. None when a path can't be known before rendering, e.g. a templated
+// path of an ApplicationSet
+fn source_paths(
+    applications: &[serde_yaml::Value],
+    repo: &RepoUrl,
+    checkouts: &[&Path],
+) -> Option<BTreeSet<String>> {
+    let mut paths = BTreeSet::new();
+    for application in applications {
+        // a path can't be known when the spec can't be read
+        let spec = ApplicationSpec::of(application).ok()?;
+        let sources: Vec<&ApplicationSource> = spec.sources().collect();
+        // sources with a 'ref' make their repository available to the value files of other sources
+        let refs: BTreeSet<&str> = sources
+            .iter()
+            .filter(|s| s.is_from(repo))
+            .filter_map(|s| s.reference.as_deref())
+            .collect();
+        for source in &sources {
+            // a source with only a 'ref' doesn't render anything itself
+            let renders = source.path.is_some() || source.reference.is_none();
+            if source.is_from(repo) && source.chart.is_none() && renders {
+                paths.insert(source.path.clone().unwrap_or_default());
+            }
+            for file in source.value_files() {
+                if let Some((name, path)) = file.trim_start_matches('$').split_once('/') {
+                    if file.starts_with('$') && refs.contains(name) {
+                        paths.insert(path.to_string());
+                    }
+                }
+                // other value files are relative to the path of the source
+                if !file.starts_with('$') && !is_remote(file) && source.is_from(repo) {
+                    let path = Path::new(source.path.as_deref().unwrap_or_default()).join(file);
+                    paths.extend(clean(&path));
+                }
+            }
+        }
+    }
+    let paths: BTreeSet<String> = paths
+        .into_iter()
+        .map(|p| p.trim_start_matches("./").trim_matches('/').to_string())
+        .collect();
+    match paths.iter().any(|p| p.contains("{{")) {
+        true => None,
+        false => Some(with_references(paths, checkouts)),
+    }
+}
+
+fn is_in(file: &str, path: &str) -> bool {
+    path.is_empty() || path == "." || file == path || file.starts_with(&format!("{}/", path))
+}
+
+fn normalize(changed_files: &[String]) -> Vec<&str> {
+    changed_files
+        .iter()
+        .map(|f| f.trim().trim_start_matches("./"))
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+// Applications are only read from the repository under preview, whose changed files are known
+fn reads_other_repositories(app_roots: &[AppRoot], repo: &RepoUrl) -> bool {
+    app_roots
+        .iter()
+        .any(|r| !repo.matches(&RepoUrl::parse(&r.repo)))
+}
+
+// Whether rendering can be skipped, given the files changed between the branches (relative to the
+// root of the repository)
+pub async fn nothing_to_render(
+    app_roots: &[AppRoot],
+    repo: &str,
+    base_folder: &str,
+    target_folder: &str,
+    regex: &Option<Regex>,
+    changed_files: &[String],
+) -> bool {
+    // changed files are only known for the repository under preview
+    let repo = RepoUrl::parse(repo);
+    if reads_other_repositories(app_roots, &repo) {
+        debug!("Not skipping rendering, since applications are read from other repositories");
+        return false;
+    }
+
+    let base_folders: Vec<String> = app_roots.iter().map(|r| r.folder(base_folder)).collect();
+    let target_folders: Vec<String> = app_roots.iter().map(|r| r.folder(target_folder)).collect();
+    let base = read_applications(&base_folders, regex).await;
+    let target = read_applications(&target_folders, regex).await;
+    if base != target {
+        info!("🔎 Applications differ between the branches, so they are rendered");
+        return false;
+    }
+
+    let checkouts = [Path::new(base_folder), Path::new(target_folder)];
+    let Some(paths) = source_paths(&target, &repo, &checkouts) else {
+        info!("🔎 Applications have templated source paths, so they are rendered");
+        return false;
+    };
+    for file in normalize(changed_files) {
+        if let Some(path) = paths.iter().find(|p| is_in(file, p)) {
+            info!(
+                "🔎 {} changed in the source path '{}', so the applications are rendered",
+                file, path
+            );
+            return false;
+        }
+    }
+    true
+}
+
+// The names of the applications the changes can affect: the ones that differ between the branches
+// or exist in one of them only, have a templated source path, or render from or load a directory
+// with a changed file. None when it can't be known, e.g. for applications of other repositories
+pub async fn affected_applications(
+    app_roots: &[AppRoot],
+    repo: &str,
+    base_folder: &str,
+    target_folder: &str,
+    regex: &Option<Regex>,
+    changed_files: &[String],
+) -> Option<BTreeSet<String>> {
+    let repo = RepoUrl::parse(repo);
+    if reads_other_repositories(app_roots, &repo) {
+        info!("⚠️ Rendering all applications, since applications are read from other repositories");
+        return None;
+    }
+
+    let base_folders: Vec<String> = app_roots.iter().map(|r| r.folder(base_folder)).collect();
+    let target_folders: Vec<String> = app_roots.iter().map(|r| r.folder(target_folder)).collect();
+    let base = read_applications(&base_folders, regex).await;
+    let target = read_applications(&target_folders, regex).await;
+    let files = normalize(changed_files);
+    let checkouts = [Path::new(base_folder), Path::new(target_folder)];
+
+    let name = |a: &serde_yaml::Value| {
+        a["metadata"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut affected = BTreeSet::new();
+    for application in &target {
+        let renders_changed_file =
+            match source_paths(std::slice::from_ref(application), &repo, &checkouts) {
+                Some(paths) => files.iter().any(|f| paths.iter().any(|p| is_in(f, p))),
+                None => true,
+            };
+        if renders_changed_file || !base.contains(application) {
+            debug!(
+                "Application {} is affected by the changes",
+                name(application)
+            );
+            affected.insert(name(application));
+        }
+    }
+    for application in base.iter().filter(|a| !target.contains(a)) {
+        debug!(
+            "Application {} is affected by the changes",
+            name(application)
+        );
+        affected.insert(name(application));
+    }
+    Some(affected)
+}
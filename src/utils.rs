@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::SystemTime;
 use std::{
     fs,
     process::{Command, Output},
@@ -15,21 +17,92 @@ pub fn check_if_folder_exists(folder_name: &str) -> bool {
     PathBuf::from(folder_name).is_dir()
 }
 
+// Emojis (and the characters joining them) are mangled by some CI log processors and terminals
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x2300..=0x23FF // technical symbols, e.g. ⏳
+        | 0x2600..=0x27BF // symbols and dingbats, e.g. ⚠ ✨ ❌
+        | 0x2B00..=0x2BFF // arrows and shapes, e.g. ⭐
+        | 0x1F000..=0x1FAFF // pictographs
+        | 0x200D // zero width joiner
+        | 0xFE0F // variation selector
+    )
+}
+
+// A log line for --plain-logs: the level as prefix and no emojis. Warnings are logged as info
+// with a ⚠️, so they get their own prefix
+pub fn plain_log_line(level: log::Level, message: &str) -> String {
+    let prefix = match level {
+        log::Level::Info if message.starts_with('⚠') => "WARN".to_string(),
+        level => level.to_string(),
+    };
+    let mut plain = String::with_capacity(message.len());
+    let mut after_emoji = false;
+    for c in message.chars() {
+        match c {
+            c if is_emoji(c) => after_emoji = true,
+            // the space separating an emoji from the text
+            ' ' if after_emoji && (plain.is_empty() || plain.ends_with(' ')) => after_emoji = false,
+            '→' => plain.push_str("->"),
+            c => {
+                after_emoji = false;
+                plain.push(c);
+            }
+        }
+    }
+    format!("[{}] {}", prefix, plain)
+}
+
+// FNV-1a, for hashes that must be the same on every platform and Rust version
+pub fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// How the argocd CLI connects to Argo CD: its options, and in core mode a kubeconfig whose context
+// has the Argo CD namespace. Set whenever Argo CD is installed, depending on the installed components
+struct ArgoCDClient {
+    opts: String,
+    kubeconfig: Option<String>,
+}
+
+static ARGOCD_CLIENT: Mutex<Option<ArgoCDClient>> = Mutex::new(None);
+
+const DEFAULT_ARGOCD_OPTS: &str = "--port-forward --port-forward-namespace=argocd";
+
+pub fn set_argocd_opts(opts: &str, kubeconfig: Option<&str>) {
+    if let Ok(mut client) = ARGOCD_CLIENT.lock() {
+        *client = Some(ArgoCDClient {
+            opts: opts.to_string(),
+            kubeconfig: kubeconfig.map(|k| k.to_string()),
+        });
+    }
+}
+
 pub async fn run_command(command: &str, current_dir: Option<&str>) -> Result<Output, Output> {
     let args = command.split_whitespace().collect::<Vec<&str>>();
     run_command_from_list(args, current_dir).await
 }
 
 pub async fn run_command_from_list(command: Vec<&str>, current_dir: Option<&str>) -> Result<Output, Output> {
-    let output = Command::new(command[0])
-        .args(&command[1..])
-        .env(
-            "ARGOCD_OPTS",
-            "--port-forward --port-forward-namespace=argocd",
-        )
+    let mut process = Command::new(command[0]);
+    process.args(&command[1..]);
+    match ARGOCD_CLIENT.lock().ok().as_deref() {
+        Some(Some(client)) => {
+            process.env("ARGOCD_OPTS", &client.opts);
+            if let (Some(kubeconfig), "argocd") = (&client.kubeconfig, command[0]) {
+                process.env("KUBECONFIG", kubeconfig);
+            }
+        }
+        _ => {
+            process.env("ARGOCD_OPTS", DEFAULT_ARGOCD_OPTS);
+        }
+    }
+    let output = process
         .current_dir(current_dir.unwrap_or("."))
         .output()
-        .expect(format!("Failed to execute command: {}", command.join(" ")).as_str());
+        .unwrap_or_else(|_| panic!("Failed to execute command: {}", command.join(" ")));
 
     if !output.status.success() {
         return Err(output);
@@ -38,6 +111,18 @@ pub async fn run_command_from_list(command: Vec<&str>, current_dir: Option<&str>
     Ok(output)
 }
 
+// Runs a command and waits for it, ignoring its output. Used when there is no runtime to await on
+pub fn wait_for_command(command: &str) {
+    let args = command.split_whitespace().collect::<Vec<&str>>();
+    let _ = Command::new(args[0])
+        .args(&args[1..])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+// The spawned process is intentionally left running in the background
+#[allow(clippy::zombie_processes)]
 pub fn spawn_command(command: &str, current_dir: Option<&str>) {
     let args = command.split_whitespace().collect::<Vec<&str>>();
     Command::new(args[0])
@@ -46,5 +131,29 @@ pub fn spawn_command(command: &str, current_dir: Option<&str>) {
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .spawn()
-        .expect(format!("Failed to execute command: {}", command).as_str());
+        .unwrap_or_else(|_| panic!("Failed to execute command: {}", command));
+}
+
+// Returns a fingerprint of all files in the folders, which changes when a file is added, removed or modified
+fn folder_fingerprint(folders: &[&str]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    folders
+        .iter()
+        .flat_map(|folder| walkdir::WalkDir::new(folder).sort_by_file_name())
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .map(|e| {
+            let modified = e.metadata().ok().and_then(|m| m.modified().ok());
+            (e.into_path(), modified)
+        })
+        .collect()
+}
+
+pub async fn wait_for_changes(folders: &[&str]) {
+    let fingerprint = folder_fingerprint(folders);
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        if folder_fingerprint(folders) != fingerprint {
+            return;
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use crate::diff::ReportSection;
+use crate::manifests::resource_id;
+use crate::model::ApplicationSpec;
+use crate::parsing::get_resources_of_kinds;
+use crate::specs::diff_branch_folders;
+use log::info;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+const VALUES_KINDS: [&str; 2] = ["Application", "ApplicationSet"];
+const VALUES_FOLDER: &str = "values-changes";
+
+// The inline values of every source, by a file name naming the application and, for multi-source
+// applications, the index of the source
+async fn read_inline_values(
+    folders: &[String],
+    regex: &Option<Regex>,
+) -> BTreeMap<String, serde_yaml::Value> {
+    let mut values = BTreeMap::new();
+    for folder in folders {
+        for r in get_resources_of_kinds(folder, regex, &VALUES_KINDS).await {
+            let Ok(spec) = ApplicationSpec::of(&r) else {
+                continue;
+            };
+            let name = resource_id(&r).to_string().replace('/', "_");
+            let multi_source = spec.sources.is_some();
+            for (i, source) in spec.sources().enumerate() {
+                if let Some(v) = source.inline_values() {
+                    let key = match multi_source {
+                        true => format!("{}_source-{}.yaml", name, i),
+                        false => format!("{}.yaml", name),
+                    };
+                    values.insert(key, v);
+                }
+            }
+        }
+    }
+    values
+}
+
+// Diffs the inline Helm values (helm.values and helm.valuesObject) of the applications in both
+// branches, so a change to a value can be reviewed next to the manifests it changes
+pub async fn generate_values_section(
+    output_folder: &str,
+    base_folders: &[String],
+    target_folders: &[String],
+    regex: &Option<Regex>,
+) -> Result<Option<ReportSection>, Box<dyn Error>> {
+    info!("🎚️ Comparing the inline Helm values of both branches");
+
+    let base = read_inline_values(base_folders, regex).await;
+    let target = read_inline_values(target_folders, regex).await;
+    let changed: BTreeSet<&String> = base
+        .keys()
+        .chain(target.keys())
+        .filter(|key| base.get(*key) != target.get(*key))
+        .collect();
+    let folder = format!("{}/{}", output_folder, VALUES_FOLDER);
+    fs::remove_dir_all(&folder).unwrap_or_default();
+    if changed.is_empty() {
+        info!("🎚️ No changes to inline Helm values found");
+        return Ok(None);
+    }
+
+    for (branch, values) in [("base", &base), ("target", &target)] {
+        fs::create_dir_all(format!("{}/{}", folder, branch))?;
+        for key in &changed {
+            if let Some(v) = values.get(*key) {
+                fs::write(
+                    format!("{}/{}/{}", folder, branch, key),
+                    serde_yaml::to_string(v)?,
+                )?;
+            }
+        }
+    }
+
+    let diff = diff_branch_folders(&folder).await?;
+
+    Ok(Some(ReportSection {
+        title: format!("🎚️ Helm values changes ({}):", changed.len()),
+        content: format!("```diff\n{}\n```", diff),
+    }))
+}
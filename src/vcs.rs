@@ -0,0 +1,173 @@
+// The files changed in a pull request decide whether anything has to be rendered (see
+// '--changed-files'). Instead of passing them in, which needs the variables of each CI provider, they
+// can be computed from the git history of the target branch folder. The history is read with the
+// git CLI, or with gitoxide when the tool is built with the 'gitoxide' feature
+use log::debug;
+use std::error::Error;
+use std::process::Command;
+
+pub trait Vcs {
+    // Resolves a revision (a SHA, a branch or a remote branch) to a commit ID
+    fn resolve(&self, revision: &str) -> Result<String, Box<dyn Error>>;
+    // The best common ancestor of two commits
+    fn merge_base(&self, one: &str, two: &str) -> Result<String, Box<dyn Error>>;
+    // Paths of the files that differ between two commits, relative to the root of the repository
+    fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+pub struct GitCli {
+    dir: String,
+}
+
+impl GitCli {
+    fn git(&self, args: &[&str]) -> Result<String, Box<dyn Error>> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Vcs for GitCli {
+    fn resolve(&self, revision: &str) -> Result<String, Box<dyn Error>> {
+        self.git(&["rev-parse", "--verify", &format!("{}^{{commit}}", revision)])
+    }
+
+    fn merge_base(&self, one: &str, two: &str) -> Result<String, Box<dyn Error>> {
+        self.git(&["merge-base", one, two])
+    }
+
+    fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        // a renamed file is changed in both places
+        let files = self.git(&["diff", "--name-only", "--no-renames", from, to])?;
+        Ok(files.lines().map(|f| f.to_string()).collect())
+    }
+}
+
+#[cfg(feature = "gitoxide")]
+pub struct Gitoxide {
+    repo: gix::Repository,
+}
+
+#[cfg(feature = "gitoxide")]
+impl Gitoxide {
+    fn tree(&self, commit: &str) -> Result<gix::Tree<'_>, Box<dyn Error>> {
+        let id = gix::ObjectId::from_hex(commit.as_bytes())?;
+        Ok(self.repo.find_commit(id)?.tree()?)
+    }
+}
+
+#[cfg(feature = "gitoxide")]
+impl Vcs for Gitoxide {
+    fn resolve(&self, revision: &str) -> Result<String, Box<dyn Error>> {
+        let commit = self
+            .repo
+            .rev_parse_single(revision)?
+            .object()?
+            .peel_to_commit()?;
+        Ok(commit.id.to_string())
+    }
+
+    fn merge_base(&self, one: &str, two: &str) -> Result<String, Box<dyn Error>> {
+        let one = gix::ObjectId::from_hex(one.as_bytes())?;
+        let two = gix::ObjectId::from_hex(two.as_bytes())?;
+        Ok(self.repo.merge_base(one, two)?.to_string())
+    }
+
+    fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let (from, to) = (self.tree(from)?, self.tree(to)?);
+        // the default options track no renames, like 'git diff --no-renames'
+        let changes =
+            self.repo
+                .diff_tree_to_tree(Some(&from), Some(&to), gix::diff::Options::default())?;
+        Ok(changes
+            .iter()
+            .filter(|c| !c.entry_mode().is_tree())
+            .map(|c| c.location().to_string())
+            .collect())
+    }
+}
+
+// A commit as shown at the top of the report
+pub struct Commit {
+    pub sha: String,
+    pub author: String,
+    pub subject: String,
+}
+
+// Reads a commit of the repository in 'dir' with the git CLI, which every checkout has
+pub fn read_commit(dir: &str, revision: &str) -> Result<Commit, Box<dyn Error>> {
+    let git = GitCli {
+        dir: dir.to_string(),
+    };
+    let log = git.git(&["log", "-1", "--format=%H%x00%an%x00%s", revision])?;
+    let mut fields = log.splitn(3, '\0').map(|f| f.to_string());
+    Ok(Commit {
+        sha: fields.next().unwrap_or_default(),
+        author: fields.next().unwrap_or_default(),
+        subject: fields.next().unwrap_or_default(),
+    })
+}
+
+// Checks that this build can read the history with the VCS, before anything is rendered
+pub fn check(name: &str) -> Result<(), Box<dyn Error>> {
+    match name {
+        "git" => Ok(()),
+        "gitoxide" if cfg!(feature = "gitoxide") => Ok(()),
+        "gitoxide" => {
+            Err("this build has no gitoxide support. Build with '--features gitoxide'".into())
+        }
+        name => Err(format!("unknown VCS '{}'. Options: git, gitoxide", name).into()),
+    }
+}
+
+// Opens the repository in 'dir' with the git CLI ('git') or gitoxide ('gitoxide')
+pub fn open(name: &str, dir: &str) -> Result<Box<dyn Vcs>, Box<dyn Error>> {
+    check(name)?;
+    match name {
+        #[cfg(feature = "gitoxide")]
+        "gitoxide" => Ok(Box::new(Gitoxide {
+            repo: gix::open(dir)?,
+        })),
+        _ => Ok(Box::new(GitCli {
+            dir: dir.to_string(),
+        })),
+    }
+}
+
+// The files changed in 'head' since it branched off the base, like 'git diff base...head'. The
+// base is the first of 'base_revisions' that exists, e.g. the remote branch before a local one
+pub fn changed_files(
+    vcs: &dyn Vcs,
+    base_revisions: &[String],
+    head: &str,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let base = base_revisions
+        .iter()
+        .find_map(|r| vcs.resolve(r).ok())
+        .ok_or_else(|| {
+            format!(
+                "none of {} exists. Is the base branch fetched?",
+                base_revisions.join(", ")
+            )
+        })?;
+    let head = vcs.resolve(head)?;
+    let merge_base = vcs.merge_base(&base, &head).map_err(|e| {
+        format!(
+            "no common history of the branches, e.g. in a shallow clone ('fetch-depth: 0' fetches all of it): {}",
+            e
+        )
+    })?;
+    debug!("Changed files between {} and {}", merge_base, head);
+    vcs.changed_files(&merge_base, &head)
+}
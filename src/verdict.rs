@@ -0,0 +1,273 @@
+use crate::config::read_config;
+use crate::conversions::{find_conversions, renamed};
+use crate::diff::ReportSection;
+use crate::manifests::{flatten, index_resources, read_rendered_apps, ResourceId};
+use crate::Branch;
+use log::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+
+const RBAC_KINDS: [&str; 4] = ["Role", "ClusterRole", "RoleBinding", "ClusterRoleBinding"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    AutoApprove,
+    NeedsReview,
+    Block,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Verdict::AutoApprove => write!(f, "auto-approve"),
+            Verdict::NeedsReview => write!(f, "needs-review"),
+            Verdict::Block => write!(f, "block"),
+        }
+    }
+}
+
+impl Verdict {
+    // Used with --verdict-exit-code, so CI can act on the verdict without parsing verdict.json
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Verdict::AutoApprove => 0,
+            Verdict::NeedsReview => 2,
+            Verdict::Block => 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Condition {
+    NoChanges,
+    OnlyImageTagChanges,
+    NoRbacChanges,
+    NoDeletions,
+    MaxChangedApplications(usize),
+    MaxChangedResources(usize),
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Condition::NoChanges => write!(f, "noChanges"),
+            Condition::OnlyImageTagChanges => write!(f, "onlyImageTagChanges"),
+            Condition::NoRbacChanges => write!(f, "noRbacChanges"),
+            Condition::NoDeletions => write!(f, "noDeletions"),
+            Condition::MaxChangedApplications(n) => write!(f, "maxChangedApplications: {}", n),
+            Condition::MaxChangedResources(n) => write!(f, "maxChangedResources: {}", n),
+        }
+    }
+}
+
+// Conditions are either a name ('noDeletions') or a name with a limit ('maxChangedResources: 10')
+fn parse_condition(value: &serde_yaml::Value) -> Result<Condition, Box<dyn Error>> {
+    if let Some(name) = value.as_str() {
+        return match name {
+            "noChanges" => Ok(Condition::NoChanges),
+            "onlyImageTagChanges" => Ok(Condition::OnlyImageTagChanges),
+            "noRbacChanges" => Ok(Condition::NoRbacChanges),
+            "noDeletions" => Ok(Condition::NoDeletions),
+            _ => Err(format!("unknown condition '{}'", name).into()),
+        };
+    }
+    let (name, limit) = value
+        .as_mapping()
+        .filter(|m| m.len() == 1)
+        .and_then(|m| m.iter().next())
+        .and_then(|(k, v)| Some((k.as_str()?, v.as_u64()? as usize)))
+        .ok_or_else(|| format!("invalid condition: {:?}", value))?;
+    match name {
+        "maxChangedApplications" => Ok(Condition::MaxChangedApplications(limit)),
+        "maxChangedResources" => Ok(Condition::MaxChangedResources(limit)),
+        _ => Err(format!("unknown condition '{}'", name).into()),
+    }
+}
+
+pub struct VerdictRules {
+    // all of them must hold for 'auto-approve'
+    auto_approve_if: Vec<Condition>,
+    // 'block' if any of them doesn't hold
+    block_unless: Vec<Condition>,
+}
+
+pub fn read_verdict_rules(path: &str) -> Result<VerdictRules, Box<dyn Error>> {
+    let config: serde_yaml::Value = read_config(path)?;
+    debug!("Verdict rules: {:?}", config);
+    let conditions = |key: &str| -> Result<Vec<Condition>, Box<dyn Error>> {
+        config[key]
+            .as_sequence()
+            .into_iter()
+            .flatten()
+            .map(parse_condition)
+            .collect()
+    };
+    let rules = VerdictRules {
+        auto_approve_if: conditions("autoApproveIf")?,
+        block_unless: conditions("blockUnless")?,
+    };
+    if rules.auto_approve_if.is_empty() && rules.block_unless.is_empty() {
+        return Err("verdict rules have neither 'autoApproveIf' nor 'blockUnless'".into());
+    }
+    Ok(rules)
+}
+
+// What changed between the branches, as far as the conditions are concerned
+#[derive(Default)]
+pub struct Facts {
+    pub changed_applications: usize,
+    pub changed_resources: usize,
+    pub deleted_resources: usize,
+    pub rbac_changes: usize,
+    // changes to anything but the tag or digest of container images
+    pub other_than_image_tags: usize,
+}
+
+// 'registry:5000/app:1.2.3' and 'app@sha256:...' without the tag or digest
+fn image_name(image: &str) -> &str {
+    let image = image.split('@').next().unwrap_or_default();
+    match image.rfind(':') {
+        Some(i) if !image[i..].contains('/') => &image[..i],
+        _ => image,
+    }
+}
+
+fn only_image_tags_differ(before: &serde_yaml::Value, after: &serde_yaml::Value) -> bool {
+    let (mut before_fields, mut after_fields) = (BTreeMap::new(), BTreeMap::new());
+    flatten(before, "", &mut before_fields);
+    flatten(after, "", &mut after_fields);
+    let paths: BTreeSet<&String> = before_fields.keys().chain(after_fields.keys()).collect();
+    let only_images =
+        paths.into_iter().all(
+            |path| match (before_fields.get(path), after_fields.get(path)) {
+                (b, a) if b == a => true,
+                (Some(b), Some(a)) if path.ends_with(".image") => image_name(b) == image_name(a),
+                _ => false,
+            },
+        );
+    only_images
+}
+
+pub fn collect_facts(output_folder: &str) -> Facts {
+    let base_apps = read_rendered_apps(output_folder, &Branch::Base);
+    let target_apps = read_rendered_apps(output_folder, &Branch::Target);
+    // converted applications are compared with their application of the base branch
+    let renamed = renamed(&find_conversions(output_folder));
+    let names: BTreeSet<&String> = base_apps
+        .keys()
+        .filter(|name| !renamed.values().any(|base| base == *name))
+        .chain(target_apps.keys())
+        .collect();
+
+    let mut facts = Facts::default();
+    for name in names {
+        let base_name = renamed.get(name).unwrap_or(name);
+        let (before, after) = (base_apps.get(base_name), target_apps.get(name));
+        if before == after {
+            continue;
+        }
+        facts.changed_applications += 1;
+
+        let before = index_resources(before.map(|m| m.as_str()).unwrap_or_default());
+        let after = index_resources(after.map(|m| m.as_str()).unwrap_or_default());
+        let ids: BTreeSet<&ResourceId> = before.keys().chain(after.keys()).collect();
+        for id in ids {
+            let (b, a) = (before.get(id), after.get(id));
+            if b == a {
+                continue;
+            }
+            facts.changed_resources += 1;
+            if RBAC_KINDS.contains(&id.kind.as_str()) {
+                facts.rbac_changes += 1;
+            }
+            match (b, a) {
+                (Some(_), None) => {
+                    facts.deleted_resources += 1;
+                    facts.other_than_image_tags += 1;
+                }
+                (Some(b), Some(a)) if only_image_tags_differ(b, a) => (),
+                _ => facts.other_than_image_tags += 1,
+            }
+        }
+    }
+    facts
+}
+
+fn holds(condition: &Condition, facts: &Facts) -> bool {
+    match condition {
+        Condition::NoChanges => facts.changed_resources == 0,
+        Condition::OnlyImageTagChanges => facts.other_than_image_tags == 0,
+        Condition::NoRbacChanges => facts.rbac_changes == 0,
+        Condition::NoDeletions => facts.deleted_resources == 0,
+        Condition::MaxChangedApplications(n) => facts.changed_applications <= *n,
+        Condition::MaxChangedResources(n) => facts.changed_resources <= *n,
+    }
+}
+
+// Evaluates the rules against the rendered manifests, writes the verdict to 'verdict.json' in the
+// output folder and returns it, together with the block conditions that don't hold and a report
+// section explaining it
+pub fn evaluate(
+    output_folder: &str,
+    rules: &VerdictRules,
+) -> Result<(Verdict, Vec<String>, ReportSection), Box<dyn Error>> {
+    let facts = collect_facts(output_folder);
+
+    let check = |conditions: &[Condition]| -> Vec<(String, bool)> {
+        conditions
+            .iter()
+            .map(|c| (c.to_string(), holds(c, &facts)))
+            .collect()
+    };
+    let block_unless = check(&rules.block_unless);
+    let auto_approve_if = check(&rules.auto_approve_if);
+
+    let verdict = match () {
+        _ if block_unless.iter().any(|(_, ok)| !ok) => Verdict::Block,
+        _ if !auto_approve_if.is_empty() && auto_approve_if.iter().all(|(_, ok)| *ok) => {
+            Verdict::AutoApprove
+        }
+        _ => Verdict::NeedsReview,
+    };
+    info!("🚦 Verdict: {}", verdict);
+
+    let failed = |results: &[(String, bool)]| -> Vec<String> {
+        results
+            .iter()
+            .filter(|(_, ok)| !ok)
+            .map(|(c, _)| c.clone())
+            .collect()
+    };
+    let failed_block_conditions = failed(&block_unless);
+    let json = serde_json::json!({
+        "verdict": verdict.to_string(),
+        "failedBlockConditions": failed_block_conditions,
+        "failedAutoApproveConditions": failed(&auto_approve_if),
+        "changedApplications": facts.changed_applications,
+        "changedResources": facts.changed_resources,
+        "deletedResources": facts.deleted_resources,
+    });
+    fs::write(
+        format!("{}/verdict.json", output_folder),
+        serde_json::to_string_pretty(&json)?,
+    )?;
+
+    let rows = block_unless
+        .iter()
+        .map(|(c, ok)| (c, ok, "block unless"))
+        .chain(
+            auto_approve_if
+                .iter()
+                .map(|(c, ok)| (c, ok, "auto-approve if")),
+        )
+        .map(|(c, ok, rule)| format!("| {} | `{}` | {} |", rule, c, if *ok { "✅" } else { "❌" }))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let section = ReportSection {
+        title: format!("🚦 Verdict: {}", verdict),
+        content: format!("| Rule | Condition | Holds |\n|---|---|---|\n{}", rows),
+    };
+    Ok((verdict, failed_block_conditions, section))
+}
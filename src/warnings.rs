@@ -0,0 +1,118 @@
+// Warnings of a run, like unknown fields in an application or sources that aren't pointed to the
+// branch, are easy to miss in the log. Besides being logged, they are collected and written to
+// 'warnings.json' as an array, and listed in a section of the report, so pipeline owners can track
+// their number over time
+use crate::diff::ReportSection;
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    // A field of an application spec or source that Argo CD doesn't know, e.g. a typo
+    UnknownField,
+    // A document that looks like an application, but isn't valid YAML or has an invalid spec
+    SkippedDocument,
+    // A source of another repository, so changes to it are not previewed
+    UnmatchedRepoUrl,
+    // An application without a destination
+    MissingDestination,
+    // A value file that refers to a source that is not pointed to the branch, or doesn't exist
+    ValueFileRef,
+    // An ignore annotation with a value other than 'true', 'base-only' or 'target-only'
+    IgnoreAnnotation,
+    // A project of an application that is neither in the branch nor in the cluster (--keep-projects)
+    MissingProject,
+}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let kind = serde_json::to_value(self).unwrap_or_default();
+        write!(f, "{}", kind.as_str().unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub file: String,
+    pub message: String,
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+// Logs the warning and collects it. A warning that was collected before, e.g. because a file is read
+// twice, is neither logged nor collected again
+pub fn warn(kind: WarningKind, file: &str, message: String) {
+    let warning = Warning {
+        kind,
+        file: file.to_string(),
+        message,
+    };
+    let mut warnings = WARNINGS.lock().unwrap();
+    if !warnings.contains(&warning) {
+        info!("⚠️ {}", warning.message);
+        warnings.push(warning);
+    }
+}
+
+// Forgets the warnings of the previous diff (--watch)
+pub fn clear() {
+    WARNINGS.lock().unwrap().clear();
+}
+
+fn warnings_file(output_folder: &str) -> String {
+    format!("{}/warnings.json", output_folder)
+}
+
+pub fn remove_warnings(output_folder: &str) {
+    fs::remove_file(warnings_file(output_folder)).unwrap_or_default();
+}
+
+pub fn write_warnings(output_folder: &str) -> Result<(), Box<dyn Error>> {
+    let warnings = WARNINGS.lock().unwrap().clone();
+    fs::write(
+        warnings_file(output_folder),
+        serde_json::to_string_pretty(&warnings)?,
+    )?;
+    Ok(())
+}
+
+// Lists the warnings of the run, with the number of warnings of each kind
+pub fn generate_warnings_section() -> Option<ReportSection> {
+    let warnings = WARNINGS.lock().unwrap().clone();
+    if warnings.is_empty() {
+        return None;
+    }
+    let mut counts: BTreeMap<WarningKind, usize> = BTreeMap::new();
+    for warning in &warnings {
+        *counts.entry(warning.kind).or_default() += 1;
+    }
+    let counts: Vec<String> = counts
+        .iter()
+        .map(|(kind, count)| format!("{}: {}", kind, count))
+        .collect();
+    let rows: Vec<String> = warnings
+        .iter()
+        .map(|w| {
+            format!(
+                "| {} | {} | {} |",
+                w.kind,
+                w.file,
+                w.message.replace('|', "\\|")
+            )
+        })
+        .collect();
+    Some(ReportSection {
+        title: format!("⚠️ Warnings ({}):", warnings.len()),
+        content: format!(
+            "{}\n\n| Kind | File | Message |\n|---|---|---|\n{}",
+            counts.join(", "),
+            rows.join("\n")
+        ),
+    })
+}
@@ -0,0 +1,72 @@
+// Intermediate files of a run (patched applications, batches, generated values, the envtest
+// cluster, ...) live in a directory of their own, which is removed when the run succeeds and kept
+// for debugging when it fails
+use log::{debug, info};
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+static RUN_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// A new directory in 'workdir', or in the system temp directory. A directory left by an earlier
+// process with the same id is never reused
+fn create(workdir: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+    let base = workdir
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    fs::create_dir_all(&base)?;
+    let name = format!("argocd-diff-preview-{}", std::process::id());
+    let mut dir = base.join(&name);
+    let mut attempt = 0;
+    loop {
+        match fs::create_dir(&dir) {
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                attempt += 1;
+                dir = base.join(format!("{}-{}", name, attempt));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // commands run in other directories, so the path must be absolute
+    let dir = dir.canonicalize()?;
+    debug!("Work directory: {}", dir.display());
+    Ok(dir)
+}
+
+// Creates the directory of this run in 'workdir', or in the system temp directory
+pub fn init(workdir: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(dir) = RUN_DIR.get() {
+        return Ok(dir.clone());
+    }
+    let dir = create(workdir)?;
+    let _ = RUN_DIR.set(dir.clone());
+    Ok(dir)
+}
+
+// A file in the work directory of this run. The directory is created in the system temp directory
+// when it wasn't initialized
+pub fn path(name: &str) -> PathBuf {
+    RUN_DIR
+        .get_or_init(|| match create(None) {
+            Ok(dir) => dir,
+            Err(e) => panic!("failed to create work directory: {}", e),
+        })
+        .join(name)
+}
+
+// Removes the work directory after a successful run
+pub fn cleanup() {
+    if let Some(dir) = RUN_DIR.get() {
+        match fs::remove_dir_all(dir) {
+            Ok(_) => debug!("Removed work directory: {}", dir.display()),
+            Err(e) => info!(
+                "⚠️ Failed to remove work directory {}: {}",
+                dir.display(),
+                e
+            ),
+        }
+    }
+}